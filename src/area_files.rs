@@ -11,8 +11,14 @@
 //! The area_files module contains file handling functionality, to be used by the areas module.
 
 use crate::context;
+use crate::osm_diff;
+use crate::overpass_local;
 use crate::stats;
 use crate::util;
+use rusqlite::OptionalExtension as _;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
 
 #[cfg(not(test))]
 use log::info;
@@ -21,7 +27,7 @@ use log::info;
 use std::println as info;
 
 /// OverpassTags contains various tags about one Overpass element.
-#[derive(serde::Deserialize)]
+#[derive(Clone, serde::Deserialize)]
 struct OverpassTags {
     name: Option<String>,
     highway: Option<String>,
@@ -52,10 +58,19 @@ struct OverpassTags {
     unit: Option<String>,
     #[serde(rename(deserialize = "addr:city"))]
     city: Option<String>,
+    #[serde(rename(deserialize = "addr:interpolation"))]
+    interpolation: Option<String>,
     // endregion housenumbers
     fixme: Option<String>,
 }
 
+/// OverpassPoint is one point of an element geometry.
+#[derive(Clone, serde::Deserialize)]
+struct OverpassPoint {
+    lat: f64,
+    lon: f64,
+}
+
 /// OverpassElement represents one result from Overpass.
 #[derive(serde::Deserialize)]
 struct OverpassElement {
@@ -65,6 +80,26 @@ struct OverpassElement {
     pub user: Option<String>,
     pub timestamp: Option<String>,
     tags: OverpassTags,
+    /// Only set for ways, with out geom.
+    geometry: Option<Vec<OverpassPoint>>,
+    /// Only set for ways, with out body.
+    nodes: Option<Vec<u64>>,
+}
+
+impl OverpassElement {
+    /// Creates an element from the new state of an object in a replication diff.
+    fn from_change(change: &osm_diff::Change) -> anyhow::Result<Self> {
+        let tags: OverpassTags = serde_json::from_value(serde_json::to_value(&change.tags)?)?;
+        Ok(OverpassElement {
+            id: change.id,
+            osm_type: change.osm_type.to_string(),
+            user: Some(change.user.to_string()),
+            timestamp: Some(change.timestamp.to_string()),
+            tags,
+            geometry: None,
+            nodes: None,
+        })
+    }
 }
 
 #[derive(serde::Deserialize)]
@@ -82,6 +117,33 @@ struct OverpassResult {
     elements: Vec<OverpassElement>,
 }
 
+/// OverpassMember is one member of a relation in an Overpass result, with out geom.
+#[derive(serde::Deserialize)]
+struct OverpassMember {
+    #[serde(rename(deserialize = "type"))]
+    member_type: String,
+    role: String,
+    /// Only set for way members.
+    geometry: Option<Vec<OverpassPoint>>,
+}
+
+/// OverpassGeometry is the geometry of one element in an Overpass result, with out geom.
+#[derive(serde::Deserialize)]
+struct OverpassGeometry {
+    id: u64,
+    /// Only set for ways.
+    geometry: Option<Vec<OverpassPoint>>,
+    /// Only set for relations.
+    #[serde(default)]
+    members: Vec<OverpassMember>,
+}
+
+/// OverpassGeometryResult is the result from Overpass, with out geom.
+#[derive(serde::Deserialize)]
+struct OverpassGeometryResult {
+    elements: Vec<OverpassGeometry>,
+}
+
 /// One row in the `osm_streets` SQL table for a relation. Keep this in sync with data/streets-template.overpassql.
 pub struct OsmStreet {
     /// Object ID.
@@ -104,6 +166,350 @@ impl OsmStreet {
     }
 }
 
+/// Inserts one element into the osm_streets table.
+fn insert_osm_street(
+    conn: &rusqlite::Connection,
+    relation: &str,
+    element: OverpassElement,
+) -> rusqlite::Result<usize> {
+    let relation = relation.to_string();
+    let osm_id = element.id.to_string();
+    let name = element.tags.name.unwrap_or("".into());
+    let highway = element.tags.highway.unwrap_or("".into());
+    let service = element.tags.service.unwrap_or("".into());
+    let surface = element.tags.surface.unwrap_or("".into());
+    let leisure = element.tags.leisure.unwrap_or("".into());
+    let osm_type = element.osm_type.to_string();
+    let geometry = element.geometry.map(|points| {
+        let coordinates: Vec<[f64; 2]> = points.iter().map(|i| [i.lon, i.lat]).collect();
+        serde_json::to_string(&coordinates).unwrap()
+    });
+    conn.execute(
+        "insert into osm_streets (relation, osm_id, name, highway, service, surface, leisure, osm_type, geometry) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        rusqlite::params![relation, osm_id, name, highway, service, surface, leisure, osm_type, geometry],
+    )
+}
+
+/// Inserts one element into the osm_housenumbers table.
+fn insert_osm_housenumber(
+    conn: &rusqlite::Connection,
+    relation: &str,
+    element: OverpassElement,
+) -> rusqlite::Result<usize> {
+    let relation = relation.to_string();
+    let osm_id = element.id.to_string();
+    let street = element.tags.street.unwrap_or("".into());
+    let housenumber = element.tags.housenumber.unwrap_or("".into());
+    let postcode = element.tags.postcode.unwrap_or("".into());
+    let place = element.tags.place.unwrap_or("".into());
+    let housename = element.tags.housename.unwrap_or("".into());
+    let conscriptionnumber = element.tags.conscriptionnumber.unwrap_or("".into());
+    let flats = element.tags.flats.unwrap_or("".into());
+    let floor = element.tags.floor.unwrap_or("".into());
+    let door = element.tags.door.unwrap_or("".into());
+    let unit = element.tags.unit.unwrap_or("".into());
+    let name = element.tags.name.unwrap_or("".into());
+    let osm_type = element.osm_type.to_string();
+    conn.execute(
+        "insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        [relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type],
+    )
+}
+
+/// Expands an addr:interpolation way to the house numbers between its start and end, not
+/// including them. Returns None if the interpolation can't be expanded.
+fn expand_interpolation(interpolation: &str, start: &str, end: &str) -> Option<Vec<i64>> {
+    let step = match interpolation {
+        "all" => 1,
+        "even" | "odd" => 2,
+        _ => return None,
+    };
+    let mut start = start.trim().parse::<i64>().ok()?;
+    let mut end = end.trim().parse::<i64>().ok()?;
+    if start > end {
+        std::mem::swap(&mut start, &mut end);
+    }
+    if start <= 0 || end > 1000 {
+        // Typically just noise in the input data.
+        return None;
+    }
+    let parity = match interpolation {
+        "even" => Some(0),
+        "odd" => Some(1),
+        _ => None,
+    };
+    if let Some(parity) = parity {
+        if start % 2 != parity || end % 2 != parity {
+            return None;
+        }
+    }
+    Some((start + step..end).step_by(step as usize).collect())
+}
+
+/// Inserts one addr:interpolation way into the osm_housenumbers table of a relation, with its
+/// expanded house numbers. Its start and end nodes are looked up in nodes.
+fn insert_osm_interpolation(
+    conn: &rusqlite::Connection,
+    relation: &str,
+    element: &OverpassElement,
+    nodes: &HashMap<u64, OverpassTags>,
+) -> anyhow::Result<()> {
+    let interpolation = element.tags.interpolation.clone().unwrap_or_default();
+    let (start, end) = match element.nodes {
+        Some(ref value) if value.len() >= 2 => (value[0], value[value.len() - 1]),
+        _ => return Ok(()),
+    };
+    let (start, end) = match (nodes.get(&start), nodes.get(&end)) {
+        (Some(start), Some(end)) => (start, end),
+        _ => return Ok(()),
+    };
+    let numbers = match (&start.housenumber, &end.housenumber) {
+        (Some(start_number), Some(end_number)) => {
+            match expand_interpolation(&interpolation, start_number, end_number) {
+                Some(value) if !value.is_empty() => value,
+                _ => return Ok(()),
+            }
+        }
+        _ => return Ok(()),
+    };
+    let housenumber: Vec<String> = numbers.iter().map(|i| i.to_string()).collect();
+    let street = element
+        .tags
+        .street
+        .clone()
+        .or_else(|| start.street.clone())
+        .unwrap_or_default();
+    let place = element
+        .tags
+        .place
+        .clone()
+        .or_else(|| start.place.clone())
+        .unwrap_or_default();
+    conn.execute(
+        "insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type, interpolation) values (?1, ?2, ?3, ?4, '', ?5, '', '', '', '', '', '', '', ?6, ?7)",
+        [relation, &element.id.to_string(), &street, &housenumber.join(";"), &place, &element.osm_type, &interpolation],
+    )?;
+    Ok(())
+}
+
+/// Inserts one element into the whole_country table.
+fn insert_whole_country(
+    conn: &rusqlite::Connection,
+    element: OverpassElement,
+) -> rusqlite::Result<usize> {
+    let postcode = element.tags.postcode.unwrap_or("".into());
+    let city = element.tags.city.unwrap_or("".into());
+    let street = element.tags.street.unwrap_or("".into());
+    let housenumber = element.tags.housenumber.unwrap_or("".into());
+    let user = element.user.unwrap_or("".into());
+    let osm_id = element.id.to_string();
+    let osm_type = element.osm_type.to_string();
+    let timestamp = element.timestamp.unwrap_or("".into());
+    let place = element.tags.place.unwrap_or("".into());
+    let unit = element.tags.unit.unwrap_or("".into());
+    let name = element.tags.name.unwrap_or("".into());
+    let fixme = element.tags.fixme.unwrap_or("".into());
+    conn.execute(
+        "insert into whole_country (postcode, city, street, housenumber, user, osm_id, osm_type, timestamp, place, unit, name, fixme) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        [postcode, city, street, housenumber, user, osm_id, osm_type, timestamp, place, unit, name, fixme],
+    )
+}
+
+/// A stored boundary is queried again after this many seconds: a boundary way may move without
+/// its relation changing.
+const BOUNDARY_EXPIRY: i64 = 30 * 24 * 3600;
+
+/// Boundary of a relation, to place the objects of replication diffs.
+pub struct Boundary {
+    relation_id: u64,
+    area: overpass_local::Area,
+}
+
+/// Stores the boundary of a relation, result is the output of a `rel(ID); out geom;` Overpass
+/// query.
+pub fn write_boundary(
+    ctx: &context::Context,
+    relation_id: u64,
+    result: &str,
+) -> anyhow::Result<()> {
+    let overpass: OverpassGeometryResult = serde_json::from_str(result)?;
+    let mut segments: Vec<(f64, f64, f64, f64)> = Vec::new();
+    for element in overpass.elements {
+        for member in element.members {
+            if member.member_type != "way" || member.role == "subarea" {
+                continue;
+            }
+            let geometry = member.geometry.unwrap_or_default();
+            for pair in geometry.windows(2) {
+                segments.push((pair[0].lon, pair[0].lat, pair[1].lon, pair[1].lat));
+            }
+        }
+    }
+    if segments.is_empty() {
+        return Err(anyhow::anyhow!(
+            "no boundary ways for relation {relation_id}"
+        ));
+    }
+
+    let conn = ctx.get_database_connection()?;
+    conn.execute(
+        r#"insert into osm_boundaries (relation_id, segments, updated) values (?1, ?2, ?3)
+             on conflict(relation_id) do update set segments = excluded.segments, updated = excluded.updated"#,
+        [
+            relation_id.to_string(),
+            serde_json::to_string(&segments)?,
+            ctx.get_time().now_string(),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Gets the stored boundary of a relation, None if it's missing or expired.
+pub fn get_boundary(ctx: &context::Context, relation_id: u64) -> anyhow::Result<Option<Boundary>> {
+    let cutoff = ctx.get_time().now() - time::Duration::seconds(BOUNDARY_EXPIRY);
+    let cutoff = i64::try_from(cutoff.unix_timestamp_nanos())?;
+    let conn = ctx.get_database_connection()?;
+    let segments: Option<String> = conn
+        .query_row(
+            "select segments from osm_boundaries where relation_id = ?1 and cast(updated as integer) >= ?2",
+            rusqlite::params![relation_id.to_string(), cutoff],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let segments: Vec<(f64, f64, f64, f64)> = match segments {
+        Some(value) => serde_json::from_str(&value)?,
+        None => return Ok(None),
+    };
+    Ok(overpass_local::Area::new(&segments).map(|area| Boundary { relation_id, area }))
+}
+
+/// Checks if the relation of a boundary changes in diffs. The stored boundary is outdated then, so
+/// it gets removed.
+fn is_boundary_changed(
+    ctx: &context::Context,
+    diffs: &[osm_diff::Diff],
+    boundary: &Boundary,
+) -> anyhow::Result<bool> {
+    for diff in diffs {
+        let changes = diff.get_changes(ctx)?;
+        if changes
+            .iter()
+            .any(|change| change.osm_type == "relation" && change.id == boundary.relation_id)
+        {
+            let conn = ctx.get_database_connection()?;
+            conn.execute(
+                "delete from osm_boundaries where relation_id = ?1",
+                [boundary.relation_id.to_string()],
+            )?;
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Replication diffs to be applied to the osm_streets, osm_housenumbers or whole_country table.
+pub struct OsmDiffs<'a> {
+    /// "streets", "housenumbers" or "whole-country".
+    kind: &'static str,
+    diffs: &'a [osm_diff::Diff],
+    /// Decides if an object is part of the area query's result.
+    matcher: overpass_local::TagMatcher,
+    /// Way id -> geometry: diffs have no way nodes, so the changed ways are queried separately.
+    way_geometries: HashMap<u64, Vec<OverpassPoint>>,
+}
+
+impl<'a> OsmDiffs<'a> {
+    fn new(
+        kind: &'static str,
+        diffs: &'a [osm_diff::Diff],
+        matcher: overpass_local::TagMatcher,
+    ) -> Self {
+        OsmDiffs {
+            kind,
+            diffs,
+            matcher,
+            way_geometries: HashMap::new(),
+        }
+    }
+
+    /// Creates diffs for the osm_streets table, matcher is based on the streets query.
+    pub fn new_streets(diffs: &'a [osm_diff::Diff], matcher: overpass_local::TagMatcher) -> Self {
+        OsmDiffs::new("streets", diffs, matcher)
+    }
+
+    /// Creates diffs for the osm_housenumbers table, matcher is based on the housenumbers query.
+    pub fn new_housenumbers(
+        diffs: &'a [osm_diff::Diff],
+        matcher: overpass_local::TagMatcher,
+    ) -> Self {
+        OsmDiffs::new("housenumbers", diffs, matcher)
+    }
+
+    /// Creates diffs for the whole_country table, matcher is based on the whole country query.
+    pub fn new_whole_country(
+        diffs: &'a [osm_diff::Diff],
+        matcher: overpass_local::TagMatcher,
+    ) -> Self {
+        OsmDiffs::new("whole-country", diffs, matcher)
+    }
+
+    /// Gets the created or modified ways which match the query, their geometry is needed to place
+    /// them.
+    pub fn get_way_ids(&self, ctx: &context::Context) -> anyhow::Result<Vec<u64>> {
+        let mut ids: BTreeSet<u64> = BTreeSet::new();
+        for diff in self.diffs {
+            for change in diff.get_changes(ctx)?.iter() {
+                if change.osm_type == "way" && self.matches(change) {
+                    ids.insert(change.id);
+                }
+            }
+        }
+        Ok(ids.into_iter().collect())
+    }
+
+    /// Sets the geometry of changed ways, result is the output of a `way(id:...); out geom;`
+    /// Overpass query.
+    pub fn set_way_geometries(&mut self, result: &str) -> anyhow::Result<()> {
+        let overpass: OverpassGeometryResult = serde_json::from_str(result)?;
+        for element in overpass.elements {
+            if let Some(geometry) = element.geometry {
+                self.way_geometries.insert(element.id, geometry);
+            }
+        }
+        Ok(())
+    }
+
+    /// Decides if the new state of a changed object is part of the query's result, ignoring the
+    /// area.
+    fn matches(&self, change: &osm_diff::Change) -> bool {
+        change.action != osm_diff::Action::Delete
+            && self.matcher.matches(&change.osm_type, &change.tags)
+    }
+
+    /// Gets the queried geometry of a changed way.
+    fn get_way_geometry(&self, change: &osm_diff::Change) -> Option<&Vec<OverpassPoint>> {
+        if change.osm_type != "way" {
+            return None;
+        }
+        self.way_geometries.get(&change.id)
+    }
+
+    /// Decides if a changed object is inside a boundary: nodes are placed by their coordinates,
+    /// ways by their queried geometry. Like an Overpass area query, a way is inside if any of its
+    /// nodes is inside. Returns None if the object can't be placed.
+    fn is_inside(&self, change: &osm_diff::Change, boundary: &Boundary) -> Option<bool> {
+        if let Some((lat, lon)) = change.coordinates {
+            return Some(boundary.area.contains(lat, lon));
+        }
+        let geometry = self.get_way_geometry(change)?;
+        Some(
+            geometry
+                .iter()
+                .any(|point| boundary.area.contains(point.lat, point.lon)),
+        )
+    }
+}
+
 /// A relation's file interface provides access to files associated with a relation.
 #[derive(Clone)]
 pub struct RelationFiles {
@@ -131,6 +537,27 @@ impl RelationFiles {
         Ok(ret)
     }
 
+    /// Gets the geometries of the OSM streets of a relation with the provided name: (id,
+    /// coordinates) pairs, coordinates are [lon, lat] pairs.
+    pub fn get_osm_street_geometries(
+        &self,
+        ctx: &context::Context,
+        name: &str,
+    ) -> anyhow::Result<Vec<(u64, Vec<[f64; 2]>)>> {
+        let mut ret: Vec<(u64, Vec<[f64; 2]>)> = Vec::new();
+        let conn = ctx.get_database_connection()?;
+        let mut stmt = conn.prepare(
+            "select osm_id, geometry from osm_streets where relation = ?1 and name = ?2 and geometry is not null order by cast(osm_id as integer)",
+        )?;
+        let mut rows = stmt.query([&self.name, name])?;
+        while let Some(row) = rows.next()? {
+            let id: String = row.get(0)?;
+            let geometry: String = row.get(1)?;
+            ret.push((id.parse()?, serde_json::from_str(&geometry)?));
+        }
+        Ok(ret)
+    }
+
     /// Opens the OSM house number list of a relation for reading.
     pub fn get_osm_json_housenumbers(
         &self,
@@ -139,7 +566,7 @@ impl RelationFiles {
         let mut ret: Vec<util::OsmHouseNumber> = Vec::new();
         let conn = ctx.get_database_connection()?;
         let mut stmt =
-            conn.prepare("select osm_id, housenumber, conscriptionnumber, street, place, osm_type from osm_housenumbers where relation = ?1")?;
+            conn.prepare("select osm_id, housenumber, conscriptionnumber, street, place, osm_type, interpolation, postcode from osm_housenumbers where relation = ?1")?;
         let mut rows = stmt.query([&self.name])?;
         while let Some(row) = rows.next()? {
             let id: String = row.get(0).unwrap();
@@ -148,14 +575,19 @@ impl RelationFiles {
             let street: String = row.get(3).unwrap();
             let place: String = row.get(4).unwrap();
             let object_type: String = row.get(5).unwrap();
-            ret.push(util::OsmHouseNumber::new(
+            let interpolation: String = row.get(6).unwrap();
+            let postcode: String = row.get(7).unwrap();
+            let mut osm_housenumber = util::OsmHouseNumber::new(
                 id.parse()?,
                 &housenumber,
                 &conscriptionnumber,
                 &street,
                 &Some(place),
                 &object_type,
-            ));
+            );
+            osm_housenumber.interpolated = !interpolation.is_empty();
+            osm_housenumber.postcode = postcode;
+            ret.push(osm_housenumber);
         }
         Ok(ret)
     }
@@ -184,20 +616,10 @@ impl RelationFiles {
             [self.name.to_string()],
         )?;
         for element in overpass.elements {
-            let relation = self.name.to_string();
-            let osm_id = element.id.to_string();
-            let name = element.tags.name.unwrap_or("".into());
-            let highway = element.tags.highway.unwrap_or("".into());
-            let service = element.tags.service.unwrap_or("".into());
-            let surface = element.tags.surface.unwrap_or("".into());
-            let leisure = element.tags.leisure.unwrap_or("".into());
-            let osm_type = element.osm_type.to_string();
-            let ret = tx.execute(
-                "insert into osm_streets (relation, osm_id, name, highway, service, surface, leisure, osm_type) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-                [relation, osm_id, name, highway, service, surface, leisure, osm_type],
-            );
+            let id = element.id;
+            let ret = insert_osm_street(&tx, &self.name, element);
             if ret.is_err() {
-                info!("write_osm_json_streets: ignoring duplicated street: relation is '{}', id is '{}'", self.name, element.id);
+                info!("write_osm_json_streets: ignoring duplicated street: relation is '{}', id is '{}'", self.name, id);
             }
         }
 
@@ -244,27 +666,26 @@ impl RelationFiles {
             "delete from osm_housenumbers where relation = ?1",
             [self.name.to_string()],
         )?;
+        let mut interpolations: Vec<OverpassElement> = Vec::new();
+        let mut nodes: HashMap<u64, OverpassTags> = HashMap::new();
         for element in overpass.elements {
-            let relation = self.name.to_string();
-            let osm_id = element.id.to_string();
-            let street = element.tags.street.unwrap_or("".into());
-            let housenumber = element.tags.housenumber.unwrap_or("".into());
-            let postcode = element.tags.postcode.unwrap_or("".into());
-            let place = element.tags.place.unwrap_or("".into());
-            let housename = element.tags.housename.unwrap_or("".into());
-            let conscriptionnumber = element.tags.conscriptionnumber.unwrap_or("".into());
-            let flats = element.tags.flats.unwrap_or("".into());
-            let floor = element.tags.floor.unwrap_or("".into());
-            let door = element.tags.door.unwrap_or("".into());
-            let unit = element.tags.unit.unwrap_or("".into());
-            let name = element.tags.name.unwrap_or("".into());
-            let osm_type = element.osm_type.to_string();
-            let ret = tx.execute(
-                "insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
-                [relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type],
-            );
+            let id = element.id;
+            if element.osm_type == "way" && element.tags.interpolation.is_some() {
+                interpolations.push(element);
+                continue;
+            }
+            if element.osm_type == "node" {
+                nodes.insert(id, element.tags.clone());
+            }
+            let ret = insert_osm_housenumber(&tx, &self.name, element);
             if ret.is_err() {
-                info!("write_osm_json_housenumbers: ignoring duplicated housenumber: relation is '{}', id is '{}'", self.name, element.id);
+                info!("write_osm_json_housenumbers: ignoring duplicated housenumber: relation is '{}', id is '{}'", self.name, id);
+            }
+        }
+        for element in interpolations {
+            let ret = insert_osm_interpolation(&tx, &self.name, &element, &nodes);
+            if ret.is_err() {
+                info!("write_osm_json_housenumbers: ignoring duplicated interpolation: relation is '{}', id is '{}'", self.name, element.id);
             }
         }
 
@@ -287,6 +708,181 @@ impl RelationFiles {
 
         Ok(())
     }
+    /// Applies replication diffs to the OSM street or housenumber list of a relation.
+    ///
+    /// Changed objects are placed by their location against the boundary of the relation. Returns
+    /// false if the diffs can't be applied and a full query is needed, e.g. a new way or relation
+    /// which can't be located.
+    pub fn apply_osm_diffs(
+        &self,
+        ctx: &context::Context,
+        diffs: &OsmDiffs<'_>,
+        boundary: &Boundary,
+    ) -> anyhow::Result<bool> {
+        let kind = diffs.kind;
+        let all_diffs = diffs.diffs;
+        let osm_page = format!("{kind}/{}/osm-base", self.name);
+        if !stats::has_sql_mtime(ctx, &osm_page)? {
+            return Ok(false);
+        }
+        let osm_base = stats::get_sql_mtime(ctx, &osm_page)?;
+        let start = match osm_diff::find_pending(all_diffs, osm_base) {
+            Some(value) => value,
+            None => {
+                return Ok(false);
+            }
+        };
+        let last = match all_diffs.last() {
+            Some(value) if start < all_diffs.len() => value,
+            _ => {
+                return Ok(true);
+            }
+        };
+        if is_boundary_changed(ctx, &all_diffs[start..], boundary)? {
+            info!(
+                "apply_osm_diffs: boundary changed: relation is '{}'",
+                self.name
+            );
+            return Ok(false);
+        }
+
+        let mut changed = false;
+        {
+            let mut conn = ctx.get_database_connection()?;
+            let tx = conn.transaction()?;
+            let mut tracked: HashSet<(String, u64)> = HashSet::new();
+            {
+                let mut stmt = tx.prepare(&format!(
+                    "select osm_type, osm_id from osm_{kind} where relation = ?1"
+                ))?;
+                let mut rows = stmt.query([&self.name])?;
+                while let Some(row) = rows.next()? {
+                    let osm_type: String = row.get(0)?;
+                    let osm_id: String = row.get(1)?;
+                    tracked.insert((osm_type, osm_id.parse()?));
+                }
+            }
+            for diff in &all_diffs[start..] {
+                for change in diff.get_changes(ctx)?.iter() {
+                    let key = (change.osm_type.to_string(), change.id);
+                    let was_tracked = tracked.contains(&key);
+                    let inside = if diffs.matches(change) {
+                        match diffs.is_inside(change, boundary) {
+                            Some(value) => value,
+                            // A tracked way or relation keeps its place when only its tags change.
+                            None if was_tracked => true,
+                            None => {
+                                info!(
+                                    "apply_osm_diffs: can't place {} {}: relation is '{}'",
+                                    change.osm_type, change.id, self.name
+                                );
+                                return Ok(false);
+                            }
+                        }
+                    } else {
+                        false
+                    };
+                    if !was_tracked && !inside {
+                        continue;
+                    }
+
+                    let id = change.id.to_string();
+                    let mut old_geometry: Option<String> = None;
+                    if was_tracked && inside {
+                        if kind == "streets" {
+                            old_geometry = tx
+                                .query_row(
+                                    "select geometry from osm_streets where relation = ?1 and osm_id = ?2 and osm_type = ?3",
+                                    [&self.name, &id, &change.osm_type],
+                                    |row| row.get(0),
+                                )
+                                .optional()?
+                                .flatten();
+                        } else {
+                            let interpolation: Option<String> = tx
+                                .query_row(
+                                    "select interpolation from osm_housenumbers where relation = ?1 and osm_id = ?2 and osm_type = ?3",
+                                    [&self.name, &id, &change.osm_type],
+                                    |row| row.get(0),
+                                )
+                                .optional()?;
+                            if interpolation.is_some_and(|i| !i.is_empty()) {
+                                return Ok(false);
+                            }
+                        }
+                    }
+                    if inside
+                        && kind == "housenumbers"
+                        && change.tags.contains_key("addr:interpolation")
+                    {
+                        // Diffs have no way nodes, an interpolation can't be expanded.
+                        return Ok(false);
+                    }
+                    if was_tracked {
+                        tx.execute(
+                            &format!(
+                                "delete from osm_{kind} where relation = ?1 and osm_id = ?2 and osm_type = ?3"
+                            ),
+                            [&self.name, &id, &change.osm_type],
+                        )?;
+                        tracked.remove(&key);
+                        changed = true;
+                    }
+                    if !inside {
+                        continue;
+                    }
+
+                    let mut element = OverpassElement::from_change(change)?;
+                    element.geometry = diffs.get_way_geometry(change).cloned();
+                    if element.geometry.is_none() {
+                        if let Some(old_geometry) = old_geometry {
+                            // No queried geometry, keep the old one.
+                            let coordinates: Vec<[f64; 2]> = serde_json::from_str(&old_geometry)?;
+                            element.geometry = Some(
+                                coordinates
+                                    .iter()
+                                    .map(|[lon, lat]| OverpassPoint {
+                                        lat: *lat,
+                                        lon: *lon,
+                                    })
+                                    .collect(),
+                            );
+                        }
+                    }
+                    if kind == "streets" && change.osm_type == "way" && element.geometry.is_none() {
+                        // No geometry to keep and none was queried: needs a full query.
+                        return Ok(false);
+                    }
+                    let ret = if kind == "streets" {
+                        insert_osm_street(&tx, &self.name, element)
+                    } else {
+                        insert_osm_housenumber(&tx, &self.name, element)
+                    };
+                    if ret.is_err() {
+                        info!("apply_osm_diffs: ignoring duplicated {kind}: relation is '{}', id is '{}'", self.name, change.id);
+                        continue;
+                    }
+                    tracked.insert(key);
+                    changed = true;
+                }
+            }
+
+            let osm_time = last.timestamp.unix_timestamp_nanos();
+            tx.execute(
+                r#"insert into mtimes (page, last_modified) values (?1, ?2)
+                 on conflict(page) do update set last_modified = excluded.last_modified"#,
+                [osm_page, osm_time.to_string()],
+            )?;
+            tx.commit()?;
+        }
+
+        if changed {
+            // Invalidate caches which depend on the OSM data of this relation.
+            stats::set_sql_mtime(ctx, &format!("{kind}/{}", self.name))?;
+        }
+
+        Ok(true)
+    }
 }
 
 pub fn write_whole_country(ctx: &context::Context, result: &str) -> anyhow::Result<()> {
@@ -303,22 +899,7 @@ pub fn write_whole_country(ctx: &context::Context, result: &str) -> anyhow::Resu
     let tx = conn.transaction()?;
     tx.execute("delete from whole_country", [])?;
     for element in overpass.elements {
-        let postcode = element.tags.postcode.unwrap_or("".into());
-        let city = element.tags.city.unwrap_or("".into());
-        let street = element.tags.street.unwrap_or("".into());
-        let housenumber = element.tags.housenumber.unwrap_or("".into());
-        let user = element.user.unwrap_or("".into());
-        let osm_id = element.id.to_string();
-        let osm_type = element.osm_type.to_string();
-        let timestamp = element.timestamp.unwrap_or("".into());
-        let place = element.tags.place.unwrap_or("".into());
-        let unit = element.tags.unit.unwrap_or("".into());
-        let name = element.tags.name.unwrap_or("".into());
-        let fixme = element.tags.fixme.unwrap_or("".into());
-        tx.execute(
-                "insert into whole_country (postcode, city, street, housenumber, user, osm_id, osm_type, timestamp, place, unit, name, fixme) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
-                [postcode, city, street, housenumber, user, osm_id, osm_type, timestamp, place, unit, name, fixme],
-            )?;
+        insert_whole_country(&tx, element)?;
     }
 
     let osm_time = overpass.osm3s.timestamp_osm_base.unix_timestamp_nanos();
@@ -339,5 +920,84 @@ pub fn write_whole_country(ctx: &context::Context, result: &str) -> anyhow::Resu
     Ok(())
 }
 
+/// Applies replication diffs to the whole_country table, changed objects are placed by their
+/// location against the boundary of the country. Returns false if the diffs can't be applied and a
+/// full query is needed.
+pub fn apply_whole_country_diffs(
+    ctx: &context::Context,
+    diffs: &OsmDiffs<'_>,
+    boundary: &Boundary,
+) -> anyhow::Result<bool> {
+    let all_diffs = diffs.diffs;
+    if !stats::has_sql_mtime(ctx, "whole-country/osm-base")? {
+        return Ok(false);
+    }
+    let osm_base = stats::get_sql_mtime(ctx, "whole-country/osm-base")?;
+    let start = match osm_diff::find_pending(all_diffs, osm_base) {
+        Some(value) => value,
+        None => {
+            return Ok(false);
+        }
+    };
+    let last = match all_diffs.last() {
+        Some(value) if start < all_diffs.len() => value,
+        _ => {
+            return Ok(true);
+        }
+    };
+    if is_boundary_changed(ctx, &all_diffs[start..], boundary)? {
+        info!("apply_whole_country_diffs: boundary changed");
+        return Ok(false);
+    }
+
+    let mut conn = ctx.get_database_connection()?;
+    let tx = conn.transaction()?;
+    for diff in &all_diffs[start..] {
+        for change in diff.get_changes(ctx)?.iter() {
+            // The table is large, look up the tracked state of each change instead of loading it.
+            let tracked = tx
+                .prepare_cached("select 1 from whole_country where osm_id = ?1 and osm_type = ?2")?
+                .exists([&change.id.to_string(), &change.osm_type])?;
+            let inside = if diffs.matches(change) {
+                match diffs.is_inside(change, boundary) {
+                    Some(value) => value,
+                    // A tracked way or relation keeps its place when only its tags change.
+                    None if tracked => true,
+                    None => {
+                        info!(
+                            "apply_whole_country_diffs: can't place {} {}",
+                            change.osm_type, change.id
+                        );
+                        return Ok(false);
+                    }
+                }
+            } else {
+                false
+            };
+
+            if tracked {
+                tx.execute(
+                    "delete from whole_country where osm_id = ?1 and osm_type = ?2",
+                    [&change.id.to_string(), &change.osm_type],
+                )?;
+            }
+            if !inside {
+                continue;
+            }
+            insert_whole_country(&tx, OverpassElement::from_change(change)?)?;
+        }
+    }
+
+    let osm_time = last.timestamp.unix_timestamp_nanos();
+    tx.execute(
+        r#"insert into mtimes (page, last_modified) values ('whole-country/osm-base', ?1)
+                 on conflict(page) do update set last_modified = excluded.last_modified"#,
+        [osm_time.to_string()],
+    )?;
+    tx.commit()?;
+
+    Ok(true)
+}
+
 #[cfg(test)]
 mod tests;
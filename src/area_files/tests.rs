@@ -12,7 +12,8 @@
 
 use super::*;
 use crate::areas;
-use std::rc::Rc;
+use std::io::Write as _;
+use std::sync::Arc;
 
 /// Tests RelationFiles::write_osm_json_streets(), when the json has duplicated streets.
 #[test]
@@ -32,7 +33,7 @@ fn test_write_osm_json_streets_duplicate() {
     );
     let mut file_system = context::tests::TestFileSystem::new();
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
     let mut relations = areas::Relations::new(&ctx).unwrap();
     let relation = relations.get_relation("gazdagret").unwrap();
@@ -63,7 +64,7 @@ fn test_write_osm_json_housenumbers_duplicate() {
     );
     let mut file_system = context::tests::TestFileSystem::new();
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
     let mut relations = areas::Relations::new(&ctx).unwrap();
     let relation = relations.get_relation("gazdagret").unwrap();
@@ -77,6 +78,76 @@ fn test_write_osm_json_housenumbers_duplicate() {
         .unwrap();
 }
 
+/// Tests RelationFiles::write_osm_json_housenumbers(): addr:interpolation ways are expanded.
+#[test]
+fn test_write_osm_json_housenumbers_interpolation() {
+    let osc = r#"<osmChange version="0.6">
+  <modify>
+    <way id="3" version="2" timestamp="2023-11-17T10:00:00Z" user="alice">
+      <nd ref="1"/>
+      <nd ref="2"/>
+      <tag k="addr:interpolation" v="all"/>
+    </way>
+  </modify>
+</osmChange>"#;
+    let ctx = make_diffs_context(osc);
+    let mut relations = areas::Relations::new(&ctx).unwrap();
+    let relation = relations.get_relation("gazdagret").unwrap();
+    let result = serde_json::json!({
+        "osm3s": {
+            "timestamp_osm_base": "2023-11-16T13:34:15Z",
+            "timestamp_areas_base": "2023-11-16T10:23:59Z"
+        },
+        "elements": [
+            {"type": "node", "id": 1, "tags": {"addr:street": "Törökugrató utca", "addr:housenumber": "1"}},
+            {"type": "node", "id": 2, "tags": {"addr:street": "Törökugrató utca", "addr:housenumber": "9"}},
+            {"type": "way", "id": 3, "nodes": [1, 10, 2], "tags": {"addr:interpolation": "odd"}},
+            // Not expanded: alphabetic interpolation.
+            {"type": "way", "id": 4, "nodes": [1, 2], "tags": {"addr:interpolation": "alphabetic"}},
+        ]
+    });
+    let files = relation.get_files();
+
+    files
+        .write_osm_json_housenumbers(&ctx, &result.to_string())
+        .unwrap();
+
+    let housenumbers = files.get_osm_json_housenumbers(&ctx).unwrap();
+    assert_eq!(housenumbers.len(), 3);
+    let way = housenumbers.iter().find(|i| i.id == 3).unwrap();
+    assert_eq!(way.street, "Törökugrató utca");
+    assert_eq!(way.housenumber, "3;5;7");
+    assert_eq!(way.object_type, "way");
+    assert_eq!(way.interpolated, true);
+    let node = housenumbers.iter().find(|i| i.id == 1).unwrap();
+    assert_eq!(node.interpolated, false);
+
+    // Diffs have no way nodes, so a change to an interpolation way needs a full query.
+    let diffs = osm_diff::read_diffs(&ctx).unwrap();
+    let matcher = overpass_local::TagMatcher::new(
+        "nwr[\"addr:housenumber\"];out;way[\"addr:interpolation\"];out;",
+    )
+    .unwrap();
+    let osm_diffs = OsmDiffs::new_housenumbers(&diffs, matcher);
+    let ret = files
+        .apply_osm_diffs(&ctx, &osm_diffs, &make_boundary(&ctx))
+        .unwrap();
+    assert_eq!(ret, false);
+    assert_eq!(files.get_osm_json_housenumbers(&ctx).unwrap().len(), 3);
+}
+
+/// Tests expand_interpolation().
+#[test]
+fn test_expand_interpolation() {
+    assert_eq!(expand_interpolation("even", "2", "10"), Some(vec![4, 6, 8]));
+    assert_eq!(expand_interpolation("odd", "9", "3"), Some(vec![5, 7]));
+    assert_eq!(expand_interpolation("all", "1", "4"), Some(vec![2, 3]));
+    // Parity mismatch.
+    assert_eq!(expand_interpolation("even", "1", "10"), None);
+    assert_eq!(expand_interpolation("alphabetic", "1", "1c"), None);
+    assert_eq!(expand_interpolation("all", "1", "2000"), None);
+}
+
 /// Tests write_whole_country(), when it gets non-JSON input.
 #[test]
 fn test_write_whole_country_non_json_input() {
@@ -86,3 +157,498 @@ fn test_write_whole_country_non_json_input() {
 
     assert!(ret.is_ok());
 }
+
+/// Creates a context with the gazdagret relation and two replication diffs: the first one is
+/// already applied, the second one has osc as its changes.
+fn make_diffs_context(osc: &str) -> context::Context {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "osmrelation": 2713748,
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let state1 = context::tests::TestFileSystem::make_file();
+    state1
+        .lock()
+        .unwrap()
+        .write_all(b"sequenceNumber=1\ntimestamp=2023-11-16T13\\:34\\:15Z\n")
+        .unwrap();
+    let osc1 = context::tests::TestFileSystem::make_file();
+    osc1.lock()
+        .unwrap()
+        .write_all(b"<osmChange version=\"0.6\"/>")
+        .unwrap();
+    let state2 = context::tests::TestFileSystem::make_file();
+    state2
+        .lock()
+        .unwrap()
+        .write_all(b"sequenceNumber=2\ntimestamp=2023-11-17T13\\:34\\:15Z\n")
+        .unwrap();
+    let osc2 = context::tests::TestFileSystem::make_file();
+    osc2.lock().unwrap().write_all(osc.as_bytes()).unwrap();
+    let files = context::tests::TestFileSystem::make_files(
+        &ctx,
+        &[
+            ("data/yamls.cache", &yamls_cache_value),
+            ("workdir/replication/1.state.txt", &state1),
+            ("workdir/replication/1.osc", &osc1),
+            ("workdir/replication/2.state.txt", &state2),
+            ("workdir/replication/2.osc", &osc2),
+        ],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    ctx.set_file_system(&file_system);
+    ctx
+}
+
+/// Stores the boundary of the gazdagret relation: a square around lat 47.45, lon 19.0.
+fn make_boundary(ctx: &context::Context) -> Boundary {
+    let result = serde_json::json!({
+        "elements": [
+            {
+                "type": "relation",
+                "id": 2713748,
+                "members": [
+                    {
+                        "type": "way",
+                        "ref": 1,
+                        "role": "outer",
+                        "geometry": [
+                            {"lat": 47.4, "lon": 18.9},
+                            {"lat": 47.4, "lon": 19.1},
+                            {"lat": 47.5, "lon": 19.1},
+                            {"lat": 47.5, "lon": 18.9},
+                            {"lat": 47.4, "lon": 18.9}
+                        ]
+                    },
+                    {"type": "node", "ref": 2, "role": "admin_centre", "lat": 47.45, "lon": 19.0}
+                ]
+            }
+        ]
+    });
+    write_boundary(ctx, 2713748, &result.to_string()).unwrap();
+    get_boundary(ctx, 2713748).unwrap().unwrap()
+}
+
+/// Tests RelationFiles::apply_osm_diffs(): tracked streets are updated or removed.
+#[test]
+fn test_apply_osm_diffs() {
+    let osc = r#"<osmChange version="0.6">
+  <modify>
+    <way id="1" version="2" timestamp="2023-11-17T10:00:00Z" user="alice">
+      <tag k="highway" v="residential"/>
+      <tag k="name" v="Tűzkő utca"/>
+    </way>
+    <way id="2" version="2" timestamp="2023-11-17T10:00:00Z" user="alice">
+      <tag k="highway" v="residential"/>
+    </way>
+  </modify>
+  <create>
+    <way id="4" version="1" timestamp="2023-11-17T10:00:00Z" user="alice">
+      <tag k="highway" v="residential"/>
+    </way>
+  </create>
+  <delete>
+    <way id="3" version="2" timestamp="2023-11-17T10:00:00Z" user="alice"/>
+  </delete>
+</osmChange>"#;
+    let ctx = make_diffs_context(osc);
+    let mut relations = areas::Relations::new(&ctx).unwrap();
+    let relation = relations.get_relation("gazdagret").unwrap();
+    let result = serde_json::json!({
+        "osm3s": {
+            "timestamp_osm_base": "2023-11-16T13:34:15Z",
+            "timestamp_areas_base": "2023-11-16T10:23:59Z"
+        },
+        "elements": [
+            {
+                "type": "way",
+                "id": 1,
+                "tags": {"name": "Törökugrató utca", "highway": "residential"},
+                "geometry": [{"lat": 47.46, "lon": 19.01}, {"lat": 47.47, "lon": 19.02}]
+            },
+            {"type": "way", "id": 2, "tags": {"name": "Hamzsabégi út", "highway": "residential"}},
+            {"type": "way", "id": 3, "tags": {"name": "Tűzkő utca", "highway": "residential"}},
+        ]
+    });
+    let files = relation.get_files();
+    files
+        .write_osm_json_streets(&ctx, &result.to_string())
+        .unwrap();
+    assert_eq!(
+        files
+            .get_osm_street_geometries(&ctx, "Törökugrató utca")
+            .unwrap(),
+        vec![(1, vec![[19.01, 47.46], [19.02, 47.47]])]
+    );
+    {
+        let conn = ctx.get_database_connection().unwrap();
+        conn.execute(
+            "update mtimes set last_modified = '0' where page = 'streets/gazdagret'",
+            [],
+        )
+        .unwrap();
+    }
+    let diffs = osm_diff::read_diffs(&ctx).unwrap();
+    let matcher = overpass_local::TagMatcher::new("way[highway][name];out;").unwrap();
+    let osm_diffs = OsmDiffs::new_streets(&diffs, matcher);
+
+    let ret = files
+        .apply_osm_diffs(&ctx, &osm_diffs, &make_boundary(&ctx))
+        .unwrap();
+
+    assert_eq!(ret, true);
+    let streets: Vec<(u64, String)> = files
+        .get_osm_json_streets(&ctx)
+        .unwrap()
+        .into_iter()
+        .map(|street| (street.id, street.name))
+        .collect();
+    // 1 is renamed, 2 no longer matches, 3 is deleted, 4 doesn't match.
+    assert_eq!(streets, vec![(1, "Tűzkő utca".to_string())]);
+    // Diffs have no geometry, the old one is kept.
+    assert_eq!(
+        files.get_osm_street_geometries(&ctx, "Tűzkő utca").unwrap(),
+        vec![(1, vec![[19.01, 47.46], [19.02, 47.47]])]
+    );
+    let mtime = stats::get_sql_mtime(&ctx, "streets/gazdagret").unwrap();
+    assert!(mtime > time::OffsetDateTime::UNIX_EPOCH);
+    let osm_base = stats::get_sql_mtime(&ctx, "streets/gazdagret/osm-base").unwrap();
+    assert_eq!(osm_base, diffs[1].timestamp);
+}
+
+/// Tests RelationFiles::apply_osm_diffs(): a changed street without a geometry needs a full query.
+#[test]
+fn test_apply_osm_diffs_no_geometry() {
+    let osc = r#"<osmChange version="0.6">
+  <modify>
+    <way id="1" version="2" timestamp="2023-11-17T10:00:00Z" user="alice">
+      <tag k="highway" v="residential"/>
+      <tag k="name" v="Tűzkő utca"/>
+    </way>
+  </modify>
+</osmChange>"#;
+    let ctx = make_diffs_context(osc);
+    let mut relations = areas::Relations::new(&ctx).unwrap();
+    let relation = relations.get_relation("gazdagret").unwrap();
+    let result = serde_json::json!({
+        "osm3s": {
+            "timestamp_osm_base": "2023-11-16T13:34:15Z",
+            "timestamp_areas_base": "2023-11-16T10:23:59Z"
+        },
+        "elements": [
+            {"type": "way", "id": 1, "tags": {"name": "Törökugrató utca", "highway": "residential"}},
+        ]
+    });
+    let files = relation.get_files();
+    files
+        .write_osm_json_streets(&ctx, &result.to_string())
+        .unwrap();
+    let diffs = osm_diff::read_diffs(&ctx).unwrap();
+    let matcher = overpass_local::TagMatcher::new("way[highway][name];out;").unwrap();
+    let osm_diffs = OsmDiffs::new_streets(&diffs, matcher);
+
+    let ret = files
+        .apply_osm_diffs(&ctx, &osm_diffs, &make_boundary(&ctx))
+        .unwrap();
+
+    assert_eq!(ret, false);
+    let streets = files.get_osm_json_streets(&ctx).unwrap();
+    assert_eq!(streets.len(), 1);
+    assert_eq!(streets[0].name, "Törökugrató utca");
+}
+
+/// Tests RelationFiles::apply_osm_diffs(): a new way which can't be located needs a full query,
+/// even if its street is not yet known to the relation.
+#[test]
+fn test_apply_osm_diffs_new_object() {
+    let osc = r#"<osmChange version="0.6">
+  <create>
+    <way id="5" version="1" timestamp="2023-11-17T10:00:00Z" user="alice">
+      <nd ref="7"/>
+      <nd ref="8"/>
+      <tag k="addr:street" v="Hamzsabégi út"/>
+      <tag k="addr:housenumber" v="3"/>
+    </way>
+  </create>
+</osmChange>"#;
+    let ctx = make_diffs_context(osc);
+    let mut relations = areas::Relations::new(&ctx).unwrap();
+    let relation = relations.get_relation("gazdagret").unwrap();
+    let result = serde_json::json!({
+        "osm3s": {
+            "timestamp_osm_base": "2023-11-16T13:34:15Z",
+            "timestamp_areas_base": "2023-11-16T10:23:59Z"
+        },
+        "elements": [
+            {"type": "node", "id": 6, "tags": {"addr:street": "Törökugrató utca", "addr:housenumber": "1"}},
+        ]
+    });
+    let files = relation.get_files();
+    files
+        .write_osm_json_housenumbers(&ctx, &result.to_string())
+        .unwrap();
+    let diffs = osm_diff::read_diffs(&ctx).unwrap();
+    let matcher = overpass_local::TagMatcher::new("nwr[\"addr:housenumber\"];out;").unwrap();
+    let osm_diffs = OsmDiffs::new_housenumbers(&diffs, matcher);
+
+    let ret = files
+        .apply_osm_diffs(&ctx, &osm_diffs, &make_boundary(&ctx))
+        .unwrap();
+
+    assert_eq!(ret, false);
+    assert_eq!(files.get_osm_json_housenumbers(&ctx).unwrap().len(), 1);
+    let osm_base = stats::get_sql_mtime(&ctx, "housenumbers/gazdagret/osm-base").unwrap();
+    assert_eq!(osm_base, diffs[0].timestamp);
+}
+
+/// Tests RelationFiles::apply_osm_diffs(): a tracked node which moved out of the relation is
+/// removed.
+#[test]
+fn test_apply_osm_diffs_moved_node() {
+    let osc = r#"<osmChange version="0.6">
+  <delete>
+    <way id="7" version="2" timestamp="2023-11-17T10:00:00Z" user="alice"/>
+  </delete>
+  <modify>
+    <node id="6" version="2" timestamp="2023-11-17T10:00:00Z" user="alice" lat="47.1" lon="17.9">
+      <tag k="addr:street" v="Törökugrató utca"/>
+      <tag k="addr:housenumber" v="1"/>
+    </node>
+  </modify>
+</osmChange>"#;
+    let ctx = make_diffs_context(osc);
+    let mut relations = areas::Relations::new(&ctx).unwrap();
+    let relation = relations.get_relation("gazdagret").unwrap();
+    let result = serde_json::json!({
+        "osm3s": {
+            "timestamp_osm_base": "2023-11-16T13:34:15Z",
+            "timestamp_areas_base": "2023-11-16T10:23:59Z"
+        },
+        "elements": [
+            {"type": "node", "id": 6, "tags": {"addr:street": "Törökugrató utca", "addr:housenumber": "1"}},
+            {"type": "way", "id": 7, "tags": {"addr:street": "Törökugrató utca", "addr:housenumber": "2"}},
+        ]
+    });
+    let files = relation.get_files();
+    files
+        .write_osm_json_housenumbers(&ctx, &result.to_string())
+        .unwrap();
+    let diffs = osm_diff::read_diffs(&ctx).unwrap();
+    let matcher = overpass_local::TagMatcher::new("nwr[\"addr:housenumber\"];out;").unwrap();
+    let osm_diffs = OsmDiffs::new_housenumbers(&diffs, matcher);
+
+    let ret = files
+        .apply_osm_diffs(&ctx, &osm_diffs, &make_boundary(&ctx))
+        .unwrap();
+
+    assert_eq!(ret, true);
+    assert_eq!(files.get_osm_json_housenumbers(&ctx).unwrap().len(), 0);
+}
+
+/// Tests RelationFiles::apply_osm_diffs(): new objects are placed by their location, an unrelated
+/// new address elsewhere doesn't need a full query.
+#[test]
+fn test_apply_osm_diffs_placed() {
+    let osc = r#"<osmChange version="0.6">
+  <create>
+    <node id="8" version="1" timestamp="2023-11-17T10:00:00Z" user="alice" lat="46.1" lon="18.2">
+      <tag k="addr:street" v="Kossuth utca"/>
+      <tag k="addr:housenumber" v="1"/>
+    </node>
+    <node id="9" version="1" timestamp="2023-11-17T10:00:00Z" user="alice" lat="47.45" lon="19.0">
+      <tag k="addr:street" v="Törökugrató utca"/>
+      <tag k="addr:housenumber" v="3"/>
+    </node>
+    <way id="10" version="1" timestamp="2023-11-17T10:00:00Z" user="alice">
+      <nd ref="11"/>
+      <nd ref="12"/>
+      <tag k="addr:street" v="Kossuth utca"/>
+      <tag k="addr:housenumber" v="2"/>
+    </way>
+    <way id="13" version="1" timestamp="2023-11-17T10:00:00Z" user="alice">
+      <nd ref="14"/>
+      <nd ref="15"/>
+      <tag k="addr:street" v="Törökugrató utca"/>
+      <tag k="addr:housenumber" v="5"/>
+    </way>
+  </create>
+</osmChange>"#;
+    let ctx = make_diffs_context(osc);
+    let mut relations = areas::Relations::new(&ctx).unwrap();
+    let relation = relations.get_relation("gazdagret").unwrap();
+    let result = serde_json::json!({
+        "osm3s": {
+            "timestamp_osm_base": "2023-11-16T13:34:15Z",
+            "timestamp_areas_base": "2023-11-16T10:23:59Z"
+        },
+        "elements": [
+            {"type": "node", "id": 6, "tags": {"addr:street": "Törökugrató utca", "addr:housenumber": "1"}},
+        ]
+    });
+    let files = relation.get_files();
+    files
+        .write_osm_json_housenumbers(&ctx, &result.to_string())
+        .unwrap();
+    let diffs = osm_diff::read_diffs(&ctx).unwrap();
+    let matcher = overpass_local::TagMatcher::new("nwr[\"addr:housenumber\"];out;").unwrap();
+    let mut osm_diffs = OsmDiffs::new_housenumbers(&diffs, matcher);
+    assert_eq!(osm_diffs.get_way_ids(&ctx).unwrap(), vec![10, 13]);
+    let ways = serde_json::json!({
+        "elements": [
+            {"type": "way", "id": 10, "geometry": [{"lat": 46.1, "lon": 18.2}, {"lat": 46.2, "lon": 18.2}]},
+            {"type": "way", "id": 13, "geometry": [{"lat": 47.3, "lon": 19.0}, {"lat": 47.41, "lon": 19.0}]},
+        ]
+    });
+    osm_diffs.set_way_geometries(&ways.to_string()).unwrap();
+
+    let ret = files
+        .apply_osm_diffs(&ctx, &osm_diffs, &make_boundary(&ctx))
+        .unwrap();
+
+    // 8 and 10 are outside, 9 is inside, 13 has a node inside.
+    assert_eq!(ret, true);
+    let mut ids: Vec<u64> = files
+        .get_osm_json_housenumbers(&ctx)
+        .unwrap()
+        .iter()
+        .map(|i| i.id)
+        .collect();
+    ids.sort();
+    assert_eq!(ids, vec![6, 9, 13]);
+}
+
+/// Tests RelationFiles::apply_osm_diffs(): a change to the relation itself invalidates its stored
+/// boundary and needs a full query.
+#[test]
+fn test_apply_osm_diffs_boundary_changed() {
+    let osc = r#"<osmChange version="0.6">
+  <modify>
+    <relation id="2713748" version="2" timestamp="2023-11-17T10:00:00Z" user="alice">
+      <member type="way" ref="1" role="outer"/>
+      <tag k="boundary" v="administrative"/>
+    </relation>
+  </modify>
+</osmChange>"#;
+    let ctx = make_diffs_context(osc);
+    let mut relations = areas::Relations::new(&ctx).unwrap();
+    let relation = relations.get_relation("gazdagret").unwrap();
+    let result = serde_json::json!({
+        "osm3s": {
+            "timestamp_osm_base": "2023-11-16T13:34:15Z",
+            "timestamp_areas_base": "2023-11-16T10:23:59Z"
+        },
+        "elements": []
+    });
+    let files = relation.get_files();
+    files
+        .write_osm_json_housenumbers(&ctx, &result.to_string())
+        .unwrap();
+    let diffs = osm_diff::read_diffs(&ctx).unwrap();
+    let matcher = overpass_local::TagMatcher::new("nwr[\"addr:housenumber\"];out;").unwrap();
+    let osm_diffs = OsmDiffs::new_housenumbers(&diffs, matcher);
+
+    let ret = files
+        .apply_osm_diffs(&ctx, &osm_diffs, &make_boundary(&ctx))
+        .unwrap();
+
+    assert_eq!(ret, false);
+    assert_eq!(get_boundary(&ctx, 2713748).unwrap().is_none(), true);
+}
+
+/// Tests apply_whole_country_diffs().
+#[test]
+fn test_apply_whole_country_diffs() {
+    let osc = r#"<osmChange version="0.6">
+  <modify>
+    <way id="7" version="2" timestamp="2023-11-17T10:00:00Z" user="bob">
+      <tag k="addr:street" v="Tűzkő utca"/>
+      <tag k="addr:housenumber" v="3"/>
+    </way>
+    <node id="8" version="2" timestamp="2023-11-17T10:00:00Z" user="bob" lat="47.46" lon="19.01"/>
+  </modify>
+  <delete>
+    <node id="5" version="2" timestamp="2023-11-17T10:00:00Z" user="alice"/>
+  </delete>
+</osmChange>"#;
+    let ctx = make_diffs_context(osc);
+    let result = serde_json::json!({
+        "osm3s": {
+            "timestamp_osm_base": "2023-11-16T13:34:15Z",
+            "timestamp_areas_base": "2023-11-16T10:23:59Z"
+        },
+        "elements": [
+            {"type": "node", "id": 5, "user": "alice", "tags": {"addr:street": "Tűzkő utca", "addr:housenumber": "1"}},
+            {"type": "node", "id": 6, "user": "alice", "tags": {"addr:street": "Tűzkő utca", "addr:housenumber": "2"}},
+            {"type": "way", "id": 7, "user": "alice", "tags": {"addr:street": "Tűzkő utca", "addr:housenumber": "4"}},
+        ]
+    });
+    write_whole_country(&ctx, &result.to_string()).unwrap();
+    let diffs = osm_diff::read_diffs(&ctx).unwrap();
+    let matcher = overpass_local::TagMatcher::new("nwr[\"addr:housenumber\"];out;").unwrap();
+
+    let osm_diffs = OsmDiffs::new_whole_country(&diffs, matcher);
+
+    let ret = apply_whole_country_diffs(&ctx, &osm_diffs, &make_boundary(&ctx)).unwrap();
+
+    // 5 is deleted, 7 gets a new house number, untagged 8 is not tracked.
+    assert_eq!(ret, true);
+    let conn = ctx.get_database_connection().unwrap();
+    let mut stmt = conn
+        .prepare("select osm_id, housenumber, user from whole_country order by osm_id")
+        .unwrap();
+    let rows: Vec<(String, String, String)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .unwrap()
+        .map(|row| row.unwrap())
+        .collect();
+    assert_eq!(
+        rows,
+        vec![
+            ("6".to_string(), "2".to_string(), "alice".to_string()),
+            ("7".to_string(), "3".to_string(), "bob".to_string()),
+        ]
+    );
+}
+
+/// Tests apply_whole_country_diffs(): a new way which can't be located needs a full query.
+#[test]
+fn test_apply_whole_country_diffs_new_object() {
+    let osc = r#"<osmChange version="0.6">
+  <create>
+    <way id="8" version="1" timestamp="2023-11-17T10:00:00Z" user="bob">
+      <nd ref="9"/>
+      <nd ref="10"/>
+      <tag k="addr:street" v="Václavské náměstí"/>
+      <tag k="addr:housenumber" v="1"/>
+    </way>
+  </create>
+</osmChange>"#;
+    let ctx = make_diffs_context(osc);
+    let result = serde_json::json!({
+        "osm3s": {
+            "timestamp_osm_base": "2023-11-16T13:34:15Z",
+            "timestamp_areas_base": "2023-11-16T10:23:59Z"
+        },
+        "elements": [
+            {"type": "node", "id": 6, "user": "alice", "tags": {"addr:street": "Tűzkő utca", "addr:housenumber": "2"}},
+        ]
+    });
+    write_whole_country(&ctx, &result.to_string()).unwrap();
+    let diffs = osm_diff::read_diffs(&ctx).unwrap();
+    let matcher = overpass_local::TagMatcher::new("nwr[\"addr:housenumber\"];out;").unwrap();
+
+    let osm_diffs = OsmDiffs::new_whole_country(&diffs, matcher);
+
+    let ret = apply_whole_country_diffs(&ctx, &osm_diffs, &make_boundary(&ctx)).unwrap();
+
+    assert_eq!(ret, false);
+    let conn = ctx.get_database_connection().unwrap();
+    let count: i64 = conn
+        .query_row("select count(*) from whole_country", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 1);
+}
@@ -14,14 +14,21 @@ use crate::area_files;
 use crate::cache;
 use crate::context;
 use crate::i18n::translate as tr;
+use crate::postcodes;
 use crate::ranges;
 use crate::stats;
+use crate::street_matching;
 use crate::util;
+use crate::util::Diff as _;
 use crate::yattag;
 use anyhow::Context;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fmt::Write as _;
 use std::ops::DerefMut;
+use std::sync::Arc;
+use std::sync::Mutex;
 
 /// The filters -> <street> -> ranges key from data/relation-<name>.yaml.
 #[derive(Clone, serde::Deserialize)]
@@ -32,13 +39,51 @@ pub struct RelationRangesDict {
     pub start: String,
 }
 
+/// A structured item of the filters -> <street> -> invalid key from data/relation-<name>.yaml.
+#[derive(Clone, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+pub struct RelationInvalidEntryDict {
+    pub number: String,
+    pub reason: Option<String>,
+    /// Date in the YYYY-MM-DD format, after which the item should be surveyed again.
+    pub resurvey_after: Option<String>,
+}
+
+/// An item of the filters -> <street> -> invalid key from data/relation-<name>.yaml: either just a
+/// house number or a house number with details.
+#[derive(Clone, serde::Deserialize)]
+#[serde(untagged)]
+pub enum RelationInvalidDict {
+    Plain(String),
+    Structured(RelationInvalidEntryDict),
+}
+
+impl RelationInvalidDict {
+    /// Gets the house number of the item.
+    pub fn get_number(&self) -> &str {
+        match self {
+            RelationInvalidDict::Plain(number) => number,
+            RelationInvalidDict::Structured(entry) => &entry.number,
+        }
+    }
+
+    /// Gets the date after which the item should be surveyed again, if any.
+    pub fn get_resurvey_after(&self) -> Option<&str> {
+        match self {
+            RelationInvalidDict::Plain(_) => None,
+            RelationInvalidDict::Structured(entry) => entry.resurvey_after.as_deref(),
+        }
+    }
+}
+
 /// The filters key from data/relation-<name>.yaml.
 #[derive(Clone, serde::Deserialize)]
 #[serde(rename_all = "kebab-case")]
 #[serde(deny_unknown_fields)]
 pub struct RelationFiltersDict {
     pub interpolation: Option<String>,
-    pub invalid: Option<Vec<String>>,
+    pub invalid: Option<Vec<RelationInvalidDict>>,
     pub ranges: Option<Vec<RelationRangesDict>>,
     pub valid: Option<Vec<String>>,
     refsettlement: Option<String>,
@@ -70,6 +115,8 @@ pub struct RelationDict {
     missing_streets: Option<String>,
     osm_street_filters: Option<Vec<String>>,
     pub osmrelation: Option<u64>,
+    pub parent: Option<String>,
+    postcode_lints: Option<bool>,
     pub refcounty: Option<String>,
     pub refsettlement: Option<String>,
     pub refstreets: Option<HashMap<String, String>>,
@@ -87,6 +134,8 @@ impl Default for RelationDict {
         let missing_streets = None;
         let osm_street_filters = None;
         let osmrelation = None;
+        let parent = None;
+        let postcode_lints = None;
         let refcounty = None;
         let refsettlement = None;
         let refstreets = None;
@@ -101,6 +150,8 @@ impl Default for RelationDict {
             missing_streets,
             osm_street_filters,
             osmrelation,
+            parent,
+            postcode_lints,
             refcounty,
             refsettlement,
             refstreets,
@@ -174,6 +225,11 @@ impl RelationConfig {
         RelationConfig::get_property(&self.parent.alias, &self.dict.alias).unwrap_or_default()
     }
 
+    /// Gets the name of the group this relation belongs to, e.g. the city of a district.
+    pub fn get_parent(&self) -> Option<String> {
+        RelationConfig::get_property(&self.parent.parent, &self.dict.parent)
+    }
+
     /// Return value can be 'yes', 'no' and 'only'.
     pub fn should_check_missing_streets(&self) -> String {
         match RelationConfig::get_property(&self.parent.missing_streets, &self.dict.missing_streets)
@@ -201,6 +257,12 @@ impl RelationConfig {
         .unwrap_or(false)
     }
 
+    /// Do we care if the postcodes of OSM house numbers are missing or inconsistent?
+    pub fn should_check_postcodes(&self) -> bool {
+        RelationConfig::get_property(&self.parent.postcode_lints, &self.dict.postcode_lints)
+            .unwrap_or(false)
+    }
+
     /// Returns an OSM name -> ref name map.
     pub fn get_refstreets(&self) -> HashMap<String, String> {
         match self.dict.refstreets {
@@ -323,6 +385,25 @@ impl RelationConfig {
     }
 }
 
+/// One daily coverage snapshot of a relation.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CoverageSnapshot {
+    /// Date of the snapshot, e.g. 2020-05-10.
+    pub date: String,
+    pub todo_count: u64,
+    pub done_count: u64,
+    /// Coverage in percent, e.g. 50.00.
+    pub percent: String,
+}
+
+/// Return type of Relation::get_coverage_history().
+#[derive(Debug, Default, PartialEq, serde::Serialize)]
+pub struct CoverageHistory {
+    pub housenumbers: Vec<CoverageSnapshot>,
+    pub streets: Vec<CoverageSnapshot>,
+}
+
 /// Return type of Relation::get_missing_housenumbers().
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(rename_all = "kebab-case")]
@@ -345,12 +426,18 @@ pub struct RelationLint {
     pub id: u64,
     #[derivative(PartialEq = "ignore")]
     pub object_type: String,
+    /// The other OSM object, in case the lint is about two objects, e.g. a duplicate.
+    pub other_id: u64,
+    pub other_object_type: String,
 }
 
 #[derive(Clone, Debug, Eq, Ord, PartialOrd, PartialEq)]
 pub enum RelationLintSource {
     Range,
     Invalid,
+    Resurvey,
+    Duplicate,
+    Postcode,
 }
 
 impl TryFrom<&str> for RelationLintSource {
@@ -360,6 +447,9 @@ impl TryFrom<&str> for RelationLintSource {
         match value {
             "range" => Ok(RelationLintSource::Range),
             "invalid" => Ok(RelationLintSource::Invalid),
+            "resurvey" => Ok(RelationLintSource::Resurvey),
+            "duplicate" => Ok(RelationLintSource::Duplicate),
+            "postcode" => Ok(RelationLintSource::Postcode),
             _ => Err(anyhow::anyhow!("invalid value: {value}")),
         }
     }
@@ -379,6 +469,9 @@ impl std::fmt::Display for RelationLintSource {
         match self {
             RelationLintSource::Range => write!(f, "range"),
             RelationLintSource::Invalid => write!(f, "invalid"),
+            RelationLintSource::Resurvey => write!(f, "resurvey"),
+            RelationLintSource::Duplicate => write!(f, "duplicate"),
+            RelationLintSource::Postcode => write!(f, "postcode"),
         }
     }
 }
@@ -388,6 +481,12 @@ pub enum RelationLintReason {
     CreatedInOsm,
     DeletedFromRef,
     OutOfRange,
+    ResurveyDue,
+    DuplicateInOsm,
+    MissingPostcode,
+    MalformedPostcode,
+    UnknownPostcode,
+    PostcodeMismatch,
 }
 
 impl TryFrom<&str> for RelationLintReason {
@@ -398,6 +497,12 @@ impl TryFrom<&str> for RelationLintReason {
             "created-in-osm" => Ok(RelationLintReason::CreatedInOsm),
             "deleted-from-ref" => Ok(RelationLintReason::DeletedFromRef),
             "out-of-range" => Ok(RelationLintReason::OutOfRange),
+            "resurvey-due" => Ok(RelationLintReason::ResurveyDue),
+            "duplicate-in-osm" => Ok(RelationLintReason::DuplicateInOsm),
+            "missing-postcode" => Ok(RelationLintReason::MissingPostcode),
+            "malformed-postcode" => Ok(RelationLintReason::MalformedPostcode),
+            "unknown-postcode" => Ok(RelationLintReason::UnknownPostcode),
+            "postcode-mismatch" => Ok(RelationLintReason::PostcodeMismatch),
             _ => Err(anyhow::anyhow!("invalid value: {value}")),
         }
     }
@@ -418,6 +523,12 @@ impl std::fmt::Display for RelationLintReason {
             RelationLintReason::CreatedInOsm => write!(f, "created-in-osm"),
             RelationLintReason::DeletedFromRef => write!(f, "deleted-from-ref"),
             RelationLintReason::OutOfRange => write!(f, "out-of-range"),
+            RelationLintReason::ResurveyDue => write!(f, "resurvey-due"),
+            RelationLintReason::DuplicateInOsm => write!(f, "duplicate-in-osm"),
+            RelationLintReason::MissingPostcode => write!(f, "missing-postcode"),
+            RelationLintReason::MalformedPostcode => write!(f, "malformed-postcode"),
+            RelationLintReason::UnknownPostcode => write!(f, "unknown-postcode"),
+            RelationLintReason::PostcodeMismatch => write!(f, "postcode-mismatch"),
         }
     }
 }
@@ -438,18 +549,10 @@ impl<'a> Relation<'a> {
         ctx: &'a context::Context,
         name: &str,
         parent_config: &RelationDict,
-        yaml_cache: &HashMap<String, serde_json::Value>,
+        my_config: &RelationDict,
     ) -> anyhow::Result<Relation<'a>> {
-        let mut my_config = RelationDict::default();
         let file = area_files::RelationFiles::new(name);
-        let relation_path = format!("relation-{name}.yaml");
-        // Intentionally don't require this cache to be present, it's fine to omit it for simple
-        // relations.
-        if let Some(value) = yaml_cache.get(&relation_path) {
-            my_config = serde_json::from_value(value.clone())
-                .context(format!("failed to parse '{relation_path}'"))?;
-        }
-        let config = RelationConfig::new(parent_config, &my_config);
+        let config = RelationConfig::new(parent_config, my_config);
         // osm street name -> house number list map, so we don't have to read the on-disk list of the
         // relation again and again for each street.
         let osm_housenumbers: HashMap<String, Vec<util::HouseNumber>> = HashMap::new();
@@ -532,7 +635,8 @@ impl<'a> Relation<'a> {
         if let Some(filters) = self.config.get_filters() {
             for (street, filter) in filters {
                 if let Some(ref value) = filter.invalid {
-                    invalid_dict.insert(street.into(), value.to_vec());
+                    let numbers = value.iter().map(|i| i.get_number().to_string()).collect();
+                    invalid_dict.insert(street.into(), numbers);
                 }
             }
         }
@@ -540,6 +644,158 @@ impl<'a> Relation<'a> {
         invalid_dict
     }
 
+    /// Gets lints for addresses which are present on multiple OSM objects, with the same street
+    /// and raw house number. Each additional object is reported together with the first one.
+    fn get_duplicate_lints(&self, osm_housenumbers: &[util::OsmHouseNumber]) -> Vec<RelationLint> {
+        // Street and house number -> object type and ID list.
+        let mut objects: HashMap<(String, String), Vec<(String, u64)>> = HashMap::new();
+        for row in osm_housenumbers {
+            // Interpolation ways are expected to share house numbers with their end nodes.
+            if row.interpolated || row.housenumber.is_empty() {
+                continue;
+            }
+            let entry = objects
+                .entry((get_osm_street_name(row), row.housenumber.to_string()))
+                .or_default();
+            if !entry.contains(&(row.object_type.clone(), row.id)) {
+                entry.push((row.object_type.clone(), row.id));
+            }
+        }
+
+        let mut lints: Vec<RelationLint> = Vec::new();
+        for ((street_name, housenumber), mut entry) in objects {
+            entry.sort();
+            let (first_type, first_id) = &entry[0];
+            for (other_type, other_id) in entry.iter().skip(1) {
+                lints.push(RelationLint {
+                    relation_name: self.get_name(),
+                    street_name: street_name.to_string(),
+                    source: RelationLintSource::Duplicate,
+                    housenumber: housenumber.to_string(),
+                    reason: RelationLintReason::DuplicateInOsm,
+                    id: *first_id,
+                    object_type: first_type.to_string(),
+                    other_id: *other_id,
+                    other_object_type: other_type.to_string(),
+                });
+            }
+        }
+        lints.sort();
+        lints
+    }
+
+    /// Gets lints for OSM house numbers with a missing, malformed, unknown or inconsistent
+    /// postcode.
+    fn get_postcode_lints(
+        &self,
+        osm_housenumbers: &[util::OsmHouseNumber],
+    ) -> anyhow::Result<Vec<RelationLint>> {
+        let mut lints: Vec<RelationLint> = Vec::new();
+        if !self.config.should_check_postcodes() {
+            return Ok(lints);
+        }
+
+        let valid_postcodes = util::get_valid_postcodes(self.ctx)?;
+        // Interpolation ways typically have no postcode, their end nodes have.
+        let rows: Vec<&util::OsmHouseNumber> = osm_housenumbers
+            .iter()
+            .filter(|i| !i.interpolated)
+            .collect();
+        let addresses: Vec<(String, String)> = rows
+            .iter()
+            .map(|row| (get_osm_street_name(row), row.postcode.to_string()))
+            .collect();
+        let problems = postcodes::get_problems(&addresses, &valid_postcodes);
+        for ((row, (street, _postcode)), problem) in rows.iter().zip(addresses).zip(problems) {
+            let reason = match problem {
+                Some(postcodes::PostcodeProblem::Missing) => RelationLintReason::MissingPostcode,
+                Some(postcodes::PostcodeProblem::Malformed) => {
+                    RelationLintReason::MalformedPostcode
+                }
+                Some(postcodes::PostcodeProblem::Unknown) => RelationLintReason::UnknownPostcode,
+                Some(postcodes::PostcodeProblem::Mismatch) => RelationLintReason::PostcodeMismatch,
+                None => continue,
+            };
+            lints.push(RelationLint {
+                relation_name: self.get_name(),
+                street_name: street,
+                source: RelationLintSource::Postcode,
+                housenumber: row.housenumber.to_string(),
+                reason,
+                id: row.id,
+                object_type: row.object_type.to_string(),
+                other_id: 0,
+                other_object_type: "".to_string(),
+            });
+        }
+        lints.sort();
+        Ok(lints)
+    }
+
+    /// Gets the invalid items which have a resurvey date: (street, house number, date) triples.
+    fn get_resurvey_dates(&self) -> anyhow::Result<Vec<(String, String, time::Date)>> {
+        let mut ret: Vec<(String, String, time::Date)> = Vec::new();
+        let filters = match self.config.get_filters() {
+            Some(value) => value,
+            None => {
+                return Ok(ret);
+            }
+        };
+        let format = time::format_description::parse("[year]-[month]-[day]")?;
+        for (street, filter) in filters {
+            let invalids = match filter.invalid {
+                Some(ref value) => value,
+                None => continue,
+            };
+            for invalid in invalids {
+                let resurvey_after = match invalid.get_resurvey_after() {
+                    Some(value) => value,
+                    None => continue,
+                };
+                let date = time::Date::parse(resurvey_after, &format)
+                    .context("failed to parse() 'resurvey-after'")?;
+                ret.push((street.to_string(), invalid.get_number().to_string(), date));
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Gets lints for invalid items where the resurvey date has passed.
+    pub fn get_resurvey_lints(&self) -> anyhow::Result<Vec<RelationLint>> {
+        let mut lints: Vec<RelationLint> = Vec::new();
+        let today = self.ctx.get_time().now().date();
+        for (street, housenumber, date) in self.get_resurvey_dates()? {
+            if date >= today {
+                continue;
+            }
+            lints.push(RelationLint {
+                relation_name: self.get_name(),
+                street_name: street,
+                source: RelationLintSource::Resurvey,
+                housenumber,
+                reason: RelationLintReason::ResurveyDue,
+                id: 0,
+                object_type: "".to_string(),
+                other_id: 0,
+                other_object_type: "".to_string(),
+            });
+        }
+        lints.sort();
+        Ok(lints)
+    }
+
+    /// Gets the last day when a resurvey date passed, if there was such a day already. Lints
+    /// computed before this day miss a resurvey lint.
+    pub fn get_last_resurvey_due(&self) -> anyhow::Result<Option<time::Date>> {
+        let today = self.ctx.get_time().now().date();
+        Ok(self
+            .get_resurvey_dates()?
+            .into_iter()
+            .filter(|(_, _, date)| *date < today)
+            .filter_map(|(_, _, date)| date.next_day())
+            .max())
+    }
+
     /// Reads list of streets for an area from OSM.
     pub fn get_osm_streets(&self, sorted_result: bool) -> anyhow::Result<Vec<util::Street>> {
         let mut ret: Vec<util::Street> = Vec::new();
@@ -576,13 +832,14 @@ impl<'a> Relation<'a> {
             self.ctx.get_abspath("data"),
             "streets-template.overpassql"
         ))?;
+        let contents = util::process_country_template(&contents, self.ctx.get_ini());
         Ok(util::process_template(
             &contents,
             self.config.get_osmrelation(),
         ))
     }
 
-    /// Produces a query which lists streets in relation, in JSON format.
+    /// Produces a query which lists streets in relation, in JSON format, with geometries.
     pub fn get_osm_streets_json_query(&self) -> anyhow::Result<String> {
         let query = self.get_osm_streets_query()?;
         let mut i = 0;
@@ -594,6 +851,11 @@ impl<'a> Relation<'a> {
                 continue;
             }
 
+            if line == "out;" {
+                lines.push("out geom;".to_string());
+                continue;
+            }
+
             lines.push(line.to_string());
         }
         Ok(lines.join("\n"))
@@ -633,12 +895,7 @@ impl<'a> Relation<'a> {
             let osm_housenumbers = self.file.get_osm_json_housenumbers(self.ctx)?;
             let mut lints: Vec<RelationLint> = Vec::new();
             for row in osm_housenumbers {
-                let mut street = &row.street;
-                if street.is_empty() {
-                    if let Some(ref value) = row.place {
-                        street = value;
-                    }
-                }
+                let street = get_osm_street_name(&row);
                 for house_number in row.housenumber.split(&[';', ',']) {
                     house_numbers
                         .entry(street.to_string())
@@ -646,7 +903,7 @@ impl<'a> Relation<'a> {
                         .append(&mut normalize(
                             self,
                             house_number,
-                            street,
+                            &street,
                             &street_ranges,
                             &mut Some(&mut lints),
                             Some(&row),
@@ -655,7 +912,11 @@ impl<'a> Relation<'a> {
             }
             self.lints.append(&mut lints);
             for (key, mut value) in house_numbers {
-                value.sort_unstable();
+                // Prefer explicit house numbers over interpolated ones when deduplicating.
+                value.sort_by(|a, b| {
+                    a.cmp(b)
+                        .then_with(|| a.is_interpolated().cmp(&b.is_interpolated()))
+                });
                 value.dedup();
                 self.osm_housenumbers
                     .insert(key, util::sort_numerically(&value));
@@ -697,6 +958,8 @@ impl<'a> Relation<'a> {
                             reason,
                             id,
                             object_type,
+                            other_id: 0,
+                            other_object_type: "".to_string(),
                         };
                         self.lints.push(lint);
                     }
@@ -850,6 +1113,8 @@ impl<'a> Relation<'a> {
                             reason,
                             id,
                             object_type,
+                            other_id: 0,
+                            other_object_type: "".to_string(),
                         };
                         self.lints.push(lint);
                     }
@@ -866,6 +1131,26 @@ impl<'a> Relation<'a> {
         Ok(ret)
     }
 
+    /// Gets the coordinates of the reference house numbers of an OSM street, where the reference
+    /// has them: maps the reference house numbers to latitude-longitude pairs.
+    pub fn get_ref_housenumber_coordinates(
+        &self,
+        osm_street_name: &str,
+    ) -> anyhow::Result<HashMap<String, (String, String)>> {
+        let mut ret: HashMap<String, (String, String)> = HashMap::new();
+        let conn = self.ctx.get_database_connection()?;
+        let mut stmt = conn.prepare(
+            "select housenumber, lat, lon from ref_housenumbers where county_code = ?1 and settlement_code = ?2 and street = ?3 and lat is not null and lon is not null")?;
+        let street = self.config.get_ref_street_from_osm_street(osm_street_name);
+        for refsettlement in self.config.get_street_refsettlement(&street) {
+            let mut rows = stmt.query([&self.config.get_refcounty(), &refsettlement, &street])?;
+            while let Some(row) = rows.next()? {
+                ret.insert(row.get(0)?, (row.get(1)?, row.get(2)?));
+            }
+        }
+        Ok(ret)
+    }
+
     /// Compares ref and osm house numbers, prints the ones which are in ref, but not in osm.
     /// Return value is a pair of ongoing and done streets.
     /// Each of of these is a pair of a street name and a house number list.
@@ -882,7 +1167,19 @@ impl<'a> Relation<'a> {
             let ref_house_numbers = &all_ref_house_numbers[osm_street_name];
             let osm_house_numbers = self.get_osm_housenumbers(osm_street_name)?;
             let only_in_reference = util::get_only_in_first(ref_house_numbers, &osm_house_numbers);
-            let in_both = util::get_in_both(ref_house_numbers, &osm_house_numbers);
+            let interpolated: Vec<String> = osm_house_numbers
+                .iter()
+                .filter(|i| i.is_interpolated())
+                .map(|i| i.get_diff_key())
+                .collect();
+            let in_both: Vec<util::HouseNumber> =
+                util::get_in_both(ref_house_numbers, &osm_house_numbers)
+                    .into_iter()
+                    .map(|mut i| {
+                        i.set_interpolated(interpolated.contains(&i.get_diff_key()));
+                        i
+                    })
+                    .collect();
             let ref_street_name = self.config.get_ref_street_from_osm_street(osm_street_name);
             let street = util::Street::new(
                 osm_street_name,
@@ -906,6 +1203,14 @@ impl<'a> Relation<'a> {
         // Sort by length, reverse.
         ongoing_streets.sort_by(|a, b| b.house_numbers.len().cmp(&a.house_numbers.len()));
 
+        let mut resurvey_lints = self.get_resurvey_lints()?;
+        self.lints.append(&mut resurvey_lints);
+        let osm_housenumbers = self.file.get_osm_json_housenumbers(self.ctx)?;
+        let mut duplicate_lints = self.get_duplicate_lints(&osm_housenumbers);
+        self.lints.append(&mut duplicate_lints);
+        let mut postcode_lints = self.get_postcode_lints(&osm_housenumbers)?;
+        self.lints.append(&mut postcode_lints);
+
         Ok(MissingHousenumbers {
             ongoing_streets,
             done_streets,
@@ -968,6 +1273,23 @@ impl<'a> Relation<'a> {
         Ok(only_in_osm)
     }
 
+    /// Suggests refstreets entries, pairing missing streets with additional streets which are
+    /// probably the same, just spelled differently.
+    pub fn get_suggested_refstreets(&self) -> anyhow::Result<Vec<street_matching::Suggestion>> {
+        let (only_in_ref, _in_both) = self.get_missing_streets()?;
+        let only_in_osm: Vec<String> = self
+            .get_additional_streets(/*sorted_result=*/ true)?
+            .iter()
+            .map(|street| street.get_osm_name().to_string())
+            .collect();
+        let abbreviations = self.ctx.get_ini().get_street_abbreviations()?;
+        Ok(street_matching::get_suggestions(
+            &abbreviations,
+            &only_in_ref,
+            &only_in_osm,
+        ))
+    }
+
     /// Calculate and write stat for the street coverage of a relation.
     pub fn write_missing_streets(&self) -> anyhow::Result<(usize, usize, f64, Vec<String>)> {
         let (todo_streets, done_streets) = self.get_missing_streets()?;
@@ -982,7 +1304,7 @@ impl<'a> Relation<'a> {
         };
 
         // Write the bottom line to a file, so the index page show it fast.
-        self.set_osm_street_coverage(&format!("{percent:.2}"))?;
+        self.set_osm_street_coverage(&format!("{percent:.2}"), todo_count, done_count)?;
 
         Ok((todo_count, done_count, percent, streets))
     }
@@ -1099,7 +1421,7 @@ impl<'a> Relation<'a> {
         };
 
         // Write the bottom line to a file, so the index page show it fast.
-        self.set_osm_housenumber_coverage(&format!("{percent:.2}"))?;
+        self.set_osm_housenumber_coverage(&format!("{percent:.2}"), todo_count, done_count)?;
 
         Ok((
             missing_housenumbers.ongoing_streets.len(),
@@ -1189,6 +1511,7 @@ impl<'a> Relation<'a> {
             self.ctx.get_abspath("data"),
             "street-housenumbers-template.overpassql"
         ))?;
+        let contents = util::process_country_template(&contents, self.ctx.get_ini());
         Ok(util::process_template(
             &contents,
             self.config.get_osmrelation(),
@@ -1277,12 +1600,17 @@ impl<'a> Relation<'a> {
         Ok(row.is_some())
     }
 
-    pub fn set_osm_housenumber_coverage(&self, coverage: &str) -> anyhow::Result<()> {
+    pub fn set_osm_housenumber_coverage(
+        &self,
+        coverage: &str,
+        todo_count: usize,
+        done_count: usize,
+    ) -> anyhow::Result<()> {
         let conn = self.ctx.get_database_connection()?;
         conn.execute(
-            r#"insert into osm_housenumber_coverages (relation_name, coverage, last_modified) values (?1, ?2, ?3)
-                 on conflict(relation_name) do update set coverage = excluded.coverage, last_modified = excluded.last_modified"#,
-            [&self.name, coverage, &self.ctx.get_time().now().unix_timestamp_nanos().to_string()],
+            r#"insert into osm_housenumber_coverages (relation_name, coverage, last_modified, todo_count, done_count) values (?1, ?2, ?3, ?4, ?5)
+                 on conflict(relation_name) do update set coverage = excluded.coverage, last_modified = excluded.last_modified, todo_count = excluded.todo_count, done_count = excluded.done_count"#,
+            [self.name.as_str(), coverage, &self.ctx.get_time().now().unix_timestamp_nanos().to_string(), &todo_count.to_string(), &done_count.to_string()],
         )?;
         Ok(())
     }
@@ -1297,8 +1625,8 @@ impl<'a> Relation<'a> {
         self.lints.dedup();
         for lint in self.lints.iter() {
             conn.execute(
-                r#"insert into relation_lints (relation_name, street_name, source, housenumber, reason, object_id, object_type) values (?1, ?2, ?3, ?4, ?5, ?6, ?7)"#,
-                 [&lint.relation_name, &lint.street_name, &lint.source.to_string(), &lint.housenumber, &lint.reason.to_string(), &lint.id.to_string(), &lint.object_type],
+                r#"insert into relation_lints (relation_name, street_name, source, housenumber, reason, object_id, object_type, other_object_id, other_object_type) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"#,
+                 [&lint.relation_name, &lint.street_name, &lint.source.to_string(), &lint.housenumber, &lint.reason.to_string(), &lint.id.to_string(), &lint.object_type, &lint.other_id.to_string(), &lint.other_object_type],
                  )?;
         }
         Ok(())
@@ -1337,16 +1665,125 @@ impl<'a> Relation<'a> {
         Ok(row.is_some())
     }
 
-    pub fn set_osm_street_coverage(&self, coverage: &str) -> anyhow::Result<()> {
+    pub fn set_osm_street_coverage(
+        &self,
+        coverage: &str,
+        todo_count: usize,
+        done_count: usize,
+    ) -> anyhow::Result<()> {
+        let conn = self.ctx.get_database_connection()?;
+        conn.execute(
+            r#"insert into osm_street_coverages (relation_name, coverage, last_modified, todo_count, done_count) values (?1, ?2, ?3, ?4, ?5)
+                 on conflict(relation_name) do update set coverage = excluded.coverage, last_modified = excluded.last_modified, todo_count = excluded.todo_count, done_count = excluded.done_count"#,
+            [self.name.as_str(), coverage, &self.ctx.get_time().now().unix_timestamp_nanos().to_string(), &todo_count.to_string(), &done_count.to_string()],
+        )?;
+        Ok(())
+    }
+
+    /// Records today's coverage of a relation, kind is 'housenumbers' or 'streets'. A later
+    /// snapshot from the same day replaces the earlier one.
+    pub fn add_coverage_snapshot(
+        &self,
+        kind: &str,
+        todo_count: usize,
+        done_count: usize,
+        percent: f64,
+    ) -> anyhow::Result<()> {
+        let format = time::format_description::parse("[year]-[month]-[day]")?;
+        let today = self.ctx.get_time().now().format(&format)?;
         let conn = self.ctx.get_database_connection()?;
         conn.execute(
-            r#"insert into osm_street_coverages (relation_name, coverage, last_modified) values (?1, ?2, ?3)
-                 on conflict(relation_name) do update set coverage = excluded.coverage, last_modified = excluded.last_modified"#,
-            [&self.name, coverage, &self.ctx.get_time().now().unix_timestamp_nanos().to_string()],
+            r#"insert into relation_coverage_history (relation_name, kind, date, todo_count, done_count, percent) values (?1, ?2, ?3, ?4, ?5, ?6)
+                 on conflict(relation_name, kind, date) do update set todo_count = excluded.todo_count, done_count = excluded.done_count, percent = excluded.percent"#,
+            [&self.name, kind, &today, &todo_count.to_string(), &done_count.to_string(), &format!("{percent:.2}")],
         )?;
         Ok(())
     }
 
+    /// Gets the last coverage snapshot of a relation, kind is 'housenumbers' or 'streets'.
+    pub fn get_last_coverage_snapshot(
+        &self,
+        kind: &str,
+    ) -> anyhow::Result<Option<CoverageSnapshot>> {
+        let conn = self.ctx.get_database_connection()?;
+        let mut stmt = conn.prepare(
+            "select date, todo_count, done_count, percent from relation_coverage_history where relation_name = ?1 and kind = ?2 order by date desc limit 1",
+        )?;
+        let mut rows = stmt.query([&self.name, kind])?;
+        let row = match rows.next()? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let todo_count: String = row.get(1)?;
+        let done_count: String = row.get(2)?;
+        Ok(Some(CoverageSnapshot {
+            date: row.get(0)?,
+            todo_count: todo_count.parse()?,
+            done_count: done_count.parse()?,
+            percent: row.get(3)?,
+        }))
+    }
+
+    /// Gets the current coverage of a relation, kind is 'housenumbers' or 'streets'. This is what
+    /// the main page shows, unlike the daily snapshots.
+    pub fn get_current_coverage(&self, kind: &str) -> anyhow::Result<Option<CoverageSnapshot>> {
+        let table = match kind {
+            "housenumbers" => "osm_housenumber_coverages",
+            _ => "osm_street_coverages",
+        };
+        let conn = self.ctx.get_database_connection()?;
+        let mut stmt = conn.prepare(&format!(
+            "select coverage, last_modified, todo_count, done_count from {table} where relation_name = ?1"
+        ))?;
+        let mut rows = stmt.query([&self.name])?;
+        let row = match rows.next()? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        // Older versions only stored the percent.
+        let (todo_count, done_count): (Option<String>, Option<String>) = (row.get(2)?, row.get(3)?);
+        let (todo_count, done_count) = match (todo_count, done_count) {
+            (Some(todo_count), Some(done_count)) => (todo_count.parse()?, done_count.parse()?),
+            _ => return Ok(None),
+        };
+        let last_modified: String = row.get(1)?;
+        let modified = time::OffsetDateTime::from_unix_timestamp_nanos(last_modified.parse()?)?;
+        let modified = modified.to_offset(self.ctx.get_time().now().offset());
+        let format = time::format_description::parse("[year]-[month]-[day]")?;
+        Ok(Some(CoverageSnapshot {
+            date: modified.format(&format)?,
+            todo_count,
+            done_count,
+            percent: row.get(0)?,
+        }))
+    }
+
+    /// Gets the daily coverage snapshots of a relation, ordered by date.
+    pub fn get_coverage_history(&self) -> anyhow::Result<CoverageHistory> {
+        let mut ret = CoverageHistory::default();
+        let conn = self.ctx.get_database_connection()?;
+        let mut stmt = conn.prepare(
+            "select kind, date, todo_count, done_count, percent from relation_coverage_history where relation_name = ?1 order by date",
+        )?;
+        let mut rows = stmt.query([&self.name])?;
+        while let Some(row) = rows.next()? {
+            let kind: String = row.get(0)?;
+            let todo_count: String = row.get(2)?;
+            let done_count: String = row.get(3)?;
+            let snapshot = CoverageSnapshot {
+                date: row.get(1)?,
+                todo_count: todo_count.parse()?,
+                done_count: done_count.parse()?,
+                percent: row.get(4)?,
+            };
+            match kind.as_str() {
+                "housenumbers" => ret.housenumbers.push(snapshot),
+                _ => ret.streets.push(snapshot),
+            }
+        }
+        Ok(ret)
+    }
+
     pub fn get_osm_street_coverage(&self) -> anyhow::Result<String> {
         let conn = self.ctx.get_database_connection()?;
         let mut stmt =
@@ -1371,28 +1808,62 @@ impl<'a> Relation<'a> {
     }
 }
 
+/// Gets the aggregated coverage of the relations of a group, kind is 'housenumbers' or 'streets'.
+/// The current coverages of the relations are summed, relations without one are ignored.
+pub fn get_group_coverage(
+    group_relations: &[Relation<'_>],
+    kind: &str,
+) -> anyhow::Result<Option<CoverageSnapshot>> {
+    let mut ret: Option<CoverageSnapshot> = None;
+    for relation in group_relations {
+        let coverage = match relation.get_current_coverage(kind)? {
+            Some(value) => value,
+            None => continue,
+        };
+        let aggregate = ret.get_or_insert_with(|| CoverageSnapshot {
+            date: coverage.date.to_string(),
+            todo_count: 0,
+            done_count: 0,
+            percent: "".into(),
+        });
+        // The date of the aggregate is the date of its oldest part.
+        if coverage.date < aggregate.date {
+            aggregate.date = coverage.date;
+        }
+        aggregate.todo_count += coverage.todo_count;
+        aggregate.done_count += coverage.done_count;
+    }
+    if let Some(ref mut aggregate) = ret {
+        let total = aggregate.todo_count + aggregate.done_count;
+        let percent: f64 = if total > 0 {
+            aggregate.done_count as f64 / total as f64 * 100_f64
+        } else {
+            100_f64
+        };
+        aggregate.percent = format!("{percent:.2}");
+    }
+    Ok(ret)
+}
+
 /// List of relations from data/relations.yaml.
 pub type RelationsDict = HashMap<String, RelationDict>;
 
-/// A relations object is a container of named relation objects.
-pub struct Relations<'a> {
-    ctx: &'a context::Context,
-    yaml_cache: HashMap<String, serde_json::Value>,
+/// The parsed data/yamls.cache. It's immutable, so it can be shared by all requests till the
+/// file changes on disk.
+#[derive(Default)]
+pub struct RelationsSnapshot {
     dict: RelationsDict,
-    relations: HashMap<String, Relation<'a>>,
-    activate_all: bool,
-    activate_new: bool,
-    activate_invalid: bool,
+    relation_dicts: HashMap<String, Result<RelationDict, String>>,
     refcounty_names: HashMap<String, String>,
     refsettlement_names: HashMap<String, HashMap<String, String>>,
 }
 
-impl<'a> Relations<'a> {
-    pub fn new(ctx: &'a context::Context) -> anyhow::Result<Relations<'a>> {
-        let yamls_cache_path = format!("{}/{}", ctx.get_abspath("data"), "yamls.cache");
+impl RelationsSnapshot {
+    /// Parses the yamls cache at path.
+    fn new(ctx: &context::Context, path: &str) -> anyhow::Result<Self> {
         let mut yaml_cache: HashMap<String, serde_json::Value> = HashMap::new();
-        if let Ok(stream) = ctx.get_file_system().open_read(&yamls_cache_path) {
-            let mut guard = stream.borrow_mut();
+        if let Ok(stream) = ctx.get_file_system().open_read(path) {
+            let mut guard = stream.lock().unwrap();
             let read = guard.deref_mut();
             yaml_cache = serde_json::from_reader(read)?;
         }
@@ -1401,10 +1872,21 @@ impl<'a> Relations<'a> {
             dict =
                 serde_json::from_value(value.clone()).context("failed to parse relations.yaml")?;
         }
-        let relations: HashMap<String, Relation<'a>> = HashMap::new();
-        let activate_all = false;
-        let activate_new = false;
-        let activate_invalid = false;
+        // Intentionally don't require the per-relation yaml to be present, it's fine to omit it for
+        // simple relations. Parse errors are only reported when the relation is used.
+        let mut relation_dicts: HashMap<String, Result<RelationDict, String>> = HashMap::new();
+        for (key, value) in &yaml_cache {
+            let name = match key
+                .strip_prefix("relation-")
+                .and_then(|i| i.strip_suffix(".yaml"))
+            {
+                Some(value) => value,
+                None => continue,
+            };
+            let relation_dict: Result<RelationDict, String> = serde_json::from_value(value.clone())
+                .map_err(|err| format!("failed to parse '{key}': {err}"));
+            relation_dicts.insert(name.to_string(), relation_dict);
+        }
         let refcounty_names: HashMap<String, String> = match yaml_cache.get("refcounty-names.yaml")
         {
             Some(value) => serde_json::from_value(value.clone())
@@ -1417,28 +1899,100 @@ impl<'a> Relations<'a> {
                     .context("failed to parse refsettlement-names.yaml")?,
                 None => HashMap::new(),
             };
+        Ok(RelationsSnapshot {
+            dict,
+            relation_dicts,
+            refcounty_names,
+            refsettlement_names,
+        })
+    }
+}
+
+/// The mtime and size of a file, to tell if it changed.
+type FileVersion = (time::OffsetDateTime, u64);
+
+/// Holds the last relations snapshot, together with the mtime and size of the yamls cache it was
+/// parsed from.
+#[derive(Default)]
+pub struct RelationsCache {
+    snapshot: Mutex<Option<(FileVersion, Arc<RelationsSnapshot>)>>,
+}
+
+impl RelationsCache {
+    /// Gets the current snapshot, parsing data/yamls.cache again only if it changed on disk.
+    pub fn get(&self, ctx: &context::Context) -> anyhow::Result<Arc<RelationsSnapshot>> {
+        let path = format!("{}/{}", ctx.get_abspath("data"), "yamls.cache");
+        // The mtime may have a coarse resolution, so compare the size as well.
+        let file_system = ctx.get_file_system();
+        let version = match (file_system.getmtime(&path), file_system.getsize(&path)) {
+            (Ok(mtime), Ok(size)) => (mtime, size),
+            // Can't tell if the file changed, don't cache.
+            _ => {
+                return Ok(Arc::new(RelationsSnapshot::new(ctx, &path)?));
+            }
+        };
+        if let Some((snapshot_version, snapshot)) = self.snapshot.lock().unwrap().as_ref() {
+            if *snapshot_version == version {
+                return Ok(snapshot.clone());
+            }
+        }
+
+        // Parse without holding the lock, then swap in the new snapshot, so readers either see
+        // the old or the new one.
+        let snapshot = Arc::new(RelationsSnapshot::new(ctx, &path)?);
+        *self.snapshot.lock().unwrap() = Some((version, snapshot.clone()));
+        Ok(snapshot)
+    }
+}
+
+/// A relations object is a container of named relation objects.
+pub struct Relations<'a> {
+    ctx: &'a context::Context,
+    snapshot: Arc<RelationsSnapshot>,
+    names: HashSet<String>,
+    relations: HashMap<String, Relation<'a>>,
+    activate_all: bool,
+    activate_new: bool,
+    activate_invalid: bool,
+}
+
+impl<'a> Relations<'a> {
+    pub fn new(ctx: &'a context::Context) -> anyhow::Result<Relations<'a>> {
+        let snapshot = ctx.get_relations_cache().get(ctx)?;
+        let names: HashSet<String> = snapshot.dict.keys().cloned().collect();
+        let relations: HashMap<String, Relation<'a>> = HashMap::new();
+        let activate_all = false;
+        let activate_new = false;
+        let activate_invalid = false;
         Ok(Relations {
             ctx,
-            yaml_cache,
-            dict,
+            snapshot,
+            names,
             relations,
             activate_all,
             activate_new,
             activate_invalid,
-            refcounty_names,
-            refsettlement_names,
         })
     }
 
     /// Gets the relation that has the specified name.
     pub fn get_relation(&mut self, name: &str) -> anyhow::Result<Relation<'a>> {
         if !self.relations.contains_key(name) {
+            let default = RelationDict::default();
+            let my_config = match self.snapshot.relation_dicts.get(name) {
+                Some(Ok(value)) => value,
+                Some(Err(err)) => {
+                    return Err(anyhow::anyhow!(err.clone()));
+                }
+                None => &default,
+            };
             let relation = Relation::new(
                 self.ctx,
                 name,
-                self.dict.entry(name.to_string()).or_default(),
-                &self.yaml_cache,
+                self.snapshot.dict.get(name).unwrap_or(&default),
+                my_config,
             )?;
+            self.names.insert(name.to_string());
             self.relations.insert(name.into(), relation);
         }
 
@@ -1447,7 +2001,7 @@ impl<'a> Relations<'a> {
 
     /// Gets a sorted list of relation names.
     pub fn get_names(&self) -> Vec<String> {
-        let mut ret: Vec<String> = self.dict.keys().map(|key| key.into()).collect();
+        let mut ret: Vec<String> = self.names.iter().map(|key| key.into()).collect();
         ret.sort();
         ret.dedup();
         ret
@@ -1517,9 +2071,29 @@ impl<'a> Relations<'a> {
         Ok(ret)
     }
 
+    /// Gets the relation groups, i.e. the values of the parent keys, with their relations.
+    pub fn get_groups(&mut self) -> anyhow::Result<BTreeMap<String, Vec<Relation<'a>>>> {
+        let mut ret: BTreeMap<String, Vec<Relation<'a>>> = BTreeMap::new();
+        for relation in self.get_relations()? {
+            if let Some(parent) = relation.get_config().get_parent() {
+                ret.entry(parent).or_default().push(relation);
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Gets the relations of a group, sorted by name.
+    pub fn get_group_relations(&mut self, group_name: &str) -> anyhow::Result<Vec<Relation<'a>>> {
+        Ok(self
+            .get_relations()?
+            .into_iter()
+            .filter(|i| i.get_config().get_parent().as_deref() == Some(group_name))
+            .collect())
+    }
+
     /// Produces a UI name for a refcounty.
     pub fn refcounty_get_name(&self, refcounty: &str) -> String {
-        match self.refcounty_names.get(refcounty) {
+        match self.snapshot.refcounty_names.get(refcounty) {
             Some(value) => value.into(),
             None => "".into(),
         }
@@ -1527,7 +2101,7 @@ impl<'a> Relations<'a> {
 
     /// Produces a UI name for a refsettlement in refcounty.
     pub fn refsettlement_get_name(&self, refcounty_name: &str, refsettlement: &str) -> String {
-        let refcounty = match self.refsettlement_names.get(refcounty_name) {
+        let refcounty = match self.snapshot.refsettlement_names.get(refcounty_name) {
             Some(value) => value,
             None => {
                 return "".into();
@@ -1562,13 +2136,13 @@ impl<'a> Relations<'a> {
                 return Ok(());
             }
         };
-        let relation_names: Vec<String> = self.dict.keys().cloned().collect();
+        let relation_names: Vec<String> = self.names.iter().cloned().collect();
         for relation_name in relation_names {
             let relation = self.get_relation(&relation_name)?;
             if relation.config.get_refcounty() == refcounty {
                 continue;
             }
-            self.dict.remove(&relation_name);
+            self.names.remove(&relation_name);
         }
 
         Ok(())
@@ -1585,13 +2159,13 @@ impl<'a> Relations<'a> {
                 return Ok(());
             }
         };
-        let relation_names: Vec<String> = self.dict.keys().cloned().collect();
+        let relation_names: Vec<String> = self.names.iter().cloned().collect();
         for relation_name in relation_names {
             let relation = self.get_relation(&relation_name)?;
             if relation.config.get_refsettlement() == refsettlement {
                 continue;
             }
-            self.dict.remove(&relation_name);
+            self.names.remove(&relation_name);
         }
 
         Ok(())
@@ -1605,13 +2179,13 @@ impl<'a> Relations<'a> {
                 return Ok(());
             }
         };
-        let relation_names: Vec<String> = self.dict.keys().cloned().collect();
+        let relation_names: Vec<String> = self.names.iter().cloned().collect();
         for relation_name in relation_names {
             if relation_name == refarea {
                 continue;
             }
 
-            self.dict.remove(&relation_name);
+            self.names.remove(&relation_name);
         }
 
         Ok(())
@@ -1619,7 +2193,7 @@ impl<'a> Relations<'a> {
 
     /// Produces refsettlement IDs of a refcounty.
     pub fn refcounty_get_refsettlement_ids(&self, refcounty_name: &str) -> Vec<String> {
-        let refcounty = match self.refsettlement_names.get(refcounty_name) {
+        let refcounty = match self.snapshot.refsettlement_names.get(refcounty_name) {
             Some(value) => value,
             None => {
                 return Vec::new();
@@ -1646,6 +2220,16 @@ impl<'a> Relations<'a> {
     }
 }
 
+/// Gets the street name of an OSM house number, falling back to addr:place.
+fn get_osm_street_name(row: &util::OsmHouseNumber) -> String {
+    if row.street.is_empty() {
+        if let Some(ref value) = row.place {
+            return value.to_string();
+        }
+    }
+    row.street.to_string()
+}
+
 pub fn normalizer_contains(
     number: i64,
     normalizer: &ranges::Ranges,
@@ -1680,6 +2264,8 @@ pub fn normalizer_contains(
                 reason,
                 id,
                 object_type,
+                other_id: 0,
+                other_object_type: "".to_string(),
             };
             lints.push(lint);
         }
@@ -1848,6 +2434,7 @@ fn normalize<'a>(
             if let Some(osm_housenumber) = osm_housenumber {
                 housenumber.set_id(osm_housenumber.id);
                 housenumber.set_object_type(&osm_housenumber.object_type);
+                housenumber.set_interpolated(osm_housenumber.interpolated);
             }
             housenumber
         })
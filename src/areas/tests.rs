@@ -13,7 +13,7 @@
 use super::*;
 use rusqlite::types::FromSql as _;
 use std::io::Write;
-use std::rc::Rc;
+use std::sync::Arc;
 
 /// Tests normalize().
 #[test]
@@ -624,7 +624,8 @@ fn test_relation_get_osm_streets_query() {
     let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
     let template_value = context::tests::TestFileSystem::make_file();
     template_value
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(b"aaa @RELATION@ bbb @AREA@ ccc\n")
         .unwrap();
     let files = context::tests::TestFileSystem::make_files(
@@ -657,8 +658,9 @@ fn test_relation_get_osm_streets_json_query() {
     let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
     let template_value = context::tests::TestFileSystem::make_file();
     template_value
-        .borrow_mut()
-        .write_all(b"[out:csv(::id)] [timeout:425];\naaa @RELATION@ bbb @AREA@ ccc\n")
+        .lock()
+        .unwrap()
+        .write_all(b"[out:csv(::id)] [timeout:425];\naaa @RELATION@ bbb @AREA@ ccc\nout;\n")
         .unwrap();
     let files = context::tests::TestFileSystem::make_files(
         &ctx,
@@ -673,7 +675,7 @@ fn test_relation_get_osm_streets_json_query() {
     let relation_name = "gazdagret";
     let relation = relations.get_relation(relation_name).unwrap();
     let ret = relation.get_osm_streets_json_query().unwrap();
-    assert_eq!(ret, "[out:json];\naaa 42 bbb 3600000042 ccc");
+    assert_eq!(ret, "[out:json];\naaa 42 bbb 3600000042 ccc\nout geom;");
 }
 
 /// Tests Relation.get_osm_housenumbers_query().
@@ -690,7 +692,8 @@ fn test_relation_get_osm_housenumbers_query() {
     let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
     let overpass_template = context::tests::TestFileSystem::make_file();
     overpass_template
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(b"housenr aaa @RELATION@ bbb @AREA@ ccc\n")
         .unwrap();
     let files = context::tests::TestFileSystem::make_files(
@@ -725,7 +728,8 @@ fn test_relation_get_osm_housenumbers_json_query() {
     let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
     let overpass_template = context::tests::TestFileSystem::make_file();
     overpass_template
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(b"[out:csv(::id)] [timeout:425];\nhousenr aaa @RELATION@ bbb @AREA@ ccc\n")
         .unwrap();
     let files = context::tests::TestFileSystem::make_files(
@@ -1493,6 +1497,127 @@ fn test_relation_get_lints() {
     );
 }
 
+/// Tests Relation::get_lints(), the duplicate address case.
+#[test]
+fn test_relation_get_lints_duplicate() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "refcounty": "0",
+                "refsettlement": "0",
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        &ctx,
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    ctx.set_file_system(&file_system);
+    {
+        let conn = ctx.get_database_connection().unwrap();
+        conn.execute_batch(
+            "insert into ref_housenumbers (county_code, settlement_code, street, housenumber, comment) values ('0', '0', 'Tűzkő utca', '1', '');
+             insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values ('gazdagret', '1', 'Tűzkő utca', '1', '', '', '', '', '', '', '', '', '', 'way');
+             insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values ('gazdagret', '2', 'Tűzkő utca', '1', '', '', '', '', '', '', '', '', '', 'node');
+             insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values ('gazdagret', '3', 'Tűzkő utca', '1', '', '', '', '', '', '', '', '', '', 'node');
+             insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values ('gazdagret', '4', 'Tűzkő utca', '2;2', '', '', '', '', '', '', '', '', '', 'node');
+             insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values ('gazdagret', '5', 'Tűzkő utca', '5-7', '', '', '', '', '', '', '', '', '', 'way');
+             insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values ('gazdagret', '6', 'Tűzkő utca', '7', '', '', '', '', '', '', '', '', '', 'node');
+             insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values ('gazdagret', '7', 'Tűzkő utca', '9/A', '', '', '', '', '', '', '', '', '', 'way');
+             insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values ('gazdagret', '8', 'Tűzkő utca', '9a', '', '', '', '', '', '', '', '', '', 'node');
+             insert into mtimes (page, last_modified) values ('housenumbers/gazdagret', '0');",
+        )
+        .unwrap();
+    }
+    let mut relations = Relations::new(&ctx).unwrap();
+    let mut relation = relations.get_relation("gazdagret").unwrap();
+    let _missing_housenumbers = relation.get_missing_housenumbers().unwrap();
+
+    let lints = relation.get_lints();
+
+    // 2 is only on one object, 1 is on 3 objects, the first object is paired with the other two.
+    // 5-7 and 7, 9/A and 9a are different raw values, so not duplicates.
+    let actual: Vec<(&str, &str, u64, &str, u64)> = lints
+        .iter()
+        .map(|i| {
+            assert_eq!(i.source, RelationLintSource::Duplicate);
+            assert_eq!(i.reason, RelationLintReason::DuplicateInOsm);
+            (
+                i.housenumber.as_str(),
+                i.object_type.as_str(),
+                i.id,
+                i.other_object_type.as_str(),
+                i.other_id,
+            )
+        })
+        .collect();
+    assert_eq!(
+        actual,
+        vec![("1", "node", 2, "way", 1), ("1", "node", 2, "node", 3)]
+    );
+}
+
+/// Tests Relation::get_lints(), the postcode case.
+#[test]
+fn test_relation_get_lints_postcode() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "refcounty": "0",
+                "refsettlement": "0",
+            },
+        },
+        "relation-gazdagret.yaml": {
+            "postcode-lints": true,
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        &ctx,
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    ctx.set_file_system(&file_system);
+    {
+        let conn = ctx.get_database_connection().unwrap();
+        conn.execute_batch(
+            "insert into ref_housenumbers (county_code, settlement_code, street, housenumber, comment) values ('0', '0', 'Tűzkő utca', '1', '');
+             insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values ('gazdagret', '1', 'Tűzkő utca', '1', '1111', '', '', '', '', '', '', '', '', 'node');
+             insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values ('gazdagret', '2', 'Tűzkő utca', '2', '1111', '', '', '', '', '', '', '', '', 'node');
+             insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values ('gazdagret', '3', 'Tűzkő utca', '3', '1121', '', '', '', '', '', '', '', '', 'node');
+             insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values ('gazdagret', '4', 'Tűzkő utca', '4', '', '', '', '', '', '', '', '', '', 'node');
+             insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values ('gazdagret', '5', 'Tűzkő utca', '5', 'H-1111', '', '', '', '', '', '', '', '', 'node');
+             insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values ('gazdagret', '6', 'Tűzkő utca', '6', '9999', '', '', '', '', '', '', '', '', 'node');
+             insert into mtimes (page, last_modified) values ('housenumbers/gazdagret', '0');",
+        )
+        .unwrap();
+    }
+    let mut relations = Relations::new(&ctx).unwrap();
+    let mut relation = relations.get_relation("gazdagret").unwrap();
+    let _missing_housenumbers = relation.get_missing_housenumbers().unwrap();
+
+    let lints = relation.get_lints();
+
+    let actual: Vec<(&str, RelationLintReason, u64)> = lints
+        .iter()
+        .filter(|i| i.source == RelationLintSource::Postcode)
+        .map(|i| (i.housenumber.as_str(), i.reason.clone(), i.id))
+        .collect();
+    assert_eq!(
+        actual,
+        vec![
+            ("3", RelationLintReason::PostcodeMismatch, 3),
+            ("4", RelationLintReason::MissingPostcode, 4),
+            ("5", RelationLintReason::MalformedPostcode, 5),
+            ("6", RelationLintReason::UnknownPostcode, 6),
+        ]
+    );
+}
+
 /// Tests Relation::get_lints(), the housenumber-letters=true case.
 #[test]
 fn test_relation_get_lints_hn_letters() {
@@ -2148,6 +2273,143 @@ fn test_relation_get_additional_streets() {
     assert_eq!(osm_street_blacklist, ["Second Only In OSM utca"]);
 }
 
+/// Tests Relation::get_suggested_refstreets().
+#[test]
+fn test_relation_get_suggested_refstreets() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    {
+        let ref_streets = ctx.get_ini().get_reference_street_path().unwrap();
+        util::build_street_reference_index(&ctx, &ref_streets).unwrap();
+    }
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "osmrelation": 42,
+                "refcounty": "01",
+                "refsettlement": "011",
+            },
+        },
+        "relation-gazdagret.yaml": {
+            "refstreets": {
+                "OSM Name 1": "Ref Name 1",
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        &ctx,
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    ctx.set_file_system(&file_system);
+    {
+        let conn = ctx.get_database_connection().unwrap();
+        conn.execute_batch(
+            "insert into osm_streets (relation, osm_id, name, highway, service, surface, leisure, osm_type) values ('gazdagret', '1', 'Tűzkő utca', '', '', '', '', '');
+             insert into osm_streets (relation, osm_id, name, highway, service, surface, leisure, osm_type) values ('gazdagret', '2', 'Törökugrató utca', '', '', '', '', '');
+             insert into osm_streets (relation, osm_id, name, highway, service, surface, leisure, osm_type) values ('gazdagret', '3', 'OSM Name 1', '', '', '', '', '');
+             insert into osm_streets (relation, osm_id, name, highway, service, surface, leisure, osm_type) values ('gazdagret', '4', 'Hamzsabégi út', '', '', '', '', '');
+             insert into osm_streets (relation, osm_id, name, highway, service, surface, leisure, osm_type) values ('gazdagret', '5', 'Only In Ref u.', '', '', '', '', '');",
+        )
+        .unwrap();
+    }
+    let mut relations = Relations::new(&ctx).unwrap();
+    let relation = relations.get_relation("gazdagret").unwrap();
+
+    let suggestions = relation.get_suggested_refstreets().unwrap();
+
+    // 'Only In Ref Nonsense utca' has no match in OSM.
+    assert_eq!(
+        suggestions,
+        vec![street_matching::Suggestion {
+            osm_name: "Only In Ref u.".into(),
+            ref_name: "Only In Ref utca".into(),
+            confidence: 1.0,
+        }]
+    );
+}
+
+/// Tests Relation::get_resurvey_lints(): only structured invalid items with a past resurvey date
+/// are reported.
+#[test]
+fn test_relation_get_resurvey_lints() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "osmrelation": 42,
+                "refcounty": "01",
+                "refsettlement": "011",
+            },
+        },
+        "relation-gazdagret.yaml": {
+            "filters": {
+                "Törökugrató utca": {
+                    "invalid": [
+                        "1",
+                        {
+                            "number": "11",
+                            "reason": "school",
+                            "resurvey-after": "2020-05-09",
+                        },
+                        {
+                            "number": "12",
+                            "resurvey-after": "2020-05-10",
+                        },
+                        {
+                            "number": "42",
+                            "reason": "no resurvey date",
+                        },
+                    ],
+                },
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        &ctx,
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    ctx.set_file_system(&file_system);
+    let mut relations = Relations::new(&ctx).unwrap();
+    let relation = relations.get_relation("gazdagret").unwrap();
+
+    let lints = relation.get_resurvey_lints().unwrap();
+
+    // The test time is 2020-05-10, so only 11 is due.
+    let actual: Vec<(String, String, String, String)> = lints
+        .iter()
+        .map(|i| {
+            (
+                i.street_name.to_string(),
+                i.source.to_string(),
+                i.housenumber.to_string(),
+                i.reason.to_string(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        actual,
+        vec![(
+            "Törökugrató utca".to_string(),
+            "resurvey".to_string(),
+            "11".to_string(),
+            "resurvey-due".to_string()
+        )]
+    );
+    // The structured items are still invalid items.
+    assert_eq!(
+        relation.get_street_invalid()["Törökugrató utca"],
+        vec!["1", "11", "12", "42"]
+    );
+    // 11 is due since 2020-05-10.
+    assert_eq!(
+        relation.get_last_resurvey_due().unwrap(),
+        Some(time::macros::date!(2020 - 05 - 10))
+    );
+}
+
 /// Tests Relation::get_additional_streets(): when the osm-street-filters key is missing.
 #[test]
 fn test_relation_get_additional_streets_no_osm_street_filters() {
@@ -2309,7 +2571,7 @@ fn test_relation_write_missing_housenumbers() {
     );
     let mut file_system = context::tests::TestFileSystem::new();
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
     {
         let conn = ctx.get_database_connection().unwrap();
@@ -2400,7 +2662,7 @@ fn test_relation_write_missing_housenumbers_interpolation_all() {
     );
     let mut file_system = context::tests::TestFileSystem::new();
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
     {
         let conn = ctx.get_database_connection().unwrap();
@@ -2881,6 +3143,72 @@ fn test_relations_new_invalid_refsettlement_names() {
     assert_eq!(ret.is_err(), true);
 }
 
+/// Tests RelationsCache::get(): the snapshot is only parsed again if yamls.cache changes.
+#[test]
+fn test_relations_cache() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "osmrelation": 42,
+            },
+        },
+        "relation-gazdagret.yaml": {
+            "refsettlement": "011",
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        &ctx,
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    let mut file_system = context::tests::TestFileSystem::new();
+    file_system.set_files(&files);
+    let mtime = Arc::new(Mutex::new(ctx.get_time().now()));
+    let mut mtimes: HashMap<String, Arc<Mutex<time::OffsetDateTime>>> = HashMap::new();
+    mtimes.insert(ctx.get_abspath("data/yamls.cache"), mtime.clone());
+    file_system.set_mtimes(&mtimes);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
+    ctx.set_file_system(&file_system_rc);
+    let first = ctx.get_relations_cache().get(&ctx).unwrap();
+    let mut relations = Relations::new(&ctx).unwrap();
+    assert_eq!(relations.get_names(), ["gazdagret"]);
+    let relation = relations.get_relation("gazdagret").unwrap();
+    assert_eq!(relation.get_config().get_osmrelation(), 42);
+    assert_eq!(relation.get_config().get_refsettlement(), "011");
+
+    // No change: the old snapshot is reused.
+    assert_eq!(
+        Arc::ptr_eq(&ctx.get_relations_cache().get(&ctx).unwrap(), &first),
+        true
+    );
+
+    // Change the content and the size, but not the mtime: the new content is parsed.
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "budafok": {
+                "osmrelation": 43,
+            },
+        },
+    });
+    let new_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let new_content = new_value.lock().unwrap().get_ref().clone();
+    *yamls_cache_value.lock().unwrap() = std::io::Cursor::new(new_content);
+    assert_eq!(Relations::new(&ctx).unwrap().get_names(), ["budafok"]);
+    assert_eq!(
+        Arc::ptr_eq(&ctx.get_relations_cache().get(&ctx).unwrap(), &first),
+        false
+    );
+    let second = ctx.get_relations_cache().get(&ctx).unwrap();
+
+    // Change the mtime: the new content is parsed.
+    *mtime.lock().unwrap() = ctx.get_time().now() + time::Duration::seconds(1);
+    assert_eq!(
+        Arc::ptr_eq(&ctx.get_relations_cache().get(&ctx).unwrap(), &second),
+        false
+    );
+}
+
 /// Tests refsettlement_get_name().
 #[test]
 fn test_refsettlement_get_name() {
@@ -2940,6 +3268,66 @@ fn test_relations_get_aliases() {
     assert_eq!(relations.get_aliases().unwrap(), expected);
 }
 
+/// Tests get_group_coverage().
+#[test]
+fn test_get_group_coverage() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "budafok": {
+                "parent": "budapest",
+            },
+            "gazdagret": {
+                "parent": "budapest",
+            },
+            "ujbuda": {
+                "parent": "budapest",
+            },
+            "orszag": {
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        &ctx,
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    ctx.set_file_system(&file_system);
+    let mut relations = Relations::new(&ctx).unwrap();
+    for (name, todo_count, done_count) in [("budafok", 1, 3), ("gazdagret", 3, 1)] {
+        let relation = relations.get_relation(name).unwrap();
+        relation
+            .set_osm_housenumber_coverage("0.00", todo_count, done_count)
+            .unwrap();
+        // Older snapshots are ignored.
+        relation
+            .add_coverage_snapshot("housenumbers", 100, 0, 0_f64)
+            .unwrap();
+    }
+
+    let groups = relations.get_groups().unwrap();
+
+    assert_eq!(groups.keys().collect::<Vec<_>>(), vec!["budapest"]);
+    let group_relations = &groups["budapest"];
+    let names: Vec<String> = group_relations.iter().map(|i| i.get_name()).collect();
+    assert_eq!(names, vec!["budafok", "gazdagret", "ujbuda"]);
+    // ujbuda has no coverage, so it's ignored.
+    assert_eq!(
+        get_group_coverage(group_relations, "housenumbers").unwrap(),
+        Some(CoverageSnapshot {
+            date: "2020-05-10".into(),
+            todo_count: 4,
+            done_count: 4,
+            percent: "50.00".into(),
+        })
+    );
+    assert_eq!(
+        get_group_coverage(group_relations, "streets").unwrap(),
+        None
+    );
+}
+
 /// Tests RelationConfig::get_street_is_even_odd().
 #[test]
 fn test_relation_config_get_street_is_even_odd() {
@@ -3397,7 +3785,7 @@ fn test_relations_is_inactive_no_osm_streets() {
     );
     let mut file_system = context::tests::TestFileSystem::new();
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
     let mut relations = Relations::new(&ctx).unwrap();
     relations.activate_invalid();
@@ -3460,6 +3848,8 @@ fn test_relation_list_ord_source() {
             reason,
             id,
             object_type,
+            other_id: 0,
+            other_object_type: "".to_string(),
         }
     };
     let lint2 = {
@@ -3478,6 +3868,8 @@ fn test_relation_list_ord_source() {
             reason,
             id,
             object_type,
+            other_id: 0,
+            other_object_type: "".to_string(),
         }
     };
     assert_eq!(lint1.cmp(&lint2), std::cmp::Ordering::Less);
@@ -3506,6 +3898,8 @@ fn test_relation_list_ord_reason() {
             reason,
             id,
             object_type,
+            other_id: 0,
+            other_object_type: "".to_string(),
         }
     };
     let lint2 = {
@@ -3524,6 +3918,8 @@ fn test_relation_list_ord_reason() {
             reason,
             id,
             object_type,
+            other_id: 0,
+            other_object_type: "".to_string(),
         }
     };
     assert_eq!(lint1.cmp(&lint2), std::cmp::Ordering::Less);
@@ -147,6 +147,31 @@ async function initGps()
     gpsLink.onclick = onGpsClick;
 }
 
+/**
+ * Enqueues an update job using an update-result.json link, then polls the job status till it
+ * finishes. Throws the error of a failed job.
+ */
+async function runUpdateJob(link: string)
+{
+    let response = await window.fetch(new Request(link));
+    if (!response.ok)
+    {
+        throw response.statusText;
+    }
+    let job = await response.json();
+    while (job.state == "queued" || job.state == "running")
+    {
+        await new Promise(resolve => setTimeout(resolve, 2000));
+        const statusLink = config.uriPrefix + "/jobs/" + job.id + "/view-result.json";
+        response = await window.fetch(new Request(statusLink));
+        job = await response.json();
+    }
+    if (job.error != "")
+    {
+        throw job.error;
+    }
+}
+
 /**
  * Starts various JSON requests in case some input of a ref vs osm diff is missing (or the other way
  * around).
@@ -162,15 +187,9 @@ async function initRedirects()
         createLoader(noOsmStreets, getOsmString("str-overpass-wait"));
         const relationName = tokens[tokens.length - 2];
         const link = config.uriPrefix + "/streets/" + relationName + "/update-result.json";
-        const request = new Request(link);
         try
         {
-            const response = await window.fetch(request);
-            const osmStreets = await response.json();
-            if (osmStreets.error != "")
-            {
-                throw osmStreets.error;
-            }
+            await runUpdateJob(link);
             window.location.reload();
         }
         catch (reason)
@@ -187,15 +206,9 @@ async function initRedirects()
         createLoader(noOsmHousenumbers, getOsmString("str-overpass-wait"));
         const relationName = tokens[tokens.length - 2];
         const link = config.uriPrefix + "/street-housenumbers/" + relationName + "/update-result.json";
-        const request = new Request(link);
         try
         {
-            const response = await window.fetch(request);
-            const osmHousenumbers = await response.json();
-            if (osmHousenumbers.error != "")
-            {
-                throw osmHousenumbers.error;
-            }
+            await runUpdateJob(link);
             window.location.reload();
         }
         catch (reason)
@@ -217,25 +230,10 @@ async function onUpdateOsmStreets()
     streets.removeChild(streets.childNodes[0]);
     createLoader(streets, getOsmString("str-toolbar-overpass-wait"));
     const relationName = tokens[tokens.length - 2];
-    let link = config.uriPrefix + "/streets/" + relationName + "/update-result.json";
-    let request = new Request(link);
     try
     {
-        let response = await window.fetch(request);
-        const osmStreets = await response.json();
-        if (osmStreets.error != "")
-        {
-            throw osmStreets.error;
-        }
-
-        link = config.uriPrefix + "/street-housenumbers/" + relationName + "/update-result.json";
-        request = new Request(link);
-        response = await window.fetch(request);
-        const osmHousenumbers = await response.json();
-        if (osmHousenumbers.error != "")
-        {
-            throw osmHousenumbers.error;
-        }
+        await runUpdateJob(config.uriPrefix + "/streets/" + relationName + "/update-result.json");
+        await runUpdateJob(config.uriPrefix + "/street-housenumbers/" + relationName + "/update-result.json");
         window.location.reload();
     }
     catch (reason)
@@ -255,15 +253,9 @@ async function onUpdateOsmHousenumbers()
     createLoader(housenumbers, getOsmString("str-toolbar-overpass-wait"));
     const relationName = tokens[tokens.length - 2];
     const link = config.uriPrefix + "/street-housenumbers/" + relationName + "/update-result.json";
-    const request = new Request(link);
     try
     {
-        const response = await window.fetch(request);
-        const osmHousenumbers = await response.json();
-        if (osmHousenumbers.error != "")
-        {
-            throw osmHousenumbers.error;
-        }
+        await runUpdateJob(link);
         window.location.reload();
     }
     catch (reason)
@@ -333,6 +325,7 @@ document.addEventListener("DOMContentLoaded", async function() {
     initRedirects();
     initTriggerUpdate();
     stats.initStats();
+    stats.initCoverageHistory();
 });
 
 // vim: shiftwidth=4 softtabstop=4 expandtab:
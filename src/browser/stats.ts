@@ -586,6 +586,83 @@ async function initStats(): Promise<void>
     return;
 }
 
-export { initStats };
+// CoverageSnapshot is one item of the /coverage-history/<relation>/view-result.json output.
+interface CoverageSnapshot {
+    date: string;
+    'todo-count': number;
+    'done-count': number;
+    percent: string;
+}
+
+// CoverageHistory is the root of the /coverage-history/<relation>/view-result.json output.
+interface CoverageHistory {
+    housenumbers: CoverageSnapshot[];
+    streets: CoverageSnapshot[];
+}
+
+async function initCoverageHistory(): Promise<void>
+{
+    const canvas = <HTMLCanvasElement>document.getElementById("coverage-history");
+    if (!canvas) {
+        // Not on the coverage history page.
+        return;
+    }
+
+    const response = await window.fetch(canvas.getAttribute("data-url"));
+    const history = await<Promise<CoverageHistory>> response.json();
+    // Streets and house numbers may have snapshots on different days.
+    const dates = Array.from(new Set(history.housenumbers.concat(history.streets).map(function(x: CoverageSnapshot) { return x.date; }))).sort();
+    const toData = function(snapshots: CoverageSnapshot[]) {
+        return dates.map(function(date: string) {
+            const snapshot = snapshots.find(function(x: CoverageSnapshot) { return x.date == date; });
+            return snapshot ? Number(snapshot.percent) : null;
+        });
+    };
+    const data = {
+        labels: dates,
+        datasets: [{
+            label: getString("str-coverage-history-housenumbers"),
+            borderColor: "rgba(0, 255, 0, 0.5)",
+            backgroundColor: "rgba(0, 255, 0, 0.5)",
+            data: toData(history.housenumbers),
+            spanGaps: true,
+        }, {
+            label: getString("str-coverage-history-streets"),
+            borderColor: "rgba(0, 0, 255, 0.5)",
+            backgroundColor: "rgba(0, 0, 255, 0.5)",
+            data: toData(history.streets),
+            spanGaps: true,
+        }]
+    };
+    new Chart(canvas.getContext("2d"), {
+        type: "line",
+        data: data,
+        options: {
+            plugins: {
+                datalabels: {
+                    display: false,
+                }
+            },
+            scales: {
+                x: {
+                    title: {
+                        display: true,
+                        text: getString("str-coverage-history-x-axis"),
+                    },
+                },
+                y: {
+                    suggestedMin: 0,
+                    suggestedMax: 100,
+                    title: {
+                        display: true,
+                        text: getString("str-coverage-history-y-axis"),
+                    },
+                }
+            },
+        }
+    });
+}
+
+export { initStats, initCoverageHistory };
 
 // vim: shiftwidth=4 softtabstop=4 expandtab:
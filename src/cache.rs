@@ -15,12 +15,24 @@ use crate::context;
 use crate::stats;
 use anyhow::Context as _;
 
-/// Decides if we have an up to date cache entry or not.
+/// Decides if we have an up to date cache entry or not, and records it in the metrics.
 fn is_sql_cache_current(
     ctx: &context::Context,
     cache_key: &str,
     dependencies: &[String],
     sql_dependencies: &[String],
+) -> anyhow::Result<bool> {
+    let current = is_sql_cache_entry_current(ctx, cache_key, dependencies, sql_dependencies)?;
+    ctx.get_metrics().record_cache(cache_key, current);
+    Ok(current)
+}
+
+/// Decides if we have an up to date cache entry or not, based on the mtimes of its dependencies.
+fn is_sql_cache_entry_current(
+    ctx: &context::Context,
+    cache_key: &str,
+    dependencies: &[String],
+    sql_dependencies: &[String],
 ) -> anyhow::Result<bool> {
     if !stats::has_sql_mtime(ctx, cache_key)? {
         return Ok(false);
@@ -60,13 +72,30 @@ fn is_missing_housenumbers_json_cached(relation: &mut areas::Relation<'_>) -> an
         format!("streets/{}", relation.get_name()),
         format!("housenumbers/{}", relation.get_name()),
     ];
-    is_sql_cache_current(
+    let cache_key = format!("missing-housenumbers-cache/{}", relation.get_name());
+    if !is_sql_cache_current(
         relation.get_ctx(),
-        &format!("missing-housenumbers-cache/{}", relation.get_name()),
+        &cache_key,
         &dependencies,
         &sql_dependencies,
     )
-    .context("is_sql_cache_current() failed")
+    .context("is_sql_cache_current() failed")?
+    {
+        return Ok(false);
+    }
+
+    // The resurvey lints depend on the current date: a resurvey date passing since the cache
+    // entry was written invalidates it.
+    if let Some(due) = relation.get_last_resurvey_due()? {
+        let ctx = relation.get_ctx();
+        let cache_mtime = stats::get_sql_mtime(ctx, &cache_key)?;
+        let offset = ctx.get_time().now().offset();
+        if cache_mtime.to_offset(offset).date() < due {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
 }
 
 /// Gets the cached json of the missing housenumbers for a relation.
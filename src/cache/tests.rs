@@ -11,9 +11,9 @@
 //! Tests for the cache module.
 
 use super::*;
-use std::cell::RefCell;
 use std::collections::HashMap;
-use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::Mutex;
 
 /// Tests get_missing_housenumbers_json(): the cached case.
 ///
@@ -44,7 +44,7 @@ fn test_get_missing_housenumbers_json() {
         &[("data/yamls.cache", &yamls_cache_value)],
     );
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
     let mut relations = areas::Relations::new(&ctx).unwrap();
     let mut relation = relations.get_relation("gazdagret").unwrap();
@@ -82,7 +82,7 @@ fn test_get_missing_housenumbers_json_sql_newer() {
         &[("data/yamls.cache", &yamls_cache_value)],
     );
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
     let mut relations = areas::Relations::new(&ctx).unwrap();
     let mut relation = relations.get_relation("gazdagret").unwrap();
@@ -92,6 +92,52 @@ fn test_get_missing_housenumbers_json_sql_newer() {
     assert!(ret != r#"{"cached":"yes"}"#);
 }
 
+/// Tests get_missing_housenumbers_json(): the cached case, when a resurvey date passed since the
+/// cache entry was written.
+#[test]
+fn test_get_missing_housenumbers_json_resurvey_due() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    {
+        // 2020-05-09, the test time is 2020-05-10.
+        let conn = ctx.get_database_connection().unwrap();
+        conn.execute_batch(
+            r#"insert into missing_housenumbers_cache (relation, json) values ('gazdagret', '{"cached":"yes"}');
+               insert into mtimes (page, last_modified) values ('missing-housenumbers-cache/gazdagret', '1588982400000000000');"#,
+        )
+        .unwrap();
+    }
+    let mut file_system = context::tests::TestFileSystem::new();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "osmrelation": 42,
+            },
+        },
+        "relation-gazdagret.yaml": {
+            "filters": {
+                "Törökugrató utca": {
+                    "invalid": [{ "number": "11", "resurvey-after": "2020-05-09" }],
+                },
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        &ctx,
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    file_system.set_files(&files);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
+    ctx.set_file_system(&file_system_rc);
+    let mut relations = areas::Relations::new(&ctx).unwrap();
+    let mut relation = relations.get_relation("gazdagret").unwrap();
+
+    let ret = get_missing_housenumbers_json(&mut relation).unwrap();
+
+    // The entry has no resurvey lint for 11, so it's outdated.
+    assert!(ret != r#"{"cached":"yes"}"#);
+}
+
 /// Tests get_additional_housenumbers_json(): the cached case.
 ///
 /// The non-cached case is covered by higher level
@@ -121,7 +167,7 @@ fn test_get_additional_housenumbers_json() {
         &[("data/yamls.cache", &yamls_cache_value)],
     );
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
     let mut relations = areas::Relations::new(&ctx).unwrap();
     let mut relation = relations.get_relation("gazdagret").unwrap();
@@ -163,13 +209,13 @@ fn test_get_missing_housenumbers_json_file_newer() {
         ],
     );
     file_system.set_files(&files);
-    let mut mtimes: HashMap<String, Rc<RefCell<time::OffsetDateTime>>> = HashMap::new();
+    let mut mtimes: HashMap<String, Arc<Mutex<time::OffsetDateTime>>> = HashMap::new();
     mtimes.insert(
         ctx.get_abspath("data/relation-gazdagret.yaml"),
-        Rc::new(RefCell::new(ctx.get_time().now())),
+        Arc::new(Mutex::new(ctx.get_time().now())),
     );
     file_system.set_mtimes(&mtimes);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
     let mut relations = areas::Relations::new(&ctx).unwrap();
     let mut relation = relations.get_relation("gazdagret").unwrap();
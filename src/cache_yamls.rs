@@ -43,13 +43,16 @@ pub fn our_main(argv: &[String], ctx: &context::Context) -> anyhow::Result<()> {
         cache.insert(cache_key, cache_value);
     }
 
+    // Write to a temporary file first, so the web server never reads a partial cache.
     let cache_path = format!("{datadir}/yamls.cache");
+    let tmp_path = format!("{cache_path}.new");
     {
-        let write_stream = ctx.get_file_system().open_write(&cache_path)?;
-        let mut guard = write_stream.borrow_mut();
+        let write_stream = ctx.get_file_system().open_write(&tmp_path)?;
+        let mut guard = write_stream.lock().unwrap();
         let write = guard.deref_mut();
         serde_json::to_writer(write, &cache)?;
     }
+    ctx.get_file_system().rename(&tmp_path, &cache_path)?;
 
     let workdir = ctx.get_abspath(&argv[2]);
     let yaml_path = format!("{datadir}/relations.yaml");
@@ -68,7 +71,7 @@ pub fn our_main(argv: &[String], ctx: &context::Context) -> anyhow::Result<()> {
         let write_stream = ctx
             .get_file_system()
             .open_write(&format!("{statsdir}/relations.json"))?;
-        let mut guard = write_stream.borrow_mut();
+        let mut guard = write_stream.lock().unwrap();
         let write = guard.deref_mut();
         serde_json::to_writer(write, &relation_ids)?;
     }
@@ -13,7 +13,7 @@
 use super::*;
 use std::io::Seek;
 use std::io::SeekFrom;
-use std::rc::Rc;
+use std::sync::Arc;
 
 /// Tests main().
 #[test]
@@ -29,7 +29,8 @@ fn test_main() {
     refsettlement: "011"
 "#;
     relations_value
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(relations_content.as_bytes())
         .unwrap();
     let refsettlements_names_value = context::tests::TestFileSystem::make_file();
@@ -38,10 +39,12 @@ fn test_main() {
     '012': 'Hegyvidék'
 "#;
     refsettlements_names_value
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(refsettlements_names_content.as_bytes())
         .unwrap();
     let cache_value = context::tests::TestFileSystem::make_file();
+    let cache_new_value = context::tests::TestFileSystem::make_file();
     let stats_value = context::tests::TestFileSystem::make_file();
     let files = context::tests::TestFileSystem::make_files(
         &ctx,
@@ -52,11 +55,12 @@ fn test_main() {
                 &refsettlements_names_value,
             ),
             ("data/yamls.cache", &cache_value),
+            ("data/yamls.cache.new", &cache_new_value),
             ("workdir/stats/relations.json", &stats_value),
         ],
     );
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
 
     let ret = main(&argv, &mut buf, &ctx);
@@ -65,11 +69,11 @@ fn test_main() {
     // tests.
     assert_eq!(ret, 0);
     {
-        let mut guard = cache_value.borrow_mut();
+        let mut guard = cache_value.lock().unwrap();
         assert_eq!(guard.seek(SeekFrom::Current(0)).unwrap() > 0, true);
     }
 
-    let mut guard = stats_value.borrow_mut();
+    let mut guard = stats_value.lock().unwrap();
     assert_eq!(guard.seek(SeekFrom::Current(0)).unwrap() > 0, true);
     guard.seek(SeekFrom::Start(0)).unwrap();
     let read = guard.deref_mut();
@@ -88,22 +92,24 @@ fn test_main() {
 fn test_main_error() {
     let mut ctx = context::tests::make_test_context().unwrap();
     let unit = context::tests::TestUnit::new();
-    let unit_rc: Rc<dyn context::Unit> = Rc::new(unit);
+    let unit_rc: Arc<dyn context::Unit> = Arc::new(unit);
     ctx.set_unit(&unit_rc);
     let argv = vec!["".to_string(), "data".to_string(), "workdir".to_string()];
     let mut buf: std::io::Cursor<Vec<u8>> = std::io::Cursor::new(Vec::new());
     let mut file_system = context::tests::TestFileSystem::new();
     let cache_value = context::tests::TestFileSystem::make_file();
+    let cache_new_value = context::tests::TestFileSystem::make_file();
     let stats_value = context::tests::TestFileSystem::make_file();
     let files = context::tests::TestFileSystem::make_files(
         &ctx,
         &[
             ("data/yamls.cache", &cache_value),
+            ("data/yamls.cache.new", &cache_new_value),
             ("workdir/stats/relations.json", &stats_value),
         ],
     );
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
 
     let ret = main(&argv, &mut buf, &ctx);
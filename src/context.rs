@@ -11,30 +11,39 @@
 //! Abstractions to help writing unit tests: filesystem, network, etc.
 
 use anyhow::Context as _;
-use once_cell::unsync::OnceCell;
-use std::cell::RefCell;
-use std::cell::RefMut;
 use std::io::Read;
 use std::io::Write;
+use std::ops::Deref;
+use std::ops::DerefMut;
 use std::path::Path;
-use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
 use std::time::Duration;
 
+use crate::areas;
+use crate::metrics;
+use crate::network_replay;
+use crate::overpass_local;
+use crate::overpass_query;
 use crate::sql;
 
 /// File system interface.
-pub trait FileSystem {
+pub trait FileSystem: Send + Sync {
     /// Test whether a path exists.
     fn path_exists(&self, path: &str) -> bool;
 
     /// Return the last modification time of a file.
     fn getmtime(&self, path: &str) -> anyhow::Result<time::OffsetDateTime>;
 
+    /// Return the size of a file, in bytes.
+    fn getsize(&self, path: &str) -> anyhow::Result<u64>;
+
     /// Opens a file for reading in binary mode.
-    fn open_read(&self, path: &str) -> anyhow::Result<Rc<RefCell<dyn Read>>>;
+    fn open_read(&self, path: &str) -> anyhow::Result<Arc<Mutex<dyn Read + Send>>>;
 
     /// Opens a file for writing in binary mode.
-    fn open_write(&self, path: &str) -> anyhow::Result<Rc<RefCell<dyn Write>>>;
+    fn open_write(&self, path: &str) -> anyhow::Result<Arc<Mutex<dyn Write + Send>>>;
 
     /// Removes a file.
     fn unlink(&self, path: &str) -> anyhow::Result<()>;
@@ -42,10 +51,20 @@ pub trait FileSystem {
     /// Return a list containing the names of the files in the directory.
     fn listdir(&self, path: &str) -> anyhow::Result<Vec<String>>;
 
+    /// Atomically replaces the file at to with the file at from.
+    fn rename(&self, from: &str, to: &str) -> anyhow::Result<()>;
+
+    /// Atomically replaces the directory at to with the directory at from, readers of to see
+    /// either the old or the new contents. Returns the location of the old contents, if any.
+    fn replace_dir(&self, from: &str, to: &str) -> anyhow::Result<Option<String>>;
+
+    /// Removes a directory and all its contents.
+    fn remove_dir_all(&self, path: &str) -> anyhow::Result<()>;
+
     /// Read the entire contents of a file into a string.
     fn read_to_string(&self, path: &str) -> anyhow::Result<String> {
         let stream = self.open_read(path)?;
-        let mut guard = stream.borrow_mut();
+        let mut guard = stream.lock().unwrap();
         let mut bytes: Vec<u8> = Vec::new();
         guard.read_to_end(&mut bytes).unwrap();
         Ok(String::from_utf8(bytes)?)
@@ -54,7 +73,7 @@ pub trait FileSystem {
     /// Write the entire string to a file.
     fn write_from_string(&self, string: &str, path: &str) -> anyhow::Result<()> {
         let stream = self.open_write(path)?;
-        let mut guard = stream.borrow_mut();
+        let mut guard = stream.lock().unwrap();
         Ok(guard.write_all(string.as_bytes())?)
     }
 }
@@ -62,10 +81,20 @@ pub trait FileSystem {
 pub use system::StdFileSystem;
 
 /// Database interface.
-pub trait Database {
+pub trait Database: Send + Sync {
     /// Opens the connection.
     fn open(&self) -> anyhow::Result<rusqlite::Connection>;
 
+    /// The maximum number of connections in the pool of a Context.
+    fn get_max_connections(&self) -> usize {
+        8
+    }
+
+    /// How long to wait for a connection when all connections of the pool are in use.
+    fn get_connection_timeout(&self) -> Duration {
+        Duration::from_secs(30)
+    }
+
     /// Opens and initializes a new database connection.
     fn create(&self) -> anyhow::Result<rusqlite::Connection> {
         let mut conn = self.open()?;
@@ -77,15 +106,31 @@ pub trait Database {
 pub use system::StdDatabase;
 
 /// Network interface.
-pub trait Network {
+pub trait Network: Send + Sync {
     /// Opens an URL. Empty data means HTTP GET, otherwise it means a HTTP POST.
     fn urlopen(&self, url: &str, data: &str) -> anyhow::Result<String>;
+
+    /// Opens an URL, giving up after timeout, returning the HTTP status code and the response
+    /// body. Implementations may ignore the timeout.
+    fn urlopen_with_status(
+        &self,
+        url: &str,
+        data: &str,
+        _timeout: std::time::Duration,
+    ) -> anyhow::Result<(u16, String)> {
+        Ok((200, self.urlopen(url, data)?))
+    }
+
+    /// Downloads an URL with HTTP GET, without decoding the response.
+    fn urlopen_bytes(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        Ok(self.urlopen(url, "")?.into_bytes())
+    }
 }
 
 pub use system::StdNetwork;
 
 /// Time interface.
-pub trait Time {
+pub trait Time: Send + Sync {
     /// Calculates the current time.
     fn now(&self) -> time::OffsetDateTime;
 
@@ -105,7 +150,7 @@ pub trait Time {
 pub use system::StdTime;
 
 /// Subprocess interface.
-pub trait Subprocess {
+pub trait Subprocess: Send + Sync {
     /// Runs a commmand, capturing its output.
     fn run(&self, args: Vec<String>) -> anyhow::Result<String>;
 
@@ -119,7 +164,7 @@ pub trait Subprocess {
 pub use system::StdSubprocess;
 
 /// Unit testing interface.
-pub trait Unit {
+pub trait Unit: Send + Sync {
     /// Injects a fake error.
     fn make_error(&self) -> anyhow::Result<()>;
 }
@@ -131,14 +176,51 @@ pub use system::StdUnit;
 pub struct IniConfig {
     /// The wsgi section in the config file.
     pub wsgi: WsgiConfig,
+    /// The reference_source sections in the config file.
+    #[serde(default)]
+    pub reference_source: Vec<ReferenceSourceConfig>,
+}
+
+/// A reference_source section in the config file: a house number or street reference in some
+/// file format.
+#[derive(Clone, Default, serde::Deserialize)]
+pub struct ReferenceSourceConfig {
+    /// What the source contains: 'housenumbers' or 'streets'.
+    pub kind: String,
+    /// File format: 'tsv', 'csv' or 'geojson'.
+    pub format: String,
+    /// Path of the file, relative to the root.
+    pub path: String,
+    /// Field delimiter for the 'csv' format, defaults to ','.
+    pub delimiter: Option<String>,
+    /// County code column (or property), defaults to COUNTY_CODE.
+    pub county_column: Option<String>,
+    /// Settlement code column (or property), defaults to SETTLEMENT_CODE.
+    pub settlement_column: Option<String>,
+    /// Street name column (or property), defaults to STREET.
+    pub street_column: Option<String>,
+    /// House number column (or property), defaults to HOUSENUMBER.
+    pub housenumber_column: Option<String>,
+    /// Comment column (or property), defaults to COMMENT.
+    pub comment_column: Option<String>,
+    /// Latitude column (or property), optional, defaults to the geometry for GeoJSON points.
+    pub lat_column: Option<String>,
+    /// Longitude column (or property), optional, defaults to the geometry for GeoJSON points.
+    pub lon_column: Option<String>,
+    /// House numbers of a supplementary source are marked with a '*' suffix and keep their
+    /// comments.
+    #[serde(default)]
+    pub supplementary: bool,
 }
 
 /// The wsgi section in the config file.
 #[derive(Default, serde::Deserialize)]
 pub struct WsgiConfig {
     /// Space-separated list of housenumber references.
+    #[serde(default)]
     pub reference_housenumbers: String,
     /// Street reference file path.
+    #[serde(default)]
     pub reference_street: String,
     /// City counts reference file path.
     pub reference_citycounts: String,
@@ -148,6 +230,25 @@ pub struct WsgiConfig {
     tcp_port: Option<String>,
     overpass_uri: Option<String>,
     cron_update_inactive: Option<String>,
+    overpass_local_extract: Option<String>,
+    replication_dir: Option<String>,
+    network_record_dir: Option<String>,
+    network_replay_dir: Option<String>,
+    country_area: Option<String>,
+    street_name_pattern: Option<String>,
+    street_name_exclude_pattern: Option<String>,
+    parking_aisle_name_pattern: Option<String>,
+    parking_name_pattern: Option<String>,
+    parking_name_exclude_pattern: Option<String>,
+    capital_name: Option<String>,
+    capital_postcode_prefix: Option<String>,
+    capital_district_count: Option<String>,
+    street_abbreviations: Option<String>,
+    rate_limit_client_burst: Option<String>,
+    rate_limit_client_per_minute: Option<String>,
+    rate_limit_global_burst: Option<String>,
+    rate_limit_global_per_minute: Option<String>,
+    trusted_proxies: Option<String>,
 }
 
 /// Configuration file reader.
@@ -158,7 +259,7 @@ pub struct Ini {
 
 impl Ini {
     fn new(
-        file_system: &Rc<dyn FileSystem>,
+        file_system: &Arc<dyn FileSystem>,
         config_path: &str,
         root: &str,
     ) -> anyhow::Result<Self> {
@@ -182,14 +283,24 @@ impl Ini {
         let value = &self.config.wsgi.reference_housenumbers;
         let relpaths = value.split(' ');
         Ok(relpaths
+            .filter(|relpath| !relpath.is_empty())
             .map(|relpath| format!("{}/{}", self.root, relpath))
             .collect())
     }
 
-    /// Gets the abs path of ref streets.
-    pub fn get_reference_street_path(&self) -> anyhow::Result<String> {
+    /// Gets the abs path of ref streets, if it's configured.
+    pub fn get_reference_street_path(&self) -> Option<String> {
         let relpath = &self.config.wsgi.reference_street;
-        Ok(format!("{}/{}", self.root, relpath))
+        if relpath.is_empty() {
+            return None;
+        }
+
+        Some(format!("{}/{}", self.root, relpath))
+    }
+
+    /// Gets the reference sources in other formats.
+    pub fn get_reference_sources(&self) -> &[ReferenceSourceConfig] {
+        &self.config.reference_source
     }
 
     /// Gets the abs path of ref citycounts.
@@ -223,9 +334,59 @@ impl Ini {
             .parse::<i64>()?)
     }
 
-    /// Gets the URI of the overpass instance to be used.
+    /// Gets the URI of the first overpass instance to be used.
     pub fn get_overpass_uri(&self) -> String {
-        self.get_with_fallback(&self.config.wsgi.overpass_uri, "https://overpass-api.de")
+        self.get_overpass_uris()[0].to_string()
+    }
+
+    /// Gets the URIs of the overpass instances to be used, in the order of preference.
+    pub fn get_overpass_uris(&self) -> Vec<String> {
+        let fallback = "https://overpass-api.de";
+        let value = self.get_with_fallback(&self.config.wsgi.overpass_uri, fallback);
+        let uris: Vec<String> = value.split_whitespace().map(|i| i.to_string()).collect();
+        if uris.is_empty() {
+            return vec![fallback.to_string()];
+        }
+        uris
+    }
+
+    /// Gets the abs paths of the local OSM extract files to be used instead of overpass, if any.
+    pub fn get_overpass_local_extract_paths(&self) -> Vec<String> {
+        let value = self.get_with_fallback(&self.config.wsgi.overpass_local_extract, "");
+        value
+            .split(' ')
+            .filter(|relpath| !relpath.is_empty())
+            .map(|relpath| format!("{}/{}", self.root, relpath))
+            .collect()
+    }
+
+    /// Gets the abs path of the directory containing OSM replication diffs.
+    pub fn get_replication_dir(&self) -> String {
+        let relpath =
+            self.get_with_fallback(&self.config.wsgi.replication_dir, "workdir/replication");
+        format!("{}/{}", self.root, relpath)
+    }
+
+    /// Gets the abs path of the directory where network requests are recorded, if any. The
+    /// OSM_GIMMISN_NETWORK_RECORD environment variable overrides the config.
+    pub fn get_network_record_dir(&self) -> Option<String> {
+        let value = std::env::var("OSM_GIMMISN_NETWORK_RECORD").ok();
+        self.get_network_dir(value.or(self.config.wsgi.network_record_dir.clone()))
+    }
+
+    /// Gets the abs path of the directory where network requests are replayed from, if any. The
+    /// OSM_GIMMISN_NETWORK_REPLAY environment variable overrides the config.
+    pub fn get_network_replay_dir(&self) -> Option<String> {
+        let value = std::env::var("OSM_GIMMISN_NETWORK_REPLAY").ok();
+        self.get_network_dir(value.or(self.config.wsgi.network_replay_dir.clone()))
+    }
+
+    fn get_network_dir(&self, path: Option<String>) -> Option<String> {
+        match path {
+            Some(value) if value.starts_with('/') => Some(value),
+            Some(value) if !value.is_empty() => Some(format!("{}/{}", self.root, value)),
+            _ => None,
+        }
     }
 
     /// Should the cron job update inactive relations?
@@ -233,19 +394,197 @@ impl Ini {
         let value = self.get_with_fallback(&self.config.wsgi.cron_update_inactive, "False");
         value == "True"
     }
+
+    /// Gets the overpass area of the whole country, defaults to Hungary.
+    pub fn get_country_area(&self) -> String {
+        self.get_with_fallback(&self.config.wsgi.country_area, "3600021335")
+    }
+
+    /// Gets the regex which matches path names that are street names.
+    pub fn get_street_name_pattern(&self) -> String {
+        self.get_with_fallback(
+            &self.config.wsgi.street_name_pattern,
+            "utca|út|köz|árok|lépcső|gát|part|tér|tere|sétány|park|dűlő|sor|kert",
+        )
+    }
+
+    /// Gets the regex which matches path names that are not street names, despite
+    /// get_street_name_pattern().
+    pub fn get_street_name_exclude_pattern(&self) -> String {
+        self.get_with_fallback(&self.config.wsgi.street_name_exclude_pattern, "garázssor")
+    }
+
+    /// Gets the regex which matches parking aisle names that are street names.
+    pub fn get_parking_aisle_name_pattern(&self) -> String {
+        self.get_with_fallback(
+            &self.config.wsgi.parking_aisle_name_pattern,
+            "utca|köz|sétány|tér|tere",
+        )
+    }
+
+    /// Gets the regex which matches parking names that are street names.
+    pub fn get_parking_name_pattern(&self) -> String {
+        self.get_with_fallback(
+            &self.config.wsgi.parking_name_pattern,
+            " utca$| köz$| sétány$| tér$| tere$| udvar$",
+        )
+    }
+
+    /// Gets the regex which matches parking names that are not street names, despite
+    /// get_parking_name_pattern().
+    pub fn get_parking_name_exclude_pattern(&self) -> String {
+        self.get_with_fallback(
+            &self.config.wsgi.parking_name_exclude_pattern,
+            "gazdasági udvar",
+        )
+    }
+
+    /// Gets the name of the capital, which is split into districts based on postcodes.
+    pub fn get_capital_name(&self) -> String {
+        self.get_with_fallback(&self.config.wsgi.capital_name, "Budapest")
+    }
+
+    /// Gets the first character of the postcodes which encode a capital district in their middle
+    /// digits, e.g. 1 for 1234 (district 23).
+    pub fn get_capital_postcode_prefix(&self) -> String {
+        self.get_with_fallback(&self.config.wsgi.capital_postcode_prefix, "1")
+    }
+
+    /// Gets the number of capital districts.
+    pub fn get_capital_district_count(&self) -> anyhow::Result<i32> {
+        Ok(self
+            .get_with_fallback(&self.config.wsgi.capital_district_count, "23")
+            .parse::<i32>()?)
+    }
+
+    /// Gets the abbreviations which are expanded when comparing street names: (abbreviation,
+    /// expansion) pairs, in lowercase and without diacritics.
+    pub fn get_street_abbreviations(&self) -> anyhow::Result<Vec<(String, String)>> {
+        let value = self.get_with_fallback(
+            &self.config.wsgi.street_abbreviations,
+            "u=utca,krt=korut,rkp=rakpart,sgt=sugarut,stny=setany,ltp=lakotelep,hrsz=helyrajzi szam",
+        );
+        let mut ret: Vec<(String, String)> = Vec::new();
+        for item in value.split(',') {
+            if item.trim().is_empty() {
+                continue;
+            }
+            let (abbreviation, expansion) = item.split_once('=').context(format!(
+                "street_abbreviations: '{item}' is not abbreviation=expansion"
+            ))?;
+            ret.push((abbreviation.trim().into(), expansion.trim().into()));
+        }
+        Ok(ret)
+    }
+
+    /// Gets how many Overpass-triggering requests a single client can make in a burst.
+    pub fn get_rate_limit_client_burst(&self) -> anyhow::Result<f64> {
+        Ok(self
+            .get_with_fallback(&self.config.wsgi.rate_limit_client_burst, "5")
+            .parse::<f64>()?)
+    }
+
+    /// Gets how many Overpass-triggering requests a single client can make per minute, once its
+    /// burst is used up.
+    pub fn get_rate_limit_client_per_minute(&self) -> anyhow::Result<f64> {
+        Ok(self
+            .get_with_fallback(&self.config.wsgi.rate_limit_client_per_minute, "2")
+            .parse::<f64>()?)
+    }
+
+    /// Gets how many Overpass-triggering requests all clients can make in a burst.
+    pub fn get_rate_limit_global_burst(&self) -> anyhow::Result<f64> {
+        Ok(self
+            .get_with_fallback(&self.config.wsgi.rate_limit_global_burst, "30")
+            .parse::<f64>()?)
+    }
+
+    /// Gets how many Overpass-triggering requests all clients can make per minute, once the
+    /// global burst is used up.
+    pub fn get_rate_limit_global_per_minute(&self) -> anyhow::Result<f64> {
+        Ok(self
+            .get_with_fallback(&self.config.wsgi.rate_limit_global_per_minute, "10")
+            .parse::<f64>()?)
+    }
+
+    /// Gets the addresses of the reverse proxies whose X-Forwarded-For header is trusted.
+    pub fn get_trusted_proxies(&self) -> anyhow::Result<Vec<std::net::IpAddr>> {
+        let mut ret = Vec::new();
+        for item in self
+            .get_with_fallback(&self.config.wsgi.trusted_proxies, "127.0.0.1,::1")
+            .split(',')
+        {
+            let item = item.trim();
+            if item.is_empty() {
+                continue;
+            }
+            ret.push(
+                item.parse()
+                    .context(format!("trusted_proxies: '{item}' is not an IP address"))?,
+            );
+        }
+        Ok(ret)
+    }
 }
 
-/// Context owns global state which is set up once and then read everywhere.
+/// The idle connections of a ConnectionPool and the number of all opened connections.
+#[derive(Default)]
+struct PoolState {
+    idle: Vec<rusqlite::Connection>,
+    opened: usize,
+}
+
+/// A pool of database connections, shared by the threads of the web server.
+#[derive(Default)]
+struct ConnectionPool {
+    state: Mutex<PoolState>,
+    available: Condvar,
+}
+
+/// A database connection borrowed from the pool of a Context, returned to the pool on drop.
+pub struct PooledConnection<'a> {
+    pool: &'a ConnectionPool,
+    connection: Option<rusqlite::Connection>,
+}
+
+impl Deref for PooledConnection<'_> {
+    type Target = rusqlite::Connection;
+
+    fn deref(&self) -> &Self::Target {
+        self.connection.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledConnection<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.connection.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            self.pool.state.lock().unwrap().idle.push(connection);
+            self.pool.available.notify_one();
+        }
+    }
+}
+
+/// Context owns global state which is set up once and then read everywhere. It's shared by the
+/// threads of the web server.
 pub struct Context {
     root: String,
     ini: Ini,
-    network: Rc<dyn Network>,
-    time: Rc<dyn Time>,
-    subprocess: Rc<dyn Subprocess>,
-    unit: Rc<dyn Unit>,
-    file_system: Rc<dyn FileSystem>,
-    database: Rc<dyn Database>,
-    connection: OnceCell<Rc<RefCell<rusqlite::Connection>>>,
+    network: Arc<dyn Network>,
+    time: Arc<dyn Time>,
+    subprocess: Arc<dyn Subprocess>,
+    unit: Arc<dyn Unit>,
+    file_system: Arc<dyn FileSystem>,
+    database: Arc<dyn Database>,
+    pool: ConnectionPool,
+    overpass_health: overpass_query::EndpointHealth,
+    relations_cache: areas::RelationsCache,
+    metrics: metrics::Metrics,
 }
 
 impl Context {
@@ -254,14 +593,32 @@ impl Context {
         let current_dir = std::env::current_dir()?;
         let current_dir_str = current_dir.to_str().context("current_dir() failed")?;
         let root = format!("{current_dir_str}/{prefix}");
-        let network = Rc::new(StdNetwork {});
-        let time = Rc::new(StdTime {});
-        let subprocess = Rc::new(StdSubprocess {});
-        let unit = Rc::new(StdUnit {});
-        let file_system: Rc<dyn FileSystem> = Rc::new(StdFileSystem {});
-        let database: Rc<dyn Database> = Rc::new(StdDatabase {});
+        let time = Arc::new(StdTime {});
+        let subprocess = Arc::new(StdSubprocess {});
+        let unit = Arc::new(StdUnit {});
+        let file_system: Arc<dyn FileSystem> = Arc::new(StdFileSystem {});
+        let database: Arc<dyn Database> = Arc::new(StdDatabase {});
         let ini = Ini::new(&file_system, &format!("{root}/workdir/wsgi.ini"), &root)?;
-        let connection = OnceCell::new();
+        let mut network: Arc<dyn Network> = Arc::new(StdNetwork {});
+        if let Some(dir) = ini.get_network_replay_dir() {
+            network = Arc::new(network_replay::ReplayNetwork::new(&file_system, &dir)?);
+        }
+        let extract_paths = ini.get_overpass_local_extract_paths();
+        if !extract_paths.is_empty() {
+            network = Arc::new(overpass_local::LocalOverpass::new(
+                &file_system,
+                &extract_paths,
+                &ini.get_overpass_uris(),
+                &network,
+            ));
+        }
+        if let Some(dir) = ini.get_network_record_dir() {
+            network = Arc::new(network_replay::RecordNetwork::new(
+                &file_system,
+                &dir,
+                &network,
+            ));
+        }
         Ok(Context {
             root,
             ini,
@@ -271,7 +628,10 @@ impl Context {
             unit,
             file_system,
             database,
-            connection,
+            pool: ConnectionPool::default(),
+            overpass_health: overpass_query::EndpointHealth::default(),
+            relations_cache: areas::RelationsCache::default(),
+            metrics: metrics::Metrics::default(),
         })
     }
 
@@ -286,68 +646,118 @@ impl Context {
     }
 
     /// Gets the network implementation.
-    pub fn get_network(&self) -> &Rc<dyn Network> {
+    pub fn get_network(&self) -> &Arc<dyn Network> {
         &self.network
     }
 
     /// Sets the network implementation.
-    pub fn set_network(&mut self, network: Rc<dyn Network>) {
+    pub fn set_network(&mut self, network: Arc<dyn Network>) {
         self.network = network;
     }
 
     /// Gets the time implementation.
-    pub fn get_time(&self) -> &Rc<dyn Time> {
+    pub fn get_time(&self) -> &Arc<dyn Time> {
         &self.time
     }
 
     /// Sets the time implementation.
-    pub fn set_time(&mut self, time: &Rc<dyn Time>) {
+    pub fn set_time(&mut self, time: &Arc<dyn Time>) {
         self.time = time.clone();
     }
 
     /// Gets the subprocess implementation.
-    pub fn get_subprocess(&self) -> &Rc<dyn Subprocess> {
+    pub fn get_subprocess(&self) -> &Arc<dyn Subprocess> {
         &self.subprocess
     }
 
     /// Sets the subprocess implementation.
-    pub fn set_subprocess(&mut self, subprocess: &Rc<dyn Subprocess>) {
+    pub fn set_subprocess(&mut self, subprocess: &Arc<dyn Subprocess>) {
         self.subprocess = subprocess.clone();
     }
 
     /// Gets the testing interface.
-    pub fn get_unit(&self) -> &Rc<dyn Unit> {
+    pub fn get_unit(&self) -> &Arc<dyn Unit> {
         &self.unit
     }
 
     /// Sets the unit implementation.
-    pub fn set_unit(&mut self, unit: &Rc<dyn Unit>) {
+    pub fn set_unit(&mut self, unit: &Arc<dyn Unit>) {
         self.unit = unit.clone();
     }
 
     /// Gets the file system implementation.
-    pub fn get_file_system(&self) -> &Rc<dyn FileSystem> {
+    pub fn get_file_system(&self) -> &Arc<dyn FileSystem> {
         &self.file_system
     }
 
-    /// Sets the file system implementation.
-    pub fn set_file_system(&mut self, file_system: &Rc<dyn FileSystem>) {
+    /// Sets the file system implementation, dropping the already parsed relations.
+    pub fn set_file_system(&mut self, file_system: &Arc<dyn FileSystem>) {
         self.file_system = file_system.clone();
+        self.relations_cache = areas::RelationsCache::default();
     }
 
-    /// Sets the database implementation.
-    pub fn set_database(&mut self, database: &Rc<dyn Database>) {
+    /// Sets the database implementation, dropping the already opened connections.
+    pub fn set_database(&mut self, database: &Arc<dyn Database>) {
         self.database = database.clone();
+        self.pool = ConnectionPool::default();
+    }
+
+    /// Gets the health of the overpass endpoints.
+    pub fn get_overpass_health(&self) -> &overpass_query::EndpointHealth {
+        &self.overpass_health
+    }
+
+    /// Gets the cache of the parsed relations.
+    pub fn get_relations_cache(&self) -> &areas::RelationsCache {
+        &self.relations_cache
     }
 
-    /// Gets the database connection.
-    pub fn get_database_connection(&self) -> anyhow::Result<RefMut<'_, rusqlite::Connection>> {
-        let connection: &Rc<RefCell<rusqlite::Connection>> = self.connection.get_or_try_init(
-            || -> anyhow::Result<Rc<RefCell<rusqlite::Connection>>> {
-                Ok(Rc::new(RefCell::new(self.database.create()?)))
-            },
-        )?;
-        Ok(connection.borrow_mut())
+    /// Gets the metrics of this process.
+    pub fn get_metrics(&self) -> &metrics::Metrics {
+        &self.metrics
+    }
+
+    /// Gets a database connection from the pool. A new connection is opened if all connections
+    /// are in use, unless the pool is full: then this waits for a connection to be returned.
+    pub fn get_database_connection(&self) -> anyhow::Result<PooledConnection<'_>> {
+        let deadline = std::time::Instant::now() + self.database.get_connection_timeout();
+        let mut state = self.pool.state.lock().unwrap();
+        loop {
+            if let Some(connection) = state.idle.pop() {
+                return Ok(PooledConnection {
+                    pool: &self.pool,
+                    connection: Some(connection),
+                });
+            }
+
+            if state.opened < self.database.get_max_connections() {
+                state.opened += 1;
+                drop(state);
+                return match self.database.create() {
+                    Ok(connection) => Ok(PooledConnection {
+                        pool: &self.pool,
+                        connection: Some(connection),
+                    }),
+                    Err(err) => {
+                        self.pool.state.lock().unwrap().opened -= 1;
+                        Err(err)
+                    }
+                };
+            }
+
+            let now = std::time::Instant::now();
+            if now >= deadline {
+                return Err(anyhow::anyhow!(
+                    "get_database_connection: timed out waiting for a free connection"
+                ));
+            }
+            state = self
+                .pool
+                .available
+                .wait_timeout(state, deadline - now)
+                .unwrap()
+                .0;
+        }
     }
 }
 
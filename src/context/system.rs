@@ -31,7 +31,11 @@ pub struct StdDatabase {}
 impl Database for StdDatabase {
     fn open(&self) -> anyhow::Result<rusqlite::Connection> {
         // https://rust-lang-nursery.github.io/rust-cookbook/database/sqlite.html
-        Ok(rusqlite::Connection::open("workdir/state.db")?)
+        let connection = rusqlite::Connection::open("workdir/state.db")?;
+        // WAL allows reading while an other connection of the pool is writing.
+        connection.pragma_update_and_check(None, "journal_mode", "WAL", |_row| Ok(()))?;
+        connection.busy_timeout(Duration::from_secs(10))?;
+        Ok(connection)
     }
 }
 
@@ -50,22 +54,27 @@ impl FileSystem for StdFileSystem {
         Ok(modified.to_offset(get_tz_offset()))
     }
 
-    fn open_read(&self, path: &str) -> anyhow::Result<Rc<RefCell<dyn Read>>> {
-        let ret: Rc<RefCell<dyn Read>> = Rc::new(RefCell::new(
+    fn getsize(&self, path: &str) -> anyhow::Result<u64> {
+        let metadata = std::fs::metadata(path)?;
+        Ok(metadata.len())
+    }
+
+    fn open_read(&self, path: &str) -> anyhow::Result<Arc<Mutex<dyn Read + Send>>> {
+        let ret: Arc<Mutex<dyn Read + Send>> = Arc::new(Mutex::new(
             std::fs::File::open(path)
                 .with_context(|| format!("failed to open {path} for reading"))?,
         ));
         Ok(ret)
     }
 
-    fn open_write(&self, path: &str) -> anyhow::Result<Rc<RefCell<dyn Write>>> {
+    fn open_write(&self, path: &str) -> anyhow::Result<Arc<Mutex<dyn Write + Send>>> {
         // Create containing directory if needed.
         let path_obj = std::path::Path::new(path);
         let dir_obj = path_obj.parent().context("failed to get parent dir")?;
         let dir = dir_obj.to_str().context("failed to get dir as string")?;
         std::fs::create_dir_all(dir)?;
 
-        let ret: Rc<RefCell<dyn Write>> = Rc::new(RefCell::new(
+        let ret: Arc<Mutex<dyn Write + Send>> = Arc::new(Mutex::new(
             std::fs::File::create(path)
                 .with_context(|| format!("failed to open {path} for writing"))?,
         ));
@@ -86,6 +95,67 @@ impl FileSystem for StdFileSystem {
         }
         Ok(contents)
     }
+
+    fn rename(&self, from: &str, to: &str) -> anyhow::Result<()> {
+        std::fs::rename(from, to).with_context(|| format!("failed to rename {from} to {to}"))
+    }
+
+    #[cfg(unix)]
+    fn replace_dir(&self, from: &str, to: &str) -> anyhow::Result<Option<String>> {
+        // to is a symlink to a versioned directory, so the switch is a single rename of a symlink.
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let version = format!("{to}.{nanos}");
+        self.rename(from, &version)?;
+        let old = match std::fs::symlink_metadata(to) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                let target = std::fs::read_link(to)?;
+                let parent = Path::new(to).parent().context("failed to get parent dir")?;
+                let old = parent.join(target);
+                Some(
+                    old.to_str()
+                        .context("failed to get old dir as string")?
+                        .to_string(),
+                )
+            }
+            Ok(_) => {
+                // A plain directory, created by an older version: this is not atomic, but only
+                // happens once.
+                let old = format!("{to}.old");
+                self.rename(to, &old)?;
+                Some(old)
+            }
+            Err(_) => None,
+        };
+        let link = format!("{to}.link");
+        if std::fs::symlink_metadata(&link).is_ok() {
+            std::fs::remove_file(&link)?;
+        }
+        let target = Path::new(&version)
+            .file_name()
+            .context("failed to get file name")?;
+        std::os::unix::fs::symlink(target, &link)?;
+        self.rename(&link, to)?;
+        Ok(old)
+    }
+
+    #[cfg(not(unix))]
+    fn replace_dir(&self, from: &str, to: &str) -> anyhow::Result<Option<String>> {
+        // No symlinks here: this is not atomic, but Windows is only used to run the validator.
+        let mut old = None;
+        if Path::new(to).exists() {
+            let path = format!("{to}.old");
+            self.rename(to, &path)?;
+            old = Some(path);
+        }
+        self.rename(from, to)?;
+        Ok(old)
+    }
+
+    fn remove_dir_all(&self, path: &str) -> anyhow::Result<()> {
+        std::fs::remove_dir_all(path).with_context(|| format!("failed to remove {path}"))
+    }
 }
 
 /// Network implementation, backed by a real HTTP library.
@@ -94,23 +164,42 @@ pub struct StdNetwork {}
 // Real network is intentionally mocked.
 impl Network for StdNetwork {
     fn urlopen(&self, url: &str, data: &str) -> anyhow::Result<String> {
+        let (_status, ret) = self.urlopen_with_status(url, data, Duration::from_secs(425))?;
+        Ok(ret)
+    }
+
+    fn urlopen_with_status(
+        &self,
+        url: &str,
+        data: &str,
+        timeout: Duration,
+    ) -> anyhow::Result<(u16, String)> {
         if !data.is_empty() {
             let mut buf = isahc::Request::post(url)
                 .redirect_policy(isahc::config::RedirectPolicy::Limit(1))
-                .timeout(Duration::from_secs(425))
+                .timeout(timeout)
                 .body(data)?
                 .send()?;
             let ret = buf.text()?;
-            return Ok(ret);
+            return Ok((buf.status().as_u16(), ret));
         }
 
         let mut buf = isahc::Request::get(url)
             .redirect_policy(isahc::config::RedirectPolicy::Limit(1))
-            .timeout(Duration::from_secs(425))
+            .timeout(timeout)
             .body(())?
             .send()?;
         let ret = buf.text()?;
-        Ok(ret)
+        Ok((buf.status().as_u16(), ret))
+    }
+
+    fn urlopen_bytes(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        let mut buf = isahc::Request::get(url)
+            .redirect_policy(isahc::config::RedirectPolicy::Limit(1))
+            .timeout(Duration::from_secs(425))
+            .body(())?
+            .send()?;
+        Ok(buf.bytes()?)
     }
 }
 
@@ -22,24 +22,45 @@ pub fn make_test_context() -> anyhow::Result<Context> {
     let mut ctx = Context::new("tests")?;
 
     let file_system = TestFileSystem::new();
-    let file_system_rc: Rc<dyn FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
     let time = TestTime::new(2020, 5, 10);
-    let time_rc: Rc<dyn Time> = Rc::new(time);
+    let time_rc: Arc<dyn Time> = Arc::new(time);
     ctx.set_time(&time_rc);
     let network = TestNetwork::new(&[]);
-    let network_rc: Rc<dyn Network> = Rc::new(network);
+    let network_rc: Arc<dyn Network> = Arc::new(network);
     ctx.set_network(network_rc);
     let subprocess = TestSubprocess::new(&HashMap::new());
-    let subprocess_rc: Rc<dyn Subprocess> = Rc::new(subprocess);
+    let subprocess_rc: Arc<dyn Subprocess> = Arc::new(subprocess);
     ctx.set_subprocess(&subprocess_rc);
     let database = TestDatabase {};
-    let database_rc: Rc<dyn Database> = Rc::new(database);
+    let database_rc: Arc<dyn Database> = Arc::new(database);
     ctx.set_database(&database_rc);
 
     Ok(ctx)
 }
 
+/// Replaces the config of ctx with the provided keys of the wsgi section. The required keys
+/// default to empty values.
+pub fn set_ini(ctx: &mut Context, keys: &str) {
+    let mut data = String::from("[wsgi]\n");
+    for key in [
+        "reference_housenumbers",
+        "reference_street",
+        "reference_citycounts",
+        "reference_zipcounts",
+    ] {
+        if !keys.contains(&format!("{key} =")) {
+            data += &format!("{key} = ''\n");
+        }
+    }
+    data += keys;
+    ctx.ini = Ini {
+        config: toml::from_str(&data).unwrap(),
+        root: ctx.root.clone(),
+    };
+}
+
 /// Database implementation, for test purposes.
 pub struct TestDatabase {}
 
@@ -47,42 +68,67 @@ impl Database for TestDatabase {
     fn open(&self) -> anyhow::Result<rusqlite::Connection> {
         Ok(rusqlite::Connection::open(":memory:")?)
     }
+
+    /// Each in-memory connection would have its own database, so use a single connection.
+    fn get_max_connections(&self) -> usize {
+        1
+    }
+}
+
+/// Database implementation with a pool of 2 in-memory databases, for test purposes.
+struct TestPoolDatabase {}
+
+impl Database for TestPoolDatabase {
+    fn open(&self) -> anyhow::Result<rusqlite::Connection> {
+        Ok(rusqlite::Connection::open(":memory:")?)
+    }
+
+    fn get_max_connections(&self) -> usize {
+        2
+    }
+
+    fn get_connection_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(10)
+    }
 }
 
+/// Path -> contents of the in-memory files.
+type Files = HashMap<String, Arc<Mutex<std::io::Cursor<Vec<u8>>>>>;
+
 /// File system implementation, for test purposes.
 pub struct TestFileSystem {
-    hide_paths: Rc<RefCell<Vec<String>>>,
-    mtimes: HashMap<String, Rc<RefCell<time::OffsetDateTime>>>,
-    files: HashMap<String, Rc<RefCell<std::io::Cursor<Vec<u8>>>>>,
+    hide_paths: Arc<Mutex<Vec<String>>>,
+    mtimes: HashMap<String, Arc<Mutex<time::OffsetDateTime>>>,
+    files: Mutex<Files>,
 }
 
 impl TestFileSystem {
     pub fn new() -> Self {
         TestFileSystem {
-            hide_paths: Rc::new(RefCell::new(Vec::new())),
+            hide_paths: Arc::new(Mutex::new(Vec::new())),
             mtimes: HashMap::new(),
-            files: HashMap::new(),
+            files: Mutex::new(HashMap::new()),
         }
     }
 
     /// Shorthand for new() + set_files() + cast to trait.
     pub fn from_files(
-        files: &HashMap<String, Rc<RefCell<std::io::Cursor<Vec<u8>>>>>,
-    ) -> Rc<dyn FileSystem> {
+        files: &HashMap<String, Arc<Mutex<std::io::Cursor<Vec<u8>>>>>,
+    ) -> Arc<dyn FileSystem> {
         let mut file_system = TestFileSystem::new();
         file_system.set_files(files);
-        let file_system_rc: Rc<dyn FileSystem> = Rc::new(file_system);
+        let file_system_rc: Arc<dyn FileSystem> = Arc::new(file_system);
         file_system_rc
     }
 
-    pub fn make_file() -> Rc<RefCell<std::io::Cursor<Vec<u8>>>> {
-        Rc::new(RefCell::new(std::io::Cursor::new(Vec::new())))
+    pub fn make_file() -> Arc<Mutex<std::io::Cursor<Vec<u8>>>> {
+        Arc::new(Mutex::new(std::io::Cursor::new(Vec::new())))
     }
 
-    pub fn write_json_to_file(json: &serde_json::Value) -> Rc<RefCell<std::io::Cursor<Vec<u8>>>> {
+    pub fn write_json_to_file(json: &serde_json::Value) -> Arc<Mutex<std::io::Cursor<Vec<u8>>>> {
         let file = TestFileSystem::make_file();
         {
-            let mut guard = file.borrow_mut();
+            let mut guard = file.lock().unwrap();
             let write = guard.deref_mut();
             serde_json::to_writer(write, json).unwrap();
         }
@@ -91,8 +137,8 @@ impl TestFileSystem {
 
     pub fn make_files(
         ctx: &Context,
-        files: &[(&str, &Rc<RefCell<Cursor<Vec<u8>>>>)],
-    ) -> HashMap<String, Rc<RefCell<std::io::Cursor<Vec<u8>>>>> {
+        files: &[(&str, &Arc<Mutex<Cursor<Vec<u8>>>>)],
+    ) -> HashMap<String, Arc<Mutex<std::io::Cursor<Vec<u8>>>>> {
         let mut ret = HashMap::new();
         for file in files {
             let (path, content) = file;
@@ -103,27 +149,27 @@ impl TestFileSystem {
 
     /// Sets the hide paths.
     pub fn set_hide_paths(&mut self, hide_paths: &[String]) {
-        self.hide_paths = Rc::new(RefCell::new(hide_paths.to_vec()));
+        self.hide_paths = Arc::new(Mutex::new(hide_paths.to_vec()));
     }
 
     /// Sets the mtimes.
-    pub fn set_mtimes(&mut self, mtimes: &HashMap<String, Rc<RefCell<time::OffsetDateTime>>>) {
+    pub fn set_mtimes(&mut self, mtimes: &HashMap<String, Arc<Mutex<time::OffsetDateTime>>>) {
         self.mtimes = mtimes.clone();
     }
 
     /// Sets the files.
-    pub fn set_files(&mut self, files: &HashMap<String, Rc<RefCell<std::io::Cursor<Vec<u8>>>>>) {
-        self.files = files.clone()
+    pub fn set_files(&mut self, files: &HashMap<String, Arc<Mutex<std::io::Cursor<Vec<u8>>>>>) {
+        self.files = Mutex::new(files.clone())
     }
 }
 
 impl FileSystem for TestFileSystem {
     fn path_exists(&self, path: &str) -> bool {
-        if self.hide_paths.borrow().contains(&path.to_string()) {
+        if self.hide_paths.lock().unwrap().contains(&path.to_string()) {
             return false;
         }
 
-        if self.files.contains_key(path) {
+        if self.files.lock().unwrap().contains_key(path) {
             return true;
         }
 
@@ -132,7 +178,7 @@ impl FileSystem for TestFileSystem {
 
     fn getmtime(&self, path: &str) -> anyhow::Result<time::OffsetDateTime> {
         if let Some(value) = self.mtimes.get(path) {
-            return Ok(*value.borrow());
+            return Ok(*value.lock().unwrap());
         }
 
         let metadata =
@@ -140,31 +186,44 @@ impl FileSystem for TestFileSystem {
         Ok(time::OffsetDateTime::try_from(metadata.modified()?)?)
     }
 
-    fn open_read(&self, path: &str) -> anyhow::Result<Rc<RefCell<dyn Read>>> {
-        if self.hide_paths.borrow().contains(&path.to_string()) {
+    fn getsize(&self, path: &str) -> anyhow::Result<u64> {
+        if let Some(file) = self.files.lock().unwrap().get(path) {
+            return Ok(file.lock().unwrap().get_ref().len() as u64);
+        }
+
+        let metadata =
+            std::fs::metadata(path).context(format!("metadata() failed for '{path}'"))?;
+        Ok(metadata.len())
+    }
+
+    fn open_read(&self, path: &str) -> anyhow::Result<Arc<Mutex<dyn Read + Send>>> {
+        if self.hide_paths.lock().unwrap().contains(&path.to_string()) {
             return Err(anyhow::anyhow!("'{}' is hidden", path));
         }
 
-        if self.files.contains_key(path) {
-            let ret = self.files[path].clone();
-            ret.borrow_mut().seek(SeekFrom::Start(0))?;
+        if let Some(file) = self.files.lock().unwrap().get(path) {
+            let ret = file.clone();
+            ret.lock().unwrap().seek(SeekFrom::Start(0))?;
             return Ok(ret);
         }
-        let ret: Rc<RefCell<dyn Read>> = Rc::new(RefCell::new(
+        let ret: Arc<Mutex<dyn Read + Send>> = Arc::new(Mutex::new(
             std::fs::File::open(path).context(format!("failed to open '{path}'"))?,
         ));
         Ok(ret)
     }
 
-    fn open_write(&self, path: &str) -> anyhow::Result<Rc<RefCell<dyn Write>>> {
-        if !self.files.contains_key(path) {
-            return Err(anyhow::anyhow!(
-                "open_write: self.files doesn't contain '{}'",
-                path
-            ));
-        }
+    fn open_write(&self, path: &str) -> anyhow::Result<Arc<Mutex<dyn Write + Send>>> {
+        let ret = match self.files.lock().unwrap().get(path) {
+            Some(value) => value.clone(),
+            None => {
+                return Err(anyhow::anyhow!(
+                    "open_write: self.files doesn't contain '{}'",
+                    path
+                ));
+            }
+        };
 
-        let mut hide_paths = self.hide_paths.borrow_mut();
+        let mut hide_paths = self.hide_paths.lock().unwrap();
         if hide_paths.contains(&path.to_string()) {
             let position = hide_paths.iter().position(|i| *i == path).unwrap();
             hide_paths.remove(position);
@@ -172,14 +231,14 @@ impl FileSystem for TestFileSystem {
 
         assert!(self.mtimes.get(path).is_none());
 
-        let ret = self.files[path].clone();
-        ret.borrow_mut().seek(SeekFrom::Start(0))?;
+        ret.lock().unwrap().seek(SeekFrom::Start(0))?;
         Ok(ret)
     }
 
     fn unlink(&self, path: &str) -> anyhow::Result<()> {
-        let mut hide_paths = self.hide_paths.borrow_mut();
-        if !self.files.contains_key(path) || hide_paths.contains(&path.to_string()) {
+        let mut hide_paths = self.hide_paths.lock().unwrap();
+        if !self.files.lock().unwrap().contains_key(path) || hide_paths.contains(&path.to_string())
+        {
             return Err(anyhow::anyhow!("unlink: {}: no such file", path));
         }
 
@@ -189,8 +248,9 @@ impl FileSystem for TestFileSystem {
 
     fn listdir(&self, path: &str) -> anyhow::Result<Vec<String>> {
         let mut contents: Vec<String> = Vec::new();
-        for file in self.files.iter() {
-            if self.hide_paths.borrow().contains(file.0) {
+        let hide_paths = self.hide_paths.lock().unwrap();
+        for file in self.files.lock().unwrap().iter() {
+            if hide_paths.contains(file.0) {
                 continue;
             }
 
@@ -203,12 +263,59 @@ impl FileSystem for TestFileSystem {
 
         Ok(contents)
     }
+
+    fn rename(&self, from: &str, to: &str) -> anyhow::Result<()> {
+        let mut data: Vec<u8> = Vec::new();
+        self.open_read(from)?
+            .lock()
+            .unwrap()
+            .read_to_end(&mut data)?;
+        self.open_write(to)?.lock().unwrap().write_all(&data)?;
+        self.unlink(from)
+    }
+
+    fn replace_dir(&self, from: &str, to: &str) -> anyhow::Result<Option<String>> {
+        // Emulate the swap by moving the files: the old files of to go to to.old.
+        let old = format!("{to}.old");
+        let mut hide_paths = self.hide_paths.lock().unwrap();
+        let mut files = self.files.lock().unwrap();
+        let paths: Vec<String> = files.keys().cloned().collect();
+        let mut moved: Files = HashMap::new();
+        for path in paths {
+            if hide_paths.contains(&path) {
+                continue;
+            }
+
+            let dest = if let Some(name) = path.strip_prefix(&format!("{to}/")) {
+                format!("{old}/{name}")
+            } else if let Some(name) = path.strip_prefix(&format!("{from}/")) {
+                format!("{to}/{name}")
+            } else {
+                continue;
+            };
+            moved.insert(dest, files.remove(&path).unwrap());
+        }
+        hide_paths.retain(|path| !moved.contains_key(path));
+        files.extend(moved);
+        Ok(Some(old))
+    }
+
+    fn remove_dir_all(&self, path: &str) -> anyhow::Result<()> {
+        let mut hide_paths = self.hide_paths.lock().unwrap();
+        let files = self.files.lock().unwrap();
+        for file in files.keys() {
+            if file.starts_with(&format!("{path}/")) && !hide_paths.contains(file) {
+                hide_paths.push(file.to_string());
+            }
+        }
+        Ok(())
+    }
 }
 
 /// Time implementation, for test purposes.
 pub struct TestTime {
     now: time::OffsetDateTime,
-    sleep: Rc<RefCell<u64>>,
+    sleep: Arc<Mutex<u64>>,
 }
 
 impl TestTime {
@@ -221,13 +328,13 @@ impl TestTime {
         .unwrap()
         .midnight();
         let now = date.assume_utc();
-        let sleep = Rc::new(RefCell::new(0_u64));
+        let sleep = Arc::new(Mutex::new(0_u64));
         TestTime { now, sleep }
     }
 
     /// Gets the duration of the last sleep.
     pub fn get_sleep(&self) -> u64 {
-        *self.sleep.borrow_mut()
+        *self.sleep.lock().unwrap()
     }
 }
 
@@ -237,7 +344,7 @@ impl Time for TestTime {
     }
 
     fn sleep(&self, seconds: u64) {
-        let mut guard = self.sleep.borrow_mut();
+        let mut guard = self.sleep.lock().unwrap();
         *guard.deref_mut() = seconds;
     }
 
@@ -255,6 +362,8 @@ pub struct URLRoute {
     data_path: String,
     /// Path of expected result data
     result_path: String,
+    /// HTTP status code of the result
+    status: u16,
 }
 
 impl URLRoute {
@@ -263,18 +372,25 @@ impl URLRoute {
             url: url.into(),
             data_path: data_path.into(),
             result_path: result_path.into(),
+            status: 200,
         }
     }
+
+    /// Sets a HTTP status code other than 200 OK for the result.
+    pub fn with_status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
 }
 
 /// Network implementation, for test purposes.
 pub struct TestNetwork {
-    routes: Rc<RefCell<Vec<URLRoute>>>,
+    routes: Arc<Mutex<Vec<URLRoute>>>,
 }
 
 impl TestNetwork {
     pub fn new(routes: &[URLRoute]) -> Self {
-        let routes = Rc::new(RefCell::new(routes.to_vec()));
+        let routes = Arc::new(Mutex::new(routes.to_vec()));
         TestNetwork { routes }
     }
 }
@@ -282,9 +398,20 @@ impl TestNetwork {
 impl Network for TestNetwork {
     /// Opens an URL. Empty data means HTTP GET, otherwise it means a HTTP POST.
     fn urlopen(&self, url: &str, data: &str) -> anyhow::Result<String> {
+        let (_status, ret) = self.urlopen_with_status(url, data, std::time::Duration::ZERO)?;
+        Ok(ret)
+    }
+
+    fn urlopen_with_status(
+        &self,
+        url: &str,
+        data: &str,
+        _timeout: std::time::Duration,
+    ) -> anyhow::Result<(u16, String)> {
         let mut ret: String = "".into();
+        let mut status = 200;
         let mut remove: Option<usize> = None;
-        let mut locked_routes = self.routes.borrow_mut();
+        let mut locked_routes = self.routes.lock().unwrap();
         for (index, route) in locked_routes.iter().enumerate() {
             if url != route.url {
                 continue;
@@ -299,6 +426,7 @@ impl Network for TestNetwork {
                 return Err(anyhow::anyhow!("empty result_path for url '{}'", url));
             }
             ret = std::fs::read_to_string(&route.result_path)?;
+            status = route.status;
             remove = Some(index);
             break;
         }
@@ -308,7 +436,7 @@ impl Network for TestNetwork {
         }
         // Allow specifying multiple results for the same URL.
         locked_routes.remove(remove.unwrap());
-        Ok(ret)
+        Ok((status, ret))
     }
 }
 
@@ -330,15 +458,15 @@ impl Unit for TestUnit {
 /// Subprocess implementation for test purposes.
 pub struct TestSubprocess {
     outputs: HashMap<String, String>,
-    runs: Rc<RefCell<Vec<String>>>,
-    exits: Rc<RefCell<Vec<i32>>>,
+    runs: Arc<Mutex<Vec<String>>>,
+    exits: Arc<Mutex<Vec<i32>>>,
 }
 
 impl TestSubprocess {
     pub fn new(outputs: &HashMap<String, String>) -> Self {
         let outputs = outputs.clone();
-        let runs: Rc<RefCell<Vec<String>>> = Rc::new(RefCell::new(Vec::new()));
-        let exits: Rc<RefCell<Vec<i32>>> = Rc::new(RefCell::new(Vec::new()));
+        let runs: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
+        let exits: Arc<Mutex<Vec<i32>>> = Arc::new(Mutex::new(Vec::new()));
         TestSubprocess {
             outputs,
             runs,
@@ -348,24 +476,24 @@ impl TestSubprocess {
 
     /// Gets a list of invoked commands.
     pub fn get_runs(&self) -> Vec<String> {
-        self.runs.borrow_mut().clone()
+        self.runs.lock().unwrap().clone()
     }
 
     /// Gets a list of exit codes.
     pub fn get_exits(&self) -> Vec<i32> {
-        self.exits.borrow_mut().clone()
+        self.exits.lock().unwrap().clone()
     }
 }
 
 impl Subprocess for TestSubprocess {
     fn run(&self, args: Vec<String>) -> anyhow::Result<String> {
         let key = args.join(" ");
-        self.runs.borrow_mut().push(key.clone());
+        self.runs.lock().unwrap().push(key.clone());
         Ok(self.outputs[&key].clone())
     }
 
     fn exit(&self, code: i32) {
-        self.exits.borrow_mut().push(code);
+        self.exits.lock().unwrap().push(code);
     }
 
     fn as_any(&self) -> &dyn std::any::Any {
@@ -380,6 +508,106 @@ fn test_ini_get_tcp_port() {
     assert_eq!(ctx.get_ini().get_tcp_port().unwrap(), 8000);
 }
 
+/// Tests Ini.get_overpass_uris().
+#[test]
+fn test_ini_get_overpass_uris() {
+    let mut ctx = make_test_context().unwrap();
+    set_ini(
+        &mut ctx,
+        "overpass_uri = 'https://overpass-api.de https://overpass.kumi.systems'\n",
+    );
+
+    assert_eq!(
+        ctx.get_ini().get_overpass_uris(),
+        vec!["https://overpass-api.de", "https://overpass.kumi.systems"]
+    );
+    assert_eq!(ctx.get_ini().get_overpass_uri(), "https://overpass-api.de");
+    set_ini(&mut ctx, "overpass_uri = ''\n");
+    assert_eq!(
+        ctx.get_ini().get_overpass_uris(),
+        vec!["https://overpass-api.de"]
+    );
+}
+
+/// Tests Ini.get_reference_street_path().
+#[test]
+fn test_ini_get_reference_street_path() {
+    let mut ctx = make_test_context().unwrap();
+    assert_eq!(
+        ctx.get_ini().get_reference_street_path().unwrap(),
+        ctx.get_abspath("workdir/refs/utcak_20190514.tsv")
+    );
+
+    set_ini(&mut ctx, "");
+
+    assert_eq!(ctx.get_ini().get_reference_street_path(), None);
+}
+
+/// Tests the country-specific getters of Ini: the defaults are for Hungary.
+#[test]
+fn test_ini_get_country() {
+    let mut ctx = make_test_context().unwrap();
+    assert_eq!(ctx.get_ini().get_country_area(), "3600021335");
+    assert_eq!(ctx.get_ini().get_capital_name(), "Budapest");
+    assert_eq!(ctx.get_ini().get_capital_postcode_prefix(), "1");
+    assert_eq!(ctx.get_ini().get_capital_district_count().unwrap(), 23);
+    let abbreviations = ctx.get_ini().get_street_abbreviations().unwrap();
+    assert_eq!(abbreviations[0], ("u".to_string(), "utca".to_string()));
+    assert_eq!(
+        abbreviations[6],
+        ("hrsz".to_string(), "helyrajzi szam".to_string())
+    );
+
+    set_ini(
+        &mut ctx,
+        "country_area = '3600016239'\nparking_name_exclude_pattern = ''\ncapital_district_count = 'x'\nstreet_abbreviations = 'g=gatan'\n",
+    );
+    assert_eq!(ctx.get_ini().get_country_area(), "3600016239");
+    assert_eq!(ctx.get_ini().get_parking_name_exclude_pattern(), "");
+    assert_eq!(ctx.get_ini().get_capital_district_count().is_err(), true);
+    assert_eq!(
+        ctx.get_ini().get_street_abbreviations().unwrap(),
+        vec![("g".to_string(), "gatan".to_string())]
+    );
+    set_ini(&mut ctx, "street_abbreviations = 'g'\n");
+    assert_eq!(ctx.get_ini().get_street_abbreviations().is_err(), true);
+}
+
+/// Tests the rate limit getters of Ini.
+#[test]
+fn test_ini_get_rate_limit() {
+    let mut ctx = make_test_context().unwrap();
+    assert_eq!(ctx.get_ini().get_rate_limit_client_burst().unwrap(), 5_f64);
+    assert_eq!(
+        ctx.get_ini().get_rate_limit_client_per_minute().unwrap(),
+        2_f64
+    );
+    assert_eq!(ctx.get_ini().get_rate_limit_global_burst().unwrap(), 30_f64);
+    assert_eq!(
+        ctx.get_ini().get_rate_limit_global_per_minute().unwrap(),
+        10_f64
+    );
+
+    assert_eq!(ctx.get_ini().get_trusted_proxies().unwrap().len(), 2);
+
+    set_ini(
+        &mut ctx,
+        "rate_limit_client_burst = '1'\nrate_limit_global_per_minute = 'x'\ntrusted_proxies = 'x'\n",
+    );
+    assert_eq!(ctx.get_ini().get_rate_limit_client_burst().unwrap(), 1_f64);
+    assert_eq!(
+        ctx.get_ini().get_rate_limit_global_per_minute().is_err(),
+        true
+    );
+    assert_eq!(ctx.get_ini().get_trusted_proxies().is_err(), true);
+
+    set_ini(&mut ctx, "trusted_proxies = ''\n");
+    assert_eq!(
+        ctx.get_ini().get_trusted_proxies().unwrap().is_empty(),
+        true
+    );
+}
+
 /// Tests Ini.get_with_fallack().
 #[test]
 fn test_ini_get_with_fallback() {
@@ -406,7 +634,7 @@ fn test_ini_new() {
     file_system
         .write_from_string("[wsgi]\n=", &ctx.get_abspath("workdir/wsgi.ini"))
         .unwrap();
-    let file_system_rc: Rc<dyn FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn FileSystem> = Arc::new(file_system);
 
     let ret = Ini::new(
         &file_system_rc,
@@ -423,7 +651,7 @@ fn test_ini_new_no_config() {
     let ctx = make_test_context().unwrap();
     let mut file_system = TestFileSystem::new();
     file_system.set_hide_paths(&[ctx.get_abspath("workdir/wsgi.ini")]);
-    let file_system_rc: Rc<dyn FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn FileSystem> = Arc::new(file_system);
 
     let ret = Ini::new(&file_system_rc, &ctx.get_abspath("workdir/wsgi.ini"), "");
 
@@ -471,3 +699,67 @@ fn test_file_system_list_dir() {
 
     assert_eq!(ret.is_empty(), true);
 }
+
+/// Tests Context::get_database_connection(): connections are opened on demand and reused.
+#[test]
+fn test_get_database_connection_pool() {
+    let mut ctx = make_test_context().unwrap();
+    let database_arc: Arc<dyn Database> = Arc::new(TestPoolDatabase {});
+    ctx.set_database(&database_arc);
+
+    {
+        let first = ctx.get_database_connection().unwrap();
+        first
+            .execute(
+                "insert into mtimes (page, last_modified) values ('first', '0')",
+                [],
+            )
+            .unwrap();
+        // The first connection is in use, so a second one is opened.
+        let second = ctx.get_database_connection().unwrap();
+        let count: i64 = second
+            .query_row("select count(*) from mtimes", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    {
+        let _first = ctx.get_database_connection().unwrap();
+        let _second = ctx.get_database_connection().unwrap();
+        // All connections are in use: give up after the timeout.
+        assert_eq!(ctx.get_database_connection().is_err(), true);
+    }
+
+    // The last returned connection is reused: that's the first one.
+    let conn = ctx.get_database_connection().unwrap();
+    let count: i64 = conn
+        .query_row("select count(*) from mtimes", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 1);
+}
+
+/// Tests that a Context can be shared by threads, which wait for the pooled connection.
+#[test]
+fn test_context_threads() {
+    let ctx = make_test_context().unwrap();
+
+    std::thread::scope(|scope| {
+        for page in 0..4 {
+            let ctx = &ctx;
+            scope.spawn(move || {
+                let conn = ctx.get_database_connection().unwrap();
+                conn.execute(
+                    "insert into mtimes (page, last_modified) values (?1, '0')",
+                    [page.to_string()],
+                )
+                .unwrap();
+            });
+        }
+    });
+
+    let conn = ctx.get_database_connection().unwrap();
+    let count: i64 = conn
+        .query_row("select count(*) from mtimes", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 4);
+}
@@ -13,6 +13,9 @@
 use crate::area_files;
 use crate::areas;
 use crate::context;
+use crate::metrics;
+use crate::osm_diff;
+use crate::overpass_local;
 use crate::overpass_query;
 use crate::stats;
 use crate::util;
@@ -30,95 +33,188 @@ use log::{error, info, warn};
 #[cfg(test)]
 use std::{println as info, println as warn, println as error};
 
-/// Sleeps to respect overpass rate limit.
-fn overpass_sleep(ctx: &context::Context) {
-    loop {
-        let sleep = overpass_query::overpass_query_need_sleep(ctx);
-        if sleep == 0 {
-            break;
+/// Creates a tag matcher based on a query template.
+fn make_tag_matcher(
+    ctx: &context::Context,
+    template_path: &str,
+) -> anyhow::Result<overpass_local::TagMatcher> {
+    let template = ctx
+        .get_file_system()
+        .read_to_string(&ctx.get_abspath(template_path))?;
+    let template = util::process_country_template(&template, ctx.get_ini());
+    overpass_local::TagMatcher::new(&util::process_template(&template, 0))
+}
+
+/// Gets the boundary of a relation to place the objects of diffs, queries Overpass if it's not
+/// stored yet. Returns None if the boundary is not available.
+fn get_boundary(
+    ctx: &context::Context,
+    relation_id: u64,
+) -> anyhow::Result<Option<area_files::Boundary>> {
+    if let Some(boundary) = area_files::get_boundary(ctx, relation_id)? {
+        return Ok(Some(boundary));
+    }
+
+    let query = format!("[out:json][timeout:425];\nrel({relation_id});\nout geom;");
+    let buf = match overpass_query::overpass_query_with_retry(ctx, &query) {
+        Ok(value) => value,
+        Err(err) => {
+            warn!("get_boundary: giving up on {relation_id}: {err}");
+            return Ok(None);
+        }
+    };
+    if let Err(err) = area_files::write_boundary(ctx, relation_id, &buf) {
+        warn!("get_boundary: failed to write {relation_id}: {err:?}");
+        return Ok(None);
+    }
+    area_files::get_boundary(ctx, relation_id)
+}
+
+/// Queries the geometry of the changed ways in diffs, so they can be placed. Ways without a
+/// geometry need a full query when they are not tracked yet.
+fn locate_ways(
+    ctx: &context::Context,
+    osm_diffs: &mut area_files::OsmDiffs<'_>,
+) -> anyhow::Result<()> {
+    let ids = osm_diffs.get_way_ids(ctx)?;
+    if ids.is_empty() {
+        return Ok(());
+    }
+
+    let ids: Vec<String> = ids.iter().map(|i| i.to_string()).collect();
+    let query = format!(
+        "[out:json][timeout:425];\nway(id:{});\nout geom;",
+        ids.join(",")
+    );
+    let buf = match overpass_query::overpass_query_with_retry(ctx, &query) {
+        Ok(value) => value,
+        Err(err) => {
+            warn!("locate_ways: giving up: {err}");
+            return Ok(());
         }
-        info!("overpass_sleep: waiting for {sleep} seconds");
-        ctx.get_time().sleep(sleep as u64);
+    };
+    if let Err(err) = osm_diffs.set_way_geometries(&buf) {
+        warn!("locate_ways: failed to parse the ways: {err:?}");
     }
+    Ok(())
 }
 
-/// Decides if we should retry a query or not.
-fn should_retry(retry: i32) -> bool {
-    retry < 20
+/// Update the OSM street list of one relation, using overpass.
+fn update_osm_streets_overpass(
+    ctx: &context::Context,
+    relation: &areas::Relation<'_>,
+) -> anyhow::Result<()> {
+    let query = relation.get_osm_streets_json_query()?;
+    let buf = match overpass_query::overpass_query_with_retry(ctx, &query) {
+        Ok(value) => value,
+        Err(err) => {
+            warn!(
+                "update_osm_streets, json: giving up on {}: {err}",
+                relation.get_name()
+            );
+            return Ok(());
+        }
+    };
+    relation
+        .get_files()
+        .write_osm_json_streets(ctx, &buf)
+        .context("write_osm_json_streets() failed")
 }
 
-/// Update the OSM street list of all relations.
+/// Update the OSM street list of all relations, from diffs if possible.
 fn update_osm_streets(
     ctx: &context::Context,
     relations: &mut areas::Relations<'_>,
     update: bool,
+    diffs: &[osm_diff::Diff],
 ) -> anyhow::Result<()> {
+    let osm_diffs = if diffs.is_empty() {
+        None
+    } else {
+        let matcher = make_tag_matcher(ctx, "data/streets-template.overpassql")?;
+        let mut osm_diffs = area_files::OsmDiffs::new_streets(diffs, matcher);
+        locate_ways(ctx, &mut osm_diffs)?;
+        Some(osm_diffs)
+    };
     let active_names = relations.get_active_names();
     for relation_name in active_names.context("get_active_names() failed")? {
         let relation = relations.get_relation(&relation_name)?;
         if !update && stats::has_sql_mtime(ctx, &format!("streets/{}", relation_name))? {
             continue;
         }
-        info!("update_osm_streets, json: start: {relation_name}");
-        let mut retry = 0;
-        while should_retry(retry) {
-            if retry > 0 {
-                info!("update_osm_streets, json: try #{retry}");
-            }
-            retry += 1;
-            overpass_sleep(ctx);
-            let query = relation.get_osm_streets_json_query()?;
-            let buf = match overpass_query::overpass_query(ctx, &query) {
-                Ok(value) => value,
-                Err(err) => {
-                    info!("update_osm_streets, json: http error: {err:?}");
+        if let Some(ref osm_diffs) = osm_diffs {
+            let boundary = get_boundary(ctx, relation.get_config().get_osmrelation())?;
+            if let Some(ref boundary) = boundary {
+                if relation
+                    .get_files()
+                    .apply_osm_diffs(ctx, osm_diffs, boundary)?
+                {
+                    info!("update_osm_streets, diffs: applied: {relation_name}");
                     continue;
                 }
-            };
-            relation
-                .get_files()
-                .write_osm_json_streets(ctx, &buf)
-                .context("write_osm_json_streets() failed")?;
-            break;
+            }
         }
+        info!("update_osm_streets, json: start: {relation_name}");
+        update_osm_streets_overpass(ctx, &relation)?;
         info!("update_osm_streets, json: end: {relation_name}");
     }
 
     Ok(())
 }
 
-/// Update the OSM housenumber list of all relations.
+/// Update the OSM housenumber list of one relation, using overpass.
+fn update_osm_housenumbers_overpass(
+    ctx: &context::Context,
+    relation: &areas::Relation<'_>,
+) -> anyhow::Result<()> {
+    let query = relation.get_osm_housenumbers_json_query()?;
+    let buf = match overpass_query::overpass_query_with_retry(ctx, &query) {
+        Ok(value) => value,
+        Err(err) => {
+            warn!(
+                "update_osm_housenumbers, json: giving up on {}: {err}",
+                relation.get_name()
+            );
+            return Ok(());
+        }
+    };
+    relation.get_files().write_osm_json_housenumbers(ctx, &buf)
+}
+
+/// Update the OSM housenumber list of all relations, from diffs if possible.
 fn update_osm_housenumbers(
     ctx: &context::Context,
     relations: &mut areas::Relations<'_>,
     update: bool,
+    diffs: &[osm_diff::Diff],
 ) -> anyhow::Result<()> {
+    let osm_diffs = if diffs.is_empty() {
+        None
+    } else {
+        let matcher = make_tag_matcher(ctx, "data/street-housenumbers-template.overpassql")?;
+        let mut osm_diffs = area_files::OsmDiffs::new_housenumbers(diffs, matcher);
+        locate_ways(ctx, &mut osm_diffs)?;
+        Some(osm_diffs)
+    };
     for relation_name in relations.get_active_names()? {
         let relation = relations.get_relation(&relation_name)?;
         if !update && stats::has_sql_mtime(ctx, &format!("housenumbers/{}", relation_name))? {
             continue;
         }
-        info!("update_osm_housenumbers, json: start: {relation_name}");
-        let mut retry = 0;
-        while should_retry(retry) {
-            if retry > 0 {
-                info!("update_osm_housenumbers, json: try #{retry}");
-            }
-            retry += 1;
-            overpass_sleep(ctx);
-            let query = relation.get_osm_housenumbers_json_query()?;
-            let buf = match overpass_query::overpass_query(ctx, &query) {
-                Ok(value) => value,
-                Err(err) => {
-                    info!("update_osm_housenumbers, json: http error: {err:?}");
+        if let Some(ref osm_diffs) = osm_diffs {
+            let boundary = get_boundary(ctx, relation.get_config().get_osmrelation())?;
+            if let Some(ref boundary) = boundary {
+                if relation
+                    .get_files()
+                    .apply_osm_diffs(ctx, osm_diffs, boundary)?
+                {
+                    info!("update_osm_housenumbers, diffs: applied: {relation_name}");
                     continue;
                 }
-            };
-            relation
-                .get_files()
-                .write_osm_json_housenumbers(ctx, &buf)?;
-            break;
+            }
         }
+        info!("update_osm_housenumbers, json: start: {relation_name}");
+        update_osm_housenumbers_overpass(ctx, &relation)?;
         info!("update_osm_housenumbers, json: end: {relation_name}");
     }
 
@@ -146,9 +242,10 @@ fn update_missing_housenumbers(
             continue;
         }
 
-        relation
+        let (_todo_street_count, todo_count, done_count, percent, _table) = relation
             .write_missing_housenumbers()
             .context("write_missing_housenumbers() failed")?;
+        relation.add_coverage_snapshot("housenumbers", todo_count, done_count, percent)?;
     }
     info!("update_missing_housenumbers: end");
 
@@ -171,7 +268,8 @@ fn update_missing_streets(
             continue;
         }
 
-        relation.write_missing_streets()?;
+        let (todo_count, done_count, percent, _streets) = relation.write_missing_streets()?;
+        relation.add_coverage_snapshot("streets", todo_count, done_count, percent)?;
     }
     info!("update_missing_streets: end");
 
@@ -277,7 +375,7 @@ fn update_stats_count(ctx: &context::Context, today: &str) -> anyhow::Result<()>
                 ]
                 .join("\t"),
             );
-            let city_key = util::get_city_key(&postcode, &city, &valid_settlements)
+            let city_key = util::get_city_key(ctx.get_ini(), &postcode, &city, &valid_settlements)
                 .context("get_city_key() failed")?;
             let city_value = [street.to_string(), housenumber.to_string()].join("\t");
             let entry = cities.entry(city_key).or_default();
@@ -357,7 +455,7 @@ fn update_stats_refcount(ctx: &context::Context, state_dir: &str) -> anyhow::Res
         let stream = ctx
             .get_file_system()
             .open_read(&ctx.get_ini().get_reference_citycounts_path()?)?;
-        let mut guard = stream.borrow_mut();
+        let mut guard = stream.lock().unwrap();
         let mut read = guard.deref_mut();
         let mut csv_reader = util::make_csv_reader(&mut read);
         for result in csv_reader.deserialize() {
@@ -376,7 +474,8 @@ fn update_stats_refcount(ctx: &context::Context, state_dir: &str) -> anyhow::Res
 pub fn update_stats_overpass(ctx: &context::Context) -> anyhow::Result<()> {
     let query = ctx
         .get_file_system()
-        .read_to_string(&ctx.get_abspath("data/street-housenumbers-hungary.overpassql"))?;
+        .read_to_string(&ctx.get_abspath("data/street-housenumbers-country.overpassql"))?;
+    let query = util::process_country_template(&query, ctx.get_ini());
     let mut i = 0;
     let mut lines = Vec::new();
     for line in query.lines() {
@@ -390,29 +489,16 @@ pub fn update_stats_overpass(ctx: &context::Context) -> anyhow::Result<()> {
     }
     let json_query = lines.join("\n");
     info!("update_stats_overpass: json, talking to overpass");
-    let mut retry = 0;
-    while should_retry(retry) {
-        if retry > 0 {
-            info!("update_stats_overpass: try #{retry}");
-        }
-        retry += 1;
-        overpass_sleep(ctx);
-        let response = match overpass_query::overpass_query(ctx, &json_query) {
-            Ok(value) => value,
-            Err(err) => {
-                info!("update_stats_overpass: http error: {err}");
-                continue;
-            }
-        };
-
-        area_files::write_whole_country(ctx, &response)?;
-        break;
-    }
-    Ok(())
+    let response = overpass_query::overpass_query_with_retry(ctx, &json_query)?;
+    area_files::write_whole_country(ctx, &response)
 }
 
 /// Performs the update of country-level stats.
-fn update_stats(ctx: &context::Context, overpass: bool) -> anyhow::Result<()> {
+fn update_stats(
+    ctx: &context::Context,
+    overpass: bool,
+    diffs: &[osm_diff::Diff],
+) -> anyhow::Result<()> {
     // Fetch house numbers for the whole country.
     info!("update_stats: start, updating the whole_country table");
     let statedir = ctx.get_abspath("workdir/stats");
@@ -421,7 +507,28 @@ fn update_stats(ctx: &context::Context, overpass: bool) -> anyhow::Result<()> {
     let today = now.format(&format)?;
 
     if overpass {
-        update_stats_overpass(ctx)?;
+        let mut applied = false;
+        // Area ids of relations are offset by 3600000000, other areas have no boundary to query.
+        let country_relation = ctx
+            .get_ini()
+            .get_country_area()
+            .parse::<u64>()
+            .ok()
+            .and_then(|i| i.checked_sub(3600000000));
+        if let (false, Some(relation_id)) = (diffs.is_empty(), country_relation) {
+            if let Some(boundary) = get_boundary(ctx, relation_id)? {
+                let matcher = make_tag_matcher(ctx, "data/street-housenumbers-country.overpassql")?;
+                let mut osm_diffs = area_files::OsmDiffs::new_whole_country(diffs, matcher);
+                locate_ways(ctx, &mut osm_diffs)?;
+                applied = area_files::apply_whole_country_diffs(ctx, &osm_diffs, &boundary)?;
+            }
+        }
+        if applied {
+            info!("update_stats: applied diffs");
+        } else if let Err(err) = update_stats_overpass(ctx) {
+            // Continue with the old whole_country table.
+            error!("update_stats: overpass update failed: {err:?}");
+        }
     }
 
     info!("update_stats: updating count");
@@ -431,6 +538,7 @@ fn update_stats(ctx: &context::Context, overpass: bool) -> anyhow::Result<()> {
     info!("update_stats: updating refcount");
     update_stats_refcount(ctx, &statedir)?;
     stats::update_invalid_addr_cities(ctx)?;
+    stats::update_invalid_postcodes(ctx)?;
 
     info!("update_stats: generating json");
     let json_path = format!("{}/stats.json", &statedir);
@@ -448,13 +556,19 @@ fn our_main_inner(
     mode: &String,
     update: bool,
     overpass: bool,
+    diffs: bool,
 ) -> anyhow::Result<()> {
+    let diffs = if diffs {
+        osm_diff::read_diffs(ctx).context("read_diffs() failed")?
+    } else {
+        Vec::new()
+    };
     if mode == "all" || mode == "stats" {
-        update_stats(ctx, overpass).context("update_stats failed")?;
+        update_stats(ctx, overpass, &diffs).context("update_stats failed")?;
     }
     if mode == "all" || mode == "relations" {
-        update_osm_streets(ctx, relations, update)?;
-        update_osm_housenumbers(ctx, relations, update)?;
+        update_osm_streets(ctx, relations, update, &diffs)?;
+        update_osm_housenumbers(ctx, relations, update, &diffs)?;
         update_missing_streets(relations, update)?;
         update_missing_housenumbers(relations, update)?;
         update_additional_streets(ctx, relations, update)?;
@@ -505,6 +619,10 @@ pub fn our_main(
         .long("no-overpass")
         .action(clap::ArgAction::SetTrue)
         .help("when updating stats, don't perform any overpass update");
+    let diffs = clap::Arg::new("diffs") // default: false
+        .long("diffs")
+        .action(clap::ArgAction::SetTrue)
+        .help("apply replication diffs instead of overpass queries, when possible");
     let args = [
         refcounty,
         refsettlement,
@@ -512,6 +630,7 @@ pub fn our_main(
         no_update,
         mode,
         no_overpass,
+        diffs,
     ];
     let app = clap::Command::new("osm-gimmisn");
     let args = app.args(&args).try_get_matches_from(argv)?;
@@ -532,12 +651,15 @@ pub fn our_main(
     relations.limit_to_refarea(&refarea)?;
     let update = !args.get_one::<bool>("no-update").unwrap();
     let overpass = !args.get_one::<bool>("no-overpass").unwrap();
+    // Do a full update once a month, in case a diff was applied incorrectly.
+    let diffs = *args.get_one::<bool>("diffs").unwrap() && !first_day_of_month;
     our_main_inner(
         ctx,
         &mut relations,
         args.get_one("mode").unwrap(),
         update,
         overpass,
+        diffs,
     )
     .context("our_main_inner failed")?;
     let duration = ctx.get_time().now() - start;
@@ -552,7 +674,13 @@ pub fn our_main(
 
 /// Similar to plain main(), but with an interface that allows testing.
 pub fn main(argv: &[String], stream: &mut dyn Write, ctx: &context::Context) -> i32 {
-    match our_main(argv, stream, ctx) {
+    let start = ctx.get_time().now();
+    let ret = our_main(argv, stream, ctx);
+    let duration = ctx.get_time().now() - start;
+    if let Err(err) = metrics::set_cron_run(ctx, start, duration, ret.is_ok()) {
+        error!("main: set_cron_run() failed: {err:?}");
+    }
+    match ret {
         Ok(_) => 0,
         Err(err) => {
             error!("main: unhandled error: {err:?}");
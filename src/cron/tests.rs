@@ -11,64 +11,12 @@
 //! Tests for the cron module.
 
 use super::*;
+use crate::network_replay;
 use context::FileSystem;
-use std::cell::RefCell;
 use std::io::Seek;
 use std::io::SeekFrom;
-use std::rc::Rc;
-
-/// Tests overpass_sleep(): the case when no sleep is needed.
-#[test]
-fn test_overpass_sleep_no_sleep() {
-    let mut ctx = context::tests::make_test_context().unwrap();
-    let routes = vec![context::tests::URLRoute::new(
-        /*url=*/ "https://overpass-api.de/api/status",
-        /*data_path=*/ "",
-        /*result_path=*/ "src/fixtures/network/overpass-status-happy.txt",
-    )];
-    let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
-    ctx.set_network(network_rc);
-
-    overpass_sleep(&ctx);
-
-    let time = ctx
-        .get_time()
-        .as_any()
-        .downcast_ref::<context::tests::TestTime>()
-        .unwrap();
-    assert_eq!(time.get_sleep(), 0);
-}
-
-/// Tests overpass_sleep(): the case when sleep is needed.
-#[test]
-fn test_overpass_sleep_need_sleep() {
-    let mut ctx = context::tests::make_test_context().unwrap();
-    let routes = vec![
-        context::tests::URLRoute::new(
-            /*url=*/ "https://overpass-api.de/api/status",
-            /*data_path=*/ "",
-            /*result_path=*/ "src/fixtures/network/overpass-status-wait.txt",
-        ),
-        context::tests::URLRoute::new(
-            /*url=*/ "https://overpass-api.de/api/status",
-            /*data_path=*/ "",
-            /*result_path=*/ "src/fixtures/network/overpass-status-happy.txt",
-        ),
-    ];
-    let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
-    ctx.set_network(network_rc);
-
-    overpass_sleep(&ctx);
-
-    let time = ctx
-        .get_time()
-        .as_any()
-        .downcast_ref::<context::tests::TestTime>()
-        .unwrap();
-    assert_eq!(time.get_sleep(), 12);
-}
+use std::sync::Arc;
+use std::sync::Mutex;
 
 /// Tests update_missing_housenumbers().
 #[test]
@@ -96,7 +44,7 @@ fn test_update_missing_housenumbers() {
     );
     let mut file_system = context::tests::TestFileSystem::new();
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
     {
         let conn = ctx.get_database_connection().unwrap();
@@ -123,6 +71,18 @@ fn test_update_missing_housenumbers() {
     assert_eq!(actual_mtime, expected_mtime);
     let actual = relation.get_osm_housenumber_coverage().unwrap();
     assert_eq!(actual, expected);
+    // A daily snapshot is recorded as well.
+    let history = relation.get_coverage_history().unwrap();
+    assert_eq!(
+        history.housenumbers,
+        vec![areas::CoverageSnapshot {
+            date: "2020-05-10".into(),
+            todo_count: 1,
+            done_count: 0,
+            percent: "0.00".into(),
+        }]
+    );
+    assert_eq!(history.streets.is_empty(), true);
     // Make sure housenumber stat is not created for the streets=only case.
     let relation2 = relations.get_relation("myrelation2").unwrap();
     assert_eq!(relation2.has_osm_housenumber_coverage().unwrap(), false);
@@ -160,7 +120,7 @@ fn test_update_missing_streets() {
     );
     let mut file_system = context::tests::TestFileSystem::new();
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
     {
         let conn = ctx.get_database_connection().unwrap();
@@ -186,6 +146,9 @@ fn test_update_missing_streets() {
     assert_eq!(actual_mtime, expected_mtime);
     let actual = relation.get_osm_street_coverage().unwrap();
     assert_eq!(actual, expected);
+    let history = relation.get_coverage_history().unwrap();
+    assert_eq!(history.streets.len(), 1);
+    assert_eq!(history.streets[0].percent, "50.00");
     // Make sure street stat is not created for the streets=no case.
     let relation2 = relations.get_relation("ujbuda").unwrap();
     assert_eq!(relation2.has_osm_street_coverage().unwrap(), false);
@@ -224,15 +187,15 @@ fn test_update_additional_streets() {
         &[("data/yamls.cache", &yamls_cache_value)],
     );
     let path1 = ctx.get_abspath("workdir/gazdagret-additional-streets.count");
-    let mut mtimes: HashMap<String, Rc<RefCell<time::OffsetDateTime>>> = HashMap::new();
+    let mut mtimes: HashMap<String, Arc<Mutex<time::OffsetDateTime>>> = HashMap::new();
     mtimes.insert(
         path1.to_string(),
-        Rc::new(RefCell::new(time::OffsetDateTime::UNIX_EPOCH)),
+        Arc::new(Mutex::new(time::OffsetDateTime::UNIX_EPOCH)),
     );
     let mut file_system = context::tests::TestFileSystem::new();
     file_system.set_files(&files);
     file_system.set_mtimes(&mtimes);
-    let file_system_rc: Rc<dyn FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
     {
         let conn = ctx.get_database_connection().unwrap();
@@ -286,7 +249,8 @@ fn test_update_osm_housenumbers() {
     let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
     let overpass_template = context::tests::TestFileSystem::make_file();
     overpass_template
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(b"housenr aaa @RELATION@ bbb @AREA@ ccc\n")
         .unwrap();
     let files = context::tests::TestFileSystem::make_files(
@@ -314,7 +278,7 @@ fn test_update_osm_housenumbers() {
         ),
     ];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     ctx.set_network(network_rc);
     {
         let conn = ctx.get_database_connection().unwrap();
@@ -339,11 +303,23 @@ fn test_update_osm_housenumbers() {
         .unwrap()
         .len();
 
-    update_osm_housenumbers(&ctx, &mut relations, /*update=*/ true).unwrap();
+    update_osm_housenumbers(
+        &ctx,
+        &mut relations,
+        /*update=*/ true,
+        /*diffs=*/ &[],
+    )
+    .unwrap();
 
     let mtime = stats::get_sql_mtime(&ctx, "housenumbers/gazdagret").unwrap();
 
-    update_osm_housenumbers(&ctx, &mut relations, /*update=*/ false).unwrap();
+    update_osm_housenumbers(
+        &ctx,
+        &mut relations,
+        /*update=*/ false,
+        /*diffs=*/ &[],
+    )
+    .unwrap();
 
     assert_eq!(
         stats::get_sql_mtime(&ctx, "housenumbers/gazdagret").unwrap(),
@@ -376,7 +352,7 @@ fn test_update_osm_housenumbers_http_error() {
         ),
     ];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     ctx.set_network(network_rc);
     {
         let conn = ctx.get_database_connection().unwrap();
@@ -407,10 +383,16 @@ fn test_update_osm_housenumbers_http_error() {
     );
     let mut file_system = context::tests::TestFileSystem::new();
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
     let mut relations = areas::Relations::new(&ctx).unwrap();
-    update_osm_housenumbers(&ctx, &mut relations, /*update=*/ true).unwrap();
+    update_osm_housenumbers(
+        &ctx,
+        &mut relations,
+        /*update=*/ true,
+        /*diffs=*/ &[],
+    )
+    .unwrap();
     // Make sure that in case we keep getting errors we give up at some stage and
     // leave the last state unchanged.
     assert_eq!(
@@ -439,7 +421,8 @@ fn test_update_osm_housenumbers_xml_as_json() {
     let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
     let overpass_template = context::tests::TestFileSystem::make_file();
     overpass_template
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(b"housenr aaa @RELATION@ bbb @AREA@ ccc\n")
         .unwrap();
     let files = context::tests::TestFileSystem::make_files(
@@ -467,7 +450,7 @@ fn test_update_osm_housenumbers_xml_as_json() {
         ),
     ];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     ctx.set_network(network_rc);
     let mut relations = areas::Relations::new(&ctx).unwrap();
     {
@@ -478,7 +461,13 @@ fn test_update_osm_housenumbers_xml_as_json() {
         .unwrap();
     }
 
-    update_osm_housenumbers(&ctx, &mut relations, /*update=*/ true).unwrap();
+    update_osm_housenumbers(
+        &ctx,
+        &mut relations,
+        /*update=*/ true,
+        /*diffs=*/ &[],
+    )
+    .unwrap();
 
     // Wanted JSON, got XML, make sure the db is left unchanged.
     let conn = ctx.get_database_connection().unwrap();
@@ -508,7 +497,7 @@ fn test_update_osm_streets() {
         ),
     ];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     ctx.set_network(network_rc);
     let yamls_cache = serde_json::json!({
         "relations.yaml": {
@@ -520,7 +509,8 @@ fn test_update_osm_streets() {
     let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
     let template_value = context::tests::TestFileSystem::make_file();
     template_value
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(b"aaa @RELATION@ bbb @AREA@ ccc\n")
         .unwrap();
     let files = context::tests::TestFileSystem::make_files(
@@ -532,16 +522,28 @@ fn test_update_osm_streets() {
     );
     let mut file_system = context::tests::TestFileSystem::new();
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
     let mut relations = areas::Relations::new(&ctx).unwrap();
 
-    update_osm_streets(&ctx, &mut relations, /*update=*/ true).unwrap();
+    update_osm_streets(
+        &ctx,
+        &mut relations,
+        /*update=*/ true,
+        /*diffs=*/ &[],
+    )
+    .unwrap();
 
     let mtime = stats::get_sql_mtime(&ctx, "streets/gazdagret").unwrap();
     assert!(mtime > time::OffsetDateTime::UNIX_EPOCH);
 
-    update_osm_streets(&ctx, &mut relations, /*update=*/ false).unwrap();
+    update_osm_streets(
+        &ctx,
+        &mut relations,
+        /*update=*/ false,
+        /*diffs=*/ &[],
+    )
+    .unwrap();
 
     assert_eq!(
         stats::get_sql_mtime(&ctx, "streets/gazdagret").unwrap(),
@@ -560,6 +562,178 @@ fn test_update_osm_streets() {
     );
 }
 
+/// Tests update_osm_streets(), replaying a recorded run.
+#[test]
+fn test_update_osm_streets_replay() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    let disk: Arc<dyn FileSystem> = Arc::new(context::StdFileSystem {});
+    let network =
+        network_replay::ReplayNetwork::new(&disk, "src/fixtures/network/replay-streets").unwrap();
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
+    ctx.set_network(network_rc);
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "osmrelation": 42,
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let template_value = context::tests::TestFileSystem::make_file();
+    template_value
+        .lock()
+        .unwrap()
+        .write_all(b"aaa @RELATION@ bbb @AREA@ ccc\n")
+        .unwrap();
+    let files = context::tests::TestFileSystem::make_files(
+        &ctx,
+        &[
+            ("data/yamls.cache", &yamls_cache_value),
+            ("data/streets-template.overpassql", &template_value),
+        ],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    ctx.set_file_system(&file_system);
+    let mut relations = areas::Relations::new(&ctx).unwrap();
+
+    update_osm_streets(
+        &ctx,
+        &mut relations,
+        /*update=*/ true,
+        /*diffs=*/ &[],
+    )
+    .unwrap();
+
+    assert_eq!(
+        relations
+            .get_relation("gazdagret")
+            .unwrap()
+            .get_files()
+            .get_osm_json_streets(&ctx)
+            .unwrap()
+            .len(),
+        4
+    );
+}
+
+/// Tests update_osm_streets(): the case when diffs can be applied, only the changed ways and the
+/// relation boundary are queried.
+#[test]
+fn test_update_osm_streets_diffs() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    let routes = vec![
+        context::tests::URLRoute::new(
+            /*url=*/ "https://overpass-api.de/api/status",
+            /*data_path=*/ "",
+            /*result_path=*/ "src/fixtures/network/overpass-status-happy.txt",
+        ),
+        context::tests::URLRoute::new(
+            /*url=*/ "https://overpass-api.de/api/interpreter",
+            /*data_path=*/ "src/fixtures/network/overpass-ways-tuzko.overpassql",
+            /*result_path=*/ "src/fixtures/network/overpass-ways-tuzko.json",
+        ),
+        context::tests::URLRoute::new(
+            /*url=*/ "https://overpass-api.de/api/status",
+            /*data_path=*/ "",
+            /*result_path=*/ "src/fixtures/network/overpass-status-happy.txt",
+        ),
+        context::tests::URLRoute::new(
+            /*url=*/ "https://overpass-api.de/api/interpreter",
+            /*data_path=*/ "src/fixtures/network/overpass-boundary-gazdagret.overpassql",
+            /*result_path=*/ "src/fixtures/network/overpass-boundary-gazdagret.json",
+        ),
+    ];
+    let network = context::tests::TestNetwork::new(&routes);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
+    ctx.set_network(network_rc);
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "osmrelation": 2713748,
+                "refcounty": "01",
+                "refsettlement": "011",
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let overpass_template = context::tests::TestFileSystem::make_file();
+    overpass_template
+        .lock()
+        .unwrap()
+        .write_all(b"area(@AREA@)->.searchArea;\nway(area.searchArea)[highway][name];\nout;\n")
+        .unwrap();
+    let state1 = context::tests::TestFileSystem::make_file();
+    state1
+        .lock()
+        .unwrap()
+        .write_all(b"sequenceNumber=1\ntimestamp=2020-05-09T00\\:00\\:00Z\n")
+        .unwrap();
+    let osc1 = context::tests::TestFileSystem::make_file();
+    osc1.lock()
+        .unwrap()
+        .write_all(b"<osmChange version=\"0.6\"/>")
+        .unwrap();
+    let state2 = context::tests::TestFileSystem::make_file();
+    state2
+        .lock()
+        .unwrap()
+        .write_all(b"sequenceNumber=2\ntimestamp=2020-05-10T00\\:00\\:00Z\n")
+        .unwrap();
+    let osc2 = context::tests::TestFileSystem::make_file();
+    osc2.lock()
+        .unwrap()
+        .write_all(
+            r#"<osmChange version="0.6"><modify>
+<way id="1" version="2"><tag k="highway" v="residential"/><tag k="name" v="Tűzkő utca"/></way>
+</modify></osmChange>"#
+                .as_bytes(),
+        )
+        .unwrap();
+    let files = context::tests::TestFileSystem::make_files(
+        &ctx,
+        &[
+            ("data/yamls.cache", &yamls_cache_value),
+            ("data/streets-template.overpassql", &overpass_template),
+            ("workdir/replication/1.state.txt", &state1),
+            ("workdir/replication/1.osc", &osc1),
+            ("workdir/replication/2.state.txt", &state2),
+            ("workdir/replication/2.osc", &osc2),
+        ],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    ctx.set_file_system(&file_system);
+    {
+        let conn = ctx.get_database_connection().unwrap();
+        conn.execute_batch(
+            "insert into osm_streets (relation, osm_id, name, highway, service, surface, leisure, osm_type, geometry) values ('gazdagret', '1', 'Törökugrató utca', 'residential', '', '', '', 'way', '[[19.01,47.46],[19.02,47.47]]');
+             insert into mtimes (page, last_modified) values ('streets/gazdagret/osm-base', '1588982400000000000');",
+        )
+        .unwrap();
+    }
+    let mut relations = areas::Relations::new(&ctx).unwrap();
+    let diffs = osm_diff::read_diffs(&ctx).unwrap();
+
+    update_osm_streets(&ctx, &mut relations, /*update=*/ true, &diffs).unwrap();
+
+    let streets = relations
+        .get_relation("gazdagret")
+        .unwrap()
+        .get_files()
+        .get_osm_json_streets(&ctx)
+        .unwrap();
+    assert_eq!(streets.len(), 1);
+    assert_eq!(streets[0].name, "Tűzkő utca");
+    assert_eq!(
+        stats::get_sql_mtime(&ctx, "streets/gazdagret/osm-base").unwrap(),
+        diffs[1].timestamp
+    );
+    // The boundary is stored for the next run.
+    assert_eq!(
+        area_files::get_boundary(&ctx, 2713748).unwrap().is_some(),
+        true
+    );
+}
+
 /// Tests update_osm_streets(): the case when we keep getting HTTP errors.
 #[test]
 fn test_update_osm_streets_http_error() {
@@ -577,7 +751,7 @@ fn test_update_osm_streets_http_error() {
         ),
     ];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     ctx.set_network(network_rc);
     {
         let conn = ctx.get_database_connection().unwrap();
@@ -604,11 +778,17 @@ fn test_update_osm_streets_http_error() {
     );
     let mut file_system = context::tests::TestFileSystem::new();
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
     let mut relations = areas::Relations::new(&ctx).unwrap();
 
-    update_osm_streets(&ctx, &mut relations, /*update=*/ true).unwrap();
+    update_osm_streets(
+        &ctx,
+        &mut relations,
+        /*update=*/ true,
+        /*diffs=*/ &[],
+    )
+    .unwrap();
 
     // Make sure that in case we keep getting errors we give up at some stage and
     // leave the last state unchanged.
@@ -638,7 +818,8 @@ fn test_update_osm_streets_xml_as_json() {
     let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
     let template_value = context::tests::TestFileSystem::make_file();
     template_value
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(b"aaa @RELATION@ bbb @AREA@ ccc\n")
         .unwrap();
     let files = context::tests::TestFileSystem::make_files(
@@ -663,7 +844,7 @@ fn test_update_osm_streets_xml_as_json() {
         ),
     ];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     ctx.set_network(network_rc);
     let mut relations = areas::Relations::new(&ctx).unwrap();
     {
@@ -674,7 +855,13 @@ fn test_update_osm_streets_xml_as_json() {
         .unwrap();
     }
 
-    update_osm_streets(&ctx, &mut relations, /*update=*/ true).unwrap();
+    update_osm_streets(
+        &ctx,
+        &mut relations,
+        /*update=*/ true,
+        /*diffs=*/ &[],
+    )
+    .unwrap();
 
     // Wanted JSON, got XML, make sure the db is left unchanged.
     let conn = ctx.get_database_connection().unwrap();
@@ -702,14 +889,15 @@ fn test_update_stats() {
         ),
     ];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     ctx.set_network(network_rc);
 
     let ref_count = context::tests::TestFileSystem::make_file();
     let stats_json = context::tests::TestFileSystem::make_file();
     let overpass_template = context::tests::TestFileSystem::make_file();
     overpass_template
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all("first line\nsecond line\n".as_bytes())
         .unwrap();
     let files = context::tests::TestFileSystem::make_files(
@@ -718,17 +906,17 @@ fn test_update_stats() {
             ("workdir/stats/ref.count", &ref_count),
             ("workdir/stats/stats.json", &stats_json),
             (
-                "data/street-housenumbers-hungary.overpassql",
+                "data/street-housenumbers-country.overpassql",
                 &overpass_template,
             ),
         ],
     );
     let mut file_system = context::tests::TestFileSystem::new();
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
 
-    update_stats(&ctx, /*overpass=*/ true).unwrap();
+    update_stats(&ctx, /*overpass=*/ true, /*diffs=*/ &[]).unwrap();
 
     let conn = ctx.get_database_connection().unwrap();
     let last_modified: String = conn
@@ -760,12 +948,13 @@ fn test_update_stats_http_error() {
         /*result_path=*/ "src/fixtures/network/overpass-status-happy.txt",
     )];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     ctx.set_network(network_rc);
 
     let count_value = context::tests::TestFileSystem::make_file();
     count_value
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all("254651\n".as_bytes())
         .unwrap();
     let ref_count = context::tests::TestFileSystem::make_file();
@@ -777,7 +966,7 @@ fn test_update_stats_http_error() {
             ("workdir/stats/ref.count", &ref_count),
             ("workdir/stats/stats.json", &stats_json),
             (
-                "data/street-housenumbers-hungary.overpassql",
+                "data/street-housenumbers-country.overpassql",
                 &overpass_template,
             ),
         ],
@@ -785,10 +974,10 @@ fn test_update_stats_http_error() {
     let file_system = context::tests::TestFileSystem::from_files(&files);
     ctx.set_file_system(&file_system);
 
-    update_stats(&ctx, /*overpass=*/ true).unwrap();
+    update_stats(&ctx, /*overpass=*/ true, /*diffs=*/ &[]).unwrap();
 
     {
-        let mut guard = stats_json.borrow_mut();
+        let mut guard = stats_json.lock().unwrap();
         assert_eq!(guard.seek(SeekFrom::Current(0)).unwrap() > 0, true);
     }
 }
@@ -810,7 +999,7 @@ fn test_update_stats_no_overpass() {
         ),
     ];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     ctx.set_network(network_rc);
 
     let ref_count = context::tests::TestFileSystem::make_file();
@@ -822,7 +1011,7 @@ fn test_update_stats_no_overpass() {
             ("workdir/stats/ref.count", &ref_count),
             ("workdir/stats/stats.json", &stats_json),
             (
-                "data/street-housenumbers-hungary.overpassql",
+                "data/street-housenumbers-country.overpassql",
                 &overpass_template,
             ),
         ],
@@ -830,7 +1019,7 @@ fn test_update_stats_no_overpass() {
     let file_system = context::tests::TestFileSystem::from_files(&files);
     ctx.set_file_system(&file_system);
 
-    update_stats(&ctx, /*overpass=*/ false).unwrap();
+    update_stats(&ctx, /*overpass=*/ false, /*diffs=*/ &[]).unwrap();
 
     let time = ctx
         .get_time()
@@ -868,7 +1057,7 @@ fn test_our_main() {
         ),
     ];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     ctx.set_network(network_rc);
     let yamls_cache = serde_json::json!({
         "relations.yaml": {
@@ -882,12 +1071,14 @@ fn test_our_main() {
     let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
     let template_value = context::tests::TestFileSystem::make_file();
     template_value
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(b"aaa @RELATION@ bbb @AREA@ ccc\n")
         .unwrap();
     let housenr_template = context::tests::TestFileSystem::make_file();
     housenr_template
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(b"housenr aaa @RELATION@ bbb @AREA@ ccc\n")
         .unwrap();
     let files = context::tests::TestFileSystem::make_files(
@@ -903,7 +1094,7 @@ fn test_our_main() {
     );
     let mut file_system = context::tests::TestFileSystem::new();
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
     {
         let conn = ctx.get_database_connection().unwrap();
@@ -925,6 +1116,7 @@ fn test_our_main() {
         /*mode=*/ &"relations".to_string(),
         /*update=*/ true,
         /*overpass=*/ true,
+        /*diffs=*/ false,
     )
     .unwrap();
 
@@ -977,7 +1169,7 @@ fn test_our_main_stats() {
         ),
     ];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     ctx.set_network(network_rc);
     let mut file_system = context::tests::TestFileSystem::new();
     let stats_value = context::tests::TestFileSystem::make_file();
@@ -988,14 +1180,14 @@ fn test_our_main_stats() {
         &[
             ("workdir/stats/stats.json", &stats_value),
             (
-                "data/street-housenumbers-hungary.overpassql",
+                "data/street-housenumbers-country.overpassql",
                 &overpass_template,
             ),
             ("workdir/stats/ref.count", &ref_count),
         ],
     );
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
     let mut relations = areas::Relations::new(&ctx).unwrap();
 
@@ -1005,10 +1197,11 @@ fn test_our_main_stats() {
         /*mode=*/ &"stats".to_string(),
         /*update=*/ false,
         /*overpass=*/ true,
+        /*diffs=*/ false,
     )
     .unwrap();
 
-    let mut guard = stats_value.borrow_mut();
+    let mut guard = stats_value.lock().unwrap();
     assert_eq!(guard.seek(SeekFrom::Current(0)).unwrap() > 0, true);
 }
 
@@ -1025,14 +1218,14 @@ fn test_main() {
         &[
             ("workdir/stats/stats.json", &stats_value),
             (
-                "data/street-housenumbers-hungary.overpassql",
+                "data/street-housenumbers-country.overpassql",
                 &overpass_template,
             ),
             ("workdir/stats/ref.count", &ref_count),
         ],
     );
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
     let argv = vec![
         "".to_string(),
@@ -1046,7 +1239,7 @@ fn test_main() {
 
     assert_eq!(ret, 0);
     // Make sure that stats.json is updated.
-    let mut guard = stats_value.borrow_mut();
+    let mut guard = stats_value.lock().unwrap();
     assert_eq!(guard.seek(SeekFrom::Current(0)).unwrap() > 0, true);
 
     let actual = ctx
@@ -1055,6 +1248,12 @@ fn test_main() {
         .unwrap();
     // Same as in test_update_stats().
     assert_eq!(actual, "300\n");
+    // Make sure that the run is recorded.
+    let conn = ctx.get_database_connection().unwrap();
+    let success: String = conn
+        .query_row("select success from cron_runs", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(success, "1");
 }
 
 /// Tests main(): the path when our_main() returns an error.
@@ -1062,7 +1261,7 @@ fn test_main() {
 fn test_main_error() {
     let mut ctx = context::tests::make_test_context().unwrap();
     let unit = context::tests::TestUnit::new();
-    let unit_rc: Rc<dyn context::Unit> = Rc::new(unit);
+    let unit_rc: Arc<dyn context::Unit> = Arc::new(unit);
     ctx.set_unit(&unit_rc);
     let ref_count = context::tests::TestFileSystem::make_file();
     let stats_json = context::tests::TestFileSystem::make_file();
@@ -1078,7 +1277,7 @@ fn test_main_error() {
     file_system
         .write_from_string("300", &ctx.get_abspath("workdir/stats/ref.count"))
         .unwrap();
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
     let argv = vec![
         "".to_string(),
@@ -1092,6 +1291,11 @@ fn test_main_error() {
     let ret = main(&argv, &mut buf, &mut ctx);
 
     assert_eq!(ret, 1);
+    let conn = ctx.get_database_connection().unwrap();
+    let success: String = conn
+        .query_row("select success from cron_runs", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(success, "0");
 }
 
 /// Tests update_stats_count().
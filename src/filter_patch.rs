@@ -0,0 +1,404 @@
+/*
+ * Copyright 2024 Miklos Vajna
+ *
+ * SPDX-License-Identifier: MIT
+ */
+
+#![deny(warnings)]
+#![warn(clippy::all)]
+#![warn(missing_docs)]
+
+//! The filter_patch module turns the lints of a relation into a unified diff against its
+//! relation-<name>.yaml, dropping unused invalid items and widening ranges.
+
+use crate::areas;
+use crate::cache;
+use crate::context;
+use anyhow::Context as _;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::io::Write;
+
+/// Number of unchanged lines around a change in the diff.
+const CONTEXT_LINES: usize = 3;
+
+lazy_static::lazy_static! {
+    static ref STREET_KEY: regex::Regex =
+        regex::Regex::new(r##"^  (?:'((?:[^']|'')*)'|"([^"]*)"|([^'"#][^:#]*)):\s*(#.*)?$"##).unwrap();
+    static ref INVALID: regex::Regex =
+        regex::Regex::new(r"^(\s+invalid:\s*)\[(.*)\](\s*#.*)?$").unwrap();
+    static ref INVALID_NUMBER: regex::Regex =
+        regex::Regex::new(r#"number:\s*['"]?([^'",}]*)"#).unwrap();
+    static ref RANGE: regex::Regex =
+        regex::Regex::new(r"^\s+- \{.*start:\s*'(\d+)'.*end:\s*'(\d+)'.*\}").unwrap();
+    static ref INTERPOLATION_ALL: regex::Regex =
+        regex::Regex::new(r"^\s+interpolation:\s*'?all'?\s*(#.*)?$").unwrap();
+}
+
+/// What to do with one line of the relation file.
+#[derive(Clone, Debug, PartialEq)]
+enum Edit {
+    Keep,
+    Delete,
+    Replace(String),
+}
+
+/// The actionable lints of one street.
+#[derive(Default)]
+struct StreetLints {
+    /// Invalid items which are no longer needed.
+    unused_invalids: HashSet<String>,
+    /// House numbers which exist in OSM, but are outside the ranges.
+    out_of_range: Vec<i64>,
+}
+
+/// Gets the actionable lints of a relation, per street.
+fn get_street_lints(
+    ctx: &context::Context,
+    relation_name: &str,
+) -> anyhow::Result<HashMap<String, StreetLints>> {
+    let mut ret: HashMap<String, StreetLints> = HashMap::new();
+    let conn = ctx.get_database_connection()?;
+    let mut stmt = conn.prepare(
+        "select street_name, source, housenumber, reason from relation_lints where relation_name = ?1",
+    )?;
+    let mut rows = stmt.query([relation_name])?;
+    while let Some(row) = rows.next()? {
+        let street_name: String = row.get(0)?;
+        let source: areas::RelationLintSource = row.get(1)?;
+        let housenumber: String = row.get(2)?;
+        let reason: areas::RelationLintReason = row.get(3)?;
+        match (source, reason) {
+            (
+                areas::RelationLintSource::Invalid,
+                areas::RelationLintReason::DeletedFromRef | areas::RelationLintReason::OutOfRange,
+            ) => {
+                let lints = ret.entry(street_name).or_default();
+                lints.unused_invalids.insert(housenumber);
+            }
+            (areas::RelationLintSource::Range, areas::RelationLintReason::CreatedInOsm) => {
+                if let Ok(number) = housenumber.parse::<i64>() {
+                    let lints = ret.entry(street_name).or_default();
+                    lints.out_of_range.push(number);
+                }
+            }
+            // An invalid item which is present in OSM needs a survey, not a patch.
+            _ => (),
+        }
+    }
+    Ok(ret)
+}
+
+/// Parses the street name from a key line of the filters map.
+fn parse_street_key(line: &str) -> Option<String> {
+    let captures = STREET_KEY.captures(line)?;
+    if let Some(value) = captures.get(1) {
+        return Some(value.as_str().replace("''", "'"));
+    }
+    if let Some(value) = captures.get(2) {
+        return Some(value.as_str().to_string());
+    }
+    captures
+        .get(3)
+        .map(|value| value.as_str().trim().to_string())
+}
+
+/// Splits the items of a flow sequence, ignoring commas inside structured items.
+fn split_invalid_items(items: &str) -> Vec<&str> {
+    let mut ret: Vec<&str> = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (index, ch) in items.char_indices() {
+        match ch {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                ret.push(&items[start..index]);
+                start = index + 1;
+            }
+            _ => (),
+        }
+    }
+    ret.push(&items[start..]);
+    ret.into_iter()
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Gets the house number of an invalid item, which is either a string or a structured item.
+fn get_invalid_item_number(item: &str) -> &str {
+    if item.starts_with('{') {
+        if let Some(captures) = INVALID_NUMBER.captures(item) {
+            return captures.get(1).unwrap().as_str().trim();
+        }
+    }
+    item.trim_matches(|c| c == '\'' || c == '"')
+}
+
+/// Removes the unused items from an 'invalid: [...]' line. Returns None if the line is not
+/// changed and Some(None) if the list became empty.
+fn patch_invalid_line(line: &str, unused_invalids: &HashSet<String>) -> Option<Option<String>> {
+    let captures = INVALID.captures(line)?;
+    let items = split_invalid_items(&captures[2]);
+    let kept: Vec<&str> = items
+        .iter()
+        .filter(|item| !unused_invalids.contains(get_invalid_item_number(item)))
+        .cloned()
+        .collect();
+    if kept.len() == items.len() {
+        return None;
+    }
+    if kept.is_empty() {
+        return Some(None);
+    }
+    let comment = captures.get(3).map(|i| i.as_str()).unwrap_or_default();
+    Some(Some(format!(
+        "{}[{}]{}",
+        &captures[1],
+        kept.join(", "),
+        comment
+    )))
+}
+
+/// Widens the ranges of one street, so they cover the provided house numbers. Each house number
+/// extends the closest range with the same parity, unless interpolation is 'all'.
+fn patch_ranges(lines: &[&str], block: &[usize], out_of_range: &[i64], edits: &mut [Edit]) {
+    let all = block
+        .iter()
+        .any(|index| INTERPOLATION_ALL.is_match(lines[*index]));
+    // Line index, start, end.
+    let mut ranges: Vec<(usize, i64, i64)> = Vec::new();
+    for index in block {
+        if let Some(captures) = RANGE.captures(lines[*index]) {
+            if let (Ok(start), Ok(end)) = (captures[1].parse::<i64>(), captures[2].parse::<i64>()) {
+                ranges.push((*index, start, end));
+            }
+        }
+    }
+
+    let mut widened: HashMap<usize, (i64, i64)> = HashMap::new();
+    for number in out_of_range {
+        let closest = ranges
+            .iter()
+            .filter(|(_, start, _)| all || start % 2 == number % 2)
+            .min_by_key(|(_, start, end)| {
+                if number < start {
+                    start - number
+                } else {
+                    (number - end).max(0)
+                }
+            });
+        if let Some((index, start, end)) = closest {
+            let entry = widened.entry(*index).or_insert((*start, *end));
+            entry.0 = entry.0.min(*number);
+            entry.1 = entry.1.max(*number);
+        }
+    }
+
+    for (index, start, end) in ranges {
+        if let Some((new_start, new_end)) = widened.get(&index) {
+            if (*new_start, *new_end) == (start, end) {
+                continue;
+            }
+            let line = lines[index]
+                .replacen(
+                    &format!("start: '{start}'"),
+                    &format!("start: '{new_start}'"),
+                    1,
+                )
+                .replacen(&format!("end: '{end}'"), &format!("end: '{new_end}'"), 1);
+            edits[index] = Edit::Replace(line);
+        }
+    }
+}
+
+/// Decides what to do with each line of a relation file, based on the lints.
+fn get_edits(lines: &[&str], street_lints: &HashMap<String, StreetLints>) -> Vec<Edit> {
+    let mut edits = vec![Edit::Keep; lines.len()];
+    let filters_start = match lines.iter().position(|line| line.starts_with("filters:")) {
+        Some(value) => value,
+        None => return edits,
+    };
+
+    // Street name, key line index, block line indexes.
+    let mut streets: Vec<(String, usize, Vec<usize>)> = Vec::new();
+    for (index, line) in lines.iter().enumerate().skip(filters_start + 1) {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            if let Some(street) = streets.last_mut() {
+                street.2.push(index);
+            }
+            continue;
+        }
+        let indent = line.len() - trimmed.len();
+        if indent == 0 {
+            // End of the filters map.
+            break;
+        }
+        if indent == 2 {
+            if let Some(street_name) = parse_street_key(line) {
+                streets.push((street_name, index, Vec::new()));
+            }
+            continue;
+        }
+        if let Some(street) = streets.last_mut() {
+            street.2.push(index);
+        }
+    }
+
+    for (street_name, key_index, block) in streets {
+        let lints = match street_lints.get(&street_name) {
+            Some(value) => value,
+            None => continue,
+        };
+        if !lints.unused_invalids.is_empty() {
+            for index in &block {
+                if let Some(edit) = patch_invalid_line(lines[*index], &lints.unused_invalids) {
+                    edits[*index] = match edit {
+                        Some(line) => Edit::Replace(line),
+                        None => Edit::Delete,
+                    };
+                }
+            }
+        }
+        if !lints.out_of_range.is_empty() {
+            patch_ranges(lines, &block, &lints.out_of_range, &mut edits);
+        }
+        // Don't leave a street without filters behind.
+        let has_content = block.iter().any(|index| {
+            let trimmed = lines[*index].trim_start();
+            !trimmed.is_empty() && !trimmed.starts_with('#') && edits[*index] != Edit::Delete
+        });
+        if !has_content {
+            edits[key_index] = Edit::Delete;
+        }
+    }
+    edits
+}
+
+/// Formats the edits of a file as a unified diff.
+fn format_unified_diff(path: &str, lines: &[&str], edits: &[Edit]) -> String {
+    let changed: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| **edit != Edit::Keep)
+        .map(|(index, _)| index)
+        .collect();
+    if changed.is_empty() {
+        return String::new();
+    }
+
+    // Old line index ranges, with context, overlapping ones merged.
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for index in changed {
+        let start = index.saturating_sub(CONTEXT_LINES);
+        let end = (index + CONTEXT_LINES + 1).min(lines.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut ret = format!("--- a/{path}\n+++ b/{path}\n");
+    // Number of new lines minus number of old lines before the current hunk.
+    let mut offset: i64 = 0;
+    let mut previous_end = 0;
+    for (start, end) in hunks {
+        for edit in &edits[previous_end..start] {
+            if *edit == Edit::Delete {
+                offset -= 1;
+            }
+        }
+        let mut body = String::new();
+        let mut new_count: i64 = 0;
+        for index in start..end {
+            match &edits[index] {
+                Edit::Keep => {
+                    body.push_str(&format!(" {}\n", lines[index]));
+                    new_count += 1;
+                }
+                Edit::Delete => {
+                    body.push_str(&format!("-{}\n", lines[index]));
+                }
+                Edit::Replace(line) => {
+                    body.push_str(&format!("-{}\n+{}\n", lines[index], line));
+                    new_count += 1;
+                }
+            }
+        }
+        let old_count = (end - start) as i64;
+        let mut new_start = start as i64 + 1 + offset;
+        if new_count == 0 {
+            new_start -= 1;
+        }
+        ret.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            start + 1,
+            old_count,
+            new_start,
+            new_count
+        ));
+        ret.push_str(&body);
+        offset += new_count - old_count;
+        previous_end = end;
+    }
+    ret
+}
+
+/// Produces a unified diff against data/relation-<name>.yaml, which drops the unused invalid
+/// items and widens the ranges to cover house numbers which are in OSM. Returns an empty string
+/// if there is nothing to fix.
+pub fn get_filter_patch(
+    ctx: &context::Context,
+    relation: &mut areas::Relation<'_>,
+) -> anyhow::Result<String> {
+    // Update lints if they are outdated.
+    cache::get_missing_housenumbers_json(relation)
+        .context("get_missing_housenumbers_json() failed")?;
+    let relation_name = relation.get_name();
+    let street_lints = get_street_lints(ctx, &relation_name)?;
+
+    let path = format!("data/relation-{relation_name}.yaml");
+    let contents = ctx
+        .get_file_system()
+        .read_to_string(&ctx.get_abspath(&path))
+        .context(format!("failed to read '{path}'"))?;
+    let lines: Vec<&str> = contents.lines().collect();
+    let edits = get_edits(&lines, &street_lints);
+    Ok(format_unified_diff(&path, &lines, &edits))
+}
+
+/// Inner main() that is allowed to fail.
+pub fn our_main(
+    argv: &[String],
+    stream: &mut dyn Write,
+    ctx: &context::Context,
+) -> anyhow::Result<()> {
+    let relation = clap::Arg::new("relation")
+        .required(true)
+        .help("relation name");
+    let app =
+        clap::Command::new("osm-gimmisn").override_usage("osm-gimmisn filter-patch <RELATION>");
+    let args = app.arg(relation).try_get_matches_from(argv)?;
+    let relation_name = args.get_one::<String>("relation").unwrap();
+
+    let mut relations = areas::Relations::new(ctx)?;
+    let mut relation = relations.get_relation(relation_name)?;
+    stream.write_all(get_filter_patch(ctx, &mut relation)?.as_bytes())?;
+    Ok(())
+}
+
+/// Similar to plain main(), but with an interface that allows testing.
+pub fn main(args: &[String], stream: &mut dyn Write, ctx: &context::Context) -> i32 {
+    match our_main(args, stream, ctx) {
+        Ok(_) => 0,
+        Err(err) => {
+            stream.write_all(format!("{err:?}\n").as_bytes()).unwrap();
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
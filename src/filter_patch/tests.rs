@@ -0,0 +1,247 @@
+/*
+ * Copyright 2024 Miklos Vajna
+ *
+ * SPDX-License-Identifier: MIT
+ */
+
+#![deny(warnings)]
+#![warn(clippy::all)]
+#![warn(missing_docs)]
+
+//! Tests for the filter_patch module.
+
+use super::*;
+
+/// The relation file of make_context(), with comments.
+const RELATION_YAML: &str = r#"# Comment at the top.
+filters:
+  'Törökugrató utca':
+    invalid: ['1', '11', '12', '42']  # 42 is a school
+  'Tűzkő utca':
+    ranges:
+      - {start: '1', end: '9'}
+      - {start: '2', end: '8'}  # even side
+source: survey
+"#;
+
+/// Creates a context where Törökugrató utca has an invalid item deleted from the reference and
+/// Tűzkő utca has an even house number above its ranges.
+fn make_context() -> context::Context {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "refcounty": "0",
+                "refsettlement": "0",
+                "osmrelation": 42,
+            },
+        },
+        "relation-gazdagret.yaml": {
+            "filters": {
+                "Törökugrató utca": {
+                    "invalid": [ "1", "11", "12", "42" ],
+                },
+                "Tűzkő utca": {
+                    "ranges": [
+                        {
+                            "start": "1",
+                            "end": "9",
+                        },
+                        {
+                            "start": "2",
+                            "end": "8",
+                        }
+                    ],
+                },
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let relation_yaml = context::tests::TestFileSystem::make_file();
+    relation_yaml
+        .lock()
+        .unwrap()
+        .write_all(RELATION_YAML.as_bytes())
+        .unwrap();
+    let files = context::tests::TestFileSystem::make_files(
+        &ctx,
+        &[
+            ("data/yamls.cache", &yamls_cache_value),
+            ("data/relation-gazdagret.yaml", &relation_yaml),
+        ],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    ctx.set_file_system(&file_system);
+    let mtime = ctx.get_time().now_string();
+    {
+        let conn = ctx.get_database_connection().unwrap();
+        conn.execute_batch(
+            "insert into ref_housenumbers (county_code, settlement_code, street, housenumber, comment) values ('0', '0', 'Törökugrató utca', '1', '');
+             insert into ref_housenumbers (county_code, settlement_code, street, housenumber, comment) values ('0', '0', 'Törökugrató utca', '11', '');
+             insert into ref_housenumbers (county_code, settlement_code, street, housenumber, comment) values ('0', '0', 'Törökugrató utca', '12', '');
+             insert into ref_housenumbers (county_code, settlement_code, street, housenumber, comment) values ('0', '0', 'Tűzkő utca', '1', '');
+             insert into ref_housenumbers (county_code, settlement_code, street, housenumber, comment) values ('0', '0', 'Tűzkő utca', '2', '');
+             insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values ('gazdagret', '1', 'Törökugrató utca', '1', '', '', '', '', '', '', '', '', '', 'node');
+             insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values ('gazdagret', '2', 'Tűzkő utca', '9', '', '', '', '', '', '', '', '', '', 'node');
+             insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values ('gazdagret', '3', 'Tűzkő utca', '10', '', '', '', '', '', '', '', '', '', 'node');",
+        )
+        .unwrap();
+        conn.execute(
+            "insert into mtimes (page, last_modified) values (?1, ?2)",
+            ["housenumbers/gazdagret", &mtime],
+        )
+        .unwrap();
+    }
+    ctx
+}
+
+/// Tests get_filter_patch(): an unused invalid item is dropped, a range is widened, comments are
+/// kept.
+#[test]
+fn test_get_filter_patch() {
+    let ctx = make_context();
+    let mut relations = areas::Relations::new(&ctx).unwrap();
+    let mut relation = relations.get_relation("gazdagret").unwrap();
+
+    let patch = get_filter_patch(&ctx, &mut relation).unwrap();
+
+    // '1' is invalid, but present in OSM: that needs a survey, so it's kept.
+    assert_eq!(
+        patch,
+        r#"--- a/data/relation-gazdagret.yaml
++++ b/data/relation-gazdagret.yaml
+@@ -1,9 +1,9 @@
+ # Comment at the top.
+ filters:
+   'Törökugrató utca':
+-    invalid: ['1', '11', '12', '42']  # 42 is a school
++    invalid: ['1', '11', '12']  # 42 is a school
+   'Tűzkő utca':
+     ranges:
+       - {start: '1', end: '9'}
+-      - {start: '2', end: '8'}  # even side
++      - {start: '2', end: '10'}  # even side
+ source: survey
+"#
+    );
+}
+
+/// Tests get_edits(): a street which has no filters left is removed, and the hunks are separate.
+#[test]
+fn test_get_edits_remove_street() {
+    let contents = r#"filters:
+  Hamzsabégi út:
+    invalid: ['42']
+  'Törökugrató utca':
+    invalid: ['1']
+  'Tűzkő utca':
+    invalid: ['2']
+  'Ref Name 1':
+    invalid: ['3']
+  'OSM Name 1':
+    invalid: ['4']
+  'Only In OSM utca':
+    invalid: ['5', '6']
+"#;
+    let lines: Vec<&str> = contents.lines().collect();
+    let mut street_lints: HashMap<String, StreetLints> = HashMap::new();
+    street_lints
+        .entry("Hamzsabégi út".into())
+        .or_default()
+        .unused_invalids
+        .insert("42".into());
+    street_lints
+        .entry("Only In OSM utca".into())
+        .or_default()
+        .unused_invalids
+        .insert("6".into());
+
+    let edits = get_edits(&lines, &street_lints);
+
+    assert_eq!(
+        format_unified_diff("data/relation-gazdagret.yaml", &lines, &edits),
+        r#"--- a/data/relation-gazdagret.yaml
++++ b/data/relation-gazdagret.yaml
+@@ -1,6 +1,4 @@
+ filters:
+-  Hamzsabégi út:
+-    invalid: ['42']
+   'Törökugrató utca':
+     invalid: ['1']
+   'Tűzkő utca':
+@@ -10,4 +8,4 @@
+   'OSM Name 1':
+     invalid: ['4']
+   'Only In OSM utca':
+-    invalid: ['5', '6']
++    invalid: ['5']
+"#
+    );
+}
+
+/// Tests patch_invalid_line(): structured items are kept together and matched by their number.
+#[test]
+fn test_patch_invalid_line_structured() {
+    let line =
+        "    invalid: ['1', {number: '11', reason: 'a, b', resurvey-after: '2024-09-30'}, '12']";
+    let mut unused_invalids: HashSet<String> = HashSet::new();
+    unused_invalids.insert("12".into());
+
+    assert_eq!(
+        patch_invalid_line(line, &unused_invalids),
+        Some(Some(
+            "    invalid: ['1', {number: '11', reason: 'a, b', resurvey-after: '2024-09-30'}]"
+                .into()
+        ))
+    );
+
+    unused_invalids.insert("11".into());
+    assert_eq!(
+        patch_invalid_line(line, &unused_invalids),
+        Some(Some("    invalid: ['1']".into()))
+    );
+}
+
+/// Tests main(): the patch is written to the output.
+#[test]
+fn test_main() {
+    let ctx = make_context();
+    let argv: Vec<String> = vec!["".into(), "gazdagret".into()];
+    let mut buf: std::io::Cursor<Vec<u8>> = std::io::Cursor::new(Vec::new());
+
+    let ret = main(&argv, &mut buf, &ctx);
+
+    assert_eq!(ret, 0);
+    let output = String::from_utf8(buf.into_inner()).unwrap();
+    assert!(output.contains("+      - {start: '2', end: '10'}  # even side\n"));
+}
+
+/// Tests main(): the relation file is missing.
+#[test]
+fn test_main_no_relation_file() {
+    let mut ctx = make_context();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "refcounty": "0",
+                "refsettlement": "0",
+                "osmrelation": 42,
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        &ctx,
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    ctx.set_file_system(&file_system);
+    let argv: Vec<String> = vec!["".into(), "gazdagret".into()];
+    let mut buf: std::io::Cursor<Vec<u8>> = std::io::Cursor::new(Vec::new());
+
+    let ret = main(&argv, &mut buf, &ctx);
+
+    assert_eq!(ret, 1);
+    let output = String::from_utf8(buf.into_inner()).unwrap();
+    assert!(output.starts_with("failed to read 'data/relation-gazdagret.yaml'"));
+}
@@ -0,0 +1,270 @@
+/*
+ * Copyright 2024 Miklos Vajna
+ *
+ * SPDX-License-Identifier: MIT
+ */
+
+#![deny(warnings)]
+#![warn(clippy::all)]
+#![warn(missing_docs)]
+
+//! The jobs module runs the Overpass updates of relations in the background, outside requests.
+
+use crate::areas;
+use crate::context;
+use crate::overpass_query;
+
+#[cfg(not(test))]
+use log::{error, info};
+
+#[cfg(test)]
+use std::{println as info, println as error};
+
+/// Finished jobs are deleted after this long, in seconds. Clients only poll the state of a job
+/// until it finishes.
+const JOB_RETENTION: i64 = 24 * 3600;
+
+/// What a job updates.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobKind {
+    /// The OSM streets of a relation.
+    Streets,
+    /// The OSM house numbers of a relation.
+    Housenumbers,
+}
+
+impl TryFrom<&str> for JobKind {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "streets" => Ok(JobKind::Streets),
+            "housenumbers" => Ok(JobKind::Housenumbers),
+            _ => Err(anyhow::anyhow!("invalid value: {value}")),
+        }
+    }
+}
+
+impl rusqlite::types::FromSql for JobKind {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        let i = String::column_result(value)?;
+        i.as_str()
+            .try_into()
+            .map_err(|_| rusqlite::types::FromSqlError::InvalidType)
+    }
+}
+
+impl std::fmt::Display for JobKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobKind::Streets => write!(f, "streets"),
+            JobKind::Housenumbers => write!(f, "housenumbers"),
+        }
+    }
+}
+
+/// The state of a job.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobState {
+    /// Waiting for the worker.
+    Queued,
+    /// The worker is running it.
+    Running,
+    /// Finished successfully.
+    Done,
+    /// Finished with an error.
+    Failed,
+}
+
+impl TryFrom<&str> for JobState {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "queued" => Ok(JobState::Queued),
+            "running" => Ok(JobState::Running),
+            "done" => Ok(JobState::Done),
+            "failed" => Ok(JobState::Failed),
+            _ => Err(anyhow::anyhow!("invalid value: {value}")),
+        }
+    }
+}
+
+impl rusqlite::types::FromSql for JobState {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        let i = String::column_result(value)?;
+        i.as_str()
+            .try_into()
+            .map_err(|_| rusqlite::types::FromSqlError::InvalidType)
+    }
+}
+
+impl std::fmt::Display for JobState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobState::Queued => write!(f, "queued"),
+            JobState::Running => write!(f, "running"),
+            JobState::Done => write!(f, "done"),
+            JobState::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// One row in the `jobs` SQL table.
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Job {
+    /// Identifies the job in the status URL.
+    pub id: i64,
+    /// What the job updates.
+    pub kind: JobKind,
+    /// The name of the relation to update.
+    pub relation: String,
+    /// The current state.
+    pub state: JobState,
+    /// The error message of a failed job, empty otherwise.
+    pub error: String,
+}
+
+/// Enqueues an update job for a relation and returns its id. If the same update is already queued
+/// or running, then that job is returned instead of creating a new one.
+pub fn enqueue(ctx: &context::Context, kind: &JobKind, relation: &str) -> anyhow::Result<i64> {
+    let conn = ctx.get_database_connection()?;
+    loop {
+        let now = ctx.get_time().now_string();
+        let inserted = conn.execute(
+            "insert into jobs (kind, relation, state, error, created, updated) values (?1, ?2, ?3, '', ?4, ?4)
+             on conflict do nothing",
+            [&kind.to_string(), relation, &JobState::Queued.to_string(), &now],
+        )?;
+        if inserted > 0 {
+            return Ok(conn.last_insert_rowid());
+        }
+
+        let mut stmt = conn.prepare(
+            "select id from jobs where kind = ?1 and relation = ?2 and state in ('queued', 'running')",
+        )?;
+        let mut rows = stmt.query([&kind.to_string(), relation])?;
+        if let Some(row) = rows.next()? {
+            return Ok(row.get(0)?);
+        }
+        // The conflicting job finished in the meantime, try again.
+    }
+}
+
+/// Gets a job by its id.
+pub fn get_job(ctx: &context::Context, id: i64) -> anyhow::Result<Option<Job>> {
+    let conn = ctx.get_database_connection()?;
+    let mut stmt =
+        conn.prepare("select id, kind, relation, state, error from jobs where id = ?1")?;
+    let mut rows = stmt.query([id])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(Job {
+            id: row.get(0)?,
+            kind: row.get(1)?,
+            relation: row.get(2)?,
+            state: row.get(3)?,
+            error: row.get(4)?,
+        })),
+        None => Ok(None),
+    }
+}
+
+fn set_state(ctx: &context::Context, id: i64, state: &JobState, error: &str) -> anyhow::Result<()> {
+    let conn = ctx.get_database_connection()?;
+    conn.execute(
+        "update jobs set state = ?1, error = ?2, updated = ?3 where id = ?4",
+        rusqlite::params![state.to_string(), error, ctx.get_time().now_string(), id],
+    )?;
+    Ok(())
+}
+
+/// Queries Overpass for a relation and stores the result. A user waits for the result, so
+/// temporary failures only fail over to the next endpoint, they are not retried like in cron.
+fn run_job(ctx: &context::Context, kind: &JobKind, relation_name: &str) -> anyhow::Result<()> {
+    let mut relations = areas::Relations::new(ctx)?;
+    let relation = relations.get_relation(relation_name)?;
+    match kind {
+        JobKind::Streets => {
+            let query = relation.get_osm_streets_json_query()?;
+            let buf = overpass_query::overpass_query(ctx, &query)?;
+            relation.get_files().write_osm_json_streets(ctx, &buf)
+        }
+        JobKind::Housenumbers => {
+            let query = relation.get_osm_housenumbers_json_query()?;
+            let buf = overpass_query::overpass_query(ctx, &query)?;
+            relation.get_files().write_osm_json_housenumbers(ctx, &buf)
+        }
+    }
+}
+
+/// Deletes the jobs which finished more than JOB_RETENTION ago.
+fn prune(ctx: &context::Context) -> anyhow::Result<()> {
+    let cutoff = ctx.get_time().now() - time::Duration::seconds(JOB_RETENTION);
+    let cutoff = i64::try_from(cutoff.unix_timestamp_nanos())?;
+    let conn = ctx.get_database_connection()?;
+    conn.execute(
+        "delete from jobs where state in ('done', 'failed') and cast(updated as integer) < ?1",
+        [cutoff],
+    )?;
+    Ok(())
+}
+
+/// Runs the oldest queued job. Returns false if there was nothing to run.
+pub fn run_next(ctx: &context::Context) -> anyhow::Result<bool> {
+    prune(ctx)?;
+    let job: Option<(i64, JobKind, String)> = {
+        let conn = ctx.get_database_connection()?;
+        let mut stmt = conn.prepare(
+            "select id, kind, relation from jobs where state = 'queued' order by id limit 1",
+        )?;
+        let mut rows = stmt.query([])?;
+        match rows.next()? {
+            Some(row) => Some((row.get(0)?, row.get(1)?, row.get(2)?)),
+            None => None,
+        }
+    };
+    let (id, kind, relation) = match job {
+        Some(value) => value,
+        None => {
+            return Ok(false);
+        }
+    };
+
+    info!("jobs: running job {id}, updating the {kind} of {relation}");
+    set_state(ctx, id, &JobState::Running, "")?;
+    match run_job(ctx, &kind, &relation) {
+        Ok(_) => set_state(ctx, id, &JobState::Done, "")?,
+        Err(err) => set_state(ctx, id, &JobState::Failed, &err.to_string())?,
+    }
+    Ok(true)
+}
+
+/// Runs all queued jobs.
+pub fn run_queued(ctx: &context::Context) -> anyhow::Result<()> {
+    while run_next(ctx)? {}
+    Ok(())
+}
+
+/// Runs queued jobs forever, checking for new ones every second.
+pub fn run_worker(ctx: &context::Context) -> anyhow::Result<()> {
+    // Jobs which were running when the previous worker stopped will never finish: run them again.
+    {
+        let conn = ctx.get_database_connection()?;
+        conn.execute(
+            "update jobs set state = 'queued' where state = 'running'",
+            [],
+        )?;
+    }
+    loop {
+        if let Err(err) = run_queued(ctx) {
+            error!("jobs: run_queued() failed: {err:?}");
+        }
+        ctx.get_time().sleep(1);
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,156 @@
+/*
+ * Copyright 2024 Miklos Vajna
+ *
+ * SPDX-License-Identifier: MIT
+ */
+
+#![deny(warnings)]
+#![warn(clippy::all)]
+#![warn(missing_docs)]
+
+//! Tests for the jobs module.
+
+use super::*;
+use std::io::Write as _;
+use std::sync::Arc;
+
+/// Creates a test context with a single relation and a streets template.
+fn make_test_context(result_path: &str) -> context::Context {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    let routes = vec![context::tests::URLRoute::new(
+        /*url=*/ "https://overpass-api.de/api/interpreter",
+        /*data_path=*/ "",
+        /*result_path=*/ result_path,
+    )];
+    let network = context::tests::TestNetwork::new(&routes);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
+    ctx.set_network(network_rc);
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "osmrelation": 42,
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let template_value = context::tests::TestFileSystem::make_file();
+    template_value
+        .lock()
+        .unwrap()
+        .write_all(b"aaa @RELATION@ bbb @AREA@ ccc\n")
+        .unwrap();
+    let files = context::tests::TestFileSystem::make_files(
+        &ctx,
+        &[
+            ("data/yamls.cache", &yamls_cache_value),
+            ("data/streets-template.overpassql", &template_value),
+        ],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    ctx.set_file_system(&file_system);
+    ctx
+}
+
+/// Tests enqueue(): the same queued update is not added twice.
+#[test]
+fn test_enqueue_coalesce() {
+    let ctx = make_test_context("");
+
+    let first = enqueue(&ctx, &JobKind::Streets, "gazdagret").unwrap();
+    let second = enqueue(&ctx, &JobKind::Streets, "gazdagret").unwrap();
+    let third = enqueue(&ctx, &JobKind::Housenumbers, "gazdagret").unwrap();
+
+    assert_eq!(first, second);
+    assert_ne!(first, third);
+    let job = get_job(&ctx, first).unwrap().unwrap();
+    assert_eq!(job.kind, JobKind::Streets);
+    assert_eq!(job.relation, "gazdagret");
+    assert_eq!(job.state, JobState::Queued);
+    assert_eq!(get_job(&ctx, 42).unwrap().is_none(), true);
+}
+
+/// Tests run_next(): the happy path.
+#[test]
+fn test_run_next() {
+    let ctx = make_test_context("src/fixtures/network/overpass-streets-gazdagret.json");
+    let id = enqueue(&ctx, &JobKind::Streets, "gazdagret").unwrap();
+
+    assert_eq!(run_next(&ctx).unwrap(), true);
+
+    let job = get_job(&ctx, id).unwrap().unwrap();
+    assert_eq!(job.state, JobState::Done);
+    assert_eq!(job.error, "");
+    // Nothing left to run.
+    assert_eq!(run_next(&ctx).unwrap(), false);
+    // A finished job is not reused.
+    assert_ne!(enqueue(&ctx, &JobKind::Streets, "gazdagret").unwrap(), id);
+}
+
+/// Tests run_next(): the Overpass error is recorded in the job.
+#[test]
+fn test_run_next_error() {
+    let ctx = make_test_context("");
+    let id = enqueue(&ctx, &JobKind::Streets, "gazdagret").unwrap();
+
+    run_queued(&ctx).unwrap();
+
+    let job = get_job(&ctx, id).unwrap().unwrap();
+    assert_eq!(job.state, JobState::Failed);
+    assert_eq!(job.error.is_empty(), false);
+    // The user is waiting: no retry after a wait.
+    let time = ctx
+        .get_time()
+        .as_any()
+        .downcast_ref::<context::tests::TestTime>()
+        .unwrap();
+    assert_eq!(time.get_sleep(), 0);
+}
+
+/// Tests run_next(): old finished jobs are deleted.
+#[test]
+fn test_run_next_prune() {
+    let ctx = make_test_context("");
+    let now = ctx.get_time().now();
+    let old = (now - time::Duration::days(2))
+        .unix_timestamp_nanos()
+        .to_string();
+    {
+        let conn = ctx.get_database_connection().unwrap();
+        conn.execute(
+            "insert into jobs (kind, relation, state, error, created, updated) values ('streets', 'gazdagret', 'done', '', ?1, ?1)",
+            [&old],
+        )
+        .unwrap();
+        conn.execute(
+            "insert into jobs (kind, relation, state, error, created, updated) values ('streets', 'gazdagret', 'failed', 'timeout', ?1, ?1)",
+            [&now.unix_timestamp_nanos().to_string()],
+        )
+        .unwrap();
+    }
+
+    assert_eq!(run_next(&ctx).unwrap(), false);
+
+    assert_eq!(get_job(&ctx, 1).unwrap().is_none(), true);
+    assert_eq!(get_job(&ctx, 2).unwrap().unwrap().state, JobState::Failed);
+}
+
+/// Tests the TryFrom and Display impls of JobKind and JobState.
+#[test]
+fn test_job_kind_state_strings() {
+    for kind in [JobKind::Streets, JobKind::Housenumbers] {
+        assert_eq!(JobKind::try_from(kind.to_string().as_str()).unwrap(), kind);
+    }
+    for state in [
+        JobState::Queued,
+        JobState::Running,
+        JobState::Done,
+        JobState::Failed,
+    ] {
+        assert_eq!(
+            JobState::try_from(state.to_string().as_str()).unwrap(),
+            state
+        );
+    }
+    assert_eq!(JobKind::try_from("foo").is_err(), true);
+    assert_eq!(JobState::try_from("foo").is_err(), true);
+}
@@ -17,18 +17,30 @@ mod cache;
 pub mod cache_yamls;
 pub mod context;
 pub mod cron;
+pub mod filter_patch;
 mod i18n;
+pub mod jobs;
+mod metrics;
 pub mod missing_housenumbers;
+mod network_replay;
+mod osm_diff;
+mod overpass_local;
 mod overpass_query;
 pub mod parse_access_log;
+mod postcodes;
 mod ranges;
+mod rate_limit;
+pub mod ref_snapshots;
+mod reference_import;
 mod sql;
 mod stats;
+mod street_matching;
 pub mod sync_ref;
 pub mod util;
 pub mod validator;
 mod webframe;
 pub mod wsgi;
 mod wsgi_additional;
+mod wsgi_api;
 mod wsgi_json;
 mod yattag;
@@ -12,15 +12,10 @@
 
 use std::collections::HashMap;
 use std::io::Write;
+use std::sync::Arc;
 
 type Handler = fn(&[String], &mut dyn Write, &osm_gimmisn::context::Context) -> i32;
 
-/// Wraps wsgi::application() to an app for rouille.
-fn rouille_app(request: &rouille::Request) -> rouille::Response {
-    let ctx = osm_gimmisn::context::Context::new("").unwrap();
-    osm_gimmisn::wsgi::application(request, &ctx)
-}
-
 /// Commandline interface to this module.
 ///
 /// Once this is started, a reverse proxy on top of this can add SSL support. For example, Apache
@@ -29,8 +24,8 @@ fn rouille_app(request: &rouille::Request) -> rouille::Response {
 /// ProxyPreserveHost On
 /// ProxyPass / http://127.0.0.1:8000/
 /// ProxyPassReverse / http://127.0.0.1:8000/
-/// # Default would be 60
-/// ProxyTimeout 120
+///
+/// Overpass updates run in a background worker thread, so requests don't need a longer timeout.
 fn rouille_main(
     argv: &[String],
     stream: &mut dyn Write,
@@ -53,11 +48,40 @@ fn rouille_main(
     )
     .unwrap();
     osm_gimmisn::context::system::get_tz_offset();
+    rouille_setup_logging();
+    // One context is shared by the worker and the threads of the server, so the config is read
+    // and the database connections are opened only once.
+    let ctx = Arc::new(osm_gimmisn::context::Context::new("").unwrap());
+    let worker_ctx = ctx.clone();
+    std::thread::spawn(move || loop {
+        if let Err(err) = osm_gimmisn::jobs::run_worker(&worker_ctx) {
+            log::error!("rouille: run_worker() failed: {err:?}, restarting");
+            worker_ctx.get_time().sleep(1);
+        }
+    });
     rouille::start_server_with_pool(format!("{host}:{port}"), None, move |request| {
-        rouille_app(request)
+        osm_gimmisn::wsgi::application(request, &ctx)
     });
 }
 
+/// Sets up logging for the server, e.g. for the background jobs.
+fn rouille_setup_logging() {
+    let config = simplelog::ConfigBuilder::new()
+        .set_time_format_custom(simplelog::format_description!(
+            "[year]-[month]-[day] [hour]:[minute]:[second]"
+        ))
+        .set_time_offset_to_local()
+        .unwrap()
+        .build();
+    simplelog::TermLogger::init(
+        simplelog::LevelFilter::Info,
+        config,
+        simplelog::TerminalMode::Stdout,
+        simplelog::ColorChoice::Never,
+    )
+    .expect("failed to init the term logger");
+}
+
 /// Sets up logging.
 fn cron_setup_logging(ctx: &osm_gimmisn::context::Context) {
     let config = simplelog::ConfigBuilder::new()
@@ -91,8 +115,10 @@ lazy_static::lazy_static! {
         let mut ret: HashMap<String, Handler> = HashMap::new();
         ret.insert("cache-yamls".into(), osm_gimmisn::cache_yamls::main);
         ret.insert("cron".into(), cron_main);
+        ret.insert("filter-patch".into(), osm_gimmisn::filter_patch::main);
         ret.insert("missing-housenumbers".into(), osm_gimmisn::missing_housenumbers::main);
         ret.insert("parse-access-log".into(), osm_gimmisn::parse_access_log::main);
+        ret.insert("reference-diff".into(), osm_gimmisn::ref_snapshots::main);
         ret.insert("rouille".into(), rouille_main);
         ret.insert("sync-ref".into(), osm_gimmisn::sync_ref::main);
         ret.insert("validator".into(), osm_gimmisn::validator::main);
@@ -106,10 +132,14 @@ fn main() {
     let cache_yamls =
         clap::Command::new("cache-yamls").about("Caches YAML files from the data/ directory");
     let cron = clap::Command::new("cron").about("Performs nightly tasks");
+    let filter_patch = clap::Command::new("filter-patch")
+        .about("Suggests a patch for the filters of a relation, based on its lints");
     let missing_housenumbers = clap::Command::new("missing-housenumbers")
         .about("Compares reference house numbers with OSM ones and shows the diff");
     let parse_access_log = clap::Command::new("parse-access-log")
         .about("Parses the Apache access log of osm-gimmisn for 1 month");
+    let reference_diff = clap::Command::new("reference-diff")
+        .about("Shows the reference changes between two reference imports, per relation");
     let rouille = clap::Command::new("rouille").about("Starts the web interface");
     let sync_ref = clap::Command::new("sync-ref")
         .about("Synchronizes the reference data from a public instance to a local dev instance");
@@ -117,8 +147,10 @@ fn main() {
     let subcommands = vec![
         cache_yamls,
         cron,
+        filter_patch,
         missing_housenumbers,
         parse_access_log,
+        reference_diff,
         rouille,
         sync_ref,
         validator,
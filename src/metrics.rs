@@ -0,0 +1,469 @@
+/*
+ * Copyright 2024 Miklos Vajna
+ *
+ * SPDX-License-Identifier: MIT
+ */
+
+#![deny(warnings)]
+#![warn(clippy::all)]
+#![warn(missing_docs)]
+
+//! The metrics module collects operational metrics and renders them in the Prometheus text
+//! format.
+
+use crate::areas;
+use crate::context;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::Mutex;
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+const BUCKETS: [f64; 10] = [0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 60.0, 300.0];
+
+/// A latency histogram with cumulative buckets.
+#[derive(Clone, Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+
+    /// Adds the observations of other to this histogram.
+    fn add(&mut self, other: &Histogram) {
+        for (bucket, other_bucket) in self.buckets.iter_mut().zip(other.buckets) {
+            *bucket += other_bucket;
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+    }
+}
+
+/// Counts hits and misses of a cache.
+#[derive(Default)]
+struct CacheCounts {
+    hits: u64,
+    misses: u64,
+}
+
+/// The metrics of the current process.
+#[derive(Default)]
+struct State {
+    /// Handler -> request latencies.
+    requests: BTreeMap<String, Histogram>,
+    /// Overpass endpoint -> query latencies.
+    overpass: BTreeMap<String, Histogram>,
+    /// Overpass endpoint -> failed queries.
+    overpass_failures: BTreeMap<String, u64>,
+    /// Cache name -> hits and misses.
+    caches: BTreeMap<String, CacheCounts>,
+}
+
+/// Collects the metrics of the current process, e.g. the web server.
+#[derive(Default)]
+pub struct Metrics {
+    state: Mutex<State>,
+}
+
+impl Metrics {
+    /// Records a served request of a handler.
+    pub fn record_request(&self, handler: &str, duration: time::Duration) {
+        let mut state = self.state.lock().unwrap();
+        let histogram = state.requests.entry(handler.to_string()).or_default();
+        histogram.observe(duration.as_seconds_f64());
+    }
+
+    /// Records an Overpass query sent to an endpoint.
+    pub fn record_overpass(&self, endpoint: &str, duration: time::Duration, success: bool) {
+        let mut state = self.state.lock().unwrap();
+        let histogram = state.overpass.entry(endpoint.to_string()).or_default();
+        histogram.observe(duration.as_seconds_f64());
+        let failures = state
+            .overpass_failures
+            .entry(endpoint.to_string())
+            .or_default();
+        if !success {
+            *failures += 1;
+        }
+    }
+
+    /// Records a cache lookup, cache_key is e.g. 'missing-housenumbers-cache/gazdagret'.
+    pub fn record_cache(&self, cache_key: &str, hit: bool) {
+        let name = cache_key.split('/').next().unwrap_or_default();
+        let mut state = self.state.lock().unwrap();
+        let counts = state.caches.entry(name.to_string()).or_default();
+        if hit {
+            counts.hits += 1;
+        } else {
+            counts.misses += 1;
+        }
+    }
+}
+
+/// The last cron run, as stored in the `cron_runs` SQL table.
+struct CronRun {
+    start: i64,
+    duration: f64,
+    success: bool,
+    /// Kind -> coverage of the active relations in percent, if known.
+    coverages: Vec<(&'static str, Option<f64>)>,
+}
+
+/// Records the outcome of a cron run and the coverage of the active relations. Cron runs in its
+/// own process, so this goes to the database.
+pub fn set_cron_run(
+    ctx: &context::Context,
+    start: time::OffsetDateTime,
+    duration: time::Duration,
+    success: bool,
+) -> anyhow::Result<()> {
+    // Write the outcome first: computing the coverage may fail for the same reason as cron did.
+    {
+        let mut conn = ctx.get_database_connection()?;
+        let tx = conn.transaction()?;
+        // Only the last run is used.
+        tx.execute("delete from cron_runs", [])?;
+        tx.execute(
+            "insert into cron_runs (start, duration, success) values (?1, ?2, ?3)",
+            [
+                start.unix_timestamp().to_string(),
+                duration.as_seconds_f64().to_string(),
+                (success as i32).to_string(),
+            ],
+        )?;
+        add_cron_overpass_queries(ctx, &tx)?;
+        tx.commit()?;
+    }
+
+    let mut relations = areas::Relations::new(ctx)?;
+    let housenumbers_coverage = get_coverage(&mut relations, "housenumbers")?;
+    let streets_coverage = get_coverage(&mut relations, "streets")?;
+    let conn = ctx.get_database_connection()?;
+    conn.execute(
+        "update cron_runs set housenumbers_coverage = ?1, streets_coverage = ?2",
+        [
+            housenumbers_coverage.map(|i| i.to_string()),
+            streets_coverage.map(|i| i.to_string()),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Adds the Overpass queries of this cron process to the ones of earlier cron runs in the
+/// database, so the web server can show them. They are taken from the process, so they are only
+/// added once.
+fn add_cron_overpass_queries(
+    ctx: &context::Context,
+    tx: &rusqlite::Transaction<'_>,
+) -> anyhow::Result<()> {
+    let (histograms, failures) = {
+        let mut state = ctx.get_metrics().state.lock().unwrap();
+        (
+            std::mem::take(&mut state.overpass),
+            std::mem::take(&mut state.overpass_failures),
+        )
+    };
+    let mut cron_histograms = BTreeMap::new();
+    let mut cron_failures = BTreeMap::new();
+    get_cron_overpass_queries(tx, &mut cron_histograms, &mut cron_failures)?;
+    for (endpoint, histogram) in histograms {
+        let cron_histogram: &mut Histogram =
+            cron_histograms.entry(endpoint.to_string()).or_default();
+        cron_histogram.add(&histogram);
+        let cron_failures = cron_failures.entry(endpoint.to_string()).or_default();
+        *cron_failures += failures.get(&endpoint).cloned().unwrap_or_default();
+        let buckets: Vec<String> = cron_histogram
+            .buckets
+            .iter()
+            .map(|i| i.to_string())
+            .collect();
+        tx.execute(
+            r#"insert into cron_overpass_queries (endpoint, buckets, count, sum, failures) values (?1, ?2, ?3, ?4, ?5)
+                 on conflict(endpoint) do update set buckets = excluded.buckets, count = excluded.count, sum = excluded.sum, failures = excluded.failures"#,
+            [
+                endpoint,
+                buckets.join(" "),
+                cron_histogram.count.to_string(),
+                cron_histogram.sum.to_string(),
+                cron_failures.to_string(),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// Adds the Overpass queries of all cron runs to histograms and failures.
+fn get_cron_overpass_queries(
+    conn: &rusqlite::Connection,
+    histograms: &mut BTreeMap<String, Histogram>,
+    failures: &mut BTreeMap<String, u64>,
+) -> anyhow::Result<()> {
+    let mut stmt =
+        conn.prepare("select endpoint, buckets, count, sum, failures from cron_overpass_queries")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let endpoint: String = row.get(0)?;
+        let buckets: String = row.get(1)?;
+        let count: String = row.get(2)?;
+        let sum: String = row.get(3)?;
+        let endpoint_failures: String = row.get(4)?;
+        let mut histogram = Histogram {
+            count: count.parse()?,
+            sum: sum.parse()?,
+            ..Default::default()
+        };
+        for (bucket, value) in histogram.buckets.iter_mut().zip(buckets.split(' ')) {
+            *bucket = value.parse()?;
+        }
+        histograms
+            .entry(endpoint.to_string())
+            .or_default()
+            .add(&histogram);
+        *failures.entry(endpoint).or_default() += endpoint_failures.parse::<u64>()?;
+    }
+    Ok(())
+}
+
+/// Gets the last cron run, if there was one.
+fn get_cron_run(ctx: &context::Context) -> anyhow::Result<Option<CronRun>> {
+    let conn = ctx.get_database_connection()?;
+    let mut stmt = conn.prepare(
+        "select start, duration, success, housenumbers_coverage, streets_coverage from cron_runs order by rowid desc limit 1",
+    )?;
+    let mut rows = stmt.query([])?;
+    let row = match rows.next()? {
+        Some(value) => value,
+        None => {
+            return Ok(None);
+        }
+    };
+    let start: String = row.get(0)?;
+    let duration: String = row.get(1)?;
+    let success: String = row.get(2)?;
+    let mut coverages = Vec::new();
+    for (index, kind) in [(3, "housenumbers"), (4, "streets")] {
+        let coverage: Option<String> = row.get(index)?;
+        let coverage = match coverage {
+            Some(value) => Some(value.parse()?),
+            None => None,
+        };
+        coverages.push((kind, coverage));
+    }
+    Ok(Some(CronRun {
+        start: start.parse()?,
+        duration: duration.parse()?,
+        success: success == "1",
+        coverages,
+    }))
+}
+
+/// Escapes a label value.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+/// Writes the HELP and TYPE lines of a metric.
+fn write_header(ret: &mut String, name: &str, kind: &str, help: &str) -> anyhow::Result<()> {
+    writeln!(ret, "# HELP {name} {help}")?;
+    writeln!(ret, "# TYPE {name} {kind}")?;
+    Ok(())
+}
+
+/// Writes a histogram metric, with one series per label value.
+fn write_histograms(
+    ret: &mut String,
+    name: &str,
+    label: &str,
+    histograms: &BTreeMap<String, Histogram>,
+) -> anyhow::Result<()> {
+    for (key, histogram) in histograms {
+        let key = escape_label(key);
+        for (bucket, bound) in histogram.buckets.iter().zip(BUCKETS) {
+            writeln!(
+                ret,
+                r#"{name}_bucket{{{label}="{key}",le="{bound}"}} {bucket}"#
+            )?;
+        }
+        writeln!(
+            ret,
+            r#"{name}_bucket{{{label}="{key}",le="+Inf"}} {}"#,
+            histogram.count
+        )?;
+        writeln!(ret, r#"{name}_sum{{{label}="{key}"}} {}"#, histogram.sum)?;
+        writeln!(
+            ret,
+            r#"{name}_count{{{label}="{key}"}} {}"#,
+            histogram.count
+        )?;
+    }
+    Ok(())
+}
+
+/// Gets the coverage of the active relations in percent, kind is 'housenumbers' or 'streets'.
+/// Returns None if there are no coverage snapshots yet.
+fn get_coverage(relations: &mut areas::Relations<'_>, kind: &str) -> anyhow::Result<Option<f64>> {
+    let mut todo_count = 0;
+    let mut done_count = 0;
+    let mut found = false;
+    for relation_name in relations.get_active_names()? {
+        let relation = relations.get_relation(&relation_name)?;
+        if let Some(snapshot) = relation.get_last_coverage_snapshot(kind)? {
+            todo_count += snapshot.todo_count;
+            done_count += snapshot.done_count;
+            found = true;
+        }
+    }
+    if !found {
+        return Ok(None);
+    }
+    let total = todo_count + done_count;
+    if total == 0 {
+        return Ok(Some(100_f64));
+    }
+
+    Ok(Some(done_count as f64 / total as f64 * 100_f64))
+}
+
+/// Renders all metrics in the Prometheus text exposition format.
+pub fn render(
+    ctx: &context::Context,
+    relations: &mut areas::Relations<'_>,
+) -> anyhow::Result<String> {
+    let mut ret = String::new();
+    // Overpass queries are mostly sent by cron, show its queries together with the ones of this
+    // process.
+    let mut overpass: BTreeMap<String, Histogram> = BTreeMap::new();
+    let mut overpass_failures: BTreeMap<String, u64> = BTreeMap::new();
+    {
+        let conn = ctx.get_database_connection()?;
+        get_cron_overpass_queries(&conn, &mut overpass, &mut overpass_failures)?;
+    }
+    {
+        let state = ctx.get_metrics().state.lock().unwrap();
+        for (endpoint, histogram) in &state.overpass {
+            overpass
+                .entry(endpoint.to_string())
+                .or_default()
+                .add(histogram);
+        }
+        for (endpoint, failures) in &state.overpass_failures {
+            *overpass_failures.entry(endpoint.to_string()).or_default() += failures;
+        }
+
+        let name = "osm_gimmisn_http_requests_total";
+        write_header(&mut ret, name, "counter", "Number of served requests.")?;
+        for (handler, histogram) in &state.requests {
+            let handler = escape_label(handler);
+            writeln!(ret, r#"{name}{{handler="{handler}"}} {}"#, histogram.count)?;
+        }
+        let name = "osm_gimmisn_http_request_duration_seconds";
+        write_header(&mut ret, name, "histogram", "Latency of served requests.")?;
+        write_histograms(&mut ret, name, "handler", &state.requests)?;
+
+        let name = "osm_gimmisn_overpass_queries_total";
+        write_header(&mut ret, name, "counter", "Number of Overpass queries.")?;
+        for (endpoint, histogram) in &overpass {
+            let endpoint = escape_label(endpoint);
+            writeln!(
+                ret,
+                r#"{name}{{endpoint="{endpoint}"}} {}"#,
+                histogram.count
+            )?;
+        }
+        let name = "osm_gimmisn_overpass_failures_total";
+        write_header(
+            &mut ret,
+            name,
+            "counter",
+            "Number of failed Overpass queries.",
+        )?;
+        for (endpoint, failures) in &overpass_failures {
+            let endpoint = escape_label(endpoint);
+            writeln!(ret, r#"{name}{{endpoint="{endpoint}"}} {failures}"#)?;
+        }
+        let name = "osm_gimmisn_overpass_query_duration_seconds";
+        write_header(&mut ret, name, "histogram", "Latency of Overpass queries.")?;
+        write_histograms(&mut ret, name, "endpoint", &overpass)?;
+
+        let name = "osm_gimmisn_cache_hits_total";
+        write_header(
+            &mut ret,
+            name,
+            "counter",
+            "Number of up to date cache entries.",
+        )?;
+        for (cache, counts) in &state.caches {
+            let cache = escape_label(cache);
+            writeln!(ret, r#"{name}{{cache="{cache}"}} {}"#, counts.hits)?;
+        }
+        let name = "osm_gimmisn_cache_misses_total";
+        write_header(
+            &mut ret,
+            name,
+            "counter",
+            "Number of outdated cache entries.",
+        )?;
+        for (cache, counts) in &state.caches {
+            let cache = escape_label(cache);
+            writeln!(ret, r#"{name}{{cache="{cache}"}} {}"#, counts.misses)?;
+        }
+        let name = "osm_gimmisn_cache_hit_ratio";
+        write_header(
+            &mut ret,
+            name,
+            "gauge",
+            "Ratio of up to date cache entries.",
+        )?;
+        for (cache, counts) in &state.caches {
+            let cache = escape_label(cache);
+            let ratio = counts.hits as f64 / (counts.hits + counts.misses) as f64;
+            writeln!(ret, r#"{name}{{cache="{cache}"}} {ratio}"#)?;
+        }
+    }
+
+    let name = "osm_gimmisn_active_relations";
+    write_header(&mut ret, name, "gauge", "Number of active relations.")?;
+    writeln!(ret, "{name} {}", relations.get_active_names()?.len())?;
+
+    if let Some(cron_run) = get_cron_run(ctx)? {
+        let name = "osm_gimmisn_cron_last_run_timestamp_seconds";
+        write_header(&mut ret, name, "gauge", "Start of the last cron run.")?;
+        writeln!(ret, "{name} {}", cron_run.start)?;
+        let name = "osm_gimmisn_cron_last_run_duration_seconds";
+        write_header(&mut ret, name, "gauge", "Duration of the last cron run.")?;
+        writeln!(ret, "{name} {}", cron_run.duration)?;
+        let name = "osm_gimmisn_cron_last_run_success";
+        write_header(&mut ret, name, "gauge", "If the last cron run succeeded.")?;
+        writeln!(ret, "{name} {}", cron_run.success as i32)?;
+        let name = "osm_gimmisn_coverage_percent";
+        write_header(
+            &mut ret,
+            name,
+            "gauge",
+            "Coverage of the active relations, as of the last cron run.",
+        )?;
+        for (kind, coverage) in cron_run.coverages {
+            if let Some(coverage) = coverage {
+                writeln!(ret, r#"{name}{{kind="{kind}"}} {coverage:.2}"#)?;
+            }
+        }
+    }
+
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,233 @@
+/*
+ * Copyright 2024 Miklos Vajna
+ *
+ * SPDX-License-Identifier: MIT
+ */
+
+#![deny(warnings)]
+#![warn(clippy::all)]
+#![warn(missing_docs)]
+
+//! Tests for the metrics module.
+
+use super::*;
+use std::io::Write;
+
+/// Tests render(): the per-process metrics.
+#[test]
+fn test_render_process() {
+    let ctx = context::tests::make_test_context().unwrap();
+    let metrics = ctx.get_metrics();
+    metrics.record_request("/streets/", time::Duration::milliseconds(20));
+    metrics.record_request("/streets/", time::Duration::seconds(2));
+    metrics.record_overpass("https://overpass-api.de", time::Duration::seconds(1), true);
+    metrics.record_overpass("https://overpass-api.de", time::Duration::seconds(3), false);
+    metrics.record_cache("missing-housenumbers-cache/gazdagret", true);
+    metrics.record_cache("missing-housenumbers-cache/ujbuda", true);
+    metrics.record_cache("missing-housenumbers-cache/gazdagret", false);
+    metrics.record_cache("missing-housenumbers-cache/budafok", true);
+    let mut relations = areas::Relations::new(&ctx).unwrap();
+
+    let actual = render(&ctx, &mut relations).unwrap();
+
+    let lines: Vec<&str> = actual.lines().collect();
+    assert_eq!(
+        lines.contains(&r#"osm_gimmisn_http_requests_total{handler="/streets/"} 2"#),
+        true
+    );
+    assert_eq!(
+        lines.contains(
+            &r#"osm_gimmisn_http_request_duration_seconds_bucket{handler="/streets/",le="0.05"} 1"#
+        ),
+        true
+    );
+    assert_eq!(
+        lines.contains(
+            &r#"osm_gimmisn_http_request_duration_seconds_bucket{handler="/streets/",le="+Inf"} 2"#
+        ),
+        true
+    );
+    assert_eq!(
+        lines.contains(
+            &r#"osm_gimmisn_http_request_duration_seconds_sum{handler="/streets/"} 2.02"#
+        ),
+        true
+    );
+    assert_eq!(
+        lines.contains(
+            &r#"osm_gimmisn_overpass_queries_total{endpoint="https://overpass-api.de"} 2"#
+        ),
+        true
+    );
+    assert_eq!(
+        lines.contains(
+            &r#"osm_gimmisn_overpass_failures_total{endpoint="https://overpass-api.de"} 1"#
+        ),
+        true
+    );
+    assert_eq!(
+        lines.contains(&r#"osm_gimmisn_cache_hit_ratio{cache="missing-housenumbers-cache"} 0.75"#),
+        true
+    );
+    // No cron run yet.
+    assert_eq!(actual.contains("osm_gimmisn_cron_last_run"), false);
+    assert_eq!(actual.contains("osm_gimmisn_coverage_percent{"), false);
+}
+
+/// Tests render(): the metrics stored in the database.
+#[test]
+fn test_render_sql() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "osmrelation": 42,
+            },
+            "ujbuda": {
+                "osmrelation": 43,
+            },
+            "budafok": {
+                "osmrelation": 44,
+                "inactive": true,
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        &ctx,
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    ctx.set_file_system(&file_system);
+    {
+        let conn = ctx.get_database_connection().unwrap();
+        conn.execute_batch(
+            "insert into relation_coverage_history (relation_name, kind, date, todo_count, done_count, percent) values ('gazdagret', 'housenumbers', '2020-05-09', '10', '10', '50.00');
+             insert into relation_coverage_history (relation_name, kind, date, todo_count, done_count, percent) values ('gazdagret', 'housenumbers', '2020-05-10', '5', '15', '75.00');
+             insert into relation_coverage_history (relation_name, kind, date, todo_count, done_count, percent) values ('ujbuda', 'housenumbers', '2020-05-10', '15', '5', '25.00');
+             insert into relation_coverage_history (relation_name, kind, date, todo_count, done_count, percent) values ('budafok', 'housenumbers', '2020-05-10', '20', '0', '0.00');",
+        )
+        .unwrap();
+    }
+    let start = ctx.get_time().now();
+    set_cron_run(&ctx, start, time::Duration::seconds(90), false).unwrap();
+    set_cron_run(&ctx, start, time::Duration::seconds(60), true).unwrap();
+    {
+        let conn = ctx.get_database_connection().unwrap();
+        // Cron computed the coverage, render() only reads it.
+        conn.execute("delete from relation_coverage_history", [])
+            .unwrap();
+        // Only the last run is kept.
+        let count: i64 = conn
+            .query_row("select count(*) from cron_runs", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+    let mut relations = areas::Relations::new(&ctx).unwrap();
+
+    let actual = render(&ctx, &mut relations).unwrap();
+
+    let lines: Vec<&str> = actual.lines().collect();
+    assert_eq!(
+        lines.contains(
+            &format!(
+                "osm_gimmisn_cron_last_run_timestamp_seconds {}",
+                start.unix_timestamp()
+            )
+            .as_str()
+        ),
+        true
+    );
+    assert_eq!(
+        lines.contains(&"osm_gimmisn_cron_last_run_duration_seconds 60"),
+        true
+    );
+    assert_eq!(lines.contains(&"osm_gimmisn_cron_last_run_success 1"), true);
+    assert_eq!(lines.contains(&"osm_gimmisn_active_relations 2"), true);
+    // Inactive relations and old snapshots are ignored: 20 of 40.
+    assert_eq!(
+        lines.contains(&r#"osm_gimmisn_coverage_percent{kind="housenumbers"} 50.00"#),
+        true
+    );
+    // No snapshots: no value.
+    assert_eq!(
+        actual.contains(r#"osm_gimmisn_coverage_percent{kind="streets"}"#),
+        false
+    );
+}
+
+/// Tests set_cron_run(): the outcome is recorded even if the coverage can't be computed.
+#[test]
+fn test_set_cron_run_no_coverage() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    let start = ctx.get_time().now();
+    set_cron_run(&ctx, start, time::Duration::seconds(60), true).unwrap();
+    let yamls_cache_value = context::tests::TestFileSystem::make_file();
+    yamls_cache_value
+        .lock()
+        .unwrap()
+        .write_all(b"{broken")
+        .unwrap();
+    let files = context::tests::TestFileSystem::make_files(
+        &ctx,
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    ctx.set_file_system(&file_system);
+
+    let ret = set_cron_run(&ctx, start, time::Duration::seconds(90), false);
+
+    assert_eq!(ret.is_err(), true);
+    let cron_run = get_cron_run(&ctx).unwrap().unwrap();
+    assert_eq!(cron_run.duration, 90_f64);
+    assert_eq!(cron_run.success, false);
+    assert_eq!(
+        cron_run.coverages,
+        vec![("housenumbers", None), ("streets", None)]
+    );
+}
+
+/// Tests set_cron_run(): the Overpass queries of cron runs are summed in the database.
+#[test]
+fn test_set_cron_run_overpass() {
+    let ctx = context::tests::make_test_context().unwrap();
+    let metrics = ctx.get_metrics();
+    let start = ctx.get_time().now();
+    metrics.record_overpass("https://overpass-api.de", time::Duration::seconds(1), true);
+    metrics.record_overpass("https://overpass-api.de", time::Duration::seconds(3), false);
+    set_cron_run(&ctx, start, time::Duration::seconds(60), true).unwrap();
+    metrics.record_overpass("https://overpass-api.de", time::Duration::seconds(2), true);
+    set_cron_run(&ctx, start, time::Duration::seconds(60), true).unwrap();
+    // Not yet saved: shown together with the saved ones.
+    metrics.record_overpass("https://overpass-api.de", time::Duration::seconds(4), false);
+    let mut relations = areas::Relations::new(&ctx).unwrap();
+
+    let actual = render(&ctx, &mut relations).unwrap();
+
+    let lines: Vec<&str> = actual.lines().collect();
+    assert_eq!(
+        lines.contains(
+            &r#"osm_gimmisn_overpass_queries_total{endpoint="https://overpass-api.de"} 4"#
+        ),
+        true
+    );
+    assert_eq!(
+        lines.contains(
+            &r#"osm_gimmisn_overpass_failures_total{endpoint="https://overpass-api.de"} 2"#
+        ),
+        true
+    );
+    assert_eq!(
+        lines.contains(
+            &r#"osm_gimmisn_overpass_query_duration_seconds_sum{endpoint="https://overpass-api.de"} 10"#
+        ),
+        true
+    );
+}
+
+/// Tests escape_label().
+#[test]
+fn test_escape_label() {
+    assert_eq!(escape_label(r#"a"b\c"#), r#"a\"b\\c"#);
+    assert_eq!(escape_label("a\nb"), r"a\nb");
+}
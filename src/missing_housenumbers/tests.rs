@@ -13,7 +13,7 @@
 use super::*;
 use std::io::Read;
 use std::io::Seek;
-use std::rc::Rc;
+use std::sync::Arc;
 
 /// Tests main().
 #[test]
@@ -66,7 +66,7 @@ fn test_main_error() {
     let mut buf: std::io::Cursor<Vec<u8>> = std::io::Cursor::new(Vec::new());
     let mut ctx = context::tests::make_test_context().unwrap();
     let unit = context::tests::TestUnit::new();
-    let unit_rc: Rc<dyn context::Unit> = Rc::new(unit);
+    let unit_rc: Arc<dyn context::Unit> = Arc::new(unit);
     ctx.set_unit(&unit_rc);
 
     let ret = main(&argv, &mut buf, &mut ctx);
@@ -0,0 +1,294 @@
+/*
+ * Copyright 2024 Miklos Vajna
+ *
+ * SPDX-License-Identifier: MIT
+ */
+
+#![deny(warnings)]
+#![warn(clippy::all)]
+#![warn(missing_docs)]
+
+//! The network_replay module records network requests and responses to a directory, and serves
+//! them back later, so a real run can be reproduced without network access.
+
+use crate::context;
+use anyhow::Context as _;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[cfg(not(test))]
+use log::warn;
+
+#[cfg(test)]
+use std::println as warn;
+
+/// The kind of a failed request, for errors where callers look at more than the message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ErrorKind {
+    /// The request timed out.
+    TimedOut,
+}
+
+impl ErrorKind {
+    /// Gets the kind of an error from the network layer, if it's a known one.
+    fn from_error(err: &anyhow::Error) -> Option<ErrorKind> {
+        if let Some(err) = err.downcast_ref::<isahc::Error>() {
+            if err.is_timeout() {
+                return Some(ErrorKind::TimedOut);
+            }
+        }
+        if let Some(err) = err.downcast_ref::<std::io::Error>() {
+            if err.kind() == std::io::ErrorKind::TimedOut {
+                return Some(ErrorKind::TimedOut);
+            }
+        }
+        None
+    }
+}
+
+/// One recorded request and its response.
+#[derive(serde::Deserialize, serde::Serialize)]
+struct Exchange {
+    /// The request URL.
+    url: String,
+    /// The POST data, empty for GET.
+    data: String,
+    /// The HTTP status code, if it's known and the request succeeded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    /// The response, if the request succeeded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    result: Option<String>,
+    /// The error message, if the request failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// The error kind, if the request failed with a known kind of error.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error_kind: Option<ErrorKind>,
+    /// The name of the file with the undecoded response, for urlopen_bytes().
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bytes_path: Option<String>,
+    /// The undecoded response, loaded from bytes_path.
+    #[serde(skip)]
+    bytes: Option<Vec<u8>>,
+}
+
+impl Exchange {
+    /// Creates the error of a failed request, of the same kind as the recorded one.
+    fn get_error(&self) -> anyhow::Error {
+        let message = self.error.clone().unwrap_or_default();
+        match self.error_kind {
+            Some(ErrorKind::TimedOut) => {
+                anyhow::Error::new(std::io::Error::new(std::io::ErrorKind::TimedOut, message))
+            }
+            None => anyhow::anyhow!(message),
+        }
+    }
+}
+
+/// Gets the paths of the recorded exchanges in a directory, in the order of recording.
+fn get_exchange_paths(file_system: &Arc<dyn context::FileSystem>, dir: &str) -> Vec<String> {
+    // No directory means no recordings.
+    let mut paths: Vec<String> = file_system.listdir(dir).unwrap_or_default();
+    paths.retain(|path| path.ends_with(".json"));
+    paths.sort();
+    paths
+}
+
+/// Network implementation that forwards requests to an other network and records each request
+/// and response in a directory.
+pub struct RecordNetwork {
+    file_system: Arc<dyn context::FileSystem>,
+    dir: String,
+    network: Arc<dyn context::Network>,
+    count: Mutex<usize>,
+}
+
+impl RecordNetwork {
+    /// Creates a new RecordNetwork, existing recordings in dir are kept.
+    pub fn new(
+        file_system: &Arc<dyn context::FileSystem>,
+        dir: &str,
+        network: &Arc<dyn context::Network>,
+    ) -> Self {
+        let count = get_exchange_paths(file_system, dir).len();
+        RecordNetwork {
+            file_system: file_system.clone(),
+            dir: dir.to_string(),
+            network: network.clone(),
+            count: Mutex::new(count),
+        }
+    }
+
+    /// Records the result of a request.
+    fn record(
+        &self,
+        url: &str,
+        data: &str,
+        status: Option<u16>,
+        result: Result<&String, &anyhow::Error>,
+    ) -> anyhow::Result<()> {
+        let exchange = Exchange {
+            url: url.to_string(),
+            data: data.to_string(),
+            status,
+            result: result.ok().cloned(),
+            error: result.err().map(|err| err.to_string()),
+            error_kind: result.err().and_then(ErrorKind::from_error),
+            bytes_path: None,
+            bytes: None,
+        };
+        let mut count = self.count.lock().unwrap();
+        let path = format!("{}/{:06}.json", self.dir, *count);
+        *count += 1;
+        self.file_system
+            .write_from_string(&serde_json::to_string_pretty(&exchange)?, &path)
+            .context(format!("failed to record '{url}'"))
+    }
+
+    /// Records the result of an undecoded download: the response goes to its own file, as-is.
+    fn record_bytes(&self, url: &str, result: &anyhow::Result<Vec<u8>>) -> anyhow::Result<()> {
+        let mut count = self.count.lock().unwrap();
+        let name = format!("{:06}", *count);
+        *count += 1;
+        let mut exchange = Exchange {
+            url: url.to_string(),
+            data: "".into(),
+            status: None,
+            result: None,
+            error: result.as_ref().err().map(|err| err.to_string()),
+            error_kind: result.as_ref().err().and_then(ErrorKind::from_error),
+            bytes_path: None,
+            bytes: None,
+        };
+        if let Ok(bytes) = result {
+            let bytes_path = format!("{name}.bin");
+            let stream = self
+                .file_system
+                .open_write(&format!("{}/{bytes_path}", self.dir))?;
+            let mut guard = stream.lock().unwrap();
+            guard
+                .write_all(bytes)
+                .context(format!("failed to record '{url}'"))?;
+            exchange.bytes_path = Some(bytes_path);
+        }
+        let path = format!("{}/{name}.json", self.dir);
+        self.file_system
+            .write_from_string(&serde_json::to_string_pretty(&exchange)?, &path)
+            .context(format!("failed to record '{url}'"))
+    }
+}
+
+impl context::Network for RecordNetwork {
+    fn urlopen(&self, url: &str, data: &str) -> anyhow::Result<String> {
+        let result = self.network.urlopen(url, data);
+        if let Err(err) = self.record(url, data, None, result.as_ref()) {
+            warn!("RecordNetwork: {err:#}");
+        }
+        result
+    }
+
+    fn urlopen_with_status(
+        &self,
+        url: &str,
+        data: &str,
+        timeout: Duration,
+    ) -> anyhow::Result<(u16, String)> {
+        let result = self.network.urlopen_with_status(url, data, timeout);
+        let status = result.as_ref().ok().map(|(status, _)| *status);
+        if let Err(err) = self.record(url, data, status, result.as_ref().map(|(_, body)| body)) {
+            warn!("RecordNetwork: {err:#}");
+        }
+        result
+    }
+
+    fn urlopen_bytes(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        let result = self.network.urlopen_bytes(url);
+        if let Err(err) = self.record_bytes(url, &result) {
+            warn!("RecordNetwork: {err:#}");
+        }
+        result
+    }
+}
+
+/// Network implementation that serves the requests recorded by RecordNetwork, without network
+/// access.
+pub struct ReplayNetwork {
+    exchanges: Mutex<Vec<Exchange>>,
+}
+
+impl ReplayNetwork {
+    /// Creates a new ReplayNetwork, loading the recordings from dir.
+    pub fn new(file_system: &Arc<dyn context::FileSystem>, dir: &str) -> anyhow::Result<Self> {
+        let mut exchanges: Vec<Exchange> = Vec::new();
+        for path in get_exchange_paths(file_system, dir) {
+            let exchange = file_system.read_to_string(&path)?;
+            let mut exchange: Exchange =
+                serde_json::from_str(&exchange).context(format!("failed to parse '{path}'"))?;
+            if let Some(ref bytes_path) = exchange.bytes_path {
+                let stream = file_system.open_read(&format!("{dir}/{bytes_path}"))?;
+                let mut guard = stream.lock().unwrap();
+                let mut bytes: Vec<u8> = Vec::new();
+                guard.read_to_end(&mut bytes)?;
+                exchange.bytes = Some(bytes);
+            }
+            exchanges.push(exchange);
+        }
+        Ok(ReplayNetwork {
+            exchanges: Mutex::new(exchanges),
+        })
+    }
+}
+
+impl ReplayNetwork {
+    /// Takes the first not yet served recording with a matching request.
+    fn take(&self, url: &str, data: &str) -> anyhow::Result<Exchange> {
+        let mut exchanges = self.exchanges.lock().unwrap();
+        let index = exchanges
+            .iter()
+            .position(|exchange| exchange.url == url && exchange.data == data)
+            .context(format!("url missing from the recording: '{url}'"))?;
+        Ok(exchanges.remove(index))
+    }
+}
+
+impl context::Network for ReplayNetwork {
+    /// Serves the first not yet served recording with a matching request.
+    fn urlopen(&self, url: &str, data: &str) -> anyhow::Result<String> {
+        let exchange = self.take(url, data)?;
+        match exchange.result {
+            Some(value) => Ok(value),
+            None => Err(exchange.get_error()),
+        }
+    }
+
+    /// Serves the first not yet served recording with a matching request, recordings without a
+    /// status are assumed to be 200 OK.
+    fn urlopen_with_status(
+        &self,
+        url: &str,
+        data: &str,
+        _timeout: Duration,
+    ) -> anyhow::Result<(u16, String)> {
+        let exchange = self.take(url, data)?;
+        match exchange.result {
+            Some(value) => Ok((exchange.status.unwrap_or(200), value)),
+            None => Err(exchange.get_error()),
+        }
+    }
+
+    /// Serves the first not yet served recording of a GET request, undecoded.
+    fn urlopen_bytes(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        let mut exchange = self.take(url, "")?;
+        match (exchange.bytes.take(), exchange.result.take()) {
+            (Some(value), _) => Ok(value),
+            (None, Some(value)) => Ok(value.into_bytes()),
+            (None, None) => Err(exchange.get_error()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
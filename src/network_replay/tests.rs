@@ -0,0 +1,229 @@
+/*
+ * Copyright 2024 Miklos Vajna
+ *
+ * SPDX-License-Identifier: MIT
+ */
+
+#![deny(warnings)]
+#![warn(clippy::all)]
+#![warn(missing_docs)]
+
+//! Tests for the network_replay module.
+
+use super::*;
+use context::Network as _;
+
+/// Creates a file system where recordings can be written to, the first `existing` recordings are
+/// already there.
+fn make_file_system(ctx: &context::Context, existing: usize) -> Arc<dyn context::FileSystem> {
+    let recording0 = context::tests::TestFileSystem::make_file();
+    let recording1 = context::tests::TestFileSystem::make_file();
+    let recording2 = context::tests::TestFileSystem::make_file();
+    let files = context::tests::TestFileSystem::make_files(
+        ctx,
+        &[
+            ("workdir/recording/000000.json", &recording0),
+            ("workdir/recording/000001.json", &recording1),
+            ("workdir/recording/000002.json", &recording2),
+        ],
+    );
+    let mut file_system = context::tests::TestFileSystem::new();
+    file_system.set_files(&files);
+    let hide_paths: Vec<String> = (existing..3)
+        .map(|index| ctx.get_abspath(&format!("workdir/recording/{index:06}.json")))
+        .collect();
+    file_system.set_hide_paths(&hide_paths);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
+    file_system_rc
+}
+
+/// Tests that a recording can be replayed: both results and errors.
+#[test]
+fn test_record_replay() {
+    let ctx = context::tests::make_test_context().unwrap();
+    let routes = vec![context::tests::URLRoute::new(
+        /*url=*/ "https://overpass-api.de/api/status",
+        /*data_path=*/ "",
+        /*result_path=*/ "src/fixtures/network/overpass-status-happy.txt",
+    )];
+    let network = context::tests::TestNetwork::new(&routes);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
+    let file_system = make_file_system(&ctx, 0);
+    let dir = ctx.get_abspath("workdir/recording");
+    let record = RecordNetwork::new(&file_system, &dir, &network_rc);
+
+    let status = record
+        .urlopen("https://overpass-api.de/api/status", "")
+        .unwrap();
+    let ret = record.urlopen("https://overpass-api.de/api/interpreter", "way(42);");
+
+    assert_eq!(ret.is_err(), true);
+    assert_eq!(file_system.path_exists(&format!("{dir}/000000.json")), true);
+    assert_eq!(file_system.path_exists(&format!("{dir}/000001.json")), true);
+    assert_eq!(
+        file_system.path_exists(&format!("{dir}/000002.json")),
+        false
+    );
+    let replay = ReplayNetwork::new(&file_system, &dir).unwrap();
+    assert_eq!(
+        replay
+            .urlopen("https://overpass-api.de/api/status", "")
+            .unwrap(),
+        status
+    );
+    let err = replay
+        .urlopen("https://overpass-api.de/api/interpreter", "way(42);")
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "url missing from route list: 'https://overpass-api.de/api/interpreter'"
+    );
+    // Each recording is served once.
+    let ret = replay.urlopen("https://overpass-api.de/api/status", "");
+    assert_eq!(ret.is_err(), true);
+}
+
+/// Tests that recording continues after the existing recordings.
+#[test]
+fn test_record_append() {
+    let ctx = context::tests::make_test_context().unwrap();
+    let dir = ctx.get_abspath("workdir/recording");
+    let file_system = make_file_system(&ctx, 2);
+    let network = context::tests::TestNetwork::new(&[]);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
+    let record = RecordNetwork::new(&file_system, &dir, &network_rc);
+
+    let ret = record.urlopen("https://www.example.com", "");
+
+    assert_eq!(ret.is_err(), true);
+    assert_eq!(file_system.path_exists(&format!("{dir}/000002.json")), true);
+}
+
+/// Network implementation that only serves undecoded downloads.
+struct BytesNetwork {}
+
+impl context::Network for BytesNetwork {
+    fn urlopen(&self, url: &str, _data: &str) -> anyhow::Result<String> {
+        Err(anyhow::anyhow!("unexpected urlopen: '{url}'"))
+    }
+
+    fn urlopen_bytes(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        if url == "https://osm.example.com/data/hazszamok.tsv.gz" {
+            // Not valid UTF-8.
+            return Ok(vec![0x1f, 0x8b, 0x08, 0x00, 0xff, 0xfe]);
+        }
+        Err(anyhow::anyhow!("url missing: '{url}'"))
+    }
+}
+
+/// Tests that undecoded downloads can be recorded and replayed as-is.
+#[test]
+fn test_record_replay_bytes() {
+    let ctx = context::tests::make_test_context().unwrap();
+    let recording0 = context::tests::TestFileSystem::make_file();
+    let recording0_bytes = context::tests::TestFileSystem::make_file();
+    let recording1 = context::tests::TestFileSystem::make_file();
+    let files = context::tests::TestFileSystem::make_files(
+        &ctx,
+        &[
+            ("workdir/recording/000000.json", &recording0),
+            ("workdir/recording/000000.bin", &recording0_bytes),
+            ("workdir/recording/000001.json", &recording1),
+        ],
+    );
+    let mut file_system = context::tests::TestFileSystem::new();
+    file_system.set_files(&files);
+    let hide_paths = vec![
+        ctx.get_abspath("workdir/recording/000000.json"),
+        ctx.get_abspath("workdir/recording/000000.bin"),
+        ctx.get_abspath("workdir/recording/000001.json"),
+    ];
+    file_system.set_hide_paths(&hide_paths);
+    let file_system: Arc<dyn context::FileSystem> = Arc::new(file_system);
+    let network: Arc<dyn context::Network> = Arc::new(BytesNetwork {});
+    let dir = ctx.get_abspath("workdir/recording");
+    let record = RecordNetwork::new(&file_system, &dir, &network);
+
+    let bytes = record
+        .urlopen_bytes("https://osm.example.com/data/hazszamok.tsv.gz")
+        .unwrap();
+    let ret = record.urlopen_bytes("https://osm.example.com/data/SHA256SUMS");
+
+    assert_eq!(ret.is_err(), true);
+    assert_eq!(recording0_bytes.lock().unwrap().get_ref(), &bytes);
+    let replay = ReplayNetwork::new(&file_system, &dir).unwrap();
+    assert_eq!(
+        replay
+            .urlopen_bytes("https://osm.example.com/data/hazszamok.tsv.gz")
+            .unwrap(),
+        bytes
+    );
+    let err = replay
+        .urlopen_bytes("https://osm.example.com/data/SHA256SUMS")
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "url missing: 'https://osm.example.com/data/SHA256SUMS'"
+    );
+}
+
+/// Tests that a failed recording doesn't hide the result of the request.
+#[test]
+fn test_record_failure() {
+    let ctx = context::tests::make_test_context().unwrap();
+    let routes = vec![context::tests::URLRoute::new(
+        /*url=*/ "https://overpass-api.de/api/status",
+        /*data_path=*/ "",
+        /*result_path=*/ "src/fixtures/network/overpass-status-happy.txt",
+    )];
+    let network = context::tests::TestNetwork::new(&routes);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
+    // No files to record to.
+    let file_system: Arc<dyn context::FileSystem> = Arc::new(context::tests::TestFileSystem::new());
+    let dir = ctx.get_abspath("workdir/recording");
+    let record = RecordNetwork::new(&file_system, &dir, &network_rc);
+
+    let ret = record.urlopen("https://overpass-api.de/api/status", "");
+
+    assert_eq!(ret.is_ok(), true);
+}
+
+/// Network implementation where each request times out.
+struct TimeoutNetwork {}
+
+impl context::Network for TimeoutNetwork {
+    fn urlopen(&self, _url: &str, _data: &str) -> anyhow::Result<String> {
+        Err(anyhow::Error::new(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            "timed out",
+        )))
+    }
+}
+
+/// Tests that a replayed error has the same kind as the recorded one.
+#[test]
+fn test_record_replay_error_kind() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    let network: Arc<dyn context::Network> = Arc::new(TimeoutNetwork {});
+    let file_system = make_file_system(&ctx, 0);
+    let dir = ctx.get_abspath("workdir/recording");
+    let record = RecordNetwork::new(&file_system, &dir, &network);
+    let query = "[out:json];\nway(42);\nout;";
+    record
+        .urlopen_with_status(
+            "https://overpass-api.de/api/interpreter",
+            query,
+            Duration::from_secs(1),
+        )
+        .unwrap_err();
+    let replay: Arc<dyn context::Network> =
+        Arc::new(ReplayNetwork::new(&file_system, &dir).unwrap());
+    ctx.set_network(replay);
+
+    let ret = crate::overpass_query::overpass_query(&ctx, query);
+
+    assert_eq!(
+        ret.unwrap_err(),
+        crate::overpass_query::OverpassError::Timeout
+    );
+}
@@ -0,0 +1,240 @@
+/*
+ * Copyright 2024 Miklos Vajna
+ *
+ * SPDX-License-Identifier: MIT
+ */
+
+#![deny(warnings)]
+#![warn(clippy::all)]
+#![warn(missing_docs)]
+
+//! The osm_diff module reads OSM replication diffs (osmChange files) from a local directory.
+
+use crate::context;
+use crate::overpass_local;
+use anyhow::Context as _;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io::Read as _;
+use std::ops::DerefMut;
+use std::rc::Rc;
+
+/// What happened to an object in a diff.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// The object was created.
+    Create,
+    /// The object was modified, the change has its new state.
+    Modify,
+    /// The object was deleted.
+    Delete,
+}
+
+/// One changed object in a diff.
+#[derive(Clone, Debug)]
+pub struct Change {
+    /// What happened to the object.
+    pub action: Action,
+    /// Object type: node, way or relation.
+    pub osm_type: String,
+    /// Object ID.
+    pub id: u64,
+    /// Last editor of the object.
+    pub user: String,
+    /// Last modification of the object, in RFC 3339 format.
+    pub timestamp: String,
+    /// Tags of the object, empty for deletions.
+    pub tags: BTreeMap<String, String>,
+    /// Latitude and longitude of a node, None for other objects and deletions.
+    pub coordinates: Option<(f64, f64)>,
+}
+
+/// One replication diff: the changes between the previous sequence and this one.
+pub struct Diff {
+    /// Replication sequence number.
+    pub sequence: u64,
+    /// The time of the OSM data after applying this diff.
+    pub timestamp: time::OffsetDateTime,
+    osc_path: String,
+    changes: RefCell<Option<Rc<Vec<Change>>>>,
+}
+
+impl Diff {
+    /// Gets the changes of this diff, the osmChange file is parsed on first use.
+    pub fn get_changes(&self, ctx: &context::Context) -> anyhow::Result<Rc<Vec<Change>>> {
+        if let Some(changes) = self.changes.borrow().as_ref() {
+            return Ok(changes.clone());
+        }
+
+        let stream = ctx.get_file_system().open_read(&self.osc_path)?;
+        let mut guard = stream.lock().unwrap();
+        let mut osc = String::new();
+        if self.osc_path.ends_with(".gz") {
+            let mut decoder = flate2::read::GzDecoder::new(guard.deref_mut());
+            decoder.read_to_string(&mut osc)?;
+        } else {
+            guard.read_to_string(&mut osc)?;
+        }
+        let changes =
+            Rc::new(parse_osc(&osc).context(format!("failed to parse '{}'", self.osc_path))?);
+        *self.changes.borrow_mut() = Some(changes.clone());
+        Ok(changes)
+    }
+}
+
+/// Parses a replication state file, returns the sequence number and the timestamp.
+fn parse_state(state: &str) -> anyhow::Result<(u64, time::OffsetDateTime)> {
+    let mut sequence: Option<u64> = None;
+    let mut timestamp: Option<time::OffsetDateTime> = None;
+    for line in state.lines() {
+        if let Some(value) = line.strip_prefix("sequenceNumber=") {
+            sequence = Some(value.trim().parse()?);
+        } else if let Some(value) = line.strip_prefix("timestamp=") {
+            // Java properties format, colons are escaped.
+            let value = value.trim().replace("\\:", ":");
+            timestamp = Some(time::OffsetDateTime::parse(
+                &value,
+                &time::format_description::well_known::Rfc3339,
+            )?);
+        }
+    }
+    Ok((
+        sequence.context("missing sequenceNumber")?,
+        timestamp.context("missing timestamp")?,
+    ))
+}
+
+/// Parses an osmChange XML document.
+fn parse_osc(xml: &str) -> anyhow::Result<Vec<Change>> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut ret: Vec<Change> = Vec::new();
+    let mut action: Option<Action> = None;
+    let mut current: Option<Change> = None;
+    loop {
+        let (start, empty) = match reader.read_event()? {
+            quick_xml::events::Event::Start(start) => (start, false),
+            quick_xml::events::Event::Empty(start) => (start, true),
+            quick_xml::events::Event::End(end) => {
+                match end.name().as_ref() {
+                    b"create" | b"modify" | b"delete" => action = None,
+                    b"node" | b"way" | b"relation" => ret.extend(current.take()),
+                    _ => {}
+                }
+                continue;
+            }
+            quick_xml::events::Event::Eof => break,
+            _ => continue,
+        };
+        match start.name().as_ref() {
+            b"create" => action = Some(Action::Create),
+            b"modify" => action = Some(Action::Modify),
+            b"delete" => action = Some(Action::Delete),
+            b"node" | b"way" | b"relation" => {
+                let attributes = overpass_local::get_xml_attributes(&start)?;
+                let coordinates = match (attributes.get("lat"), attributes.get("lon")) {
+                    (Some(lat), Some(lon)) => Some((lat.parse()?, lon.parse()?)),
+                    _ => None,
+                };
+                let change = Change {
+                    action: action.context("object outside create, modify or delete")?,
+                    osm_type: String::from_utf8(start.name().as_ref().to_vec())?,
+                    id: attributes.get("id").context("missing id")?.parse()?,
+                    user: attributes.get("user").cloned().unwrap_or_default(),
+                    timestamp: attributes.get("timestamp").cloned().unwrap_or_default(),
+                    tags: BTreeMap::new(),
+                    coordinates,
+                };
+                if empty {
+                    ret.push(change);
+                } else {
+                    current = Some(change);
+                }
+            }
+            b"tag" => {
+                if let Some(ref mut change) = current {
+                    let attributes = overpass_local::get_xml_attributes(&start)?;
+                    let key = attributes.get("k").context("missing k")?;
+                    let value = attributes.get("v").context("missing v")?;
+                    change.tags.insert(key.to_string(), value.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(ret)
+}
+
+/// Reads the diffs from the replication directory, sorted by their sequence number.
+///
+/// The directory has `<name>.state.txt` files, each with a matching `<name>.osc.gz` or
+/// `<name>.osc` file.
+pub fn read_diffs(ctx: &context::Context) -> anyhow::Result<Vec<Diff>> {
+    let mut ret: Vec<Diff> = Vec::new();
+    let dir = ctx.get_ini().get_replication_dir();
+    let file_system = ctx.get_file_system();
+    let paths = match file_system.listdir(&dir) {
+        Ok(value) => value,
+        // No replication directory, so no diffs.
+        Err(_) => {
+            return Ok(ret);
+        }
+    };
+
+    for path in paths {
+        let prefix = match path.strip_suffix(".state.txt") {
+            Some(value) => value,
+            None => {
+                continue;
+            }
+        };
+        let osc_path = [format!("{prefix}.osc.gz"), format!("{prefix}.osc")]
+            .into_iter()
+            .find(|osc_path| file_system.path_exists(osc_path));
+        let osc_path = match osc_path {
+            Some(value) => value,
+            // No changes for this state, which shows up as a gap.
+            None => {
+                continue;
+            }
+        };
+        let state = file_system.read_to_string(&path)?;
+        let (sequence, timestamp) =
+            parse_state(&state).context(format!("failed to parse '{path}'"))?;
+        ret.push(Diff {
+            sequence,
+            timestamp,
+            osc_path,
+            changes: RefCell::new(None),
+        });
+    }
+    ret.sort_by_key(|diff| diff.sequence);
+    Ok(ret)
+}
+
+/// Finds the index of the first diff which is newer than osm_base, i.e. the diffs to be applied
+/// from that index. Returns None if the diffs have a gap, so they can't bring data as old as
+/// osm_base up to date.
+pub fn find_pending(diffs: &[Diff], osm_base: time::OffsetDateTime) -> Option<usize> {
+    let last = diffs.last()?;
+    if last.timestamp <= osm_base {
+        return Some(diffs.len());
+    }
+
+    // The diff before the first pending one proves that nothing is missing between osm_base and
+    // the first pending diff.
+    let start = diffs.iter().position(|diff| diff.timestamp > osm_base)?;
+    if start == 0 {
+        return None;
+    }
+    let contiguous = diffs[start - 1..]
+        .windows(2)
+        .all(|pair| pair[1].sequence == pair[0].sequence + 1);
+    if !contiguous {
+        return None;
+    }
+
+    Some(start)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,180 @@
+/*
+ * Copyright 2024 Miklos Vajna
+ *
+ * SPDX-License-Identifier: MIT
+ */
+
+#![deny(warnings)]
+#![warn(clippy::all)]
+#![warn(missing_docs)]
+
+//! Tests for the osm_diff module.
+
+use super::*;
+use std::io::Write as _;
+
+/// Creates a diff without changes, for find_pending() purposes.
+fn make_diff(sequence: u64, timestamp: i64) -> Diff {
+    Diff {
+        sequence,
+        timestamp: time::OffsetDateTime::from_unix_timestamp(timestamp).unwrap(),
+        osc_path: String::new(),
+        changes: RefCell::new(Some(Rc::new(Vec::new()))),
+    }
+}
+
+/// Tests parse_state().
+#[test]
+fn test_parse_state() {
+    let state =
+        "#Sat Aug 03 00:00:00 UTC 2024\nsequenceNumber=4321\ntimestamp=2024-08-02T20\\:21\\:02Z\n";
+
+    let (sequence, timestamp) = parse_state(state).unwrap();
+
+    assert_eq!(sequence, 4321);
+    assert_eq!(timestamp.unix_timestamp(), 1722630062);
+}
+
+/// Tests parse_state(), when the timestamp is missing.
+#[test]
+fn test_parse_state_no_timestamp() {
+    let ret = parse_state("sequenceNumber=4321\n");
+
+    assert_eq!(ret.is_err(), true);
+}
+
+/// Tests parse_osc().
+#[test]
+fn test_parse_osc() {
+    let osc = r#"<?xml version='1.0' encoding='UTF-8'?>
+<osmChange version="0.6" generator="test">
+  <create>
+    <node id="7" version="1" timestamp="2024-08-02T10:00:00Z" user="alice" lat="47.05" lon="18.05">
+      <tag k="addr:street" v="Tűzkő utca"/>
+      <tag k="addr:housenumber" v="3"/>
+    </node>
+  </create>
+  <modify>
+    <way id="200" version="2" timestamp="2024-08-02T11:00:00Z" user="bob">
+      <nd ref="1"/>
+      <nd ref="2"/>
+      <tag k="highway" v="residential"/>
+      <tag k="name" v="Tűzkő utca"/>
+    </way>
+  </modify>
+  <delete>
+    <node id="5" version="3" timestamp="2024-08-02T12:00:00Z" user="alice"/>
+  </delete>
+</osmChange>
+"#;
+
+    let changes = parse_osc(osc).unwrap();
+
+    assert_eq!(changes.len(), 3);
+    assert_eq!(changes[0].action, Action::Create);
+    assert_eq!(changes[0].osm_type, "node");
+    assert_eq!(changes[0].id, 7);
+    assert_eq!(changes[0].user, "alice");
+    assert_eq!(changes[0].tags["addr:housenumber"], "3");
+    assert_eq!(changes[1].action, Action::Modify);
+    assert_eq!(changes[1].osm_type, "way");
+    assert_eq!(changes[1].timestamp, "2024-08-02T11:00:00Z");
+    assert_eq!(changes[1].tags.len(), 2);
+    assert_eq!(changes[2].action, Action::Delete);
+    assert_eq!(changes[2].id, 5);
+    assert_eq!(changes[2].tags.is_empty(), true);
+}
+
+/// Tests read_diffs(): sorting, compressed and uncompressed changes, missing changes.
+#[test]
+fn test_read_diffs() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    let osc = r#"<osmChange version="0.6"><delete><node id="5"/></delete></osmChange>"#;
+    let state1 = context::tests::TestFileSystem::make_file();
+    state1
+        .lock()
+        .unwrap()
+        .write_all(b"sequenceNumber=2\ntimestamp=2024-08-02T00\\:00\\:00Z\n")
+        .unwrap();
+    let osc1 = context::tests::TestFileSystem::make_file();
+    {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(osc.as_bytes()).unwrap();
+        osc1.lock()
+            .unwrap()
+            .write_all(&encoder.finish().unwrap())
+            .unwrap();
+    }
+    let state0 = context::tests::TestFileSystem::make_file();
+    state0
+        .lock()
+        .unwrap()
+        .write_all(b"sequenceNumber=1\ntimestamp=2024-08-01T00\\:00\\:00Z\n")
+        .unwrap();
+    let osc0 = context::tests::TestFileSystem::make_file();
+    osc0.lock().unwrap().write_all(osc.as_bytes()).unwrap();
+    let state2 = context::tests::TestFileSystem::make_file();
+    state2
+        .lock()
+        .unwrap()
+        .write_all(b"sequenceNumber=3\ntimestamp=2024-08-03T00\\:00\\:00Z\n")
+        .unwrap();
+    let files = context::tests::TestFileSystem::make_files(
+        &ctx,
+        &[
+            ("workdir/replication/002.state.txt", &state1),
+            ("workdir/replication/002.osc.gz", &osc1),
+            ("workdir/replication/001.state.txt", &state0),
+            ("workdir/replication/001.osc", &osc0),
+            ("workdir/replication/003.state.txt", &state2),
+        ],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    ctx.set_file_system(&file_system);
+
+    let diffs = read_diffs(&ctx).unwrap();
+
+    let sequences: Vec<u64> = diffs.iter().map(|diff| diff.sequence).collect();
+    assert_eq!(sequences, vec![1, 2]);
+    assert_eq!(diffs[0].get_changes(&ctx).unwrap()[0].id, 5);
+    assert_eq!(diffs[1].get_changes(&ctx).unwrap()[0].id, 5);
+}
+
+/// Tests read_diffs(), when the replication directory doesn't exist.
+#[test]
+fn test_read_diffs_no_dir() {
+    let ctx = context::tests::make_test_context().unwrap();
+
+    let diffs = read_diffs(&ctx).unwrap();
+
+    assert_eq!(diffs.is_empty(), true);
+}
+
+/// Tests find_pending().
+#[test]
+fn test_find_pending() {
+    let diffs = vec![make_diff(1, 100), make_diff(2, 200), make_diff(3, 300)];
+    let at = |timestamp| time::OffsetDateTime::from_unix_timestamp(timestamp).unwrap();
+
+    // Pending diffs.
+    assert_eq!(find_pending(&diffs, at(200)), Some(2));
+    assert_eq!(find_pending(&diffs, at(150)), Some(1));
+    // Up to date.
+    assert_eq!(find_pending(&diffs, at(300)), Some(3));
+    assert_eq!(find_pending(&diffs, at(400)), Some(3));
+    // Data is older than all diffs.
+    assert_eq!(find_pending(&diffs, at(50)), None);
+    // No diffs.
+    assert_eq!(find_pending(&[], at(50)), None);
+}
+
+/// Tests find_pending(), when a sequence is missing.
+#[test]
+fn test_find_pending_gap() {
+    let diffs = vec![make_diff(1, 100), make_diff(2, 200), make_diff(4, 400)];
+    let at = |timestamp| time::OffsetDateTime::from_unix_timestamp(timestamp).unwrap();
+
+    assert_eq!(find_pending(&diffs, at(100)), None);
+    // The gap is already applied.
+    assert_eq!(find_pending(&diffs, at(400)), Some(3));
+}
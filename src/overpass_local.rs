@@ -0,0 +1,1809 @@
+/*
+ * Copyright 2024 Miklos Vajna
+ *
+ * SPDX-License-Identifier: MIT
+ */
+
+#![deny(warnings)]
+#![warn(clippy::all)]
+#![warn(missing_docs)]
+
+//! The overpass_local module answers overpass queries from a local OSM extract, without network
+//! access.
+
+use crate::context;
+use anyhow::Context as _;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::io::Read as _;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// OSM object type, ordered the same way as overpass orders its output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum ElementType {
+    Node,
+    Way,
+    Relation,
+}
+
+impl ElementType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ElementType::Node => "node",
+            ElementType::Way => "way",
+            ElementType::Relation => "relation",
+        }
+    }
+}
+
+impl TryFrom<&str> for ElementType {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "node" => Ok(ElementType::Node),
+            "way" => Ok(ElementType::Way),
+            "rel" | "relation" => Ok(ElementType::Relation),
+            _ => Err(anyhow::anyhow!("invalid element type: {value}")),
+        }
+    }
+}
+
+/// A member of a relation.
+#[derive(Clone)]
+struct Member {
+    element_type: ElementType,
+    id: i64,
+    role: String,
+}
+
+/// A node, way or relation from the extract.
+#[derive(Clone, Default)]
+struct Element {
+    tags: BTreeMap<String, String>,
+    version: Option<i64>,
+    timestamp: Option<String>,
+    changeset: Option<i64>,
+    user: Option<String>,
+    uid: Option<i64>,
+    /// Only set for nodes.
+    lat: f64,
+    /// Only set for nodes.
+    lon: f64,
+    /// Only set for ways.
+    nodes: Vec<i64>,
+    /// Only set for relations.
+    members: Vec<Member>,
+}
+
+/// The parsed content of one or more OSM extract files.
+#[derive(Default)]
+struct Extract {
+    nodes: BTreeMap<i64, Element>,
+    ways: BTreeMap<i64, Element>,
+    relations: BTreeMap<i64, Element>,
+    /// Timestamp of the data, in RFC 3339 format, may be empty.
+    timestamp: String,
+}
+
+impl Extract {
+    fn get_elements(&self, element_type: ElementType) -> &BTreeMap<i64, Element> {
+        match element_type {
+            ElementType::Node => &self.nodes,
+            ElementType::Way => &self.ways,
+            ElementType::Relation => &self.relations,
+        }
+    }
+
+    fn get_elements_mut(&mut self, element_type: ElementType) -> &mut BTreeMap<i64, Element> {
+        match element_type {
+            ElementType::Node => &mut self.nodes,
+            ElementType::Way => &mut self.ways,
+            ElementType::Relation => &mut self.relations,
+        }
+    }
+}
+
+/// Formats a unix timestamp the way overpass does.
+fn format_unix_timestamp(seconds: i64) -> anyhow::Result<String> {
+    let date_time = time::OffsetDateTime::from_unix_timestamp(seconds)?;
+    Ok(date_time.format(&time::format_description::well_known::Rfc3339)?)
+}
+
+/// Collects the attributes of an XML element.
+pub fn get_xml_attributes(
+    start: &quick_xml::events::BytesStart<'_>,
+) -> anyhow::Result<HashMap<String, String>> {
+    let mut ret = HashMap::new();
+    for attribute in start.attributes() {
+        let attribute = attribute?;
+        let key = String::from_utf8(attribute.key.as_ref().to_vec())?;
+        let value = attribute.unescape_value()?.to_string();
+        ret.insert(key, value);
+    }
+    Ok(ret)
+}
+
+/// Parses an OSM XML extract.
+fn parse_xml(extract: &mut Extract, xml: &str) -> anyhow::Result<()> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut current: Option<(ElementType, i64, Element)> = None;
+    loop {
+        let (start, is_empty) = match reader.read_event()? {
+            quick_xml::events::Event::Start(start) => (start, false),
+            quick_xml::events::Event::Empty(start) => (start, true),
+            quick_xml::events::Event::End(end) => {
+                let name = String::from_utf8(end.name().as_ref().to_vec())?;
+                if ElementType::try_from(name.as_str()).is_ok() {
+                    if let Some((element_type, id, element)) = current.take() {
+                        extract.get_elements_mut(element_type).insert(id, element);
+                    }
+                }
+                continue;
+            }
+            quick_xml::events::Event::Eof => {
+                break;
+            }
+            _ => {
+                continue;
+            }
+        };
+        let name = String::from_utf8(start.name().as_ref().to_vec())?;
+        let attributes = get_xml_attributes(&start)?;
+        match name.as_str() {
+            "osm" => {
+                if let Some(value) = attributes.get("timestamp") {
+                    extract.timestamp = value.to_string();
+                }
+            }
+            "meta" => {
+                if let Some(value) = attributes.get("osm_base") {
+                    extract.timestamp = value.to_string();
+                }
+            }
+            "node" | "way" | "relation" => {
+                let element_type = ElementType::try_from(name.as_str())?;
+                let id: i64 = attributes.get("id").context("no id")?.parse()?;
+                let element = Element {
+                    version: attributes.get("version").and_then(|i| i.parse().ok()),
+                    timestamp: attributes.get("timestamp").cloned(),
+                    changeset: attributes.get("changeset").and_then(|i| i.parse().ok()),
+                    user: attributes.get("user").cloned(),
+                    uid: attributes.get("uid").and_then(|i| i.parse().ok()),
+                    lat: attributes
+                        .get("lat")
+                        .and_then(|i| i.parse().ok())
+                        .unwrap_or_default(),
+                    lon: attributes
+                        .get("lon")
+                        .and_then(|i| i.parse().ok())
+                        .unwrap_or_default(),
+                    ..Default::default()
+                };
+                if is_empty {
+                    extract.get_elements_mut(element_type).insert(id, element);
+                } else {
+                    current = Some((element_type, id, element));
+                }
+            }
+            "tag" => {
+                if let Some((_, _, element)) = current.as_mut() {
+                    let key = attributes.get("k").context("no k")?;
+                    let value = attributes.get("v").context("no v")?;
+                    element.tags.insert(key.to_string(), value.to_string());
+                }
+            }
+            "nd" => {
+                if let Some((_, _, element)) = current.as_mut() {
+                    element
+                        .nodes
+                        .push(attributes.get("ref").context("no ref")?.parse()?);
+                }
+            }
+            "member" => {
+                if let Some((_, _, element)) = current.as_mut() {
+                    let member_type = attributes.get("type").context("no type")?;
+                    element.members.push(Member {
+                        element_type: ElementType::try_from(member_type.as_str())?,
+                        id: attributes.get("ref").context("no ref")?.parse()?,
+                        role: attributes.get("role").cloned().unwrap_or_default(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// A value from the protobuf wire format.
+enum ProtobufValue<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// Minimal protobuf reader, just enough for the OSM PBF format.
+struct ProtobufReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ProtobufReader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        ProtobufReader { buf, pos: 0 }
+    }
+
+    fn read_varint(&mut self) -> anyhow::Result<u64> {
+        let mut ret: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = *self.buf.get(self.pos).context("truncated varint")?;
+            self.pos += 1;
+            ret |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(ret);
+            }
+            shift += 7;
+            if shift >= 64 {
+                return Err(anyhow::anyhow!("too long varint"));
+            }
+        }
+    }
+
+    /// Reads the next field number and its value.
+    fn next_field(&mut self) -> anyhow::Result<Option<(u64, ProtobufValue<'a>)>> {
+        if self.pos >= self.buf.len() {
+            return Ok(None);
+        }
+
+        let key = self.read_varint()?;
+        let value = match key & 7 {
+            0 => ProtobufValue::Varint(self.read_varint()?),
+            1 => {
+                self.pos += 8;
+                ProtobufValue::Fixed
+            }
+            2 => {
+                let len = usize::try_from(self.read_varint()?)?;
+                let end = self.pos.checked_add(len).context("truncated bytes")?;
+                let bytes = self.buf.get(self.pos..end).context("truncated bytes")?;
+                self.pos = end;
+                ProtobufValue::Bytes(bytes)
+            }
+            5 => {
+                self.pos += 4;
+                ProtobufValue::Fixed
+            }
+            wire_type => {
+                return Err(anyhow::anyhow!("unexpected wire type: {wire_type}"));
+            }
+        };
+        Ok(Some((key >> 3, value)))
+    }
+}
+
+impl<'a> ProtobufValue<'a> {
+    fn as_u64(&self) -> anyhow::Result<u64> {
+        match self {
+            ProtobufValue::Varint(value) => Ok(*value),
+            _ => Err(anyhow::anyhow!("expected varint")),
+        }
+    }
+
+    fn as_i64(&self) -> anyhow::Result<i64> {
+        Ok(self.as_u64()? as i64)
+    }
+
+    fn as_sint64(&self) -> anyhow::Result<i64> {
+        Ok(zigzag_decode(self.as_u64()?))
+    }
+
+    fn as_bytes(&self) -> anyhow::Result<&'a [u8]> {
+        match self {
+            ProtobufValue::Bytes(value) => Ok(value),
+            _ => Err(anyhow::anyhow!("expected bytes")),
+        }
+    }
+
+    /// Reads a repeated varint field, packed or not.
+    fn as_varints(&self) -> anyhow::Result<Vec<u64>> {
+        match self {
+            ProtobufValue::Varint(value) => Ok(vec![*value]),
+            ProtobufValue::Bytes(bytes) => {
+                let mut reader = ProtobufReader::new(bytes);
+                let mut ret = Vec::new();
+                while reader.pos < bytes.len() {
+                    ret.push(reader.read_varint()?);
+                }
+                Ok(ret)
+            }
+            ProtobufValue::Fixed => Err(anyhow::anyhow!("expected varints")),
+        }
+    }
+
+    fn as_sint64s(&self) -> anyhow::Result<Vec<i64>> {
+        Ok(self.as_varints()?.into_iter().map(zigzag_decode).collect())
+    }
+}
+
+fn zigzag_decode(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+/// Turns a list of deltas into a list of values.
+fn delta_decode(values: &[i64]) -> Vec<i64> {
+    let mut sum = 0;
+    values
+        .iter()
+        .map(|value| {
+            sum += value;
+            sum
+        })
+        .collect()
+}
+
+/// Context of an OSM PBF primitive block, needed to decode its elements.
+struct PrimitiveBlock {
+    strings: Vec<String>,
+    granularity: i64,
+    lat_offset: i64,
+    lon_offset: i64,
+    date_granularity: i64,
+}
+
+impl PrimitiveBlock {
+    fn get_string(&self, index: u64) -> anyhow::Result<String> {
+        let index = usize::try_from(index)?;
+        Ok(self
+            .strings
+            .get(index)
+            .context("string index out of range")?
+            .to_string())
+    }
+
+    fn get_coordinate(&self, offset: i64, value: i64) -> f64 {
+        1e-9 * (offset + self.granularity * value) as f64
+    }
+
+    fn get_timestamp(&self, value: i64) -> anyhow::Result<String> {
+        format_unix_timestamp(value * self.date_granularity / 1000)
+    }
+
+    fn set_tags(&self, element: &mut Element, keys: &[u64], values: &[u64]) -> anyhow::Result<()> {
+        for (key, value) in keys.iter().zip(values.iter()) {
+            element
+                .tags
+                .insert(self.get_string(*key)?, self.get_string(*value)?);
+        }
+        Ok(())
+    }
+
+    fn set_info(&self, element: &mut Element, info: &[u8]) -> anyhow::Result<()> {
+        let mut reader = ProtobufReader::new(info);
+        while let Some((field, value)) = reader.next_field()? {
+            match field {
+                1 => element.version = Some(value.as_i64()?),
+                2 => element.timestamp = Some(self.get_timestamp(value.as_i64()?)?),
+                3 => element.changeset = Some(value.as_i64()?),
+                4 => element.uid = Some(value.as_i64()?),
+                5 => element.user = Some(self.get_string(value.as_u64()?)?),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_node(&self, extract: &mut Extract, buf: &[u8]) -> anyhow::Result<()> {
+        let mut reader = ProtobufReader::new(buf);
+        let mut id = 0;
+        let mut keys = Vec::new();
+        let mut values = Vec::new();
+        let mut element = Element::default();
+        while let Some((field, value)) = reader.next_field()? {
+            match field {
+                1 => id = value.as_sint64()?,
+                2 => keys.append(&mut value.as_varints()?),
+                3 => values.append(&mut value.as_varints()?),
+                4 => self.set_info(&mut element, value.as_bytes()?)?,
+                8 => element.lat = self.get_coordinate(self.lat_offset, value.as_sint64()?),
+                9 => element.lon = self.get_coordinate(self.lon_offset, value.as_sint64()?),
+                _ => {}
+            }
+        }
+        self.set_tags(&mut element, &keys, &values)?;
+        extract.nodes.insert(id, element);
+        Ok(())
+    }
+
+    fn parse_dense_nodes(&self, extract: &mut Extract, buf: &[u8]) -> anyhow::Result<()> {
+        let mut reader = ProtobufReader::new(buf);
+        let mut ids = Vec::new();
+        let mut lats = Vec::new();
+        let mut lons = Vec::new();
+        let mut keys_values = Vec::new();
+        let mut versions = Vec::new();
+        let mut timestamps = Vec::new();
+        let mut changesets = Vec::new();
+        let mut uids = Vec::new();
+        let mut user_sids = Vec::new();
+        while let Some((field, value)) = reader.next_field()? {
+            match field {
+                1 => ids.append(&mut value.as_sint64s()?),
+                5 => {
+                    let mut info_reader = ProtobufReader::new(value.as_bytes()?);
+                    while let Some((field, value)) = info_reader.next_field()? {
+                        match field {
+                            1 => versions.append(&mut value.as_varints()?),
+                            2 => timestamps.append(&mut value.as_sint64s()?),
+                            3 => changesets.append(&mut value.as_sint64s()?),
+                            4 => uids.append(&mut value.as_sint64s()?),
+                            5 => user_sids.append(&mut value.as_sint64s()?),
+                            _ => {}
+                        }
+                    }
+                }
+                8 => lats.append(&mut value.as_sint64s()?),
+                9 => lons.append(&mut value.as_sint64s()?),
+                10 => keys_values.append(&mut value.as_varints()?),
+                _ => {}
+            }
+        }
+        let ids = delta_decode(&ids);
+        let lats = delta_decode(&lats);
+        let lons = delta_decode(&lons);
+        let timestamps = delta_decode(&timestamps);
+        let changesets = delta_decode(&changesets);
+        let uids = delta_decode(&uids);
+        let user_sids = delta_decode(&user_sids);
+        let mut keys_values = keys_values.into_iter();
+        for (index, id) in ids.iter().enumerate() {
+            let mut element = Element {
+                lat: self.get_coordinate(self.lat_offset, *lats.get(index).context("no lat")?),
+                lon: self.get_coordinate(self.lon_offset, *lons.get(index).context("no lon")?),
+                ..Default::default()
+            };
+            if let Some(version) = versions.get(index) {
+                element.version = Some(*version as i64);
+            }
+            if let Some(timestamp) = timestamps.get(index) {
+                element.timestamp = Some(self.get_timestamp(*timestamp)?);
+            }
+            element.changeset = changesets.get(index).cloned();
+            element.uid = uids.get(index).cloned();
+            if let Some(user_sid) = user_sids.get(index) {
+                element.user = Some(self.get_string(*user_sid as u64)?);
+            }
+            // Keys and values are interleaved, a 0 ends the tags of one node.
+            while let Some(key) = keys_values.next() {
+                if key == 0 {
+                    break;
+                }
+                let value = keys_values.next().context("no value for key")?;
+                element
+                    .tags
+                    .insert(self.get_string(key)?, self.get_string(value)?);
+            }
+            extract.nodes.insert(*id, element);
+        }
+        Ok(())
+    }
+
+    fn parse_way(&self, extract: &mut Extract, buf: &[u8]) -> anyhow::Result<()> {
+        let mut reader = ProtobufReader::new(buf);
+        let mut id = 0;
+        let mut keys = Vec::new();
+        let mut values = Vec::new();
+        let mut refs = Vec::new();
+        let mut element = Element::default();
+        while let Some((field, value)) = reader.next_field()? {
+            match field {
+                1 => id = value.as_i64()?,
+                2 => keys.append(&mut value.as_varints()?),
+                3 => values.append(&mut value.as_varints()?),
+                4 => self.set_info(&mut element, value.as_bytes()?)?,
+                8 => refs.append(&mut value.as_sint64s()?),
+                _ => {}
+            }
+        }
+        self.set_tags(&mut element, &keys, &values)?;
+        element.nodes = delta_decode(&refs);
+        extract.ways.insert(id, element);
+        Ok(())
+    }
+
+    fn parse_relation(&self, extract: &mut Extract, buf: &[u8]) -> anyhow::Result<()> {
+        let mut reader = ProtobufReader::new(buf);
+        let mut id = 0;
+        let mut keys = Vec::new();
+        let mut values = Vec::new();
+        let mut roles = Vec::new();
+        let mut member_ids = Vec::new();
+        let mut types = Vec::new();
+        let mut element = Element::default();
+        while let Some((field, value)) = reader.next_field()? {
+            match field {
+                1 => id = value.as_i64()?,
+                2 => keys.append(&mut value.as_varints()?),
+                3 => values.append(&mut value.as_varints()?),
+                4 => self.set_info(&mut element, value.as_bytes()?)?,
+                8 => roles.append(&mut value.as_varints()?),
+                9 => member_ids.append(&mut value.as_sint64s()?),
+                10 => types.append(&mut value.as_varints()?),
+                _ => {}
+            }
+        }
+        self.set_tags(&mut element, &keys, &values)?;
+        let member_ids = delta_decode(&member_ids);
+        for (index, member_id) in member_ids.iter().enumerate() {
+            let element_type = match types.get(index) {
+                Some(0) => ElementType::Node,
+                Some(1) => ElementType::Way,
+                Some(2) => ElementType::Relation,
+                _ => {
+                    return Err(anyhow::anyhow!("invalid member type"));
+                }
+            };
+            let role = match roles.get(index) {
+                Some(role) => self.get_string(*role)?,
+                None => "".into(),
+            };
+            element.members.push(Member {
+                element_type,
+                id: *member_id,
+                role,
+            });
+        }
+        extract.relations.insert(id, element);
+        Ok(())
+    }
+}
+
+/// Parses one OSMData block of an OSM PBF extract.
+fn parse_pbf_data(extract: &mut Extract, buf: &[u8]) -> anyhow::Result<()> {
+    let mut block = PrimitiveBlock {
+        strings: Vec::new(),
+        granularity: 100,
+        lat_offset: 0,
+        lon_offset: 0,
+        date_granularity: 1000,
+    };
+    let mut groups = Vec::new();
+    let mut reader = ProtobufReader::new(buf);
+    while let Some((field, value)) = reader.next_field()? {
+        match field {
+            1 => {
+                let mut string_reader = ProtobufReader::new(value.as_bytes()?);
+                while let Some((_field, value)) = string_reader.next_field()? {
+                    block
+                        .strings
+                        .push(String::from_utf8_lossy(value.as_bytes()?).to_string());
+                }
+            }
+            2 => groups.push(value.as_bytes()?),
+            17 => block.granularity = value.as_i64()?,
+            18 => block.date_granularity = value.as_i64()?,
+            19 => block.lat_offset = value.as_i64()?,
+            20 => block.lon_offset = value.as_i64()?,
+            _ => {}
+        }
+    }
+
+    for group in groups {
+        let mut reader = ProtobufReader::new(group);
+        while let Some((field, value)) = reader.next_field()? {
+            match field {
+                1 => block.parse_node(extract, value.as_bytes()?)?,
+                2 => block.parse_dense_nodes(extract, value.as_bytes()?)?,
+                3 => block.parse_way(extract, value.as_bytes()?)?,
+                4 => block.parse_relation(extract, value.as_bytes()?)?,
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parses an OSM PBF extract.
+fn parse_pbf(extract: &mut Extract, pbf: &[u8]) -> anyhow::Result<()> {
+    let mut pos = 0;
+    while pos < pbf.len() {
+        let header_len_bytes: [u8; 4] = pbf
+            .get(pos..pos + 4)
+            .context("truncated blob header length")?
+            .try_into()?;
+        let header_len = usize::try_from(u32::from_be_bytes(header_len_bytes))?;
+        pos += 4;
+        let header = pbf
+            .get(pos..pos + header_len)
+            .context("truncated blob header")?;
+        pos += header_len;
+        let mut blob_type = String::new();
+        let mut data_size = 0;
+        let mut reader = ProtobufReader::new(header);
+        while let Some((field, value)) = reader.next_field()? {
+            match field {
+                1 => blob_type = String::from_utf8(value.as_bytes()?.to_vec())?,
+                3 => data_size = usize::try_from(value.as_u64()?)?,
+                _ => {}
+            }
+        }
+        let blob = pbf.get(pos..pos + data_size).context("truncated blob")?;
+        pos += data_size;
+
+        let mut data: Vec<u8> = Vec::new();
+        let mut reader = ProtobufReader::new(blob);
+        while let Some((field, value)) = reader.next_field()? {
+            match field {
+                1 => data = value.as_bytes()?.to_vec(),
+                3 => {
+                    let mut decoder = flate2::read::ZlibDecoder::new(value.as_bytes()?);
+                    decoder.read_to_end(&mut data)?;
+                }
+                4..=7 => {
+                    return Err(anyhow::anyhow!("unsupported blob compression"));
+                }
+                _ => {}
+            }
+        }
+
+        match blob_type.as_str() {
+            "OSMHeader" => {
+                let mut reader = ProtobufReader::new(&data);
+                while let Some((field, value)) = reader.next_field()? {
+                    // osmosis_replication_timestamp
+                    if field == 32 {
+                        extract.timestamp = format_unix_timestamp(value.as_i64()?)?;
+                    }
+                }
+            }
+            "OSMData" => parse_pbf_data(extract, &data)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Loads the extract from paths.
+fn load_extract(
+    file_system: &Arc<dyn context::FileSystem>,
+    paths: &[String],
+    mtime: time::OffsetDateTime,
+) -> anyhow::Result<Extract> {
+    let mut extract = Extract::default();
+    for path in paths {
+        let stream = file_system.open_read(path)?;
+        let mut buf: Vec<u8> = Vec::new();
+        stream.lock().unwrap().read_to_end(&mut buf)?;
+        if path.ends_with(".pbf") {
+            parse_pbf(&mut extract, &buf).context(format!("failed to parse '{path}'"))?;
+        } else {
+            let xml = String::from_utf8(buf)?;
+            parse_xml(&mut extract, &xml).context(format!("failed to parse '{path}'"))?;
+        }
+    }
+    if extract.timestamp.is_empty() {
+        extract.timestamp = format_unix_timestamp(mtime.unix_timestamp())?;
+    }
+    Ok(extract)
+}
+
+/// How a query wants its results to be formatted.
+enum OutputFormat {
+    Json,
+    /// List of columns, e.g. "::id" or "addr:street".
+    Csv(Vec<String>),
+}
+
+/// A tag filter like `[highway]` or `["name"~"utca"]`.
+enum Filter {
+    Exists(String),
+    NotExists(String),
+    Equals(String, String),
+    NotEquals(String, String),
+    Matches(String, regex::Regex),
+    NotMatches(String, regex::Regex),
+}
+
+impl Filter {
+    fn matches(&self, tags: &BTreeMap<String, String>) -> bool {
+        match self {
+            Filter::Exists(key) => tags.contains_key(key),
+            Filter::NotExists(key) => !tags.contains_key(key),
+            Filter::Equals(key, value) => tags.get(key) == Some(value),
+            Filter::NotEquals(key, value) => tags.get(key) != Some(value),
+            Filter::Matches(key, regex) => match tags.get(key) {
+                Some(value) => regex.is_match(value),
+                None => false,
+            },
+            Filter::NotMatches(key, regex) => match tags.get(key) {
+                Some(value) => !regex.is_match(value),
+                None => true,
+            },
+        }
+    }
+}
+
+/// Restricts the elements of a query statement, the part in parenthesis.
+enum Input {
+    /// Elements inside one of the areas of this set.
+    Area(String),
+    /// Elements which are members of one of the relations of this set.
+    RelationMembers(String),
+    /// Nodes of one of the ways of this set.
+    WayNodes(String),
+    /// Elements with one of these ids.
+    Ids(Vec<i64>),
+}
+
+/// Verbosity of an out statement.
+#[derive(Clone, Copy)]
+enum Verbosity {
+    Skel,
+    Body,
+    Meta,
+}
+
+enum Statement {
+    Area {
+        ids: Vec<i64>,
+        output: String,
+    },
+    Query {
+        types: Vec<ElementType>,
+        inputs: Vec<Input>,
+        filters: Vec<Filter>,
+        output: String,
+    },
+    Union {
+        statements: Vec<Statement>,
+        output: String,
+    },
+    RecurseDown {
+        output: String,
+    },
+    Out {
+        verbosity: Verbosity,
+        /// Include the coordinates of way nodes.
+        geom: bool,
+    },
+}
+
+/// The parsed form of an overpass query.
+struct Query {
+    format: OutputFormat,
+    statements: Vec<Statement>,
+}
+
+/// Parses the subset of the overpass query language that our templates use.
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(query: &str) -> Self {
+        // Ignore overpass turbo style blocks.
+        let mut query = query.to_string();
+        while let Some(start) = query.find("{{") {
+            let end = match query[start..].find("}}") {
+                Some(value) => start + value + 2,
+                None => query.len(),
+            };
+            query.replace_range(start..end, "");
+        }
+        Parser {
+            chars: query.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn starts_with(&self, prefix: &str) -> bool {
+        prefix
+            .chars()
+            .enumerate()
+            .all(|(index, c)| self.chars.get(self.pos + index) == Some(&c))
+    }
+
+    fn skip_whitespace(&mut self) {
+        loop {
+            if self.starts_with("//") {
+                while let Some(c) = self.peek() {
+                    self.pos += 1;
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            } else if self.starts_with("/*") {
+                while self.peek().is_some() && !self.starts_with("*/") {
+                    self.pos += 1;
+                }
+                self.pos += 2;
+            } else if self.peek().map(|c| c.is_whitespace()) == Some(true) {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn error(&self, message: &str) -> anyhow::Error {
+        let context: String = self.chars.iter().skip(self.pos).take(40).collect();
+        anyhow::anyhow!("overpass query: {message} at '{context}'")
+    }
+
+    fn expect(&mut self, expected: char) -> anyhow::Result<()> {
+        self.skip_whitespace();
+        if self.peek() != Some(expected) {
+            return Err(self.error(&format!("expected '{expected}'")));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn parse_identifier(&mut self) -> String {
+        self.skip_whitespace();
+        let mut ret = String::new();
+        while let Some(c) = self.peek() {
+            if !c.is_alphanumeric() && c != '_' {
+                break;
+            }
+            ret.push(c);
+            self.pos += 1;
+        }
+        ret
+    }
+
+    /// Parses a quoted string or a bare word, as used for tag keys and values.
+    fn parse_string(&mut self) -> anyhow::Result<String> {
+        self.skip_whitespace();
+        let mut ret = String::new();
+        match self.peek() {
+            Some(quote) if quote == '"' || quote == '\'' => {
+                self.pos += 1;
+                loop {
+                    let c = self.peek().context("unterminated string")?;
+                    self.pos += 1;
+                    if c == quote {
+                        break;
+                    }
+                    if c == '\\' {
+                        let escaped = self.peek().context("unterminated string")?;
+                        self.pos += 1;
+                        match escaped {
+                            'n' => ret.push('\n'),
+                            't' => ret.push('\t'),
+                            _ => ret.push(escaped),
+                        }
+                        continue;
+                    }
+                    ret.push(c);
+                }
+            }
+            _ => {
+                while let Some(c) = self.peek() {
+                    if c.is_whitespace() || "[]()=!~,;".contains(c) {
+                        break;
+                    }
+                    ret.push(c);
+                    self.pos += 1;
+                }
+                if ret.is_empty() {
+                    return Err(self.error("expected string"));
+                }
+            }
+        }
+        Ok(ret)
+    }
+
+    fn parse_number(&mut self) -> anyhow::Result<i64> {
+        self.skip_whitespace();
+        let mut ret = String::new();
+        while let Some(c) = self.peek() {
+            if !c.is_ascii_digit() && c != '-' {
+                break;
+            }
+            ret.push(c);
+            self.pos += 1;
+        }
+        ret.parse().map_err(|_| self.error("expected number"))
+    }
+
+    /// Parses the optional `->.name` suffix of a statement.
+    fn parse_output(&mut self) -> anyhow::Result<String> {
+        self.skip_whitespace();
+        if !self.starts_with("->") {
+            return Ok("_".into());
+        }
+        self.pos += 2;
+        self.expect('.')?;
+        Ok(self.parse_identifier())
+    }
+
+    fn parse_settings(&mut self) -> anyhow::Result<OutputFormat> {
+        let mut format = OutputFormat::Json;
+        self.skip_whitespace();
+        if self.peek() != Some('[') {
+            return Ok(format);
+        }
+
+        while self.peek() == Some('[') {
+            self.pos += 1;
+            let key = self.parse_identifier();
+            self.expect(':')?;
+            if key == "out" {
+                let value = self.parse_identifier();
+                match value.as_str() {
+                    "json" => {}
+                    "csv" => {
+                        self.expect('(')?;
+                        let mut columns = Vec::new();
+                        loop {
+                            self.skip_whitespace();
+                            if self.starts_with("::") {
+                                self.pos += 2;
+                                columns.push(format!("::{}", self.parse_identifier()));
+                            } else {
+                                columns.push(self.parse_string()?);
+                            }
+                            self.skip_whitespace();
+                            match self.peek() {
+                                Some(',') => self.pos += 1,
+                                _ => break,
+                            }
+                        }
+                        // Ignore the optional header and separator settings.
+                        while self.peek().is_some() && self.peek() != Some(')') {
+                            self.pos += 1;
+                        }
+                        self.expect(')')?;
+                        format = OutputFormat::Csv(columns);
+                    }
+                    _ => {
+                        return Err(self.error(&format!("unsupported output format '{value}'")));
+                    }
+                }
+            }
+            // Ignore other settings, like the timeout.
+            while self.peek().is_some() && self.peek() != Some(']') {
+                self.pos += 1;
+            }
+            self.expect(']')?;
+            self.skip_whitespace();
+        }
+        self.expect(';')?;
+        Ok(format)
+    }
+
+    fn parse_input(&mut self) -> anyhow::Result<Input> {
+        self.skip_whitespace();
+        let input = if self.peek().map(|c| c.is_ascii_digit()) == Some(true) {
+            let mut ids = vec![self.parse_number()?];
+            self.skip_whitespace();
+            while self.peek() == Some(',') {
+                self.pos += 1;
+                ids.push(self.parse_number()?);
+                self.skip_whitespace();
+            }
+            Input::Ids(ids)
+        } else {
+            let kind = self.parse_identifier();
+            self.skip_whitespace();
+            let mut set = String::from("_");
+            if self.peek() == Some('.') {
+                self.pos += 1;
+                set = self.parse_identifier();
+            }
+            match kind.as_str() {
+                "area" => Input::Area(set),
+                "r" => Input::RelationMembers(set),
+                "w" => Input::WayNodes(set),
+                _ => {
+                    return Err(self.error(&format!("unsupported filter '{kind}'")));
+                }
+            }
+        };
+        self.expect(')')?;
+        Ok(input)
+    }
+
+    fn parse_filter(&mut self) -> anyhow::Result<Filter> {
+        self.skip_whitespace();
+        let negated = self.peek() == Some('!');
+        if negated {
+            self.pos += 1;
+        }
+        let key = self.parse_string()?;
+        self.skip_whitespace();
+        let filter = if negated {
+            Filter::NotExists(key)
+        } else if self.peek() == Some(']') {
+            Filter::Exists(key)
+        } else if self.starts_with("!=") {
+            self.pos += 2;
+            Filter::NotEquals(key, self.parse_string()?)
+        } else if self.starts_with("=") {
+            self.pos += 1;
+            Filter::Equals(key, self.parse_string()?)
+        } else if self.starts_with("!~") || self.starts_with("~") {
+            let negated = self.peek() == Some('!');
+            self.pos += if negated { 2 } else { 1 };
+            let mut pattern = self.parse_string()?;
+            self.skip_whitespace();
+            if self.peek() == Some(',') {
+                self.pos += 1;
+                if self.parse_identifier() == "i" {
+                    pattern = format!("(?i){pattern}");
+                }
+            }
+            let regex = regex::Regex::new(&pattern)?;
+            if negated {
+                Filter::NotMatches(key, regex)
+            } else {
+                Filter::Matches(key, regex)
+            }
+        } else {
+            return Err(self.error("unsupported tag filter"));
+        };
+        self.expect(']')?;
+        Ok(filter)
+    }
+
+    fn parse_statement(&mut self) -> anyhow::Result<Statement> {
+        self.skip_whitespace();
+        if self.peek() == Some('(') {
+            self.pos += 1;
+            let mut statements = Vec::new();
+            loop {
+                self.skip_whitespace();
+                if self.peek() == Some(')') {
+                    self.pos += 1;
+                    break;
+                }
+                statements.push(self.parse_statement()?);
+            }
+            let output = self.parse_output()?;
+            self.expect(';')?;
+            return Ok(Statement::Union { statements, output });
+        }
+
+        if self.peek() == Some('>') {
+            self.pos += 1;
+            let output = self.parse_output()?;
+            self.expect(';')?;
+            return Ok(Statement::RecurseDown { output });
+        }
+
+        let keyword = self.parse_identifier();
+        let statement = match keyword.as_str() {
+            "out" => {
+                let mut verbosity = Verbosity::Body;
+                let mut geom = false;
+                loop {
+                    let word = self.parse_identifier();
+                    match word.as_str() {
+                        "" => break,
+                        "ids" | "skel" => verbosity = Verbosity::Skel,
+                        "body" | "tags" => verbosity = Verbosity::Body,
+                        "meta" => verbosity = Verbosity::Meta,
+                        "geom" => geom = true,
+                        // Sorting and limits don't matter.
+                        _ => {}
+                    }
+                }
+                Statement::Out { verbosity, geom }
+            }
+            "area" => {
+                self.expect('(')?;
+                let ids = match self.parse_input()? {
+                    Input::Ids(ids) => ids,
+                    _ => {
+                        return Err(self.error("expected area id"));
+                    }
+                };
+                let output = self.parse_output()?;
+                Statement::Area { ids, output }
+            }
+            "node" | "way" | "rel" | "relation" | "nwr" => {
+                let types = match keyword.as_str() {
+                    "nwr" => vec![ElementType::Node, ElementType::Way, ElementType::Relation],
+                    _ => vec![ElementType::try_from(keyword.as_str())?],
+                };
+                let mut inputs = Vec::new();
+                let mut filters = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some('(') => {
+                            self.pos += 1;
+                            inputs.push(self.parse_input()?);
+                        }
+                        Some('[') => {
+                            self.pos += 1;
+                            filters.push(self.parse_filter()?);
+                        }
+                        _ => break,
+                    }
+                }
+                let output = self.parse_output()?;
+                Statement::Query {
+                    types,
+                    inputs,
+                    filters,
+                    output,
+                }
+            }
+            _ => {
+                return Err(self.error("unsupported statement"));
+            }
+        };
+        self.expect(';')?;
+        Ok(statement)
+    }
+
+    fn parse(mut self) -> anyhow::Result<Query> {
+        let format = self.parse_settings()?;
+        let mut statements = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek().is_none() {
+                break;
+            }
+            statements.push(self.parse_statement()?);
+        }
+        Ok(Query { format, statements })
+    }
+}
+
+/// Boundary of an area, a set of segments bucketed into horizontal bands for faster lookup.
+pub struct Area {
+    min_lat: f64,
+    max_lat: f64,
+    min_lon: f64,
+    max_lon: f64,
+    band_height: f64,
+    /// Segments as (lon1, lat1, lon2, lat2).
+    bands: Vec<Vec<(f64, f64, f64, f64)>>,
+}
+
+impl Area {
+    /// Creates an area from its boundary segments as (lon1, lat1, lon2, lat2), None if there are
+    /// no segments.
+    pub fn new(segments: &[(f64, f64, f64, f64)]) -> Option<Self> {
+        if segments.is_empty() {
+            return None;
+        }
+
+        let mut min_lat = f64::MAX;
+        let mut max_lat = f64::MIN;
+        let mut min_lon = f64::MAX;
+        let mut max_lon = f64::MIN;
+        for (lon1, lat1, lon2, lat2) in segments {
+            min_lat = min_lat.min(*lat1).min(*lat2);
+            max_lat = max_lat.max(*lat1).max(*lat2);
+            min_lon = min_lon.min(*lon1).min(*lon2);
+            max_lon = max_lon.max(*lon1).max(*lon2);
+        }
+        let band_count = std::cmp::min(1024, segments.len() / 4 + 1);
+        let band_height = (max_lat - min_lat) / band_count as f64;
+        let mut bands = vec![Vec::new(); band_count];
+        let get_band = |lat: f64| -> usize {
+            if band_height <= 0.0 {
+                return 0;
+            }
+            let band = ((lat - min_lat) / band_height) as usize;
+            std::cmp::min(band, band_count - 1)
+        };
+        for segment in segments {
+            let (_, lat1, _, lat2) = segment;
+            let first = get_band(lat1.min(*lat2));
+            let last = get_band(lat1.max(*lat2));
+            for band in bands.iter_mut().take(last + 1).skip(first) {
+                band.push(*segment);
+            }
+        }
+        Some(Area {
+            min_lat,
+            max_lat,
+            min_lon,
+            max_lon,
+            band_height,
+            bands,
+        })
+    }
+
+    /// Decides if a point is inside the area, using the even-odd rule, so inner rings work.
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        if lat < self.min_lat || lat > self.max_lat || lon < self.min_lon || lon > self.max_lon {
+            return false;
+        }
+
+        let mut band = 0;
+        if self.band_height > 0.0 {
+            band = ((lat - self.min_lat) / self.band_height) as usize;
+            band = std::cmp::min(band, self.bands.len() - 1);
+        }
+        let mut inside = false;
+        for (lon1, lat1, lon2, lat2) in &self.bands[band] {
+            if (*lat1 > lat) != (*lat2 > lat) {
+                let cross_lon = lon1 + (lat - lat1) * (lon2 - lon1) / (lat2 - lat1);
+                if lon < cross_lon {
+                    inside = !inside;
+                }
+            }
+        }
+        inside
+    }
+}
+
+/// A set of elements or areas, the result of a statement.
+#[derive(Clone, Default)]
+struct Set {
+    elements: BTreeSet<(ElementType, i64)>,
+    areas: BTreeSet<i64>,
+}
+
+/// Executes a parsed query on an extract.
+struct Evaluator<'a> {
+    extract: &'a Extract,
+    sets: HashMap<String, Set>,
+    /// Area id -> boundary, None means the boundary is not in the extract.
+    areas: HashMap<i64, Option<Area>>,
+    /// Elements to output, with their verbosity and if their geometry is wanted.
+    output: Vec<(ElementType, i64, Verbosity, bool)>,
+}
+
+impl<'a> Evaluator<'a> {
+    fn new(extract: &'a Extract) -> Self {
+        Evaluator {
+            extract,
+            sets: HashMap::new(),
+            areas: HashMap::new(),
+            output: Vec::new(),
+        }
+    }
+
+    fn get_set(&self, name: &str) -> Set {
+        self.sets.get(name).cloned().unwrap_or_default()
+    }
+
+    /// Builds the boundary of an area from the ways of the matching relation or way.
+    fn make_area(&self, area_id: i64) -> Option<Area> {
+        let mut ways: Vec<&Element> = Vec::new();
+        if area_id >= 3600000000 {
+            let relation = self.extract.relations.get(&(area_id - 3600000000))?;
+            for member in &relation.members {
+                if member.element_type != ElementType::Way || member.role == "subarea" {
+                    continue;
+                }
+                if let Some(way) = self.extract.ways.get(&member.id) {
+                    ways.push(way);
+                }
+            }
+        } else if area_id >= 2400000000 {
+            ways.push(self.extract.ways.get(&(area_id - 2400000000))?);
+        }
+
+        let mut segments = Vec::new();
+        for way in ways {
+            let coordinates: Vec<(f64, f64)> = way
+                .nodes
+                .iter()
+                .filter_map(|id| self.extract.nodes.get(id))
+                .map(|node| (node.lon, node.lat))
+                .collect();
+            for pair in coordinates.windows(2) {
+                segments.push((pair[0].0, pair[0].1, pair[1].0, pair[1].1));
+            }
+        }
+        Area::new(&segments)
+    }
+
+    fn is_node_in_area(&self, area: &Option<Area>, id: i64) -> bool {
+        let area = match area {
+            Some(value) => value,
+            // No boundary: assume the extract is already limited to the area.
+            None => return true,
+        };
+        match self.extract.nodes.get(&id) {
+            Some(node) => area.contains(node.lat, node.lon),
+            None => false,
+        }
+    }
+
+    fn is_way_in_area(&self, area: &Option<Area>, id: i64) -> bool {
+        match self.extract.ways.get(&id) {
+            Some(way) => way
+                .nodes
+                .iter()
+                .any(|node| self.is_node_in_area(area, *node)),
+            None => false,
+        }
+    }
+
+    fn is_in_area(&self, area_id: i64, element_type: ElementType, id: i64) -> bool {
+        let area = &self.areas[&area_id];
+        match element_type {
+            ElementType::Node => self.is_node_in_area(area, id),
+            ElementType::Way => self.is_way_in_area(area, id),
+            ElementType::Relation => match self.extract.relations.get(&id) {
+                Some(relation) => relation
+                    .members
+                    .iter()
+                    .any(|member| match member.element_type {
+                        ElementType::Node => self.is_node_in_area(area, member.id),
+                        ElementType::Way => self.is_way_in_area(area, member.id),
+                        ElementType::Relation => false,
+                    }),
+                None => false,
+            },
+        }
+    }
+
+    fn is_relation_member(&self, set: &Set, element_type: ElementType, id: i64) -> bool {
+        set.elements.iter().any(|(set_type, set_id)| {
+            if *set_type != ElementType::Relation {
+                return false;
+            }
+            match self.extract.relations.get(set_id) {
+                Some(relation) => relation
+                    .members
+                    .iter()
+                    .any(|member| member.element_type == element_type && member.id == id),
+                None => false,
+            }
+        })
+    }
+
+    /// Collects the nodes of the ways of a set.
+    fn get_way_nodes(&self, set: &Set) -> HashSet<i64> {
+        let mut ret = HashSet::new();
+        for (element_type, id) in &set.elements {
+            if *element_type != ElementType::Way {
+                continue;
+            }
+            if let Some(way) = self.extract.ways.get(id) {
+                ret.extend(way.nodes.iter());
+            }
+        }
+        ret
+    }
+
+    fn eval_query(
+        &mut self,
+        types: &[ElementType],
+        inputs: &[Input],
+        filters: &[Filter],
+    ) -> anyhow::Result<Set> {
+        let mut ret = Set::default();
+        let mut area_ids: Vec<Vec<i64>> = Vec::new();
+        for input in inputs {
+            if let Input::Area(set) = input {
+                let areas: Vec<i64> = self.get_set(set).areas.into_iter().collect();
+                for area_id in &areas {
+                    if !self.areas.contains_key(area_id) {
+                        let area = self.make_area(*area_id);
+                        self.areas.insert(*area_id, area);
+                    }
+                }
+                area_ids.push(areas);
+            }
+        }
+        let mut way_nodes: HashMap<String, HashSet<i64>> = HashMap::new();
+        let mut relation_members: HashMap<String, Set> = HashMap::new();
+        for input in inputs {
+            match input {
+                Input::WayNodes(set) => {
+                    way_nodes.insert(set.to_string(), self.get_way_nodes(&self.get_set(set)));
+                }
+                Input::RelationMembers(set) => {
+                    relation_members.insert(set.to_string(), self.get_set(set));
+                }
+                _ => {}
+            }
+        }
+
+        for element_type in types {
+            for (id, element) in self.extract.get_elements(*element_type) {
+                if !filters.iter().all(|filter| filter.matches(&element.tags)) {
+                    continue;
+                }
+                let matches_inputs = inputs.iter().all(|input| match input {
+                    Input::Ids(ids) => ids.contains(id),
+                    Input::RelationMembers(set) => {
+                        self.is_relation_member(&relation_members[set], *element_type, *id)
+                    }
+                    Input::WayNodes(set) => {
+                        *element_type == ElementType::Node && way_nodes[set].contains(id)
+                    }
+                    Input::Area(_) => true,
+                });
+                if !matches_inputs {
+                    continue;
+                }
+                let in_areas = area_ids.iter().all(|areas| {
+                    areas
+                        .iter()
+                        .any(|area_id| self.is_in_area(*area_id, *element_type, *id))
+                });
+                if !in_areas {
+                    continue;
+                }
+                ret.elements.insert((*element_type, *id));
+            }
+        }
+        Ok(ret)
+    }
+
+    fn eval_recurse_down(&self) -> Set {
+        let mut ret = Set::default();
+        let input = self.get_set("_");
+        let add_way = |ret: &mut Set, id: i64| {
+            if let Some(way) = self.extract.ways.get(&id) {
+                ret.elements.insert((ElementType::Way, id));
+                for node in &way.nodes {
+                    if self.extract.nodes.contains_key(node) {
+                        ret.elements.insert((ElementType::Node, *node));
+                    }
+                }
+            }
+        };
+        for (element_type, id) in &input.elements {
+            match element_type {
+                ElementType::Node => {}
+                ElementType::Way => {
+                    add_way(&mut ret, *id);
+                    ret.elements.remove(&(ElementType::Way, *id));
+                }
+                ElementType::Relation => {
+                    let relation = &self.extract.relations[id];
+                    for member in &relation.members {
+                        match member.element_type {
+                            ElementType::Way => add_way(&mut ret, member.id),
+                            _ => {
+                                if self
+                                    .extract
+                                    .get_elements(member.element_type)
+                                    .contains_key(&member.id)
+                                {
+                                    ret.elements.insert((member.element_type, member.id));
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        ret
+    }
+
+    fn eval(&mut self, statement: &Statement) -> anyhow::Result<(Set, String)> {
+        let ret = match statement {
+            Statement::Area { ids, output } => {
+                let set = Set {
+                    areas: ids.iter().cloned().collect(),
+                    ..Default::default()
+                };
+                (set, output.to_string())
+            }
+            Statement::Query {
+                types,
+                inputs,
+                filters,
+                output,
+            } => (self.eval_query(types, inputs, filters)?, output.to_string()),
+            Statement::Union { statements, output } => {
+                let mut set = Set::default();
+                for statement in statements {
+                    let (result, _) = self.eval(statement)?;
+                    set.elements.extend(result.elements.iter());
+                    set.areas.extend(result.areas.iter());
+                    // Statements inside the union also update the default set.
+                    self.sets.insert("_".into(), result);
+                }
+                (set, output.to_string())
+            }
+            Statement::RecurseDown { output } => (self.eval_recurse_down(), output.to_string()),
+            Statement::Out { verbosity, geom } => {
+                for (element_type, id) in &self.get_set("_").elements {
+                    self.output.push((*element_type, *id, *verbosity, *geom));
+                }
+                (self.get_set("_"), "_".to_string())
+            }
+        };
+        self.sets.insert(ret.1.clone(), ret.0.clone());
+        Ok(ret)
+    }
+}
+
+/// Gets the coordinates of the nodes of a way, if all its nodes are in the extract.
+fn get_way_geometry(extract: &Extract, element: &Element) -> Option<Vec<serde_json::Value>> {
+    element
+        .nodes
+        .iter()
+        .map(|id| {
+            let node = extract.nodes.get(id)?;
+            Some(serde_json::json!({"lat": node.lat, "lon": node.lon}))
+        })
+        .collect()
+}
+
+/// Serializes one element in the overpass JSON format.
+fn element_to_json(
+    extract: &Extract,
+    element_type: ElementType,
+    id: i64,
+    element: &Element,
+    verbosity: Verbosity,
+    geom: bool,
+) -> serde_json::Value {
+    let mut ret = serde_json::Map::new();
+    ret.insert("type".into(), element_type.as_str().into());
+    ret.insert("id".into(), id.into());
+    if element_type == ElementType::Node {
+        ret.insert("lat".into(), element.lat.into());
+        ret.insert("lon".into(), element.lon.into());
+    }
+    if let Verbosity::Meta = verbosity {
+        if let Some(ref timestamp) = element.timestamp {
+            ret.insert("timestamp".into(), timestamp.as_str().into());
+        }
+        if let Some(version) = element.version {
+            ret.insert("version".into(), version.into());
+        }
+        if let Some(changeset) = element.changeset {
+            ret.insert("changeset".into(), changeset.into());
+        }
+        if let Some(ref user) = element.user {
+            ret.insert("user".into(), user.as_str().into());
+        }
+        if let Some(uid) = element.uid {
+            ret.insert("uid".into(), uid.into());
+        }
+    }
+    if element_type == ElementType::Way {
+        ret.insert("nodes".into(), element.nodes.clone().into());
+        if geom {
+            if let Some(geometry) = get_way_geometry(extract, element) {
+                ret.insert("geometry".into(), geometry.into());
+            }
+        }
+    }
+    if element_type == ElementType::Relation {
+        let members: Vec<serde_json::Value> = element
+            .members
+            .iter()
+            .map(|member| {
+                serde_json::json!({
+                    "type": member.element_type.as_str(),
+                    "ref": member.id,
+                    "role": member.role,
+                })
+            })
+            .collect();
+        ret.insert("members".into(), members.into());
+    }
+    if !matches!(verbosity, Verbosity::Skel) && !element.tags.is_empty() {
+        ret.insert("tags".into(), serde_json::to_value(&element.tags).unwrap());
+    }
+    serde_json::Value::Object(ret)
+}
+
+/// Gets the value of one CSV column for an element.
+fn get_csv_value(element_type: ElementType, id: i64, element: &Element, column: &str) -> String {
+    match column {
+        "::id" => id.to_string(),
+        "::type" => element_type.as_str().to_string(),
+        "::user" => element.user.clone().unwrap_or_default(),
+        "::uid" => element.uid.map(|i| i.to_string()).unwrap_or_default(),
+        "::timestamp" => element.timestamp.clone().unwrap_or_default(),
+        "::version" => element.version.map(|i| i.to_string()).unwrap_or_default(),
+        "::changeset" => element.changeset.map(|i| i.to_string()).unwrap_or_default(),
+        "::lat" if element_type == ElementType::Node => element.lat.to_string(),
+        "::lon" if element_type == ElementType::Node => element.lon.to_string(),
+        _ => element.tags.get(column).cloned().unwrap_or_default(),
+    }
+}
+
+/// Runs an overpass query on an extract and returns the result string.
+fn run_query(extract: &Extract, query: &str) -> anyhow::Result<String> {
+    let query = Parser::new(query).parse()?;
+    let mut evaluator = Evaluator::new(extract);
+    for statement in &query.statements {
+        evaluator.eval(statement)?;
+    }
+
+    match query.format {
+        OutputFormat::Json => {
+            let elements: Vec<serde_json::Value> = evaluator
+                .output
+                .iter()
+                .map(|(element_type, id, verbosity, geom)| {
+                    let element = &extract.get_elements(*element_type)[id];
+                    element_to_json(extract, *element_type, *id, element, *verbosity, *geom)
+                })
+                .collect();
+            let ret = serde_json::json!({
+                "version": 0.6,
+                "generator": "osm-gimmisn overpass_local",
+                "osm3s": {
+                    "timestamp_osm_base": extract.timestamp,
+                    "timestamp_areas_base": extract.timestamp,
+                    "copyright": "The data included in this document is from www.openstreetmap.org. The data is made available under ODbL.",
+                },
+                "elements": elements,
+            });
+            Ok(serde_json::to_string_pretty(&ret)?)
+        }
+        OutputFormat::Csv(columns) => {
+            let header: Vec<String> = columns
+                .iter()
+                .map(|column| match column.strip_prefix("::") {
+                    Some(value) => format!("@{value}"),
+                    None => column.to_string(),
+                })
+                .collect();
+            let mut lines = vec![header.join("\t")];
+            for (element_type, id, _verbosity, _geom) in &evaluator.output {
+                let element = &extract.get_elements(*element_type)[id];
+                let row: Vec<String> = columns
+                    .iter()
+                    .map(|column| get_csv_value(*element_type, *id, element, column))
+                    .collect();
+                lines.push(row.join("\t"));
+            }
+            Ok(lines.join("\n") + "\n")
+        }
+    }
+}
+
+/// Decides if an object would be part of the result of a query, based on its tags only.
+pub struct TagMatcher {
+    query: Query,
+}
+
+impl TagMatcher {
+    /// Creates a new TagMatcher from an overpass query.
+    pub fn new(query: &str) -> anyhow::Result<Self> {
+        let query = Parser::new(query).parse()?;
+        Ok(TagMatcher { query })
+    }
+
+    /// Determines if an object of type osm_type with tags matches the filters of a statement.
+    pub fn matches(&self, osm_type: &str, tags: &BTreeMap<String, String>) -> bool {
+        let element_type = match ElementType::try_from(osm_type) {
+            Ok(value) => value,
+            Err(_) => {
+                return false;
+            }
+        };
+        TagMatcher::statements_match(&self.query.statements, element_type, tags)
+    }
+
+    fn statements_match(
+        statements: &[Statement],
+        element_type: ElementType,
+        tags: &BTreeMap<String, String>,
+    ) -> bool {
+        statements.iter().any(|statement| match statement {
+            Statement::Query {
+                types,
+                inputs,
+                filters,
+                ..
+            } => {
+                // Queries for explicit ids are not about tags.
+                let has_ids = inputs.iter().any(|input| matches!(input, Input::Ids(_)));
+                !has_ids
+                    && types.contains(&element_type)
+                    && filters.iter().all(|filter| filter.matches(tags))
+            }
+            Statement::Union { statements, .. } => {
+                TagMatcher::statements_match(statements, element_type, tags)
+            }
+            _ => false,
+        })
+    }
+}
+
+/// Network implementation that answers overpass requests from a local OSM extract and forwards
+/// everything else.
+pub struct LocalOverpass {
+    file_system: Arc<dyn context::FileSystem>,
+    paths: Vec<String>,
+    overpass_uris: Vec<String>,
+    network: Arc<dyn context::Network>,
+    /// The parsed extract and the mtime of its files, so it's only loaded once and not for every
+    /// request.
+    extract: Mutex<Option<(time::OffsetDateTime, Arc<Extract>)>>,
+}
+
+impl LocalOverpass {
+    /// Creates a new LocalOverpass, paths are OSM XML (.osm) or PBF (.pbf) files, overpass_uris
+    /// are the endpoints to intercept.
+    pub fn new(
+        file_system: &Arc<dyn context::FileSystem>,
+        paths: &[String],
+        overpass_uris: &[String],
+        network: &Arc<dyn context::Network>,
+    ) -> Self {
+        LocalOverpass {
+            file_system: file_system.clone(),
+            paths: paths.to_vec(),
+            overpass_uris: overpass_uris.to_vec(),
+            network: network.clone(),
+            extract: Mutex::new(None),
+        }
+    }
+
+    /// Gets the parsed extract, unless it's already loaded and the files are not modified since.
+    fn get_extract(&self) -> anyhow::Result<Arc<Extract>> {
+        let mut mtime = time::OffsetDateTime::UNIX_EPOCH;
+        for path in &self.paths {
+            mtime = std::cmp::max(mtime, self.file_system.getmtime(path)?);
+        }
+
+        let mut cached = self.extract.lock().unwrap();
+        if let Some((cached_mtime, extract)) = cached.as_ref() {
+            if *cached_mtime == mtime {
+                return Ok(extract.clone());
+            }
+        }
+
+        let extract = Arc::new(load_extract(&self.file_system, &self.paths, mtime)?);
+        *cached = Some((mtime, extract.clone()));
+        Ok(extract)
+    }
+}
+
+impl context::Network for LocalOverpass {
+    fn urlopen(&self, url: &str, data: &str) -> anyhow::Result<String> {
+        for uri in &self.overpass_uris {
+            if url == format!("{uri}/api/interpreter") {
+                let extract = self.get_extract()?;
+                return run_query(&extract, data);
+            }
+
+            if url == format!("{uri}/api/status") {
+                return Ok("Connected as: 0\nRate limit: 0\n1 slots available now.\n".into());
+            }
+        }
+
+        self.network.urlopen(url, data)
+    }
+
+    fn urlopen_with_status(
+        &self,
+        url: &str,
+        data: &str,
+        timeout: std::time::Duration,
+    ) -> anyhow::Result<(u16, String)> {
+        for uri in &self.overpass_uris {
+            if url.starts_with(&format!("{uri}/api/")) {
+                return Ok((200, self.urlopen(url, data)?));
+            }
+        }
+
+        self.network.urlopen_with_status(url, data, timeout)
+    }
+
+    fn urlopen_bytes(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        for uri in &self.overpass_uris {
+            if url.starts_with(&format!("{uri}/api/")) {
+                return Ok(self.urlopen(url, "")?.into_bytes());
+            }
+        }
+
+        self.network.urlopen_bytes(url)
+    }
+}
+
+#[cfg(test)]
+mod tests;
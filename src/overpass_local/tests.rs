@@ -0,0 +1,352 @@
+/*
+ * Copyright 2024 Miklos Vajna
+ *
+ * SPDX-License-Identifier: MIT
+ */
+
+#![deny(warnings)]
+#![warn(clippy::all)]
+#![warn(missing_docs)]
+
+//! Tests for the overpass_local module.
+
+use super::*;
+use crate::area_files;
+use crate::areas;
+use crate::overpass_query;
+use crate::util;
+
+/// Creates a context which answers overpass queries from the test extract at path.
+fn make_local_context(path: &str) -> context::Context {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    let network = LocalOverpass::new(
+        ctx.get_file_system(),
+        &[path.to_string()],
+        &ctx.get_ini().get_overpass_uris(),
+        ctx.get_network(),
+    );
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
+    ctx.set_network(network_rc);
+    ctx
+}
+
+/// Runs the query from a template on the test extract, for relation 42.
+fn query_template(ctx: &context::Context, template: &str, first_line: &str) -> String {
+    let template = std::fs::read_to_string(format!("data/{template}")).unwrap();
+    let template = util::process_country_template(&template, ctx.get_ini());
+    let query = util::process_template(&template, 42);
+    let mut lines: Vec<&str> = query.lines().collect();
+    if !first_line.is_empty() {
+        lines[0] = first_line;
+    }
+    overpass_query::overpass_query(ctx, &lines.join("\n")).unwrap()
+}
+
+/// Gets the (type, id) pairs from an overpass JSON result.
+fn get_ids(result: &str) -> Vec<(String, i64)> {
+    let value: serde_json::Value = serde_json::from_str(result).unwrap();
+    value["elements"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|element| {
+            (
+                element["type"].as_str().unwrap().to_string(),
+                element["id"].as_i64().unwrap(),
+            )
+        })
+        .collect()
+}
+
+/// Tests the streets query: only named, non-bridge highways inside the boundary.
+#[test]
+fn test_streets() {
+    let ctx = make_local_context("src/fixtures/overpass-local.osm");
+
+    let result = query_template(&ctx, "streets-template.overpassql", "[out:json];");
+
+    assert_eq!(
+        get_ids(&result),
+        vec![("way".to_string(), 200), ("way".to_string(), 203)]
+    );
+    let value: serde_json::Value = serde_json::from_str(&result).unwrap();
+    assert_eq!(value["osm3s"]["timestamp_osm_base"], "2024-08-01T10:00:00Z");
+}
+
+/// Tests out geom: ways have the coordinates of their nodes.
+#[test]
+fn test_out_geom() {
+    let ctx = make_local_context("src/fixtures/overpass-local.osm");
+
+    let result = overpass_query::overpass_query(&ctx, "[out:json];\nway(200);\nout geom;").unwrap();
+
+    let value: serde_json::Value = serde_json::from_str(&result).unwrap();
+    assert_eq!(
+        value["elements"][0]["geometry"],
+        serde_json::json!([{"lat": 47.05, "lon": 18.05}, {"lat": 47.06, "lon": 18.06}])
+    );
+}
+
+/// Tests node(w): the nodes of the ways from the previous statement of a union.
+#[test]
+fn test_way_nodes() {
+    let ctx = make_local_context("src/fixtures/overpass-local.osm");
+
+    let result = overpass_query::overpass_query(
+        &ctx,
+        "[out:json];\n(\n  way(204);\n  node(w);\n  way(200);\n  node(w)[\"addr:housenumber\"];\n);\nout;",
+    )
+    .unwrap();
+
+    assert_eq!(
+        get_ids(&result),
+        vec![
+            ("node".to_string(), 1),
+            ("node".to_string(), 2),
+            ("way".to_string(), 200),
+            ("way".to_string(), 204)
+        ]
+    );
+}
+
+/// Tests the streets query, in CSV format.
+#[test]
+fn test_streets_csv() {
+    let ctx = make_local_context("src/fixtures/overpass-local.osm");
+
+    let result = query_template(&ctx, "streets-template.overpassql", "");
+
+    assert_eq!(
+        result,
+        "@id\tname\thighway\tservice\tsurface\tleisure\t@type\n\
+         200\tTűzkő utca\tresidential\t\t\t\tway\n\
+         203\tParkoló köz\tservice\tparking_aisle\t\t\tway\n"
+    );
+}
+
+/// Tests the housenumbers query, the result can be written by RelationFiles.
+#[test]
+fn test_housenumbers() {
+    let mut ctx = make_local_context("src/fixtures/overpass-local.osm");
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "osmrelation": 42,
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        &ctx,
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    ctx.set_file_system(&file_system);
+    let mut relations = areas::Relations::new(&ctx).unwrap();
+    let relation = relations.get_relation("gazdagret").unwrap();
+
+    let result = query_template(
+        &ctx,
+        "street-housenumbers-template.overpassql",
+        "[out:json];",
+    );
+
+    assert_eq!(
+        get_ids(&result),
+        vec![("node".to_string(), 5), ("way".to_string(), 204)]
+    );
+    relation
+        .get_files()
+        .write_osm_json_housenumbers(&ctx, &result)
+        .unwrap();
+    let housenumbers = relation
+        .get_files()
+        .get_osm_json_housenumbers(&ctx)
+        .unwrap();
+    assert_eq!(housenumbers.len(), 2);
+}
+
+/// Tests the whole-country query: the boundary is not in the extract, so everything matches.
+#[test]
+fn test_whole_country() {
+    let ctx = make_local_context("src/fixtures/overpass-local.osm");
+
+    let result = query_template(
+        &ctx,
+        "street-housenumbers-country.overpassql",
+        "[out:json] [timeout:425];",
+    );
+
+    assert_eq!(
+        get_ids(&result),
+        vec![
+            ("node".to_string(), 5),
+            ("node".to_string(), 6),
+            ("way".to_string(), 204)
+        ]
+    );
+    let value: serde_json::Value = serde_json::from_str(&result).unwrap();
+    assert_eq!(value["elements"][0]["user"], "alice");
+    assert_eq!(value["elements"][0]["timestamp"], "2024-07-01T08:00:00Z");
+    area_files::write_whole_country(&ctx, &result).unwrap();
+}
+
+/// Tests that a PBF extract gives the same results as an XML one.
+#[test]
+fn test_pbf() {
+    let ctx = make_local_context("src/fixtures/overpass-local.osm.pbf");
+
+    let streets = query_template(&ctx, "streets-template.overpassql", "[out:json];");
+    let housenumbers = query_template(
+        &ctx,
+        "street-housenumbers-country.overpassql",
+        "[out:json] [timeout:425];",
+    );
+
+    assert_eq!(
+        get_ids(&streets),
+        vec![("way".to_string(), 200), ("way".to_string(), 203)]
+    );
+    let value: serde_json::Value = serde_json::from_str(&housenumbers).unwrap();
+    assert_eq!(value["osm3s"]["timestamp_osm_base"], "2024-08-01T10:00:00Z");
+    assert_eq!(value["elements"][0]["user"], "alice");
+    assert_eq!(value["elements"][0]["tags"]["addr:street"], "Tűzkő utca");
+    assert_eq!(value["elements"][0]["lat"], 47.02);
+    assert_eq!(value["elements"][2]["type"], "way");
+    assert_eq!(value["elements"][2]["timestamp"], "2024-07-03T08:00:00Z");
+}
+
+/// Tests the recurse down statement of the additional streets GPX query.
+#[test]
+fn test_recurse_down() {
+    let ctx = make_local_context("src/fixtures/overpass-local.osm");
+    let query = r#"[out:json][timeout:425];
+rel(42)->.searchRelation;
+area(3600000042)->.searchArea;
+(way(200);
+);
+out body;
+>;
+out skel qt;"#;
+
+    let result = overpass_query::overpass_query(&ctx, query).unwrap();
+
+    assert_eq!(
+        get_ids(&result),
+        vec![
+            ("way".to_string(), 200),
+            ("node".to_string(), 1),
+            ("node".to_string(), 2)
+        ]
+    );
+}
+
+/// Tests that the status is always happy and other URLs are forwarded.
+#[test]
+fn test_status() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    let routes = vec![context::tests::URLRoute::new(
+        /*url=*/ "https://www.example.com",
+        /*data_path=*/ "",
+        /*result_path=*/ "src/fixtures/network/overpass-status-happy.txt",
+    )];
+    let network = context::tests::TestNetwork::new(&routes);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
+    let network = LocalOverpass::new(
+        ctx.get_file_system(),
+        &["src/fixtures/overpass-local.osm".to_string()],
+        &ctx.get_ini().get_overpass_uris(),
+        &network_rc,
+    );
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
+    ctx.set_network(network_rc);
+
+    assert_eq!(overpass_query::overpass_query_need_sleep(&ctx), 0);
+    let ret = ctx.get_network().urlopen("https://www.example.com", "");
+    assert_eq!(ret.is_ok(), true);
+}
+
+/// Tests that all configured overpass endpoints are answered locally.
+#[test]
+fn test_all_endpoints() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    context::tests::set_ini(
+        &mut ctx,
+        "overpass_uri = 'https://overpass-api.de https://overpass.example.com'\n",
+    );
+    // No routes: any request that reaches the network fails.
+    let network = context::tests::TestNetwork::new(&[]);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
+    let network = LocalOverpass::new(
+        ctx.get_file_system(),
+        &["src/fixtures/overpass-local.osm".to_string()],
+        &ctx.get_ini().get_overpass_uris(),
+        &network_rc,
+    );
+
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
+
+    let ret = network_rc.urlopen("https://overpass.example.com/api/status", "");
+    assert_eq!(ret.unwrap().contains("slots available now"), true);
+    let template = std::fs::read_to_string("data/streets-template.overpassql").unwrap();
+    let template = util::process_country_template(&template, ctx.get_ini());
+    let query = util::process_template(&template, 42);
+    let mut lines: Vec<&str> = query.lines().collect();
+    lines[0] = "[out:json];";
+    let query = lines.join("\n");
+    let result = network_rc
+        .urlopen("https://overpass.example.com/api/interpreter", &query)
+        .unwrap();
+    assert_eq!(
+        get_ids(&result),
+        vec![("way".to_string(), 200), ("way".to_string(), 203)]
+    );
+}
+
+/// Tests that an unsupported query gives an error.
+#[test]
+fn test_unsupported_query() {
+    let ctx = make_local_context("src/fixtures/overpass-local.osm");
+
+    let ret = overpass_query::overpass_query(&ctx, "[out:json];\nway(around:10,47,18);\nout;");
+
+    assert_eq!(ret.is_err(), true);
+}
+
+/// Tests TagMatcher: statements with filters match, the ones with ids are ignored.
+#[test]
+fn test_tag_matcher() {
+    let ctx = context::tests::make_test_context().unwrap();
+    let template = std::fs::read_to_string("data/streets-template.overpassql").unwrap();
+    let template = util::process_country_template(&template, ctx.get_ini());
+    let matcher = TagMatcher::new(&util::process_template(&template, 42)).unwrap();
+    let street: BTreeMap<String, String> = [("highway", "residential"), ("name", "Tűzkő utca")]
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    let bridge: BTreeMap<String, String> = [("highway", "residential"), ("bridge", "yes")]
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+
+    assert_eq!(matcher.matches("way", &street), true);
+    assert_eq!(matcher.matches("node", &street), false);
+    assert_eq!(matcher.matches("way", &bridge), false);
+    assert_eq!(matcher.matches("area", &street), false);
+    let matcher = TagMatcher::new("[out:json];\nway(200);\nout;").unwrap();
+    assert_eq!(matcher.matches("way", &street), false);
+}
+
+/// Tests that a length which doesn't fit into the buffer gives an error, not an overflow.
+#[test]
+fn test_protobuf_length_overflow() {
+    // Field 1, wire type 2, then the largest varint as its length.
+    let buf = [
+        0x0a, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01,
+    ];
+    let mut reader = ProtobufReader::new(&buf);
+
+    let ret = reader.next_field();
+
+    assert_eq!(ret.is_err(), true);
+}
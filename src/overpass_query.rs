@@ -11,29 +11,363 @@
 //! The overpass_query module allows getting data out of the OSM DB without a full download.
 
 use crate::context;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
 
-/// Posts the query string to the overpass API and returns the result string.
-pub fn overpass_query(ctx: &context::Context, query: &str) -> anyhow::Result<String> {
-    let url = ctx.get_ini().get_overpass_uri() + "/api/interpreter";
+#[cfg(not(test))]
+use log::info;
 
-    ctx.get_network().urlopen(&url, query)
+#[cfg(test)]
+use std::println as info;
+
+/// Number of attempts before overpass_query_with_retry() gives up.
+const MAX_ATTEMPTS: u32 = 8;
+
+/// Wait before the first retry, in seconds, doubled for each further retry.
+const INITIAL_BACKOFF: u64 = 10;
+
+/// Upper limit for the wait between retries, in seconds.
+const MAX_BACKOFF: u64 = 600;
+
+/// The server-side timeout of a query without an explicit [timeout:...] setting, in seconds.
+const DEFAULT_QUERY_TIMEOUT: u64 = 180;
+
+/// Failures of an endpoint older than this are forgotten, in seconds.
+const FAILURE_EXPIRY: i64 = 3600;
+
+lazy_static::lazy_static! {
+    static ref HTML_TAG: regex::Regex = regex::Regex::new(r"<[^>]*>").unwrap();
+    static ref JSON_REMARK: regex::Regex =
+        regex::Regex::new(r#""remark"\s*:\s*"((?:[^"\\]|\\.)*)""#).unwrap();
+    static ref XML_REMARK: regex::Regex = regex::Regex::new(r"<remark>([^<]*)</remark>").unwrap();
+    static ref TIMEOUT_SETTING: regex::Regex = regex::Regex::new(r"\[timeout:(\d+)\]").unwrap();
+    static ref SLOT_AVAILABLE: regex::Regex = regex::Regex::new(r".*in (-?\d+) seconds.*").unwrap();
 }
 
-/// Checks if we need to sleep before executing an overpass query.
-pub fn overpass_query_need_sleep(ctx: &context::Context) -> i32 {
-    let url = ctx.get_ini().get_overpass_uri() + "/api/status";
-    let status = match ctx.get_network().urlopen(&url, "") {
-        Ok(value) => value,
-        _ => {
-            return 0;
+/// The health of one overpass endpoint.
+#[derive(Clone, Default)]
+struct Health {
+    /// Number of failed requests since the last success.
+    failures: u32,
+    /// Time of the last failure, as a unix timestamp.
+    last_failure: i64,
+    /// Wait needed before the next query, as reported by /api/status.
+    sleep: i32,
+}
+
+/// Tracks the health of the overpass endpoints, so queries go to the healthiest one.
+#[derive(Default)]
+pub struct EndpointHealth {
+    endpoints: Mutex<HashMap<String, Health>>,
+}
+
+impl EndpointHealth {
+    /// Gets the number of recent failures of an endpoint.
+    pub fn get_failures(&self, ctx: &context::Context, uri: &str) -> u32 {
+        let now = ctx.get_time().now().unix_timestamp();
+        match self.endpoints.lock().unwrap().get(uri) {
+            Some(health) if now - health.last_failure < FAILURE_EXPIRY => health.failures,
+            _ => 0,
+        }
+    }
+
+    fn get_sleep(&self, uri: &str) -> i32 {
+        match self.endpoints.lock().unwrap().get(uri) {
+            Some(health) => health.sleep,
+            None => 0,
+        }
+    }
+
+    fn set_sleep(&self, uri: &str, sleep: i32) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        endpoints.entry(uri.to_string()).or_default().sleep = sleep;
+    }
+
+    fn record_failure(&self, ctx: &context::Context, uri: &str) {
+        let failures = self.get_failures(ctx, uri);
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let health = endpoints.entry(uri.to_string()).or_default();
+        health.failures = failures + 1;
+        health.last_failure = ctx.get_time().now().unix_timestamp();
+    }
+
+    fn record_success(&self, uri: &str) {
+        if let Some(health) = self.endpoints.lock().unwrap().get_mut(uri) {
+            health.failures = 0;
+        }
+    }
+}
+
+/// Gets the configured overpass endpoints, the healthiest first.
+fn get_endpoints(ctx: &context::Context) -> Vec<String> {
+    let health = ctx.get_overpass_health();
+    let mut uris = ctx.get_ini().get_overpass_uris();
+    // The sort is stable, so the config order decides between equally healthy endpoints.
+    uris.sort_by_key(|uri| (health.get_failures(ctx, uri), health.get_sleep(uri)));
+    uris
+}
+
+/// A failed overpass query.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OverpassError {
+    /// The server refused the query because we ran out of slots or it is too busy.
+    RateLimited,
+    /// The query took too long, either on the server or on the network.
+    Timeout,
+    /// The query needed more memory than the server allows.
+    OutOfMemory,
+    /// The response is not in the format the query asked for.
+    Malformed(String),
+    /// The server failed without telling why, e.g. it's overloaded or restarting.
+    Server(String),
+    /// The server could not be reached.
+    Network(String),
+}
+
+impl OverpassError {
+    /// Decides if the same query may succeed later.
+    pub fn is_temporary(&self) -> bool {
+        matches!(
+            self,
+            OverpassError::RateLimited
+                | OverpassError::Timeout
+                | OverpassError::Server(_)
+                | OverpassError::Network(_)
+        )
+    }
+}
+
+impl std::fmt::Display for OverpassError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OverpassError::RateLimited => write!(f, "rate limited"),
+            OverpassError::Timeout => write!(f, "timeout"),
+            OverpassError::OutOfMemory => write!(f, "out of memory"),
+            OverpassError::Malformed(message) => write!(f, "malformed response: {message}"),
+            OverpassError::Server(message) => write!(f, "server error: {message}"),
+            OverpassError::Network(message) => write!(f, "network error: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for OverpassError {}
+
+/// Classifies an error message from overpass, e.g. a runtime error.
+fn classify_error(message: &str) -> OverpassError {
+    let lower = message.to_lowercase();
+    if lower.contains("rate_limited")
+        || lower.contains("too many requests")
+        || lower.contains("too busy")
+    {
+        return OverpassError::RateLimited;
+    }
+    if lower.contains("timed out") || lower.contains("timeout") {
+        return OverpassError::Timeout;
+    }
+    if lower.contains("out of memory") {
+        return OverpassError::OutOfMemory;
+    }
+
+    // Error pages are HTML, keep only the text.
+    let text = HTML_TAG.replace_all(message, " ");
+    let text: Vec<&str> = text.split_whitespace().collect();
+    let text: String = text.join(" ").chars().take(200).collect();
+    OverpassError::Malformed(text)
+}
+
+/// Gets the end of a response, where overpass puts its remarks.
+fn get_tail(response: &str) -> &str {
+    let mut start = response.len().saturating_sub(4096);
+    while !response.is_char_boundary(start) {
+        start += 1;
+    }
+    &response[start..]
+}
+
+/// Classifies the HTTP status of a response: the body of a failed request is an error page, even
+/// if it looks like valid output, e.g. a csv header.
+fn classify_status(status: u16, response: &str) -> Result<(), OverpassError> {
+    match status {
+        200..=299 => Ok(()),
+        429 => Err(OverpassError::RateLimited),
+        504 => Err(OverpassError::Timeout),
+        500..=599 => match classify_error(response) {
+            OverpassError::Malformed(text) => {
+                Err(OverpassError::Server(format!("HTTP {status}: {text}")))
+            }
+            err => Err(err),
+        },
+        // E.g. 400 for a query with a syntax error: sending it again won't help.
+        _ => match classify_error(response) {
+            OverpassError::Malformed(text) => {
+                Err(OverpassError::Malformed(format!("HTTP {status}: {text}")))
+            }
+            err => Err(err),
+        },
+    }
+}
+
+/// Classifies a response, based on the output format the query asked for.
+fn classify_response(query: &str, response: &str) -> Result<(), OverpassError> {
+    let head: String = response.trim_start().chars().take(256).collect();
+    let head = head.to_lowercase();
+    if head.contains("<html") || head.contains("<!doctype html") {
+        // An error page that is not recognized, assume the server has trouble.
+        return match classify_error(response) {
+            OverpassError::Malformed(text) => Err(OverpassError::Server(text)),
+            err => Err(err),
+        };
+    }
+
+    let settings = query.split(';').next().unwrap_or("");
+    if settings.contains("[out:json") {
+        if let Err(err) = serde_json::from_str::<serde::de::IgnoredAny>(response) {
+            return Err(OverpassError::Malformed(format!(
+                "failed to parse as json: {err}"
+            )));
         }
+        if let Some(cap) = JSON_REMARK.captures(get_tail(response)) {
+            if cap[1].contains("runtime error") {
+                return Err(classify_error(&cap[1]));
+            }
+        }
+        return Ok(());
+    }
+
+    if settings.contains("[out:csv") {
+        if head.starts_with('<') {
+            return Err(classify_error(response));
+        }
+        return Ok(());
+    }
+
+    if let Some(cap) = XML_REMARK.captures(get_tail(response)) {
+        if cap[1].contains("runtime error") {
+            return Err(classify_error(&cap[1]));
+        }
+    }
+    Ok(())
+}
+
+/// Gets how long to wait for the response of a query: a bit more than its server-side timeout.
+fn get_request_timeout(query: &str) -> Duration {
+    let seconds = match TIMEOUT_SETTING.captures(query) {
+        Some(cap) => cap[1].parse().unwrap_or(DEFAULT_QUERY_TIMEOUT),
+        None => DEFAULT_QUERY_TIMEOUT,
     };
+    Duration::from_secs(seconds + 30)
+}
+
+/// Classifies an error from the network layer.
+fn classify_network_error(err: &anyhow::Error) -> OverpassError {
+    if let Some(err) = err.downcast_ref::<isahc::Error>() {
+        if err.is_timeout() {
+            return OverpassError::Timeout;
+        }
+    }
+    if let Some(err) = err.downcast_ref::<std::io::Error>() {
+        if err.kind() == std::io::ErrorKind::TimedOut {
+            return OverpassError::Timeout;
+        }
+    }
+    OverpassError::Network(err.to_string())
+}
+
+/// Posts the query string to one overpass endpoint and returns the result string.
+fn query_endpoint(ctx: &context::Context, uri: &str, query: &str) -> Result<String, OverpassError> {
+    let url = format!("{uri}/api/interpreter");
+
+    let start = ctx.get_time().now();
+    let ret = ctx
+        .get_network()
+        .urlopen_with_status(&url, query, get_request_timeout(query))
+        .map_err(|err| classify_network_error(&err))
+        .and_then(|(status, response)| {
+            classify_status(status, &response)?;
+            classify_response(query, &response)?;
+            Ok(response)
+        });
+    let duration = ctx.get_time().now() - start;
+    ctx.get_metrics()
+        .record_overpass(uri, duration, ret.is_ok());
+    ret
+}
+
+/// Posts the query string to the overpass API and returns the result string. Temporary failures
+/// of an endpoint are handled by failing over to the next healthiest one.
+pub fn overpass_query(ctx: &context::Context, query: &str) -> Result<String, OverpassError> {
+    let health = ctx.get_overpass_health();
+    let mut last_err = OverpassError::Network("no overpass endpoints".into());
+    for uri in get_endpoints(ctx) {
+        info!("overpass_query: using {uri}");
+        match query_endpoint(ctx, &uri, query) {
+            Ok(value) => {
+                health.record_success(&uri);
+                return Ok(value);
+            }
+            Err(err) => {
+                if !err.is_temporary() {
+                    return Err(err);
+                }
+                info!("overpass_query: {uri} failed: {err}");
+                health.record_failure(ctx, &uri);
+                last_err = err;
+            }
+        }
+    }
+    Err(last_err)
+}
+
+/// Sleeps to respect overpass rate limit.
+pub fn overpass_sleep(ctx: &context::Context) {
+    loop {
+        let sleep = overpass_query_need_sleep(ctx);
+        if sleep == 0 {
+            break;
+        }
+        info!("overpass_sleep: waiting for {sleep} seconds");
+        ctx.get_time().sleep(sleep as u64);
+    }
+}
+
+/// Posts the query string to the overpass API, retrying temporary failures with exponential
+/// backoff.
+pub fn overpass_query_with_retry(
+    ctx: &context::Context,
+    query: &str,
+) -> Result<String, OverpassError> {
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        overpass_sleep(ctx);
+        let err = match overpass_query(ctx, query) {
+            Ok(value) => {
+                return Ok(value);
+            }
+            Err(err) => err,
+        };
+        if !err.is_temporary() || attempt >= MAX_ATTEMPTS {
+            return Err(err);
+        }
+
+        info!("overpass_query_with_retry: try #{attempt} failed: {err}, waiting for {backoff} seconds");
+        ctx.get_time().sleep(backoff);
+        backoff = std::cmp::min(backoff * 2, MAX_BACKOFF);
+        attempt += 1;
+    }
+}
+
+/// Gets the wait needed before executing an overpass query on one endpoint, None if its status
+/// is not available.
+fn get_endpoint_sleep(ctx: &context::Context, uri: &str) -> Option<i32> {
+    let url = format!("{uri}/api/status");
+    let status = ctx.get_network().urlopen(&url, "").ok()?;
     let mut sleep = 0;
     let mut available = false;
     for line in status.lines() {
         if line.starts_with("Slot available after:") {
-            let re = regex::Regex::new(r".*in (-?\d+) seconds.*").unwrap();
-            for cap in re.captures_iter(line) {
+            for cap in SLOT_AVAILABLE.captures_iter(line) {
                 // This should neve fail since the regex only allows numbers.
                 sleep = cap[1].parse::<i32>().expect("parse() to i32 failed");
                 // Wait one more second just to be safe.
@@ -49,9 +383,25 @@ pub fn overpass_query_need_sleep(ctx: &context::Context) -> i32 {
         }
     }
     if available {
-        return 0;
+        return Some(0);
+    }
+    Some(sleep)
+}
+
+/// Checks if we need to sleep before executing an overpass query, on the healthiest endpoint.
+pub fn overpass_query_need_sleep(ctx: &context::Context) -> i32 {
+    let health = ctx.get_overpass_health();
+    for uri in ctx.get_ini().get_overpass_uris() {
+        match get_endpoint_sleep(ctx, &uri) {
+            Some(sleep) => health.set_sleep(&uri, sleep),
+            None => {
+                health.set_sleep(&uri, 0);
+                health.record_failure(ctx, &uri);
+            }
+        }
     }
-    sleep
+    let uris = get_endpoints(ctx);
+    health.get_sleep(&uris[0])
 }
 
 #[cfg(test)]
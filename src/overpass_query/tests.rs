@@ -11,7 +11,7 @@
 //! Tests for the overpass_query module.
 
 use super::*;
-use std::rc::Rc;
+use std::sync::Arc;
 
 /// Tests overpass_query_need_sleep().
 #[test]
@@ -23,7 +23,7 @@ fn test_overpass_query_need_sleep() {
         /*result_path=*/ "src/fixtures/network/overpass-status-happy.txt",
     )];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     ctx.set_network(network_rc);
 
     assert_eq!(overpass_query_need_sleep(&ctx), 0);
@@ -39,7 +39,7 @@ fn test_overpass_query_need_sleep_wait() {
         /*result_path=*/ "src/fixtures/network/overpass-status-wait.txt",
     )];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     ctx.set_network(network_rc);
 
     assert_eq!(overpass_query_need_sleep(&ctx), 12);
@@ -55,7 +55,7 @@ fn test_overpass_query_need_sleep_wait_negative() {
         /*result_path=*/ "src/fixtures/network/overpass-status-wait-negative.txt",
     )];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     ctx.set_network(network_rc);
 
     assert_eq!(overpass_query_need_sleep(&ctx), 1);
@@ -71,7 +71,7 @@ fn test_overpass_query() {
         /*result_path=*/ "src/fixtures/network/overpass-happy.csv",
     )];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     ctx.set_network(network_rc);
     let query = ctx
         .get_file_system()
@@ -82,3 +82,335 @@ fn test_overpass_query() {
 
     assert_eq!(buf.starts_with("@id"), true);
 }
+
+/// Tests overpass_sleep(): the case when no sleep is needed.
+#[test]
+fn test_overpass_sleep_no_sleep() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    let routes = vec![context::tests::URLRoute::new(
+        /*url=*/ "https://overpass-api.de/api/status",
+        /*data_path=*/ "",
+        /*result_path=*/ "src/fixtures/network/overpass-status-happy.txt",
+    )];
+    let network = context::tests::TestNetwork::new(&routes);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
+    ctx.set_network(network_rc);
+
+    overpass_sleep(&ctx);
+
+    let time = ctx
+        .get_time()
+        .as_any()
+        .downcast_ref::<context::tests::TestTime>()
+        .unwrap();
+    assert_eq!(time.get_sleep(), 0);
+}
+
+/// Tests overpass_sleep(): the case when sleep is needed.
+#[test]
+fn test_overpass_sleep_need_sleep() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    let routes = vec![
+        context::tests::URLRoute::new(
+            /*url=*/ "https://overpass-api.de/api/status",
+            /*data_path=*/ "",
+            /*result_path=*/ "src/fixtures/network/overpass-status-wait.txt",
+        ),
+        context::tests::URLRoute::new(
+            /*url=*/ "https://overpass-api.de/api/status",
+            /*data_path=*/ "",
+            /*result_path=*/ "src/fixtures/network/overpass-status-happy.txt",
+        ),
+    ];
+    let network = context::tests::TestNetwork::new(&routes);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
+    ctx.set_network(network_rc);
+
+    overpass_sleep(&ctx);
+
+    let time = ctx
+        .get_time()
+        .as_any()
+        .downcast_ref::<context::tests::TestTime>()
+        .unwrap();
+    assert_eq!(time.get_sleep(), 12);
+}
+
+/// Tests classify_response().
+#[test]
+fn test_classify_response() {
+    let json = "[out:json];\nway(42);\nout;";
+    assert_eq!(classify_response(json, r#"{"elements": []}"#), Ok(()));
+    assert_eq!(
+        classify_response(
+            json,
+            r#"{"elements": [], "remark": "runtime error: Query timed out in \"query\" at line 3 after 26 seconds."}"#
+        ),
+        Err(OverpassError::Timeout)
+    );
+    assert_eq!(
+        classify_response(json, "<?xml version=\"1.0\"?><osm/>").is_err(),
+        true
+    );
+    let csv = "[out:csv(::id)];\nway(42);\nout;";
+    assert_eq!(classify_response(csv, "@id\n42\n"), Ok(()));
+    assert_eq!(
+        classify_response(
+            csv,
+            "<html><body><p>The server is probably too busy to handle your request.</p></body></html>"
+        ),
+        Err(OverpassError::RateLimited)
+    );
+    let xml = "way(42);\nout;";
+    assert_eq!(
+        classify_response(xml, "<osm><way id=\"42\"/></osm>"),
+        Ok(())
+    );
+    assert_eq!(
+        classify_response(
+            xml,
+            "<osm><remark> runtime error: Query run out of memory using about 2048 MB of RAM. </remark></osm>"
+        ),
+        Err(OverpassError::OutOfMemory)
+    );
+    assert_eq!(
+        classify_response(xml, "<html><p>Error: line 1: parse error</p></html>"),
+        Err(OverpassError::Server("Error: line 1: parse error".into()))
+    );
+}
+
+/// Tests classify_status().
+#[test]
+fn test_classify_status() {
+    assert_eq!(classify_status(200, "@id\n42\n"), Ok(()));
+    assert_eq!(
+        classify_status(429, "@id\n"),
+        Err(OverpassError::RateLimited)
+    );
+    assert_eq!(classify_status(504, "@id\n"), Err(OverpassError::Timeout));
+    assert_eq!(
+        classify_status(502, "<html><h1>Bad Gateway</h1></html>"),
+        Err(OverpassError::Server("HTTP 502: Bad Gateway".into()))
+    );
+    assert_eq!(
+        classify_status(400, "<html><p>Error: line 1: parse error</p></html>"),
+        Err(OverpassError::Malformed(
+            "HTTP 400: Error: line 1: parse error".into()
+        ))
+    );
+}
+
+/// Tests get_request_timeout().
+#[test]
+fn test_get_request_timeout() {
+    assert_eq!(
+        get_request_timeout("[out:json] [timeout:425];\nway(42);\nout;"),
+        Duration::from_secs(455)
+    );
+    assert_eq!(
+        get_request_timeout("[out:json];\nway(42);\nout;"),
+        Duration::from_secs(210)
+    );
+}
+
+/// Tests overpass_query_with_retry(): a rate limited query is retried.
+#[test]
+fn test_overpass_query_with_retry_rate_limited() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    let routes = vec![
+        context::tests::URLRoute::new(
+            /*url=*/ "https://overpass-api.de/api/interpreter",
+            /*data_path=*/ "",
+            /*result_path=*/ "src/fixtures/network/overpass-rate-limited.html",
+        ),
+        context::tests::URLRoute::new(
+            /*url=*/ "https://overpass-api.de/api/interpreter",
+            /*data_path=*/ "",
+            /*result_path=*/ "src/fixtures/network/overpass-streets-gazdagret.json",
+        ),
+    ];
+    let network = context::tests::TestNetwork::new(&routes);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
+    ctx.set_network(network_rc);
+
+    let ret = overpass_query_with_retry(&ctx, "[out:json];\nway(42);\nout;");
+
+    assert_eq!(ret.is_ok(), true);
+    let time = ctx
+        .get_time()
+        .as_any()
+        .downcast_ref::<context::tests::TestTime>()
+        .unwrap();
+    assert_eq!(time.get_sleep(), INITIAL_BACKOFF);
+}
+
+/// Tests overpass_query_with_retry(): out of memory is not retried.
+#[test]
+fn test_overpass_query_with_retry_out_of_memory() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    let routes = vec![context::tests::URLRoute::new(
+        /*url=*/ "https://overpass-api.de/api/interpreter",
+        /*data_path=*/ "",
+        /*result_path=*/ "src/fixtures/network/overpass-out-of-memory.json",
+    )];
+    let network = context::tests::TestNetwork::new(&routes);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
+    ctx.set_network(network_rc);
+
+    let ret = overpass_query_with_retry(&ctx, "[out:json];\nway(42);\nout;");
+
+    assert_eq!(ret, Err(OverpassError::OutOfMemory));
+    let time = ctx
+        .get_time()
+        .as_any()
+        .downcast_ref::<context::tests::TestTime>()
+        .unwrap();
+    assert_eq!(time.get_sleep(), 0);
+}
+
+/// Tests overpass_query_with_retry(): the case when we keep getting network errors.
+#[test]
+fn test_overpass_query_with_retry_give_up() {
+    let ctx = context::tests::make_test_context().unwrap();
+
+    let ret = overpass_query_with_retry(&ctx, "[out:json];\nway(42);\nout;");
+
+    assert_eq!(ret.unwrap_err().is_temporary(), true);
+    let time = ctx
+        .get_time()
+        .as_any()
+        .downcast_ref::<context::tests::TestTime>()
+        .unwrap();
+    // The last wait is capped.
+    assert_eq!(time.get_sleep(), MAX_BACKOFF);
+}
+
+/// Creates a test context with two overpass endpoints.
+fn make_failover_context(routes: &[context::tests::URLRoute]) -> context::Context {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    context::tests::set_ini(
+        &mut ctx,
+        "overpass_uri = 'https://overpass-api.de https://overpass.kumi.systems'\n",
+    );
+    let network = context::tests::TestNetwork::new(routes);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
+    ctx.set_network(network_rc);
+    ctx
+}
+
+/// Tests overpass_query(): a temporary failure fails over to the next endpoint.
+#[test]
+fn test_overpass_query_failover() {
+    let routes = vec![
+        context::tests::URLRoute::new(
+            /*url=*/ "https://overpass-api.de/api/interpreter",
+            /*data_path=*/ "",
+            /*result_path=*/ "src/fixtures/network/overpass-rate-limited.html",
+        ),
+        context::tests::URLRoute::new(
+            /*url=*/ "https://overpass.kumi.systems/api/interpreter",
+            /*data_path=*/ "",
+            /*result_path=*/ "src/fixtures/network/overpass-streets-gazdagret.json",
+        ),
+    ];
+    let ctx = make_failover_context(&routes);
+
+    let ret = overpass_query(&ctx, "[out:json];\nway(42);\nout;");
+
+    assert_eq!(ret.is_ok(), true);
+    let health = ctx.get_overpass_health();
+    assert_eq!(health.get_failures(&ctx, "https://overpass-api.de"), 1);
+    assert_eq!(
+        health.get_failures(&ctx, "https://overpass.kumi.systems"),
+        0
+    );
+    // The failed endpoint is now the last one.
+    assert_eq!(
+        get_endpoints(&ctx),
+        vec!["https://overpass.kumi.systems", "https://overpass-api.de"]
+    );
+}
+
+/// Tests overpass_query(): a csv body with an error status fails over to the next endpoint.
+#[test]
+fn test_overpass_query_failover_status() {
+    let routes = vec![
+        context::tests::URLRoute::new(
+            /*url=*/ "https://overpass-api.de/api/interpreter",
+            /*data_path=*/ "",
+            /*result_path=*/ "src/fixtures/network/overpass-happy.csv",
+        )
+        .with_status(504),
+        context::tests::URLRoute::new(
+            /*url=*/ "https://overpass.kumi.systems/api/interpreter",
+            /*data_path=*/ "",
+            /*result_path=*/ "src/fixtures/network/overpass-happy.csv",
+        ),
+    ];
+    let ctx = make_failover_context(&routes);
+
+    let ret = overpass_query(&ctx, "[out:csv(::id)];\nway(42);\nout;");
+
+    assert_eq!(ret.is_ok(), true);
+    let health = ctx.get_overpass_health();
+    assert_eq!(health.get_failures(&ctx, "https://overpass-api.de"), 1);
+}
+
+/// Tests overpass_query(): a permanent failure is not retried on the next endpoint.
+#[test]
+fn test_overpass_query_failover_permanent() {
+    let routes = vec![context::tests::URLRoute::new(
+        /*url=*/ "https://overpass-api.de/api/interpreter",
+        /*data_path=*/ "",
+        /*result_path=*/ "src/fixtures/network/overpass-out-of-memory.json",
+    )];
+    let ctx = make_failover_context(&routes);
+
+    let ret = overpass_query(&ctx, "[out:json];\nway(42);\nout;");
+
+    assert_eq!(ret.unwrap_err(), OverpassError::OutOfMemory);
+    let health = ctx.get_overpass_health();
+    assert_eq!(health.get_failures(&ctx, "https://overpass-api.de"), 0);
+}
+
+/// Tests overpass_query_need_sleep(): the endpoint with a free slot is preferred.
+#[test]
+fn test_overpass_query_need_sleep_failover() {
+    let routes = vec![
+        context::tests::URLRoute::new(
+            /*url=*/ "https://overpass-api.de/api/status",
+            /*data_path=*/ "",
+            /*result_path=*/ "src/fixtures/network/overpass-status-wait.txt",
+        ),
+        context::tests::URLRoute::new(
+            /*url=*/ "https://overpass.kumi.systems/api/status",
+            /*data_path=*/ "",
+            /*result_path=*/ "src/fixtures/network/overpass-status-happy.txt",
+        ),
+        context::tests::URLRoute::new(
+            /*url=*/ "https://overpass.kumi.systems/api/interpreter",
+            /*data_path=*/ "",
+            /*result_path=*/ "src/fixtures/network/overpass-streets-gazdagret.json",
+        ),
+    ];
+    let ctx = make_failover_context(&routes);
+
+    assert_eq!(overpass_query_need_sleep(&ctx), 0);
+    let ret = overpass_query(&ctx, "[out:json];\nway(42);\nout;");
+    assert_eq!(ret.is_ok(), true);
+}
+
+/// Tests overpass_query_need_sleep(): an endpoint without a status is considered unhealthy.
+#[test]
+fn test_overpass_query_need_sleep_no_status() {
+    let routes = vec![context::tests::URLRoute::new(
+        /*url=*/ "https://overpass.kumi.systems/api/status",
+        /*data_path=*/ "",
+        /*result_path=*/ "src/fixtures/network/overpass-status-wait.txt",
+    )];
+    let ctx = make_failover_context(&routes);
+
+    assert_eq!(overpass_query_need_sleep(&ctx), 12);
+    let health = ctx.get_overpass_health();
+    assert_eq!(health.get_failures(&ctx, "https://overpass-api.de"), 1);
+}
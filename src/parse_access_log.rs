@@ -34,7 +34,7 @@ fn is_complete_relation(
 }
 
 /// Determine if 'line' has a user agent which looks like a search bot.
-fn is_search_bot(line: &str) -> bool {
+pub fn is_search_bot(line: &str) -> bool {
     let search_bots = vec![
         "AhrefsBot",
         "AhrefsBot",
@@ -97,7 +97,7 @@ fn get_frequent_relations(
         "{}/frequent-relations.csv",
         ctx.get_ini().get_workdir()
     ))?;
-    let mut guard = csv_stream.borrow_mut();
+    let mut guard = csv_stream.lock().unwrap();
     for item in count_list.iter() {
         guard
             .write_all(format!("{}\t{}\n", item.0, item.1).as_bytes())
@@ -14,7 +14,7 @@ use super::*;
 
 use std::io::Read;
 use std::io::Seek;
-use std::rc::Rc;
+use std::sync::Arc;
 
 /// Tests check_top_edited_relations().
 #[test]
@@ -102,7 +102,7 @@ author-time 1588975200\n\
         .to_string();
     let outputs: HashMap<_, _> = vec![(expected_args, expected_out)].into_iter().collect();
     let subprocess = context::tests::TestSubprocess::new(&outputs);
-    let subprocess_rc: Rc<dyn context::Subprocess> = Rc::new(subprocess);
+    let subprocess_rc: Arc<dyn context::Subprocess> = Arc::new(subprocess);
     ctx.set_subprocess(&subprocess_rc);
 
     let yamls_cache = serde_json::json!({
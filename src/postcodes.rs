@@ -0,0 +1,113 @@
+/*
+ * Copyright 2024 Miklos Vajna
+ *
+ * SPDX-License-Identifier: MIT
+ */
+
+#![deny(warnings)]
+#![warn(clippy::all)]
+#![warn(missing_docs)]
+
+//! The postcodes module checks addr:postcode values against the ZIP reference.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+/// The kind of problem with the postcode of an address.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PostcodeProblem {
+    /// The address has no postcode.
+    Missing,
+    /// The postcode has a format which is not used by any reference postcode.
+    Malformed,
+    /// The postcode is well-formed, but it's not in the reference.
+    Unknown,
+    /// The postcode differs from the dominant postcode of the street.
+    Mismatch,
+}
+
+impl std::fmt::Display for PostcodeProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PostcodeProblem::Missing => write!(f, "missing-postcode"),
+            PostcodeProblem::Malformed => write!(f, "malformed-postcode"),
+            PostcodeProblem::Unknown => write!(f, "unknown-postcode"),
+            PostcodeProblem::Mismatch => write!(f, "postcode-mismatch"),
+        }
+    }
+}
+
+/// Gets the format of a postcode: digits are replaced with '9', letters with 'A', e.g. 1111 ->
+/// 9999.
+fn get_format(postcode: &str) -> String {
+    postcode
+        .chars()
+        .map(|ch| {
+            if ch.is_ascii_digit() {
+                '9'
+            } else if ch.is_alphabetic() {
+                'A'
+            } else {
+                ch
+            }
+        })
+        .collect()
+}
+
+/// Checks the postcodes of addresses, which are (street, postcode) pairs. Returns the problem of
+/// each address, in the same order. The street is only used to group addresses, so it can be e.g.
+/// a city + street pair.
+pub fn get_problems(
+    addresses: &[(String, String)],
+    valid_postcodes: &HashSet<String>,
+) -> Vec<Option<PostcodeProblem>> {
+    let formats: HashSet<String> = valid_postcodes.iter().map(|i| get_format(i)).collect();
+    let mut ret: Vec<Option<PostcodeProblem>> = addresses
+        .iter()
+        .map(|(_street, postcode)| {
+            if postcode.is_empty() {
+                Some(PostcodeProblem::Missing)
+            } else if !formats.contains(&get_format(postcode)) {
+                Some(PostcodeProblem::Malformed)
+            } else if !valid_postcodes.contains(postcode) {
+                Some(PostcodeProblem::Unknown)
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    // Street -> postcode -> count, only considering valid postcodes.
+    let mut street_postcodes: HashMap<&str, HashMap<&str, usize>> = HashMap::new();
+    for ((street, postcode), problem) in addresses.iter().zip(ret.iter()) {
+        if problem.is_none() {
+            *street_postcodes
+                .entry(street)
+                .or_default()
+                .entry(postcode)
+                .or_default() += 1;
+        }
+    }
+    // A postcode is dominant if more than half of the valid postcodes of the street use it.
+    let mut dominant_postcodes: HashMap<&str, &str> = HashMap::new();
+    for (street, postcodes) in &street_postcodes {
+        let total: usize = postcodes.values().sum();
+        if let Some((postcode, _count)) = postcodes.iter().find(|(_, count)| **count * 2 > total) {
+            dominant_postcodes.insert(street, postcode);
+        }
+    }
+    for ((street, postcode), problem) in addresses.iter().zip(ret.iter_mut()) {
+        if problem.is_some() {
+            continue;
+        }
+        if let Some(dominant) = dominant_postcodes.get(street.as_str()) {
+            if dominant != postcode {
+                *problem = Some(PostcodeProblem::Mismatch);
+            }
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests;
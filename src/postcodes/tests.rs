@@ -0,0 +1,58 @@
+/*
+ * Copyright 2024 Miklos Vajna
+ *
+ * SPDX-License-Identifier: MIT
+ */
+
+#![deny(warnings)]
+#![warn(clippy::all)]
+#![warn(missing_docs)]
+
+//! Tests for the postcodes module.
+
+use super::*;
+
+/// Tests get_problems().
+#[test]
+fn test_get_problems() {
+    let valid_postcodes: HashSet<String> = ["1111".to_string(), "1121".to_string()].into();
+    let addresses: Vec<(String, String)> = [
+        ("Tűzkő utca", ""),
+        ("Tűzkő utca", "111"),
+        ("Tűzkő utca", "9999"),
+        ("Tűzkő utca", "1111"),
+        ("Tűzkő utca", "1111"),
+        ("Tűzkő utca", "1121"),
+        ("Törökugrató utca", "1111"),
+        ("Törökugrató utca", "1121"),
+    ]
+    .iter()
+    .map(|(street, postcode)| (street.to_string(), postcode.to_string()))
+    .collect();
+
+    let problems = get_problems(&addresses, &valid_postcodes);
+
+    assert_eq!(
+        problems,
+        vec![
+            Some(PostcodeProblem::Missing),
+            Some(PostcodeProblem::Malformed),
+            Some(PostcodeProblem::Unknown),
+            None,
+            None,
+            Some(PostcodeProblem::Mismatch),
+            // No dominant postcode: no mismatch.
+            None,
+            None,
+        ]
+    );
+}
+
+/// Tests the Display impl of PostcodeProblem.
+#[test]
+fn test_postcode_problem_display() {
+    assert_eq!(PostcodeProblem::Missing.to_string(), "missing-postcode");
+    assert_eq!(PostcodeProblem::Malformed.to_string(), "malformed-postcode");
+    assert_eq!(PostcodeProblem::Unknown.to_string(), "unknown-postcode");
+    assert_eq!(PostcodeProblem::Mismatch.to_string(), "postcode-mismatch");
+}
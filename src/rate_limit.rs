@@ -0,0 +1,157 @@
+/*
+ * Copyright 2024 Miklos Vajna
+ *
+ * SPDX-License-Identifier: MIT
+ */
+
+#![deny(warnings)]
+#![warn(clippy::all)]
+#![warn(missing_docs)]
+
+//! The rate_limit module decides if a request can trigger an Overpass query, using per-client and
+//! global token buckets.
+
+use crate::context;
+use crate::parse_access_log;
+
+/// The verdict on a request which would trigger an Overpass query.
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    /// The request can continue.
+    Allowed,
+    /// The request is over the limit, the client can retry after this many seconds.
+    Throttled(u64),
+    /// The request is from a search bot.
+    Refused,
+}
+
+/// Decides if request_uri would trigger an Overpass query.
+pub fn is_overpass_route(ctx: &context::Context, request_uri: &str) -> bool {
+    let prefix = ctx.get_ini().get_uri_prefix();
+    let update =
+        request_uri.ends_with("/update-result") || request_uri.ends_with("/update-result.json");
+    if update
+        && (request_uri.starts_with(&format!("{prefix}/streets/"))
+            || request_uri.starts_with(&format!("{prefix}/street-housenumbers/")))
+    {
+        return true;
+    }
+
+    request_uri.starts_with(&format!("{prefix}/additional-streets/"))
+        && request_uri.ends_with(".gpx")
+}
+
+/// Gets the address of the client. If the request comes from a trusted reverse proxy, then the
+/// right-most X-Forwarded-For address which is not a trusted proxy is used: addresses left of that
+/// are set by the client and can't be trusted.
+pub fn get_client(ctx: &context::Context, request: &rouille::Request) -> anyhow::Result<String> {
+    let trusted_proxies = ctx.get_ini().get_trusted_proxies()?;
+    let mut client = request.remote_addr().ip();
+    if !trusted_proxies.contains(&client) {
+        return Ok(client.to_string());
+    }
+
+    if let Some(value) = request.header("X-Forwarded-For") {
+        for hop in value.split(',').rev() {
+            let hop: std::net::IpAddr = match hop.trim().parse() {
+                Ok(value) => value,
+                Err(_) => break,
+            };
+            client = hop;
+            if !trusted_proxies.contains(&client) {
+                break;
+            }
+        }
+    }
+
+    Ok(client.to_string())
+}
+
+/// Gets the tokens of a bucket, refilled based on the time elapsed since its last use.
+fn get_tokens(
+    conn: &rusqlite::Connection,
+    key: &str,
+    burst: f64,
+    per_minute: f64,
+    now: i128,
+) -> anyhow::Result<f64> {
+    let mut stmt = conn.prepare("select tokens, updated from rate_limits where key = ?1")?;
+    let mut rows = stmt.query([key])?;
+    let row = match rows.next()? {
+        Some(value) => value,
+        None => {
+            return Ok(burst);
+        }
+    };
+    let tokens: String = row.get(0)?;
+    let updated: String = row.get(1)?;
+    let elapsed = (now - updated.parse::<i128>()?) as f64 / 1_000_000_000_f64;
+    Ok((tokens.parse::<f64>()? + elapsed.max(0_f64) * per_minute / 60_f64).min(burst))
+}
+
+/// Gets the number of seconds until a bucket has a full token again.
+fn get_retry_after(tokens: f64, per_minute: f64) -> u64 {
+    if per_minute <= 0_f64 {
+        return 60;
+    }
+
+    (((1_f64 - tokens) * 60_f64 / per_minute).ceil() as u64).max(1)
+}
+
+/// Decides if a client with the given user agent can trigger an Overpass query now. If so, a token
+/// is taken from both the client and the global bucket.
+pub fn check(ctx: &context::Context, client: &str, user_agent: &str) -> anyhow::Result<Verdict> {
+    if parse_access_log::is_search_bot(user_agent) {
+        return Ok(Verdict::Refused);
+    }
+
+    let ini = ctx.get_ini();
+    let buckets = [
+        (
+            format!("client/{client}"),
+            ini.get_rate_limit_client_burst()?,
+            ini.get_rate_limit_client_per_minute()?,
+        ),
+        (
+            "global".to_string(),
+            ini.get_rate_limit_global_burst()?,
+            ini.get_rate_limit_global_per_minute()?,
+        ),
+    ];
+    let now = ctx.get_time().now().unix_timestamp_nanos();
+    let mut conn = ctx.get_database_connection()?;
+    // Read and take the tokens atomically, so parallel requests can't take the same token.
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+    // Forget the clients whose bucket is full again: a missing bucket is full.
+    let (_key, client_burst, client_per_minute) = &buckets[0];
+    tx.execute(
+        "delete from rate_limits where key like 'client/%'
+         and cast(tokens as real) + (?1 - cast(updated as integer)) / 1e9 * ?2 / 60 >= ?3",
+        rusqlite::params![i64::try_from(now)?, client_per_minute, client_burst],
+    )?;
+    let mut tokens: Vec<f64> = Vec::new();
+    let mut retry_after = 0;
+    for (key, burst, per_minute) in &buckets {
+        let value = get_tokens(&tx, key, *burst, *per_minute, now)?;
+        if value < 1_f64 {
+            retry_after = retry_after.max(get_retry_after(value, *per_minute));
+        }
+        tokens.push(value);
+    }
+    if retry_after > 0 {
+        return Ok(Verdict::Throttled(retry_after));
+    }
+
+    for ((key, _burst, _per_minute), value) in buckets.iter().zip(tokens) {
+        tx.execute(
+            "insert into rate_limits (key, tokens, updated) values (?1, ?2, ?3)
+             on conflict(key) do update set tokens = excluded.tokens, updated = excluded.updated",
+            [key, &(value - 1_f64).to_string(), &now.to_string()],
+        )?;
+    }
+    tx.commit()?;
+    Ok(Verdict::Allowed)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,145 @@
+/*
+ * Copyright 2024 Miklos Vajna
+ *
+ * SPDX-License-Identifier: MIT
+ */
+
+#![deny(warnings)]
+#![warn(clippy::all)]
+#![warn(missing_docs)]
+
+//! Tests for the rate_limit module.
+
+use super::*;
+use std::sync::Arc;
+
+/// Tests is_overpass_route().
+#[test]
+fn test_is_overpass_route() {
+    let ctx = context::tests::make_test_context().unwrap();
+
+    assert_eq!(
+        is_overpass_route(&ctx, "/osm/streets/gazdagret/update-result"),
+        true
+    );
+    assert_eq!(
+        is_overpass_route(
+            &ctx,
+            "/osm/street-housenumbers/gazdagret/update-result.json"
+        ),
+        true
+    );
+    assert_eq!(
+        is_overpass_route(&ctx, "/osm/additional-streets/gazdagret/view-result.gpx"),
+        true
+    );
+    assert_eq!(
+        is_overpass_route(&ctx, "/osm/streets/gazdagret/view-result"),
+        false
+    );
+    assert_eq!(
+        is_overpass_route(
+            &ctx,
+            "/osm/additional-streets/gazdagret/view-result.geojson"
+        ),
+        false
+    );
+}
+
+/// Tests get_client().
+#[test]
+fn test_get_client() {
+    let ctx = context::tests::make_test_context().unwrap();
+    let request = rouille::Request::fake_http("GET", "/osm", vec![], vec![]);
+    assert_eq!(get_client(&ctx, &request).unwrap(), "127.0.0.1");
+
+    // The right-most address is added by the trusted proxy, the others may be spoofed.
+    let headers = vec![(
+        "X-Forwarded-For".to_string(),
+        "198.51.100.1, 192.0.2.1".to_string(),
+    )];
+    let request = rouille::Request::fake_http("GET", "/osm", headers, vec![]);
+    assert_eq!(get_client(&ctx, &request).unwrap(), "192.0.2.1");
+
+    // Chained trusted proxies are skipped.
+    let headers = vec![(
+        "X-Forwarded-For".to_string(),
+        "198.51.100.1, 192.0.2.1, 127.0.0.1".to_string(),
+    )];
+    let request = rouille::Request::fake_http("GET", "/osm", headers, vec![]);
+    assert_eq!(get_client(&ctx, &request).unwrap(), "192.0.2.1");
+}
+
+/// Tests get_client(): X-Forwarded-For is ignored if the request is not from a trusted proxy.
+#[test]
+fn test_get_client_untrusted() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    context::tests::set_ini(&mut ctx, "trusted_proxies = '192.0.2.2'\n");
+    let headers = vec![("X-Forwarded-For".to_string(), "192.0.2.1".to_string())];
+    let request = rouille::Request::fake_http("GET", "/osm", headers, vec![]);
+
+    assert_eq!(get_client(&ctx, &request).unwrap(), "127.0.0.1");
+}
+
+/// Tests check(): the client bucket runs out, then refills over time.
+#[test]
+fn test_check_client() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    context::tests::set_ini(
+        &mut ctx,
+        "rate_limit_client_burst = '2'\nrate_limit_client_per_minute = '1'\n",
+    );
+
+    assert_eq!(check(&ctx, "192.0.2.1", "").unwrap(), Verdict::Allowed);
+    assert_eq!(check(&ctx, "192.0.2.1", "").unwrap(), Verdict::Allowed);
+    assert_eq!(
+        check(&ctx, "192.0.2.1", "").unwrap(),
+        Verdict::Throttled(60)
+    );
+    // Other clients have their own bucket.
+    assert_eq!(check(&ctx, "192.0.2.2", "").unwrap(), Verdict::Allowed);
+
+    let time = context::tests::TestTime::new(2020, 5, 11);
+    let time_rc: Arc<dyn context::Time> = Arc::new(time);
+    ctx.set_time(&time_rc);
+    assert_eq!(check(&ctx, "192.0.2.1", "").unwrap(), Verdict::Allowed);
+    // The bucket of the other client is full again, so it's deleted.
+    let conn = ctx.get_database_connection().unwrap();
+    let mut stmt = conn
+        .prepare("select key from rate_limits order by key")
+        .unwrap();
+    let keys: Vec<String> = stmt
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .map(|key| key.unwrap())
+        .collect();
+    assert_eq!(keys, ["client/192.0.2.1", "global"]);
+}
+
+/// Tests check(): the global bucket is shared by all clients.
+#[test]
+fn test_check_global() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    context::tests::set_ini(
+        &mut ctx,
+        "rate_limit_global_burst = '1'\nrate_limit_global_per_minute = '4'\n",
+    );
+
+    assert_eq!(check(&ctx, "192.0.2.1", "").unwrap(), Verdict::Allowed);
+    assert_eq!(
+        check(&ctx, "192.0.2.2", "").unwrap(),
+        Verdict::Throttled(15)
+    );
+}
+
+/// Tests check(): search bots are refused.
+#[test]
+fn test_check_bot() {
+    let ctx = context::tests::make_test_context().unwrap();
+
+    let user_agent = "Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)";
+    assert_eq!(
+        check(&ctx, "192.0.2.1", user_agent).unwrap(),
+        Verdict::Refused
+    );
+}
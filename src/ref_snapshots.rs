@@ -0,0 +1,310 @@
+/*
+ * Copyright 2024 Miklos Vajna
+ *
+ * SPDX-License-Identifier: MIT
+ */
+
+#![deny(warnings)]
+#![warn(clippy::all)]
+#![warn(missing_docs)]
+
+//! The ref_snapshots module versions the reference imports and reports what changed between two
+//! of them.
+
+use crate::areas;
+use crate::context;
+use anyhow::Context as _;
+use std::collections::BTreeSet;
+use std::io::Write;
+
+/// One reference import.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    /// Snapshot ID, increasing with each import.
+    pub id: i64,
+    /// When the snapshot was created.
+    pub created: time::OffsetDateTime,
+}
+
+/// Records the current content of ref_housenumbers and ref_streets as a new snapshot, returns
+/// its ID.
+pub fn create_snapshot(ctx: &context::Context) -> anyhow::Result<i64> {
+    let mut conn = ctx.get_database_connection()?;
+    let tx = conn.transaction()?;
+    tx.execute(
+        "insert into ref_snapshots (created) values (?1)",
+        [ctx.get_time().now_string()],
+    )?;
+    let id = tx.last_insert_rowid();
+
+    tx.execute(
+        "update ref_housenumbers_history set removed_in = ?1
+        where removed_in is null and not exists (
+            select 1 from ref_housenumbers r
+            where r.county_code = ref_housenumbers_history.county_code
+            and r.settlement_code = ref_housenumbers_history.settlement_code
+            and r.street = ref_housenumbers_history.street
+            and r.housenumber = ref_housenumbers_history.housenumber)",
+        [id],
+    )?;
+    tx.execute(
+        "insert into ref_housenumbers_history
+            (county_code, settlement_code, street, housenumber, added_in)
+        select distinct county_code, settlement_code, street, housenumber, ?1
+        from ref_housenumbers r
+        where not exists (
+            select 1 from ref_housenumbers_history h
+            where h.removed_in is null
+            and h.county_code = r.county_code
+            and h.settlement_code = r.settlement_code
+            and h.street = r.street
+            and h.housenumber = r.housenumber)",
+        [id],
+    )?;
+    tx.execute(
+        "update ref_streets_history set removed_in = ?1
+        where removed_in is null and not exists (
+            select 1 from ref_streets r
+            where r.county_code = ref_streets_history.county_code
+            and r.settlement_code = ref_streets_history.settlement_code
+            and r.street = ref_streets_history.street)",
+        [id],
+    )?;
+    tx.execute(
+        "insert into ref_streets_history (county_code, settlement_code, street, added_in)
+        select distinct county_code, settlement_code, street, ?1
+        from ref_streets r
+        where not exists (
+            select 1 from ref_streets_history h
+            where h.removed_in is null
+            and h.county_code = r.county_code
+            and h.settlement_code = r.settlement_code
+            and h.street = r.street)",
+        [id],
+    )?;
+
+    tx.commit()?;
+    Ok(id)
+}
+
+/// Gets the snapshots, oldest first.
+pub fn get_snapshots(ctx: &context::Context) -> anyhow::Result<Vec<Snapshot>> {
+    let conn = ctx.get_database_connection()?;
+    let mut stmt = conn.prepare("select id, created from ref_snapshots order by id")?;
+    let mut rows = stmt.query([])?;
+    let mut ret: Vec<Snapshot> = Vec::new();
+    let offset = ctx.get_time().now().offset();
+    while let Some(row) = rows.next()? {
+        let created: String = row.get(1)?;
+        let nanos: i128 = created.parse()?;
+        ret.push(Snapshot {
+            id: row.get(0)?,
+            created: time::OffsetDateTime::from_unix_timestamp_nanos(nanos)?.to_offset(offset),
+        });
+    }
+    Ok(ret)
+}
+
+/// Gets the last two snapshots, if there are at least two of them.
+pub fn get_latest_pair(ctx: &context::Context) -> anyhow::Result<Option<(Snapshot, Snapshot)>> {
+    let snapshots = get_snapshots(ctx)?;
+    match snapshots.as_slice() {
+        [.., from, to] => Ok(Some((from.clone(), to.clone()))),
+        _ => Ok(None),
+    }
+}
+
+/// Changes of the reference between two snapshots, for one relation.
+#[derive(Debug, Default)]
+pub struct RelationDiff {
+    /// Relation name.
+    pub relation_name: String,
+    /// Streets only in the new snapshot.
+    pub added_streets: Vec<String>,
+    /// Streets only in the old snapshot.
+    pub removed_streets: Vec<String>,
+    /// Street name - house number pairs only in the new snapshot.
+    pub added_housenumbers: Vec<(String, String)>,
+    /// Street name - house number pairs only in the old snapshot.
+    pub removed_housenumbers: Vec<(String, String)>,
+}
+
+impl RelationDiff {
+    /// Decides if the reference of the relation changed at all.
+    pub fn is_empty(&self) -> bool {
+        self.added_streets.is_empty()
+            && self.removed_streets.is_empty()
+            && self.added_housenumbers.is_empty()
+            && self.removed_housenumbers.is_empty()
+    }
+}
+
+/// Gets the reference streets of an area, as of a snapshot.
+fn get_streets_at(
+    conn: &rusqlite::Connection,
+    county: &str,
+    settlement: &str,
+    snapshot: i64,
+) -> anyhow::Result<BTreeSet<String>> {
+    let mut stmt = conn.prepare(
+        "select street from ref_streets_history
+        where county_code = ?1 and settlement_code = ?2
+        and added_in <= ?3 and (removed_in is null or removed_in > ?3)",
+    )?;
+    let mut rows = stmt.query(rusqlite::params![county, settlement, snapshot])?;
+    let mut ret: BTreeSet<String> = BTreeSet::new();
+    while let Some(row) = rows.next()? {
+        ret.insert(row.get(0)?);
+    }
+    Ok(ret)
+}
+
+/// Gets the reference street name - house number pairs of an area, as of a snapshot.
+fn get_housenumbers_at(
+    conn: &rusqlite::Connection,
+    county: &str,
+    settlement: &str,
+    snapshot: i64,
+) -> anyhow::Result<BTreeSet<(String, String)>> {
+    let mut stmt = conn.prepare(
+        "select street, housenumber from ref_housenumbers_history
+        where county_code = ?1 and settlement_code = ?2
+        and added_in <= ?3 and (removed_in is null or removed_in > ?3)",
+    )?;
+    let mut rows = stmt.query(rusqlite::params![county, settlement, snapshot])?;
+    let mut ret: BTreeSet<(String, String)> = BTreeSet::new();
+    while let Some(row) = rows.next()? {
+        ret.insert((row.get(0)?, row.get(1)?));
+    }
+    Ok(ret)
+}
+
+/// Gets the reference changes of one relation between two snapshots.
+pub fn get_relation_diff(
+    ctx: &context::Context,
+    relation: &areas::Relation<'_>,
+    from: i64,
+    to: i64,
+) -> anyhow::Result<RelationDiff> {
+    let conn = ctx.get_database_connection()?;
+    let county = relation.get_config().get_refcounty();
+    let settlement = relation.get_config().get_refsettlement();
+    let old_streets = get_streets_at(&conn, &county, &settlement, from)?;
+    let new_streets = get_streets_at(&conn, &county, &settlement, to)?;
+    let old_housenumbers = get_housenumbers_at(&conn, &county, &settlement, from)?;
+    let new_housenumbers = get_housenumbers_at(&conn, &county, &settlement, to)?;
+    Ok(RelationDiff {
+        relation_name: relation.get_name(),
+        added_streets: new_streets.difference(&old_streets).cloned().collect(),
+        removed_streets: old_streets.difference(&new_streets).cloned().collect(),
+        added_housenumbers: new_housenumbers
+            .difference(&old_housenumbers)
+            .cloned()
+            .collect(),
+        removed_housenumbers: old_housenumbers
+            .difference(&new_housenumbers)
+            .cloned()
+            .collect(),
+    })
+}
+
+/// Gets the reference changes of all relations between two snapshots, omitting the unchanged
+/// relations.
+pub fn get_relation_diffs(
+    ctx: &context::Context,
+    relations: &mut areas::Relations<'_>,
+    from: i64,
+    to: i64,
+) -> anyhow::Result<Vec<RelationDiff>> {
+    let mut ret: Vec<RelationDiff> = Vec::new();
+    for relation in relations.get_relations()? {
+        let diff = get_relation_diff(ctx, &relation, from, to)?;
+        if !diff.is_empty() {
+            ret.push(diff);
+        }
+    }
+    Ok(ret)
+}
+
+/// Writes the reference changes of one relation as plain text.
+fn write_relation_diff(stream: &mut dyn Write, diff: &RelationDiff) -> anyhow::Result<()> {
+    writeln!(stream, "{}:", diff.relation_name)?;
+    for street in &diff.added_streets {
+        writeln!(stream, "+ street: {street}")?;
+    }
+    for street in &diff.removed_streets {
+        writeln!(stream, "- street: {street}")?;
+    }
+    for (street, housenumber) in &diff.added_housenumbers {
+        writeln!(stream, "+ housenumber: {street} {housenumber}")?;
+    }
+    for (street, housenumber) in &diff.removed_housenumbers {
+        writeln!(stream, "- housenumber: {street} {housenumber}")?;
+    }
+    Ok(())
+}
+
+/// Inner main() that is allowed to fail.
+pub fn our_main(
+    argv: &[String],
+    stream: &mut dyn Write,
+    ctx: &context::Context,
+) -> anyhow::Result<()> {
+    let from = clap::Arg::new("from")
+        .long("from")
+        .value_parser(clap::value_parser!(i64))
+        .help("old snapshot ID [default: the one before the latest]");
+    let to = clap::Arg::new("to")
+        .long("to")
+        .value_parser(clap::value_parser!(i64))
+        .help("new snapshot ID [default: the latest]");
+    let relation = clap::Arg::new("relation")
+        .long("relation")
+        .help("only show this relation");
+    let args = [from, to, relation];
+    let app = clap::Command::new("osm-gimmisn")
+        .override_usage("osm-gimmisn reference-diff [--from <ID>] [--to <ID>] [--relation <NAME>]");
+    let args = app.args(&args).try_get_matches_from(argv)?;
+
+    let latest = get_latest_pair(ctx)?;
+    let get_latest = || {
+        latest
+            .as_ref()
+            .context("not enough reference snapshots yet")
+    };
+    let from = match args.get_one::<i64>("from") {
+        Some(value) => *value,
+        None => get_latest()?.0.id,
+    };
+    let to = match args.get_one::<i64>("to") {
+        Some(value) => *value,
+        None => get_latest()?.1.id,
+    };
+
+    let mut relations = areas::Relations::new(ctx)?;
+    let diffs = match args.get_one::<String>("relation") {
+        Some(relation_name) => {
+            let relation = relations.get_relation(relation_name)?;
+            vec![get_relation_diff(ctx, &relation, from, to)?]
+        }
+        None => get_relation_diffs(ctx, &mut relations, from, to)?,
+    };
+    for diff in diffs {
+        write_relation_diff(stream, &diff)?;
+    }
+    Ok(())
+}
+
+/// Similar to plain main(), but with an interface that allows testing.
+pub fn main(args: &[String], stream: &mut dyn Write, ctx: &context::Context) -> i32 {
+    match our_main(args, stream, ctx) {
+        Ok(_) => 0,
+        Err(err) => {
+            stream.write_all(format!("{err:?}\n").as_bytes()).unwrap();
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
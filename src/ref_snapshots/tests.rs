@@ -0,0 +1,157 @@
+/*
+ * Copyright 2024 Miklos Vajna
+ *
+ * SPDX-License-Identifier: MIT
+ */
+
+#![deny(warnings)]
+#![warn(clippy::all)]
+#![warn(missing_docs)]
+
+//! Tests for the ref_snapshots module.
+
+use super::*;
+
+/// Creates a test context with one relation, with empty reference indexes.
+fn make_context() -> context::Context {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "osmrelation": 42,
+                "refcounty": "01",
+                "refsettlement": "011",
+            },
+            "ujbuda": {
+                "osmrelation": 43,
+                "refcounty": "01",
+                "refsettlement": "012",
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        &ctx,
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    ctx.set_file_system(&file_system);
+    {
+        let conn = ctx.get_database_connection().unwrap();
+        conn.execute_batch("delete from ref_housenumbers; delete from ref_streets;")
+            .unwrap();
+    }
+    ctx
+}
+
+/// Replaces the reference indexes with the provided streets and house numbers.
+fn set_reference(ctx: &context::Context, streets: &[&str], housenumbers: &[(&str, &str)]) {
+    let conn = ctx.get_database_connection().unwrap();
+    conn.execute_batch("delete from ref_housenumbers; delete from ref_streets;")
+        .unwrap();
+    for street in streets {
+        conn.execute(
+            "insert into ref_streets (county_code, settlement_code, street) values ('01', '011', ?1)",
+            [street],
+        )
+        .unwrap();
+    }
+    for (street, housenumber) in housenumbers {
+        conn.execute(
+            "insert into ref_housenumbers (county_code, settlement_code, street, housenumber, comment) values ('01', '011', ?1, ?2, '')",
+            [street, housenumber],
+        )
+        .unwrap();
+    }
+}
+
+/// Tests get_relation_diffs(): added and removed streets and house numbers.
+#[test]
+fn test_get_relation_diffs() {
+    let ctx = make_context();
+    set_reference(
+        &ctx,
+        &["Tűzkő utca", "Törökugrató utca"],
+        &[("Tűzkő utca", "1"), ("Tűzkő utca", "2")],
+    );
+    let from = create_snapshot(&ctx).unwrap();
+    set_reference(
+        &ctx,
+        &["Tűzkő utca", "Hamzsabégi út"],
+        &[("Tűzkő utca", "1"), ("Tűzkő utca", "3")],
+    );
+    let to = create_snapshot(&ctx).unwrap();
+    let mut relations = areas::Relations::new(&ctx).unwrap();
+
+    let diffs = get_relation_diffs(&ctx, &mut relations, from, to).unwrap();
+
+    // ujbuda is not changed, so it's omitted.
+    assert_eq!(diffs.len(), 1);
+    assert_eq!(diffs[0].relation_name, "gazdagret");
+    assert_eq!(diffs[0].added_streets, vec!["Hamzsabégi út"]);
+    assert_eq!(diffs[0].removed_streets, vec!["Törökugrató utca"]);
+    assert_eq!(
+        diffs[0].added_housenumbers,
+        vec![("Tűzkő utca".to_string(), "3".to_string())]
+    );
+    assert_eq!(
+        diffs[0].removed_housenumbers,
+        vec![("Tűzkő utca".to_string(), "2".to_string())]
+    );
+    // The reverse direction works, too.
+    let diffs = get_relation_diffs(&ctx, &mut relations, to, from).unwrap();
+    assert_eq!(diffs[0].added_streets, vec!["Törökugrató utca"]);
+}
+
+/// Tests create_snapshot(): a house number removed and then added again.
+#[test]
+fn test_create_snapshot_readd() {
+    let ctx = make_context();
+    set_reference(&ctx, &[], &[("Tűzkő utca", "1")]);
+    let first = create_snapshot(&ctx).unwrap();
+    set_reference(&ctx, &[], &[]);
+    let second = create_snapshot(&ctx).unwrap();
+    set_reference(&ctx, &[], &[("Tűzkő utca", "1")]);
+    let third = create_snapshot(&ctx).unwrap();
+    let mut relations = areas::Relations::new(&ctx).unwrap();
+
+    let diffs = get_relation_diffs(&ctx, &mut relations, first, third).unwrap();
+    assert_eq!(diffs.is_empty(), true);
+    let diffs = get_relation_diffs(&ctx, &mut relations, second, third).unwrap();
+    assert_eq!(diffs[0].added_housenumbers.len(), 1);
+    assert_eq!(get_snapshots(&ctx).unwrap().len(), 3);
+}
+
+/// Tests main(): the latest two snapshots are compared by default.
+#[test]
+fn test_main() {
+    let ctx = make_context();
+    set_reference(&ctx, &["Tűzkő utca"], &[]);
+    create_snapshot(&ctx).unwrap();
+    set_reference(&ctx, &[], &[("Tűzkő utca", "1")]);
+    create_snapshot(&ctx).unwrap();
+    let argv: Vec<String> = vec!["".into()];
+    let mut buf: std::io::Cursor<Vec<u8>> = std::io::Cursor::new(Vec::new());
+
+    let ret = main(&argv, &mut buf, &ctx);
+
+    assert_eq!(ret, 0);
+    let output = String::from_utf8(buf.into_inner()).unwrap();
+    assert_eq!(
+        output,
+        "gazdagret:\n- street: Tűzkő utca\n+ housenumber: Tűzkő utca 1\n"
+    );
+}
+
+/// Tests main(): not enough snapshots.
+#[test]
+fn test_main_no_snapshots() {
+    let ctx = make_context();
+    create_snapshot(&ctx).unwrap();
+    let argv: Vec<String> = vec!["".into(), "--relation".into(), "gazdagret".into()];
+    let mut buf: std::io::Cursor<Vec<u8>> = std::io::Cursor::new(Vec::new());
+
+    let ret = main(&argv, &mut buf, &ctx);
+
+    assert_eq!(ret, 1);
+}
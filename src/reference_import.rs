@@ -0,0 +1,455 @@
+/*
+ * Copyright 2024 Miklos Vajna
+ *
+ * SPDX-License-Identifier: MIT
+ */
+
+#![deny(warnings)]
+#![warn(clippy::all)]
+#![warn(missing_docs)]
+
+//! The reference_import module builds the in-database reference indexes from house number and
+//! street references in various file formats.
+
+use crate::context;
+use anyhow::Context as _;
+use lazy_static::lazy_static;
+use std::ops::DerefMut;
+
+lazy_static! {
+    static ref NULL_END: regex::Regex = regex::Regex::new(r" null$").unwrap();
+}
+
+/// One row of a reference: a house number or a street.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RefRow {
+    /// County code.
+    pub county: String,
+    /// Settlement code.
+    pub settlement: String,
+    /// Street name.
+    pub street: String,
+    /// House number, empty for street references.
+    pub housenumber: String,
+    /// Comment, empty if there is none.
+    pub comment: String,
+    /// Latitude, empty if the source doesn't have it.
+    pub lat: String,
+    /// Longitude, empty if the source doesn't have it.
+    pub lon: String,
+}
+
+/// Column names (or GeoJSON property names) of a reference source.
+#[derive(Clone)]
+pub struct Columns {
+    /// County code column.
+    pub county: String,
+    /// Settlement code column.
+    pub settlement: String,
+    /// Street name column.
+    pub street: String,
+    /// House number column, None for street references.
+    pub housenumber: Option<String>,
+    /// Comment column, optional even if set.
+    pub comment: Option<String>,
+    /// Latitude column, optional even if set.
+    pub lat: Option<String>,
+    /// Longitude column, optional even if set.
+    pub lon: Option<String>,
+}
+
+impl Columns {
+    /// Creates columns with the names used in the hazszamok / utcak TSV files.
+    fn new(kind: Kind) -> Self {
+        let housenumber = match kind {
+            Kind::Housenumbers => Some("HOUSENUMBER".into()),
+            Kind::Streets => None,
+        };
+        Columns {
+            county: "COUNTY_CODE".into(),
+            settlement: "SETTLEMENT_CODE".into(),
+            street: "STREET".into(),
+            housenumber,
+            comment: Some("COMMENT".into()),
+            lat: None,
+            lon: None,
+        }
+    }
+}
+
+/// A reference file format.
+pub trait Importer {
+    /// Reads the reference at path, calling handler for each row.
+    fn import(
+        &self,
+        ctx: &context::Context,
+        path: &str,
+        handler: &mut dyn FnMut(RefRow) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()>;
+}
+
+/// Importer for delimiter-separated files with a header row, e.g. the hazszamok TSV files.
+pub struct CsvImporter {
+    delimiter: u8,
+    columns: Columns,
+}
+
+impl CsvImporter {
+    /// Creates a new CsvImporter.
+    pub fn new(delimiter: u8, columns: &Columns) -> Self {
+        CsvImporter {
+            delimiter,
+            columns: columns.clone(),
+        }
+    }
+}
+
+impl Importer for CsvImporter {
+    fn import(
+        &self,
+        ctx: &context::Context,
+        path: &str,
+        handler: &mut dyn FnMut(RefRow) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let stream = ctx.get_file_system().open_read(path)?;
+        let mut guard = stream.lock().unwrap();
+        let read = std::io::BufReader::new(guard.deref_mut());
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .double_quote(true)
+            .from_reader(read);
+        let headers = reader.headers()?.clone();
+        let find = |column: &str| headers.iter().position(|header| header == column);
+        let get_index =
+            |column: &str| find(column).context(format!("missing column '{column}' in '{path}'"));
+        let county = get_index(&self.columns.county)?;
+        let settlement = get_index(&self.columns.settlement)?;
+        let street = get_index(&self.columns.street)?;
+        let housenumber = match self.columns.housenumber {
+            Some(ref column) => Some(get_index(column)?),
+            None => None,
+        };
+        let find_optional = |column: &Option<String>| column.as_ref().and_then(|i| find(i));
+        let comment = find_optional(&self.columns.comment);
+        let lat = find_optional(&self.columns.lat);
+        let lon = find_optional(&self.columns.lon);
+
+        for result in reader.records() {
+            let record = result?;
+            let get = |index: Option<usize>| -> String {
+                index
+                    .and_then(|index| record.get(index))
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            handler(RefRow {
+                county: get(Some(county)),
+                settlement: get(Some(settlement)),
+                street: get(Some(street)),
+                housenumber: get(housenumber),
+                comment: get(comment),
+                lat: get(lat),
+                lon: get(lon),
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Importer for GeoJSON address points: a FeatureCollection, each feature has the columns as
+/// properties.
+pub struct GeoJsonImporter {
+    columns: Columns,
+}
+
+impl GeoJsonImporter {
+    /// Creates a new GeoJsonImporter.
+    pub fn new(columns: &Columns) -> Self {
+        GeoJsonImporter {
+            columns: columns.clone(),
+        }
+    }
+}
+
+/// Gets a property of a GeoJSON feature as a string.
+fn get_property(properties: &serde_json::Value, key: &str) -> Option<String> {
+    match properties.get(key)? {
+        serde_json::Value::String(value) => Some(value.to_string()),
+        serde_json::Value::Number(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Gets the latitude and longitude of a GeoJSON point geometry.
+fn get_point(geometry: &serde_json::Value) -> Option<(String, String)> {
+    if geometry["type"] != "Point" {
+        return None;
+    }
+
+    let coordinates = geometry["coordinates"].as_array()?;
+    match coordinates.as_slice() {
+        [serde_json::Value::Number(lon), serde_json::Value::Number(lat), ..] => {
+            Some((lat.to_string(), lon.to_string()))
+        }
+        _ => None,
+    }
+}
+
+impl Importer for GeoJsonImporter {
+    fn import(
+        &self,
+        ctx: &context::Context,
+        path: &str,
+        handler: &mut dyn FnMut(RefRow) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let data = ctx.get_file_system().read_to_string(path)?;
+        let collection: serde_json::Value =
+            serde_json::from_str(&data).context(format!("failed to parse '{path}'"))?;
+        let features = collection["features"]
+            .as_array()
+            .context(format!("no features in '{path}'"))?;
+        for (index, feature) in features.iter().enumerate() {
+            let properties = &feature["properties"];
+            let get = |column: &str| {
+                get_property(properties, column).context(format!(
+                    "missing property '{column}' in feature #{index} of '{path}'"
+                ))
+            };
+            let housenumber = match self.columns.housenumber {
+                Some(ref column) => get(column)?,
+                None => String::new(),
+            };
+            let get_optional = |column: &Option<String>| match column {
+                Some(ref column) => get_property(properties, column).unwrap_or_default(),
+                None => String::new(),
+            };
+            let (mut lat, mut lon) = get_point(&feature["geometry"]).unwrap_or_default();
+            if self.columns.lat.is_some() {
+                lat = get_optional(&self.columns.lat);
+            }
+            if self.columns.lon.is_some() {
+                lon = get_optional(&self.columns.lon);
+            }
+            handler(RefRow {
+                county: get(&self.columns.county)?,
+                settlement: get(&self.columns.settlement)?,
+                street: get(&self.columns.street)?,
+                housenumber,
+                comment: get_optional(&self.columns.comment),
+                lat,
+                lon,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// What a reference source contains.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// House numbers, filling ref_housenumbers.
+    Housenumbers,
+    /// Streets, filling ref_streets.
+    Streets,
+}
+
+/// A reference source: a file and the importer that can read it.
+pub struct Source {
+    kind: Kind,
+    path: String,
+    importer: Box<dyn Importer>,
+    supplementary: bool,
+}
+
+impl Source {
+    /// Creates a source from a [[reference_source]] section of workdir/wsgi.ini.
+    pub fn from_config(
+        ctx: &context::Context,
+        config: &context::ReferenceSourceConfig,
+    ) -> anyhow::Result<Self> {
+        let kind = match config.kind.as_str() {
+            "housenumbers" => Kind::Housenumbers,
+            "streets" => Kind::Streets,
+            _ => {
+                return Err(anyhow::anyhow!("unknown reference kind: '{}'", config.kind));
+            }
+        };
+        let mut columns = Columns::new(kind);
+        if let Some(ref value) = config.county_column {
+            columns.county = value.to_string();
+        }
+        if let Some(ref value) = config.settlement_column {
+            columns.settlement = value.to_string();
+        }
+        if let Some(ref value) = config.street_column {
+            columns.street = value.to_string();
+        }
+        if let (Kind::Housenumbers, Some(value)) = (kind, &config.housenumber_column) {
+            columns.housenumber = Some(value.to_string());
+        }
+        if let Some(ref value) = config.comment_column {
+            columns.comment = Some(value.to_string());
+        }
+        columns.lat.clone_from(&config.lat_column);
+        columns.lon.clone_from(&config.lon_column);
+        let importer: Box<dyn Importer> = match config.format.as_str() {
+            "tsv" => Box::new(CsvImporter::new(b'\t', &columns)),
+            "csv" => {
+                let delimiter = config.delimiter.as_deref().unwrap_or(",");
+                let delimiter = match delimiter.as_bytes() {
+                    [value] => *value,
+                    _ => {
+                        return Err(anyhow::anyhow!("invalid delimiter: '{delimiter}'"));
+                    }
+                };
+                Box::new(CsvImporter::new(delimiter, &columns))
+            }
+            "geojson" => Box::new(GeoJsonImporter::new(&columns)),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "unknown reference format: '{}'",
+                    config.format
+                ));
+            }
+        };
+        Ok(Source {
+            kind,
+            path: ctx.get_abspath(&config.path),
+            importer,
+            supplementary: config.supplementary,
+        })
+    }
+
+    /// Creates a source from a hazszamok TSV file, the ones named hazszamok_kieg are
+    /// supplementary.
+    pub fn from_housenumbers_tsv(path: &str) -> Self {
+        let name = path.split('/').next_back().unwrap_or_default();
+        let supplementary = name.starts_with("hazszamok_kieg");
+        let mut columns = Columns::new(Kind::Housenumbers);
+        if !supplementary {
+            columns.comment = None;
+        }
+        Source {
+            kind: Kind::Housenumbers,
+            path: path.to_string(),
+            importer: Box::new(CsvImporter::new(b'\t', &columns)),
+            supplementary,
+        }
+    }
+
+    /// Creates a source from an utcak TSV file.
+    pub fn from_streets_tsv(path: &str) -> Self {
+        Source {
+            kind: Kind::Streets,
+            path: path.to_string(),
+            importer: Box::new(CsvImporter::new(b'\t', &Columns::new(Kind::Streets))),
+            supplementary: false,
+        }
+    }
+}
+
+/// Gets all reference sources: the reference_housenumbers / reference_street keys and the
+/// [[reference_source]] sections of workdir/wsgi.ini.
+pub fn get_sources(ctx: &context::Context) -> anyhow::Result<Vec<Source>> {
+    let ini = ctx.get_ini();
+    let mut sources: Vec<Source> = ini
+        .get_reference_housenumber_paths()?
+        .iter()
+        .map(|path| Source::from_housenumbers_tsv(path))
+        .collect();
+    // reference_street is optional when the streets come from a reference_source section.
+    if let Some(street_path) = ini.get_reference_street_path() {
+        sources.push(Source::from_streets_tsv(&street_path));
+    }
+    for config in ini.get_reference_sources() {
+        sources.push(Source::from_config(ctx, config)?);
+    }
+    Ok(sources)
+}
+
+/// Checks if a table has rows already.
+fn has_rows(conn: &rusqlite::Connection, table: &str) -> anyhow::Result<bool> {
+    let mut stmt = conn.prepare(&format!(
+        "select count(*) from (select 0 from {table} limit 1)"
+    ))?;
+    let mut rows = stmt.query([])?;
+    let row = rows.next()?.context("no row")?;
+    let count: i64 = row.get(0)?;
+    Ok(count > 0)
+}
+
+/// Imports the matching sources into the in-database index of one kind.
+fn import_index(
+    ctx: &context::Context,
+    conn: &rusqlite::Connection,
+    kind: Kind,
+    sources: &[Source],
+) -> anyhow::Result<()> {
+    for source in sources.iter().filter(|source| source.kind == kind) {
+        source.importer.import(ctx, &source.path, &mut |row| {
+            match kind {
+                Kind::Housenumbers => {
+                    // A non-empty comment marks the house numbers of a supplementary source.
+                    let comment = match (source.supplementary, row.comment.is_empty()) {
+                        (false, _) => String::new(),
+                        (true, true) => " ".to_string(),
+                        (true, false) => row.comment,
+                    };
+                    // Coordinates are only stored if both are known.
+                    let (lat, lon) = match (row.lat.is_empty(), row.lon.is_empty()) {
+                        (false, false) => (Some(row.lat), Some(row.lon)),
+                        _ => (None, None),
+                    };
+                    conn.execute(
+                        "insert into ref_housenumbers (county_code, settlement_code, street, housenumber, comment, lat, lon) values (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                        rusqlite::params![row.county, row.settlement, row.street, row.housenumber, comment, lat, lon],
+                    )?;
+                }
+                Kind::Streets => {
+                    let street = NULL_END.replace(&row.street, "").to_string();
+                    conn.execute(
+                        "insert into ref_streets (county_code, settlement_code, street) values (?1, ?2, ?3)",
+                        [row.county, row.settlement, street],
+                    )?;
+                }
+            }
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+/// Builds the in-database index of one kind from the matching sources, unless the index is built
+/// already.
+pub fn build_index(ctx: &context::Context, kind: Kind, sources: &[Source]) -> anyhow::Result<()> {
+    let mut conn = ctx.get_database_connection()?;
+    let table = match kind {
+        Kind::Housenumbers => "ref_housenumbers",
+        Kind::Streets => "ref_streets",
+    };
+    if has_rows(&conn, table)? {
+        return Ok(());
+    }
+
+    let tx = conn.transaction()?;
+    import_index(ctx, &tx, kind, sources)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Builds all in-database reference indexes, based on workdir/wsgi.ini, in one transaction: a
+/// failure keeps the old indexes.
+pub fn rebuild_indexes(ctx: &context::Context) -> anyhow::Result<()> {
+    let sources = get_sources(ctx)?;
+    let mut conn = ctx.get_database_connection()?;
+    let tx = conn.transaction()?;
+    tx.execute("delete from ref_housenumbers", [])?;
+    tx.execute("delete from ref_streets", [])?;
+    import_index(ctx, &tx, Kind::Streets, &sources)?;
+    import_index(ctx, &tx, Kind::Housenumbers, &sources)?;
+    tx.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,214 @@
+/*
+ * Copyright 2024 Miklos Vajna
+ *
+ * SPDX-License-Identifier: MIT
+ */
+
+#![deny(warnings)]
+#![warn(clippy::all)]
+#![warn(missing_docs)]
+
+//! Tests for the reference_import module.
+
+use super::*;
+
+/// Creates a test context with the provided reference_source sections and empty indexes.
+fn make_context(sources: &str) -> context::Context {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    context::tests::set_ini(&mut ctx, sources);
+    {
+        let conn = ctx.get_database_connection().unwrap();
+        conn.execute("delete from ref_housenumbers", []).unwrap();
+        conn.execute("delete from ref_streets", []).unwrap();
+    }
+    ctx
+}
+
+/// Gets the rows of a reference table, sorted.
+fn get_rows(ctx: &context::Context, query: &str) -> Vec<Vec<String>> {
+    let conn = ctx.get_database_connection().unwrap();
+    let mut stmt = conn.prepare(query).unwrap();
+    let mut rows = stmt.query([]).unwrap();
+    let mut ret: Vec<Vec<String>> = Vec::new();
+    while let Some(row) = rows.next().unwrap() {
+        let columns = row.as_ref().column_count();
+        ret.push((0..columns).map(|index| row.get(index).unwrap()).collect());
+    }
+    ret.sort();
+    ret
+}
+
+/// Tests rebuild_indexes(): CSV with a column mapping, both for house numbers and streets.
+#[test]
+fn test_rebuild_indexes_csv() {
+    let ctx = make_context(
+        r#"
+[[reference_source]]
+kind = 'housenumbers'
+format = 'csv'
+path = 'workdir/refs/cimek.csv'
+delimiter = ';'
+county_column = 'megye'
+settlement_column = 'telepules'
+street_column = 'utca'
+housenumber_column = 'hazszam'
+comment_column = 'megjegyzes'
+lat_column = 'szelesseg'
+lon_column = 'hosszusag'
+supplementary = true
+
+[[reference_source]]
+kind = 'streets'
+format = 'csv'
+path = 'workdir/refs/cimek.csv'
+delimiter = ';'
+county_column = 'megye'
+settlement_column = 'telepules'
+street_column = 'utca'
+"#,
+    );
+
+    rebuild_indexes(&ctx).unwrap();
+
+    assert_eq!(
+        get_rows(
+            &ctx,
+            "select street, housenumber, comment from ref_housenumbers"
+        ),
+        vec![
+            vec!["Törökugrató utca", "1", " "],
+            vec!["Törökugrató utca", "2", "sarok"],
+            vec!["Tűzkő utca", "9", " "],
+        ]
+    );
+    // Coordinates are only stored when the source has them.
+    assert_eq!(
+        get_rows(
+            &ctx,
+            "select housenumber, lat, lon from ref_housenumbers where lat is not null"
+        ),
+        vec![
+            vec!["1", "47.4601", "19.0101"],
+            vec!["9", "47.4702", "19.0202"]
+        ]
+    );
+    assert_eq!(
+        get_rows(
+            &ctx,
+            "select county_code, settlement_code, street from ref_streets"
+        )
+        .len(),
+        3
+    );
+}
+
+/// Tests rebuild_indexes(): GeoJSON address points, numeric properties.
+#[test]
+fn test_rebuild_indexes_geojson() {
+    let ctx = make_context(
+        r#"
+[[reference_source]]
+kind = 'housenumbers'
+format = 'geojson'
+path = 'workdir/refs/cimek.geojson'
+county_column = 'county'
+settlement_column = 'settlement'
+street_column = 'addr:street'
+housenumber_column = 'addr:housenumber'
+"#,
+    );
+
+    rebuild_indexes(&ctx).unwrap();
+
+    assert_eq!(
+        get_rows(
+            &ctx,
+            "select county_code, settlement_code, street, housenumber, comment from ref_housenumbers"
+        ),
+        vec![
+            vec!["01", "011", "Törökugrató utca", "3", ""],
+            vec!["01", "011", "Tűzkő utca", "5/A", ""],
+        ]
+    );
+    // Coordinates are taken from the point geometries.
+    assert_eq!(
+        get_rows(&ctx, "select housenumber, lat, lon from ref_housenumbers"),
+        vec![vec!["3", "47.46", "19.01"], vec!["5/A", "47.47", "19.02"]]
+    );
+}
+
+/// Tests rebuild_indexes(): the legacy TSV keys still work, next to the new sections.
+#[test]
+fn test_rebuild_indexes_tsv() {
+    let ctx = make_context(
+        r#"reference_housenumbers = 'workdir/refs/hazszamok_20190511.tsv workdir/refs/hazszamok_kieg_20190808.tsv'
+reference_street = 'workdir/refs/utcak_20190514.tsv'
+
+[[reference_source]]
+kind = 'streets'
+format = 'tsv'
+path = 'workdir/refs/utcak_20190514.tsv'
+"#,
+    );
+
+    rebuild_indexes(&ctx).unwrap();
+
+    let housenumbers = get_rows(&ctx, "select housenumber, comment from ref_housenumbers");
+    assert_eq!(housenumbers.len(), 15);
+    assert_eq!(
+        housenumbers
+            .iter()
+            .filter(|row| row[1] == "comment")
+            .count(),
+        1
+    );
+    let streets = get_rows(&ctx, "select street from ref_streets");
+    assert_eq!(streets.len(), 12);
+    // ' null' is stripped from the end of street names.
+    assert_eq!(streets.contains(&vec!["Tűzkő utca".to_string()]), true);
+}
+
+/// Tests rebuild_indexes(): a missing column is an error, the old index is kept.
+#[test]
+fn test_rebuild_indexes_missing_column() {
+    let ctx = make_context(
+        r#"
+[[reference_source]]
+kind = 'housenumbers'
+format = 'csv'
+path = 'workdir/refs/cimek.csv'
+delimiter = ';'
+"#,
+    );
+    {
+        let conn = ctx.get_database_connection().unwrap();
+        conn.execute("insert into ref_streets (county_code, settlement_code, street) values ('01', '011', 'Tűzkő utca')", []).unwrap();
+    }
+
+    let ret = rebuild_indexes(&ctx);
+
+    assert_eq!(ret.is_err(), true);
+    assert_eq!(
+        get_rows(&ctx, "select street from ref_streets"),
+        vec![vec!["Tűzkő utca"]]
+    );
+}
+
+/// Tests Source::from_config(): unknown kind and format.
+#[test]
+fn test_source_from_config_unknown() {
+    let ctx = context::tests::make_test_context().unwrap();
+    let mut config = context::ReferenceSourceConfig {
+        kind: "buildings".into(),
+        format: "csv".into(),
+        ..Default::default()
+    };
+
+    assert_eq!(Source::from_config(&ctx, &config).is_err(), true);
+    config.kind = "streets".into();
+    config.format = "shp".into();
+    assert_eq!(Source::from_config(&ctx, &config).is_err(), true);
+    config.format = "csv".into();
+    config.delimiter = Some(";;".into());
+    assert_eq!(Source::from_config(&ctx, &config).is_err(), true);
+}
@@ -313,7 +313,184 @@ pub fn init(conn: &mut rusqlite::Connection) -> anyhow::Result<()> {
         )?;
     }
 
-    tx.execute("pragma user_version = 17", [])?;
+    if user_version < 18 {
+        // Allows looking up objects from replication diffs.
+        tx.execute_batch(
+            "create index idx_osm_streets_osm_id
+                on osm_streets(osm_id);
+            create index idx_osm_housenumbers_osm_id
+                on osm_housenumbers(osm_id);
+            create index idx_whole_country_osm_id
+                on whole_country(osm_id);",
+        )?;
+    }
+
+    if user_version < 19 {
+        // Versioned reference imports: a row is valid from the snapshot that added it until the
+        // snapshot that removed it.
+        tx.execute_batch(
+            "create table ref_snapshots (
+                    id integer primary key autoincrement,
+                    created text not null
+                );
+            create table ref_housenumbers_history (
+                    county_code text not null,
+                    settlement_code text not null,
+                    street text not null,
+                    housenumber text not null,
+                    added_in integer not null,
+                    removed_in integer
+                );
+            create index idx_ref_housenumbers_history
+                on ref_housenumbers_history(county_code, settlement_code, street);
+            create table ref_streets_history (
+                    county_code text not null,
+                    settlement_code text not null,
+                    street text not null,
+                    added_in integer not null,
+                    removed_in integer
+                );
+            create index idx_ref_streets_history
+                on ref_streets_history(county_code, settlement_code);",
+        )?;
+    }
+
+    if user_version < 20 {
+        // Coordinates of reference house numbers, null if the source doesn't have them.
+        tx.execute_batch(
+            "alter table ref_housenumbers add column lat text;
+            alter table ref_housenumbers add column lon text;",
+        )?;
+    }
+
+    if user_version < 21 {
+        // Street geometries from the last overpass update, as a JSON array of [lon, lat] pairs.
+        tx.execute_batch("alter table osm_streets add column geometry text;")?;
+    }
+
+    if user_version < 22 {
+        // Second OSM link for relation_lints rows, e.g. for duplicates.
+        tx.execute_batch(
+            "alter table relation_lints add column other_object_id text not null default '0';
+            alter table relation_lints add column other_object_type text not null default '';",
+        )?;
+    }
+
+    if user_version < 23 {
+        // The addr:interpolation value of expanded interpolation ways, empty for other objects.
+        tx.execute_batch(
+            "alter table osm_housenumbers add column interpolation text not null default '';",
+        )?;
+    }
+
+    if user_version < 24 {
+        // Whole-country house numbers with a problematic postcode.
+        tx.execute(
+            "create table stats_invalid_postcodes (
+            osm_id text not null,
+            osm_type text not null,
+            postcode text not null,
+            city text not null,
+            street text not null,
+            housenumber text not null,
+            user text not null,
+            timestamp text not null,
+            fixme text not null,
+            reason text not null
+        )",
+            [],
+        )?;
+    }
+
+    if user_version < 25 {
+        // Daily coverage snapshots of relations, kind is 'housenumbers' or 'streets'.
+        tx.execute(
+            "create table relation_coverage_history (
+            relation_name text not null,
+            kind text not null,
+            date text not null,
+            todo_count text not null,
+            done_count text not null,
+            percent text not null,
+            primary key (relation_name, kind, date)
+        )",
+            [],
+        )?;
+    }
+
+    if user_version < 26 {
+        // Background update jobs, see the jobs module. At most one queued or running job per
+        // update, so enqueue() can't race.
+        tx.execute_batch(
+            "create table jobs (
+            id integer primary key autoincrement,
+            kind text not null,
+            relation text not null,
+            state text not null,
+            error text not null,
+            created text not null,
+            updated text not null
+        );
+        create unique index idx_jobs_active on jobs (kind, relation)
+        where state in ('queued', 'running');",
+        )?;
+    }
+
+    if user_version < 27 {
+        // Token buckets of the rate limiter, see the rate_limit module.
+        tx.execute(
+            "create table rate_limits (
+            key text primary key not null,
+            tokens text not null,
+            updated text not null
+        )",
+            [],
+        )?;
+    }
+
+    if user_version < 28 {
+        // Metrics of cron, see the metrics module: the outcome of the last cron run with the
+        // coverage gauges, and the Overpass queries of all cron runs.
+        tx.execute_batch(
+            "create table cron_runs (
+            start text not null,
+            duration text not null,
+            success text not null,
+            housenumbers_coverage text,
+            streets_coverage text
+        );
+        create table cron_overpass_queries (
+            endpoint text primary key not null,
+            buckets text not null,
+            count text not null,
+            sum text not null,
+            failures text not null
+        );",
+        )?;
+    }
+
+    if user_version < 29 {
+        // The counts behind the coverage percents, to aggregate them for relation groups.
+        tx.execute_batch(
+            "alter table osm_housenumber_coverages add column todo_count text;
+             alter table osm_housenumber_coverages add column done_count text;
+             alter table osm_street_coverages add column todo_count text;
+             alter table osm_street_coverages add column done_count text;",
+        )?;
+    }
+
+    if user_version < 30 {
+        // Cached relation boundaries, to place the objects of replication diffs.
+        tx.execute_batch(
+            "create table osm_boundaries (
+            relation_id text primary key not null,
+            segments text not null,
+            updated text not null
+        );",
+        )?;
+    }
+
+    tx.execute("pragma user_version = 30", [])?;
     tx.commit()?;
     Ok(())
 }
@@ -11,6 +11,7 @@
 //! The stats module creates statistics about missing / non-missing house numbers.
 
 use crate::context;
+use crate::postcodes;
 use crate::sql;
 use crate::util;
 use anyhow::Context;
@@ -72,17 +73,21 @@ fn handle_capital_progress(
     j: &mut serde_json::Value,
 ) -> anyhow::Result<()> {
     let mut ret = serde_json::json!({});
+    let capital_name = ctx.get_ini().get_capital_name();
     let mut ref_count = 0;
     {
         let ref_path = ctx.get_ini().get_reference_citycounts_path()?;
         let stream = ctx.get_file_system().open_read(&ref_path)?;
-        let mut guard = stream.borrow_mut();
+        let mut guard = stream.lock().unwrap();
         let mut read = guard.deref_mut();
         let mut csv_reader = util::make_csv_reader(&mut read);
         for result in csv_reader.deserialize() {
             let row: util::CityCount = result?;
 
-            if row.city.starts_with("budapest_") {
+            if row
+                .city
+                .starts_with(&format!("{}_", capital_name.to_lowercase()))
+            {
                 ref_count += row.count;
             }
         }
@@ -98,7 +103,7 @@ fn handle_capital_progress(
     while let Some(row) = rows.next()? {
         let city: String = row.get(0).unwrap();
         let count: String = row.get(1).unwrap();
-        if city.starts_with("Budapest_") {
+        if city.starts_with(&format!("{capital_name}_")) {
             let count: u64 = count.parse()?;
             osm_count += count;
         }
@@ -452,7 +457,7 @@ pub fn generate_json(
     handle_invalid_addr_cities(ctx, &mut j, /*day_range=*/ 14)
         .context("invalid_addr_cities failed")?;
     let stream = ctx.get_file_system().open_write(json_path)?;
-    let mut guard = stream.borrow_mut();
+    let mut guard = stream.lock().unwrap();
     let write = guard.deref_mut();
     serde_json::to_writer(write, &j)?;
 
@@ -579,6 +584,7 @@ pub fn update_invalid_addr_cities(ctx: &context::Context) -> anyhow::Result<()>
     let valid_settlements =
         util::get_valid_settlements(ctx).context("get_valid_settlements() failed")?;
 
+    let capital_name = ctx.get_ini().get_capital_name().to_lowercase();
     let mut invalids: Vec<InvalidAddrCity> = Vec::new();
     {
         let conn = ctx.get_database_connection()?;
@@ -595,7 +601,7 @@ pub fn update_invalid_addr_cities(ctx: &context::Context) -> anyhow::Result<()>
             let osm_type: String = row.get(6).unwrap();
             let timestamp: String = row.get(7).unwrap();
             let fixme: String = row.get(8).unwrap();
-            if !valid_settlements.contains(&city) && city != "budapest" {
+            if !valid_settlements.contains(&city) && city != capital_name {
                 let invalid = InvalidAddrCity {
                     osm_id,
                     osm_type,
@@ -633,5 +639,71 @@ pub fn update_invalid_addr_cities(ctx: &context::Context) -> anyhow::Result<()>
     Ok(())
 }
 
+struct InvalidPostcode {
+    osm_id: String,
+    osm_type: String,
+    postcode: String,
+    city: String,
+    street: String,
+    housenumber: String,
+    user: String,
+    timestamp: String,
+    fixme: String,
+}
+
+/// Updates the stats_invalid_postcodes table: house numbers with a missing, malformed, unknown or
+/// inconsistent postcode.
+pub fn update_invalid_postcodes(ctx: &context::Context) -> anyhow::Result<()> {
+    info!("stats: updating invalid_postcodes");
+    let valid_postcodes = util::get_valid_postcodes(ctx).context("get_valid_postcodes() failed")?;
+
+    let mut rows: Vec<InvalidPostcode> = Vec::new();
+    {
+        let conn = ctx.get_database_connection()?;
+        let mut stmt = conn.prepare("select postcode, city, street, housenumber, user, osm_id, osm_type, timestamp, fixme from whole_country")?;
+        let mut query = stmt.query([])?;
+        while let Some(row) = query.next()? {
+            rows.push(InvalidPostcode {
+                postcode: row.get(0).unwrap(),
+                city: row.get(1).unwrap(),
+                street: row.get(2).unwrap(),
+                housenumber: row.get(3).unwrap(),
+                user: row.get(4).unwrap(),
+                osm_id: row.get(5).unwrap(),
+                osm_type: row.get(6).unwrap(),
+                timestamp: row.get(7).unwrap(),
+                fixme: row.get(8).unwrap(),
+            });
+        }
+    }
+    // The same street name can be present in multiple cities.
+    let addresses: Vec<(String, String)> = rows
+        .iter()
+        .map(|row| {
+            (
+                format!("{}\t{}", row.city, row.street),
+                row.postcode.to_string(),
+            )
+        })
+        .collect();
+    let problems = postcodes::get_problems(&addresses, &valid_postcodes);
+
+    let mut conn = ctx.get_database_connection()?;
+    let tx = conn.transaction()?;
+    tx.execute("delete from stats_invalid_postcodes", [])?;
+    for (row, problem) in rows.iter().zip(problems) {
+        let reason = match problem {
+            // Would list most addresses of the country, only the per-relation lints have this.
+            Some(postcodes::PostcodeProblem::Missing) | None => continue,
+            Some(value) => value.to_string(),
+        };
+        tx.execute("insert into stats_invalid_postcodes (osm_id, osm_type, postcode, city, street, housenumber, user, timestamp, fixme, reason) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            [&row.osm_id, &row.osm_type, &row.postcode, &row.city, &row.street, &row.housenumber, &row.user, &row.timestamp, &row.fixme, &reason])?;
+    }
+    tx.commit()?;
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests;
@@ -11,7 +11,7 @@
 //! Tests for the stats module.
 
 use super::*;
-use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::context::FileSystem as _;
 
@@ -41,7 +41,7 @@ fn test_handle_progress() {
     file_system
         .write_from_string("300", &ctx.get_abspath("workdir/stats/ref.count"))
         .unwrap();
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
 
     let src_root = ctx.get_abspath("workdir/stats");
@@ -84,7 +84,7 @@ fn test_handle_capital_progress() {
             &ctx.get_abspath("workdir/refs/varosok_count_20190717.tsv"),
         )
         .unwrap();
-    let file_system: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system);
     let mut j = serde_json::json!({});
 
@@ -102,7 +102,7 @@ fn test_handle_capital_progress() {
 fn test_handle_progress_old_time() {
     let mut ctx = context::tests::make_test_context().unwrap();
     let time = make_test_time_old();
-    let time_rc: Rc<dyn context::Time> = Rc::new(time);
+    let time_rc: Arc<dyn context::Time> = Arc::new(time);
     ctx.set_time(&time_rc);
     let ref_count = context::tests::TestFileSystem::make_file();
     let files = context::tests::TestFileSystem::make_files(
@@ -114,7 +114,7 @@ fn test_handle_progress_old_time() {
     file_system
         .write_from_string("42", &ctx.get_abspath("workdir/stats/ref.count"))
         .unwrap();
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
 
     let src_root = ctx.get_abspath("workdir/stats");
@@ -155,7 +155,7 @@ fn test_handle_topusers() {
 fn test_handle_topusers_old_time() {
     let mut ctx = context::tests::make_test_context().unwrap();
     let time = make_test_time_old();
-    let time_rc: Rc<dyn context::Time> = Rc::new(time);
+    let time_rc: Arc<dyn context::Time> = Arc::new(time);
     ctx.set_time(&time_rc);
     let mut j = serde_json::json!({});
     handle_topusers(&ctx, &mut j).unwrap();
@@ -493,3 +493,38 @@ fn test_update_invalid_addr_cities() {
         assert_eq!(count, 1);
     }
 }
+
+/// Tests update_invalid_postcodes().
+#[test]
+fn test_update_invalid_postcodes() {
+    let ctx = context::tests::make_test_context().unwrap();
+    {
+        let conn = ctx.get_database_connection().unwrap();
+        conn.execute_batch(
+            "insert into whole_country (postcode, city, street, housenumber, user, osm_id, osm_type, timestamp, place, unit, name, fixme) values ('1111', 'Budapest', 'mystreet', '1', 'myuser', '42', 'node', '2020-05-10T22:02:25Z', '', '', '', '');
+             insert into whole_country (postcode, city, street, housenumber, user, osm_id, osm_type, timestamp, place, unit, name, fixme) values ('1111', 'Budapest', 'mystreet', '2', 'myuser', '43', 'node', '2020-05-10T22:02:25Z', '', '', '', '');
+             insert into whole_country (postcode, city, street, housenumber, user, osm_id, osm_type, timestamp, place, unit, name, fixme) values ('1121', 'Budapest', 'mystreet', '3', 'myuser', '44', 'node', '2020-05-10T22:02:25Z', '', '', '', '');
+             insert into whole_country (postcode, city, street, housenumber, user, osm_id, osm_type, timestamp, place, unit, name, fixme) values ('', 'Budapest', 'mystreet', '4', 'myuser', '45', 'node', '2020-05-10T22:02:25Z', '', '', '', '');
+             insert into whole_country (postcode, city, street, housenumber, user, osm_id, osm_type, timestamp, place, unit, name, fixme) values ('1121', 'mycity', 'mystreet', '1', 'myuser', '46', 'node', '2020-05-10T22:02:25Z', '', '', '', '');"
+        )
+        .unwrap();
+    }
+
+    update_invalid_postcodes(&ctx).unwrap();
+
+    let conn = ctx.get_database_connection().unwrap();
+    let mut stmt = conn
+        .prepare("select osm_id, reason from stats_invalid_postcodes order by osm_id")
+        .unwrap();
+    let mut rows = stmt.query([]).unwrap();
+    let mut actual: Vec<(String, String)> = Vec::new();
+    while let Some(row) = rows.next().unwrap() {
+        actual.push((row.get(0).unwrap(), row.get(1).unwrap()));
+    }
+    // 46 is in a different city, so it's not compared to the dominant 1111; 45 has no postcode,
+    // that's not listed for the whole country.
+    assert_eq!(
+        actual,
+        vec![("44".to_string(), "postcode-mismatch".to_string())]
+    );
+}
@@ -0,0 +1,162 @@
+/*
+ * Copyright 2024 Miklos Vajna
+ *
+ * SPDX-License-Identifier: MIT
+ */
+
+#![deny(warnings)]
+#![warn(clippy::all)]
+#![warn(missing_docs)]
+
+//! The street_matching module pairs street names which are probably spelled differently in OSM
+//! and in the reference, to suggest refstreets mappings.
+
+use std::collections::HashSet;
+
+/// Pairs below this confidence are not suggested.
+const MIN_CONFIDENCE: f64 = 0.75;
+
+/// A suggested refstreets entry.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct Suggestion {
+    /// Street name in OSM, the key of the refstreets entry.
+    pub osm_name: String,
+    /// Street name in the reference, the value of the refstreets entry.
+    pub ref_name: String,
+    /// How likely it is that the two names refer to the same street, between 0 and 1.
+    pub confidence: f64,
+}
+
+/// Splits a street name into words, ignoring diacritics, case, punctuation and abbreviations.
+fn get_words(abbreviations: &[(String, String)], name: &str) -> Vec<String> {
+    let name: String = unidecode::unidecode(name)
+        .to_lowercase()
+        .chars()
+        .map(|ch| if ch.is_alphanumeric() { ch } else { ' ' })
+        .collect();
+    name.split_whitespace()
+        .map(|word| {
+            match abbreviations
+                .iter()
+                .find(|(abbreviation, _)| *abbreviation == word)
+            {
+                Some((_, expanded)) => expanded.to_string(),
+                None => word.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Normalizes a pair of street names: single-letter words are expanded if the other name has a
+/// matching word at the same position, e.g. 'Kossuth L.' is the same as 'Kossuth Lajos'.
+fn normalize_pair(
+    abbreviations: &[(String, String)],
+    first: &str,
+    second: &str,
+) -> (String, String) {
+    let mut first = get_words(abbreviations, first);
+    let mut second = get_words(abbreviations, second);
+    if first.len() == second.len() {
+        for (lhs, rhs) in first.iter_mut().zip(second.iter_mut()) {
+            if lhs.chars().count() == 1 && rhs.starts_with(lhs.as_str()) {
+                *lhs = rhs.clone();
+            } else if rhs.chars().count() == 1 && lhs.starts_with(rhs.as_str()) {
+                *rhs = lhs.clone();
+            }
+        }
+    }
+    (first.join(" "), second.join(" "))
+}
+
+/// Calculates the Levenshtein distance of two strings, in characters.
+fn get_edit_distance(first: &str, second: &str) -> usize {
+    let second: Vec<char> = second.chars().collect();
+    let mut row: Vec<usize> = (0..=second.len()).collect();
+    for (i, lhs) in first.chars().enumerate() {
+        let mut prev_diagonal = row[0];
+        row[0] = i + 1;
+        for (j, rhs) in second.iter().enumerate() {
+            let substitution = prev_diagonal + usize::from(lhs != *rhs);
+            prev_diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(prev_diagonal + 1);
+        }
+    }
+    row[second.len()]
+}
+
+/// Determines how likely it is that two street names refer to the same street, between 0 and 1.
+/// abbreviations are (abbreviation, expansion) pairs, see Ini::get_street_abbreviations().
+pub fn get_confidence(abbreviations: &[(String, String)], osm_name: &str, ref_name: &str) -> f64 {
+    let (osm_name, ref_name) = normalize_pair(abbreviations, osm_name, ref_name);
+    let length = osm_name.chars().count().max(ref_name.chars().count());
+    if length == 0 {
+        return 0_f64;
+    }
+    let distance = get_edit_distance(&osm_name, &ref_name);
+    let confidence = 1_f64 - distance as f64 / length as f64;
+    (confidence * 100_f64).round() / 100_f64
+}
+
+/// Pairs streets only in the reference with streets only in OSM. Each name is used at most once,
+/// the most confident pairs win. The result is sorted by decreasing confidence.
+pub fn get_suggestions(
+    abbreviations: &[(String, String)],
+    only_in_ref: &[String],
+    only_in_osm: &[String],
+) -> Vec<Suggestion> {
+    let mut candidates: Vec<Suggestion> = Vec::new();
+    for ref_name in only_in_ref {
+        for osm_name in only_in_osm {
+            let confidence = get_confidence(abbreviations, osm_name, ref_name);
+            if confidence >= MIN_CONFIDENCE {
+                candidates.push(Suggestion {
+                    osm_name: osm_name.to_string(),
+                    ref_name: ref_name.to_string(),
+                    confidence,
+                });
+            }
+        }
+    }
+    candidates.sort_by(|a, b| {
+        b.confidence
+            .total_cmp(&a.confidence)
+            .then_with(|| a.osm_name.cmp(&b.osm_name))
+            .then_with(|| a.ref_name.cmp(&b.ref_name))
+    });
+
+    let mut used_osm_names: HashSet<String> = HashSet::new();
+    let mut used_ref_names: HashSet<String> = HashSet::new();
+    let mut ret: Vec<Suggestion> = Vec::new();
+    for candidate in candidates {
+        if used_osm_names.contains(&candidate.osm_name)
+            || used_ref_names.contains(&candidate.ref_name)
+        {
+            continue;
+        }
+        used_osm_names.insert(candidate.osm_name.clone());
+        used_ref_names.insert(candidate.ref_name.clone());
+        ret.push(candidate);
+    }
+    ret
+}
+
+/// Quotes a string for YAML, using single quotes like the relation files do.
+fn quote_yaml(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Formats suggestions as entries of the refstreets map of a relation-<name>.yaml file.
+pub fn to_yaml(suggestions: &[Suggestion]) -> String {
+    let mut ret = String::new();
+    for suggestion in suggestions {
+        ret.push_str(&format!(
+            "  {}: {}\n",
+            quote_yaml(&suggestion.osm_name),
+            quote_yaml(&suggestion.ref_name)
+        ));
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests;
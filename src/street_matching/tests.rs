@@ -0,0 +1,108 @@
+/*
+ * Copyright 2024 Miklos Vajna
+ *
+ * SPDX-License-Identifier: MIT
+ */
+
+#![deny(warnings)]
+#![warn(clippy::all)]
+#![warn(missing_docs)]
+
+//! Tests for the street_matching module.
+
+use super::*;
+use crate::context;
+
+/// Gets the default abbreviations.
+fn get_abbreviations() -> Vec<(String, String)> {
+    let ctx = context::tests::make_test_context().unwrap();
+    ctx.get_ini().get_street_abbreviations().unwrap()
+}
+
+/// Tests get_edit_distance().
+#[test]
+fn test_get_edit_distance() {
+    assert_eq!(get_edit_distance("kitten", "sitting"), 3);
+    assert_eq!(get_edit_distance("", "abc"), 3);
+    assert_eq!(get_edit_distance("tűzkő", "tuzko"), 2);
+}
+
+/// Tests get_confidence(): diacritics, case, abbreviations and initials are ignored.
+#[test]
+fn test_get_confidence() {
+    let abbreviations = get_abbreviations();
+    assert_eq!(
+        get_confidence(&abbreviations, "Rézsű utca", "Rezsu utca"),
+        1.0
+    );
+    assert_eq!(
+        get_confidence(&abbreviations, "Tűzkő u.", "Tűzkő utca"),
+        1.0
+    );
+    assert_eq!(
+        get_confidence(&abbreviations, "Kossuth L. u.", "Kossuth Lajos utca"),
+        1.0
+    );
+    assert_eq!(
+        get_confidence(&abbreviations, "Hamzsabégi út", "Hamzsabégi út"),
+        1.0
+    );
+    // One typo in a 16-character name.
+    assert_eq!(
+        get_confidence(&abbreviations, "Törökugrató utca", "Törökugrato utcz"),
+        0.94
+    );
+    assert!(get_confidence(&abbreviations, "Tűzkő utca", "Hamzsabégi út") < MIN_CONFIDENCE);
+
+    // Custom abbreviations.
+    let abbreviations = vec![("g".to_string(), "gatan".to_string())];
+    assert_eq!(get_confidence(&abbreviations, "Stor g.", "Stor gatan"), 1.0);
+    assert!(get_confidence(&abbreviations, "Tűzkő krt.", "Tűzkő körút") < 1.0);
+}
+
+/// Tests get_suggestions(): each name is used only once, the best pair wins.
+#[test]
+fn test_get_suggestions() {
+    let only_in_ref = vec![
+        "Kossuth Lajos utca".to_string(),
+        "Petőfi utca".to_string(),
+        "Only In Ref utca".to_string(),
+    ];
+    let only_in_osm = vec![
+        "Kossuth L. u.".to_string(),
+        "Petőfy utca".to_string(),
+        "Petőfi köz".to_string(),
+        "Only In OSM tér".to_string(),
+    ];
+
+    let suggestions = get_suggestions(&get_abbreviations(), &only_in_ref, &only_in_osm);
+
+    let pairs: Vec<(&str, &str)> = suggestions
+        .iter()
+        .map(|i| (i.osm_name.as_str(), i.ref_name.as_str()))
+        .collect();
+    assert_eq!(
+        pairs,
+        vec![
+            ("Kossuth L. u.", "Kossuth Lajos utca"),
+            ("Petőfy utca", "Petőfi utca")
+        ]
+    );
+    assert_eq!(suggestions[0].confidence, 1.0);
+    assert_eq!(suggestions[1].confidence, 0.91);
+}
+
+/// Tests to_yaml().
+#[test]
+fn test_to_yaml() {
+    let suggestions = vec![Suggestion {
+        osm_name: "Kossuth L. u.".into(),
+        ref_name: "O'Brien utca".into(),
+        confidence: 0.8,
+    }];
+
+    assert_eq!(
+        to_yaml(&suggestions),
+        "  'Kossuth L. u.': 'O''Brien utca'\n"
+    );
+}
@@ -11,11 +11,178 @@
 //! Synchronizes reference data between a public instance and a local dev instance.
 
 use crate::context;
-use crate::util;
+use crate::ref_snapshots;
+use crate::reference_import;
 use anyhow::Context as _;
+use sha2::Digest as _;
 use std::collections::HashMap;
 use std::io::Write;
 
+/// Name of the checksum manifest, served next to the reference files, in the sha256sum format.
+const MANIFEST: &str = "SHA256SUMS";
+
+/// Gets the file name of a reference path, which must be inside workdir/refs/.
+fn get_ref_name(key: &str, path: &str) -> anyhow::Result<String> {
+    match path.strip_prefix("workdir/refs/") {
+        Some(name) if !name.is_empty() && !name.contains('/') => Ok(name.to_string()),
+        _ => Err(anyhow::anyhow!(
+            "{key}: '{path}' is not a file in workdir/refs/"
+        )),
+    }
+}
+
+/// Gets the file names of the references from the contents of config_file.
+fn get_ref_names(config_file: &str, config_data: &str) -> anyhow::Result<Vec<String>> {
+    let config: context::IniConfig =
+        toml::from_str(config_data).context(format!("failed to parse '{config_file}'"))?;
+    let mut names: Vec<String> = Vec::new();
+    for path in config.wsgi.reference_housenumbers.split_whitespace() {
+        names.push(get_ref_name("reference_housenumbers", path)?);
+    }
+    // reference_street is optional when the streets come from a reference_source section.
+    if !config.wsgi.reference_street.is_empty() {
+        names.push(get_ref_name(
+            "reference_street",
+            &config.wsgi.reference_street,
+        )?);
+    }
+    names.push(get_ref_name(
+        "reference_citycounts",
+        &config.wsgi.reference_citycounts,
+    )?);
+    names.push(get_ref_name(
+        "reference_zipcounts",
+        &config.wsgi.reference_zipcounts,
+    )?);
+    for source in &config.reference_source {
+        // Sources outside workdir/refs/ are not synchronized.
+        if source.path.starts_with("workdir/refs/") {
+            names.push(get_ref_name("reference_source", &source.path)?);
+        }
+    }
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+/// Parses a checksum manifest: maps file names to SHA-256 checksums.
+fn parse_manifest(manifest: &str) -> anyhow::Result<HashMap<String, String>> {
+    let mut checksums: HashMap<String, String> = HashMap::new();
+    for (index, line) in manifest.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (checksum, name) = line
+            .split_once(' ')
+            .context(format!("{MANIFEST}: malformed line {}", index + 1))?;
+        // sha256sum uses a '*' prefix for files read in binary mode.
+        let name = name.trim_start().trim_start_matches('*');
+        checksums.insert(name.to_string(), checksum.to_lowercase());
+    }
+    Ok(checksums)
+}
+
+/// Gets the SHA-256 checksum of data, as a hex string.
+fn get_checksum(data: &[u8]) -> String {
+    format!("{:x}", sha2::Sha256::digest(data))
+}
+
+/// Reads the entire contents of a file, without decoding it.
+fn read_bytes(ctx: &context::Context, path: &str) -> anyhow::Result<Vec<u8>> {
+    let stream = ctx.get_file_system().open_read(path)?;
+    let mut guard = stream.lock().unwrap();
+    let mut bytes: Vec<u8> = Vec::new();
+    guard.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Writes data to a file.
+fn write_bytes(ctx: &context::Context, data: &[u8], path: &str) -> anyhow::Result<()> {
+    let stream = ctx.get_file_system().open_write(path)?;
+    let mut guard = stream.lock().unwrap();
+    Ok(guard.write_all(data)?)
+}
+
+/// Checks if path exists and has the expected checksum.
+fn is_verified(ctx: &context::Context, path: &str, checksum: &str) -> bool {
+    if !ctx.get_file_system().path_exists(path) {
+        return false;
+    }
+
+    match read_bytes(ctx, path) {
+        Ok(data) => get_checksum(&data) == checksum,
+        Err(_) => false,
+    }
+}
+
+/// Downloads the references of config_file from url and verifies them, then replaces the
+/// references in workdir/refs.
+fn download_refs(
+    stream: &mut dyn Write,
+    ctx: &context::Context,
+    config_file: &str,
+    url: &str,
+) -> anyhow::Result<()> {
+    let config_data = ctx.get_file_system().read_to_string(config_file)?;
+    let names = get_ref_names(config_file, &config_data)?;
+    let manifest_url = format!("{url}{MANIFEST}");
+    let manifest = ctx.get_network().urlopen(&manifest_url, "")?;
+    let checksums = parse_manifest(&manifest)?;
+
+    // Build the complete new set in a staging directory first: a failure leaves workdir/refs
+    // untouched and the next run can continue with the already verified files.
+    let staging = ctx.get_abspath("workdir/staging-refs");
+    for name in &names {
+        let checksum = checksums
+            .get(name)
+            .context(format!("'{name}' is missing from '{manifest_url}'"))?;
+        let staged_path = format!("{staging}/{name}");
+        if is_verified(ctx, &staged_path, checksum) {
+            stream.write_all(format!("sync-ref: reusing '{name}'...\n").as_bytes())?;
+            continue;
+        }
+
+        let dest = ctx.get_abspath(&format!("workdir/refs/{name}"));
+        if is_verified(ctx, &dest, checksum) {
+            write_bytes(ctx, &read_bytes(ctx, &dest)?, &staged_path)?;
+            continue;
+        }
+
+        let url = format!("{url}{name}");
+        stream.write_all(format!("sync-ref: downloading '{url}'...\n").as_bytes())?;
+        let buf = ctx.get_network().urlopen_bytes(&url)?;
+        let actual = get_checksum(&buf);
+        if actual != *checksum {
+            return Err(anyhow::anyhow!(
+                "checksum mismatch for '{url}': expected {checksum}, got {actual}"
+            ));
+        }
+        write_bytes(ctx, &buf, &staged_path)?;
+    }
+
+    // All files are verified: switch to the new set, then update the config to refer to it.
+    let old = ctx
+        .get_file_system()
+        .replace_dir(&staging, &ctx.get_abspath("workdir/refs"))?;
+    ctx.get_file_system()
+        .write_from_string(&config_data, &ctx.get_abspath("workdir/wsgi.ini"))?;
+
+    // Only then remove the old set.
+    if let Some(old) = old {
+        for path in ctx.get_file_system().listdir(&old)? {
+            let name = path.rsplit('/').next().unwrap_or(&path);
+            if names.iter().any(|i| i == name) {
+                continue;
+            }
+            stream
+                .write_all(format!("sync-ref: removing 'workdir/refs/{name}'...\n").as_bytes())?;
+        }
+        ctx.get_file_system().remove_dir_all(&old)?;
+    }
+
+    Ok(())
+}
+
 /// Synchronizes reference data based on config_file from url.
 pub fn download(
     stream: &mut dyn Write,
@@ -27,62 +194,12 @@ pub fn download(
     if mode == "download" {
         let url = url.context("missing url")?;
         // mode is not "local", so download & write the config first.
-        let config_data = ctx.get_file_system().read_to_string(config_file)?;
-        let config: context::IniConfig = toml::from_str(&config_data)?;
-        let mut paths: Vec<String> = Vec::new();
-        let values = config.wsgi.reference_housenumbers;
-        paths.append(
-            &mut values
-                .split(' ')
-                .map(|value| value.strip_prefix("workdir/refs/").unwrap().to_string())
-                .collect(),
-        );
-        let value = config.wsgi.reference_street;
-        paths.push(value.strip_prefix("workdir/refs/").unwrap().to_string());
-        let value = config.wsgi.reference_citycounts;
-        paths.push(value.strip_prefix("workdir/refs/").unwrap().to_string());
-        let value = config.wsgi.reference_zipcounts;
-        paths.push(value.strip_prefix("workdir/refs/").unwrap().to_string());
-
-        let mut dests: Vec<String> = Vec::new();
-        for path in &paths {
-            let url = format!("{url}{path}");
-            let dest = ctx.get_abspath(&format!("workdir/refs/{path}"));
-            dests.push(dest.to_string());
-            if ctx.get_file_system().path_exists(&dest) {
-                continue;
-            }
-
-            stream.write_all(format!("sync-ref: downloading '{url}'...\n").as_bytes())?;
-            let buf = ctx.get_network().urlopen(&url, "")?;
-            ctx.get_file_system().write_from_string(&buf, &dest)?;
-        }
-        for path in ctx
-            .get_file_system()
-            .listdir(&ctx.get_abspath("workdir/refs"))?
-        {
-            if dests.contains(&path) {
-                continue;
-            }
-            let relpath = path.strip_prefix(&ctx.get_abspath("")).unwrap();
-            stream.write_all(format!("sync-ref: removing '{relpath}'...\n").as_bytes())?;
-            ctx.get_file_system().unlink(&path)?;
-        }
-
-        ctx.get_file_system()
-            .write_from_string(&config_data, &ctx.get_abspath("workdir/wsgi.ini"))?;
+        download_refs(stream, ctx, config_file, url)?;
     }
 
-    stream.write_all("sync-ref: removing old index...\n".as_bytes())?;
-    {
-        let conn = ctx.get_database_connection()?;
-        conn.execute("delete from ref_housenumbers", [])?;
-        conn.execute("delete from ref_streets", [])?;
-    }
-    let ref_streets = ctx.get_ini().get_reference_street_path()?;
-    util::build_street_reference_index(ctx, &ref_streets)?;
-    let references = ctx.get_ini().get_reference_housenumber_paths()?;
-    util::build_reference_index(ctx, &references)?;
+    stream.write_all("sync-ref: rebuilding index...\n".as_bytes())?;
+    reference_import::rebuild_indexes(ctx)?;
+    ref_snapshots::create_snapshot(ctx)?;
 
     // These caches have explicit dependencies only on OSM data, so empty them now.
     let conn = ctx.get_database_connection()?;
@@ -12,7 +12,7 @@
 
 use super::*;
 use std::ops::DerefMut as _;
-use std::rc::Rc;
+use std::sync::Arc;
 
 /// Tests main().
 #[test]
@@ -30,7 +30,7 @@ fn test_main() {
         /*result_path=*/ "src/fixtures/network/sync-ref.html",
     )];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     ctx.set_network(network_rc);
     let wsgi_ini_template = context::tests::TestFileSystem::make_file();
     let files = context::tests::TestFileSystem::make_files(
@@ -60,72 +60,284 @@ reference_zipcounts = 'workdir/refs/irsz_count_20221001.tsv'
     assert_eq!(actual, expected);
 }
 
-/// Tests main(), the download mode.
-#[test]
-fn test_main_download() {
-    let argv = vec![
-        "".to_string(),
-        "--mode".to_string(),
-        "download".to_string(),
-        "--url".to_string(),
-        "https://osm.example.com/data/".to_string(),
-    ];
-    let mut buf: std::io::Cursor<Vec<u8>> = std::io::Cursor::new(Vec::new());
+/// The download mode config for the download tests.
+const DOWNLOAD_CONFIG: &str = r#"[wsgi]
+reference_housenumbers = 'workdir/refs/hazszamok_20190511.tsv workdir/refs/hazszamok_kieg_20190808.tsv'
+reference_street = 'workdir/refs/utcak_20190514.tsv'
+reference_citycounts = 'workdir/refs/varosok_count_20190717.tsv'
+reference_zipcounts = 'workdir/refs/irsz_count_20200717.tsv'
+"#;
+
+/// Creates a context for the download mode: irsz_count_20200717.tsv is new, the
+/// irsz_count_20190717.tsv is no longer used.
+fn make_download_context(
+    config: &str,
+    manifest_path: &str,
+    routes: &[(&str, &str)],
+    staged: bool,
+) -> context::Context {
     let mut ctx = context::tests::make_test_context().unwrap();
     let wsgi_ini = context::tests::TestFileSystem::make_file();
     let wsgi_ini_template = context::tests::TestFileSystem::make_file();
     {
-        let mut guard = wsgi_ini_template.borrow_mut();
+        let mut guard = wsgi_ini_template.lock().unwrap();
         let write = guard.deref_mut();
-        write
-            .write_all(
-                r#"[wsgi]
-reference_housenumbers = 'workdir/refs/hazszamok_20190511.tsv workdir/refs/hazszamok_kieg_20190808.tsv'
-reference_street = 'workdir/refs/utcak_20190514.tsv'
-reference_citycounts = 'workdir/refs/varosok_count_20190717.tsv'
-reference_zipcounts = 'workdir/refs/irsz_count_20200717.tsv'
-"#
-                .as_bytes(),
-            )
-            .unwrap();
+        write.write_all(config.as_bytes()).unwrap();
     }
     let zipcount = context::tests::TestFileSystem::make_file();
     let zipcount_old = context::tests::TestFileSystem::make_file();
-    let files = context::tests::TestFileSystem::make_files(
+    let zipcount_staged = context::tests::TestFileSystem::make_file();
+    let mut hide_paths = vec![
+        ctx.get_abspath("workdir/wsgi.ini"),
+        ctx.get_abspath("workdir/refs/irsz_count_20200717.tsv"),
+    ];
+    if staged {
+        let mut guard = zipcount_staged.lock().unwrap();
+        let write = guard.deref_mut();
+        write
+            .write_all(&std::fs::read("src/fixtures/network/zipcount-new.tsv").unwrap())
+            .unwrap();
+    } else {
+        hide_paths.push(ctx.get_abspath("workdir/staging-refs/irsz_count_20200717.tsv"));
+    }
+    let mut files = context::tests::TestFileSystem::make_files(
         &ctx,
         &[
             ("data/wsgi.ini.template", &wsgi_ini_template),
             ("workdir/wsgi.ini", &wsgi_ini),
             ("workdir/refs/irsz_count_20200717.tsv", &zipcount),
             ("workdir/refs/irsz_count_20190717.tsv", &zipcount_old),
+            (
+                "workdir/staging-refs/irsz_count_20200717.tsv",
+                &zipcount_staged,
+            ),
         ],
     );
+    // The unchanged references are copied to the staging directory.
+    for name in [
+        "hazszamok_20190511.tsv",
+        "hazszamok_kieg_20190808.tsv",
+        "utcak_20190514.tsv",
+        "varosok_count_20190717.tsv",
+    ] {
+        let path = ctx.get_abspath(&format!("workdir/staging-refs/{name}"));
+        files.insert(
+            path.to_string(),
+            context::tests::TestFileSystem::make_file(),
+        );
+        hide_paths.push(path);
+    }
     let mut file_system = context::tests::TestFileSystem::new();
     file_system.set_files(&files);
-    file_system.set_hide_paths(&[ctx.get_abspath("workdir/refs/irsz_count_20200717.tsv")]);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    file_system.set_hide_paths(&hide_paths);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
-    let routes = vec![context::tests::URLRoute::new(
-        /*url=*/ "https://osm.example.com/data/irsz_count_20200717.tsv",
+    let mut url_routes = vec![context::tests::URLRoute::new(
+        /*url=*/ "https://osm.example.com/data/SHA256SUMS",
         /*data_path=*/ "",
-        /*result_path=*/ "src/fixtures/network/zipcount-new.tsv",
+        /*result_path=*/ manifest_path,
     )];
-    let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    for (url, result_path) in routes {
+        url_routes.push(context::tests::URLRoute::new(
+            url,
+            /*data_path=*/ "",
+            result_path,
+        ));
+    }
+    let network = context::tests::TestNetwork::new(&url_routes);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     ctx.set_network(network_rc);
+    ctx
+}
 
-    let ret = main(&argv, &mut buf, &mut ctx);
+/// Gets the argv of the download mode.
+fn get_download_argv() -> Vec<String> {
+    vec![
+        "".to_string(),
+        "--mode".to_string(),
+        "download".to_string(),
+        "--url".to_string(),
+        "https://osm.example.com/data/".to_string(),
+    ]
+}
+
+/// Tests main(), the download mode.
+#[test]
+fn test_main_download() {
+    let argv = get_download_argv();
+    let mut buf: std::io::Cursor<Vec<u8>> = std::io::Cursor::new(Vec::new());
+    let ctx = make_download_context(
+        DOWNLOAD_CONFIG,
+        "src/fixtures/network/sync-ref-sha256sums.txt",
+        &[(
+            "https://osm.example.com/data/irsz_count_20200717.tsv",
+            "src/fixtures/network/zipcount-new.tsv",
+        )],
+        /*staged=*/ false,
+    );
+
+    let ret = main(&argv, &mut buf, &ctx);
 
     let buf = String::from_utf8(buf.into_inner()).unwrap();
     assert_eq!(
         buf,
         r#"sync-ref: downloading 'https://osm.example.com/data/irsz_count_20200717.tsv'...
 sync-ref: removing 'workdir/refs/irsz_count_20190717.tsv'...
-sync-ref: removing old index...
+sync-ref: rebuilding index...
 sync-ref: ok
 "#
     );
     assert_eq!(ret, 0);
+    let file_system = ctx.get_file_system();
+    let zipcount = file_system
+        .read_to_string(&ctx.get_abspath("workdir/refs/irsz_count_20200717.tsv"))
+        .unwrap();
+    assert_eq!(zipcount.starts_with("ZIP\tCNT"), true);
+    assert_eq!(
+        file_system.path_exists(&ctx.get_abspath("workdir/staging-refs/irsz_count_20200717.tsv")),
+        false
+    );
+    // The old set is removed.
+    assert_eq!(
+        file_system.path_exists(&ctx.get_abspath("workdir/refs.old/irsz_count_20190717.tsv")),
+        false
+    );
+    assert_eq!(
+        file_system.path_exists(&ctx.get_abspath("workdir/wsgi.ini")),
+        true
+    );
+}
+
+/// Tests main(), the download mode, when a previous run already downloaded a file.
+#[test]
+fn test_main_download_resume() {
+    let argv = get_download_argv();
+    let mut buf: std::io::Cursor<Vec<u8>> = std::io::Cursor::new(Vec::new());
+    // No route for irsz_count_20200717.tsv: the staged file is reused.
+    let ctx = make_download_context(
+        DOWNLOAD_CONFIG,
+        "src/fixtures/network/sync-ref-sha256sums.txt",
+        &[],
+        /*staged=*/ true,
+    );
+
+    let ret = main(&argv, &mut buf, &ctx);
+
+    let buf = String::from_utf8(buf.into_inner()).unwrap();
+    assert_eq!(
+        buf,
+        r#"sync-ref: reusing 'irsz_count_20200717.tsv'...
+sync-ref: removing 'workdir/refs/irsz_count_20190717.tsv'...
+sync-ref: rebuilding index...
+sync-ref: ok
+"#
+    );
+    assert_eq!(ret, 0);
+}
+
+/// Tests main(), the download mode, when the checksum of a download doesn't match.
+#[test]
+fn test_main_download_checksum_mismatch() {
+    let argv = get_download_argv();
+    let mut buf: std::io::Cursor<Vec<u8>> = std::io::Cursor::new(Vec::new());
+    let ctx = make_download_context(
+        DOWNLOAD_CONFIG,
+        "src/fixtures/network/sync-ref-sha256sums-bad.txt",
+        &[(
+            "https://osm.example.com/data/irsz_count_20200717.tsv",
+            "src/fixtures/network/zipcount-new.tsv",
+        )],
+        /*staged=*/ false,
+    );
+
+    let ret = main(&argv, &mut buf, &ctx);
+
+    assert_eq!(ret, 1);
+    let buf = String::from_utf8(buf.into_inner()).unwrap();
+    assert_eq!(buf.contains("checksum mismatch"), true);
+    // Nothing is replaced or removed.
+    let file_system = ctx.get_file_system();
+    assert_eq!(
+        file_system.path_exists(&ctx.get_abspath("workdir/refs/irsz_count_20200717.tsv")),
+        false
+    );
+    assert_eq!(
+        file_system.path_exists(&ctx.get_abspath("workdir/refs/irsz_count_20190717.tsv")),
+        true
+    );
+    assert_eq!(
+        file_system.path_exists(&ctx.get_abspath("workdir/wsgi.ini")),
+        false
+    );
+}
+
+/// Tests main(), the download mode, when a reference file is missing from the manifest.
+#[test]
+fn test_main_download_not_in_manifest() {
+    let argv = get_download_argv();
+    let mut buf: std::io::Cursor<Vec<u8>> = std::io::Cursor::new(Vec::new());
+    let config = DOWNLOAD_CONFIG.replace("irsz_count_20200717", "irsz_count_20210717");
+    let ctx = make_download_context(
+        &config,
+        "src/fixtures/network/sync-ref-sha256sums.txt",
+        &[],
+        /*staged=*/ false,
+    );
+
+    let ret = main(&argv, &mut buf, &ctx);
+
+    assert_eq!(ret, 1);
+    let buf = String::from_utf8(buf.into_inner()).unwrap();
+    assert_eq!(
+        buf.starts_with(
+            "'irsz_count_20210717.tsv' is missing from 'https://osm.example.com/data/SHA256SUMS'"
+        ),
+        true
+    );
+}
+
+/// Tests main(), the download mode, with a reference outside workdir/refs/.
+#[test]
+fn test_main_download_bad_config() {
+    let argv = get_download_argv();
+    let mut buf: std::io::Cursor<Vec<u8>> = std::io::Cursor::new(Vec::new());
+    let config = DOWNLOAD_CONFIG.replace("'workdir/refs/utcak", "'refs/utcak");
+    let ctx = make_download_context(
+        &config,
+        "src/fixtures/network/sync-ref-sha256sums.txt",
+        &[],
+        /*staged=*/ false,
+    );
+
+    let ret = main(&argv, &mut buf, &ctx);
+
+    assert_eq!(ret, 1);
+    let buf = String::from_utf8(buf.into_inner()).unwrap();
+    assert_eq!(
+        buf.starts_with(
+            "reference_street: 'refs/utcak_20190514.tsv' is not a file in workdir/refs/"
+        ),
+        true
+    );
+}
+
+/// Tests main(), the download mode, with a config that is not valid TOML.
+#[test]
+fn test_main_download_invalid_config() {
+    let argv = get_download_argv();
+    let mut buf: std::io::Cursor<Vec<u8>> = std::io::Cursor::new(Vec::new());
+    let ctx = make_download_context(
+        "[wsgi\n",
+        "src/fixtures/network/sync-ref-sha256sums.txt",
+        &[],
+        /*staged=*/ false,
+    );
+
+    let ret = main(&argv, &mut buf, &ctx);
+
+    assert_eq!(ret, 1);
+    let buf = String::from_utf8(buf.into_inner()).unwrap();
+    assert_eq!(buf.starts_with("failed to parse '"), true);
 }
 
 /// Tests main(), the local mode.
@@ -141,7 +353,7 @@ fn test_main_local() {
     let buf = String::from_utf8(buf.into_inner()).unwrap();
     assert_eq!(
         buf,
-        r#"sync-ref: removing old index...
+        r#"sync-ref: rebuilding index...
 sync-ref: ok
 "#
     );
@@ -16,6 +16,7 @@ use crate::i18n;
 use crate::i18n::translate as tr;
 use crate::overpass_query;
 use crate::ranges;
+use crate::reference_import;
 use crate::yattag;
 use anyhow::anyhow;
 use anyhow::Context;
@@ -35,7 +36,6 @@ lazy_static! {
         regex::Regex::new(r"^([0-9]*)([^0-9].*|)$").unwrap();
     static ref LETTER_SUFFIX: regex::Regex = regex::Regex::new(r".*([A-Za-z]+)\*?").unwrap();
     static ref NUMBER_SUFFIX: regex::Regex = regex::Regex::new(r"^.*/([0-9])\*?$").unwrap();
-    static ref NULL_END: regex::Regex = regex::Regex::new(r" null$").unwrap();
     static ref GIT_HASH: regex::Regex = regex::Regex::new(r".*-g([0-9a-f]+)(-modified)?").unwrap();
 }
 
@@ -45,6 +45,8 @@ lazy_static! {
 pub struct HouseNumberRange {
     number: String,
     comment: String,
+    /// Comes from an OSM addr:interpolation way.
+    interpolated: bool,
 }
 
 impl HouseNumberRange {
@@ -52,6 +54,7 @@ impl HouseNumberRange {
         HouseNumberRange {
             number: number.into(),
             comment: comment.into(),
+            interpolated: false,
         }
     }
 
@@ -222,6 +225,9 @@ pub struct HouseNumber {
     id: Option<u64>,
     /// Only used for osm housenumbers, not ref ones.
     object_type: Option<String>,
+    /// Only used for osm housenumbers: comes from an addr:interpolation way.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    interpolated: bool,
 }
 
 /// A list of HouseNumber values.
@@ -251,6 +257,7 @@ impl HouseNumber {
             comment: comment.into(),
             id,
             object_type,
+            interpolated: false,
         }
     }
 
@@ -260,7 +267,7 @@ impl HouseNumber {
     }
 
     /// Returns the source range.
-    fn get_source(&self) -> &str {
+    pub fn get_source(&self) -> &str {
         &self.source
     }
 
@@ -377,6 +384,16 @@ impl HouseNumber {
     pub fn get_object_type(&self) -> Option<String> {
         self.object_type.clone()
     }
+
+    /// Sets if the housenumber comes from an OSM addr:interpolation way.
+    pub fn set_interpolated(&mut self, interpolated: bool) {
+        self.interpolated = interpolated;
+    }
+
+    /// Determines if the housenumber comes from an OSM addr:interpolation way.
+    pub fn is_interpolated(&self) -> bool {
+        self.interpolated
+    }
 }
 
 impl Diff for HouseNumber {
@@ -545,6 +562,17 @@ pub fn format_even_odd_html(only_in_ref: &[HouseNumberRange]) -> yattag::Doc {
 pub fn color_house_number(house_number: &HouseNumberRange) -> yattag::Doc {
     let doc = yattag::Doc::new();
     let number = house_number.get_number();
+    if house_number.interpolated {
+        let span = doc.tag(
+            "span",
+            &[
+                ("style", "font-style: italic;"),
+                ("title", &tr("interpolated")),
+            ],
+        );
+        span.text(number);
+        return doc;
+    }
     if !number.ends_with('*') {
         doc.text(number);
         return doc;
@@ -565,134 +593,19 @@ pub fn color_house_number(house_number: &HouseNumberRange) -> yattag::Doc {
     doc
 }
 
-/// One row in workdir/refs/streets_<DATE>.tsv.
-#[derive(serde::Deserialize)]
-pub struct RefStreet {
-    /// County code.
-    #[serde(rename = "COUNTY_CODE")]
-    pub county: String,
-    /// Settlement code.
-    #[serde(rename = "SETTLEMENT_CODE")]
-    pub settlement: String,
-    /// Street name.
-    #[serde(rename = "STREET")]
-    pub street: String,
-}
-
-/// One row in workdir/refs/housenumbers_<DATE>.tsv.
-#[derive(serde::Deserialize)]
-pub struct RefHouseNumber {
-    /// County code.
-    #[serde(rename = "COUNTY_CODE")]
-    pub county: String,
-    /// Settlement code.
-    #[serde(rename = "SETTLEMENT_CODE")]
-    pub settlement: String,
-    /// Street name.
-    #[serde(rename = "STREET")]
-    pub street: String,
-    /// House number.
-    #[serde(rename = "HOUSENUMBER")]
-    pub housenumber: String,
-    /// Comment.
-    #[serde(rename = "COMMENT")]
-    pub comment: Option<String>,
-}
-
 /// Builds an in-database index from the reference TSV (house number version).
 pub fn build_reference_index(ctx: &context::Context, paths: &[String]) -> anyhow::Result<()> {
-    let mut conn = ctx.get_database_connection()?;
-    {
-        // Check if the TSV is imported already.
-        let mut stmt =
-            conn.prepare("select count(*) from (select 0 from ref_housenumbers limit 1)")?;
-        let mut rows = stmt.query([])?;
-        while let Some(row) = rows.next()? {
-            let count: i64 = row.get(0).unwrap();
-            if count > 0 {
-                return Ok(());
-            }
-        }
-    }
-
-    for abspath in paths {
-        let mut tokens = abspath.split('/');
-        let path = tokens.next_back().unwrap();
-        if path.starts_with("hazszamok_kieg") {
-            let stream = ctx.get_file_system().open_read(abspath)?;
-            let mut guard = stream.borrow_mut();
-            let read = std::io::BufReader::new(guard.deref_mut());
-            let mut reader = csv::ReaderBuilder::new()
-                .delimiter(b'\t')
-                .double_quote(true)
-                .from_reader(read);
-            let tx = conn.transaction()?;
-            for result in reader.deserialize() {
-                let row: RefHouseNumber = result?;
-                tx.execute(
-                        "insert into ref_housenumbers (county_code, settlement_code, street, housenumber, comment) values (?1, ?2, ?3, ?4, ?5)",
-                        [row.county, row.settlement, row.street, row.housenumber, row.comment.unwrap_or(" ".into())],
-                    )?;
-            }
-            tx.commit()?;
-        } else {
-            // Assume "hazszamok_".
-            let stream = ctx.get_file_system().open_read(abspath)?;
-            let mut guard = stream.borrow_mut();
-            let read = std::io::BufReader::new(guard.deref_mut());
-            let mut reader = csv::ReaderBuilder::new()
-                .delimiter(b'\t')
-                .double_quote(true)
-                .from_reader(read);
-            let tx = conn.transaction()?;
-            for result in reader.deserialize() {
-                let row: RefHouseNumber = result?;
-                tx.execute(
-                          "insert into ref_housenumbers (county_code, settlement_code, street, housenumber, comment) values (?1, ?2, ?3, ?4, '')",
-                          [row.county, row.settlement, row.street, row.housenumber],
-                      )?;
-            }
-            tx.commit()?;
-        }
-    }
-
-    Ok(())
+    let sources: Vec<reference_import::Source> = paths
+        .iter()
+        .map(|path| reference_import::Source::from_housenumbers_tsv(path))
+        .collect();
+    reference_import::build_index(ctx, reference_import::Kind::Housenumbers, &sources)
 }
 
 /// Builds an in-database index from the reference TSV (street version).
 pub fn build_street_reference_index(ctx: &context::Context, path: &str) -> anyhow::Result<()> {
-    let mut conn = ctx.get_database_connection()?;
-    {
-        // Check if the TSV is imported already.
-        let mut stmt = conn.prepare("select count(*) from (select 0 from ref_streets limit 1)")?;
-        let mut rows = stmt.query([])?;
-        while let Some(row) = rows.next()? {
-            let count: i64 = row.get(0).unwrap();
-            if count > 0 {
-                return Ok(());
-            }
-        }
-    }
-
-    let stream = ctx.get_file_system().open_read(path)?;
-    let mut guard = stream.borrow_mut();
-    let read = std::io::BufReader::new(guard.deref_mut());
-    let mut reader = csv::ReaderBuilder::new()
-        .delimiter(b'\t')
-        .double_quote(true)
-        .from_reader(read);
-    let tx = conn.transaction()?;
-    for result in reader.deserialize() {
-        let row: RefStreet = result?;
-        let street = NULL_END.replace(&row.street, "").to_string();
-        tx.execute(
-            "insert into ref_streets (county_code, settlement_code, street) values (?1, ?2, ?3)",
-            [row.county, row.settlement, street],
-        )?;
-    }
-    tx.commit()?;
-
-    Ok(())
+    let sources = [reference_import::Source::from_streets_tsv(path)];
+    reference_import::build_index(ctx, reference_import::Kind::Streets, &sources)
 }
 
 /// Parses a filter description, like 'filter-for', 'refcounty', '42'.
@@ -717,18 +630,31 @@ pub fn parse_filters(tokens: &[String]) -> HashMap<String, String> {
             continue;
         }
 
-        if ["refcounty", "refsettlement", "relations"].contains(&value.as_str()) {
+        if ["refcounty", "refsettlement", "relations", "parent"].contains(&value.as_str()) {
             ret.insert(value.clone(), tokens[index + 1].clone());
         }
     }
     ret
 }
 
-/// Handles a HTTP error from Overpass.
-pub fn handle_overpass_error(ctx: &context::Context, http_error: &str) -> yattag::Doc {
+/// Handles an error from Overpass.
+pub fn handle_overpass_error(
+    ctx: &context::Context,
+    error: &overpass_query::OverpassError,
+) -> yattag::Doc {
     let doc = yattag::Doc::new();
     let div = doc.tag("div", &[("id", "overpass-error")]);
-    div.text(&tr("Overpass error: {0}").replace("{0}", http_error));
+    let message = match error {
+        overpass_query::OverpassError::RateLimited => tr("too many requests, try again later"),
+        overpass_query::OverpassError::Timeout => tr("the query timed out"),
+        overpass_query::OverpassError::OutOfMemory => tr("the query ran out of memory"),
+        overpass_query::OverpassError::Malformed(details)
+        | overpass_query::OverpassError::Server(details) => {
+            tr("unexpected response: {0}").replace("{0}", details)
+        }
+        overpass_query::OverpassError::Network(details) => details.to_string(),
+    };
+    div.text(&tr("Overpass error: {0}").replace("{0}", &message));
     let sleep = overpass_query::overpass_query_need_sleep(ctx);
     if sleep > 0 {
         doc.stag("br");
@@ -778,6 +704,26 @@ pub fn process_template(buf: &str, osm_relation: u64) -> String {
     buf
 }
 
+/// Turns the country-specific placeholders of an overpass query template to their configured
+/// values.
+pub fn process_country_template(buf: &str, ini: &context::Ini) -> String {
+    buf.replace("@COUNTRY_AREA@", &ini.get_country_area())
+        .replace("@STREET_NAME_PATTERN@", &ini.get_street_name_pattern())
+        .replace(
+            "@STREET_NAME_EXCLUDE_PATTERN@",
+            &ini.get_street_name_exclude_pattern(),
+        )
+        .replace(
+            "@PARKING_AISLE_NAME_PATTERN@",
+            &ini.get_parking_aisle_name_pattern(),
+        )
+        .replace("@PARKING_NAME_PATTERN@", &ini.get_parking_name_pattern())
+        .replace(
+            "@PARKING_NAME_EXCLUDE_PATTERN@",
+            &ini.get_parking_name_exclude_pattern(),
+        )
+}
+
 /// Decides if an x-y range should be expanded. Returns a sanitized end value as well.
 pub fn should_expand_range(numbers: &[i64], street_is_even_odd: bool) -> (bool, i64) {
     if numbers.len() != 2 {
@@ -975,6 +921,10 @@ pub struct OsmHouseNumber {
     pub place: Option<String>,
     /// Object type.
     pub object_type: String,
+    /// Comes from an addr:interpolation way.
+    pub interpolated: bool,
+    /// Postcode.
+    pub postcode: String,
 }
 
 impl OsmHouseNumber {
@@ -999,6 +949,8 @@ impl OsmHouseNumber {
             street,
             place,
             object_type,
+            interpolated: false,
+            postcode: "".into(),
         }
     }
 }
@@ -1039,10 +991,10 @@ pub fn get_street_from_housenumber(housenumbers: &[OsmHouseNumber]) -> anyhow::R
 pub fn get_housenumber_ranges(house_numbers: &[HouseNumber]) -> Vec<HouseNumberRange> {
     let mut ret: Vec<HouseNumberRange> = Vec::new();
     for house_number in house_numbers {
-        ret.push(HouseNumberRange::new(
-            house_number.get_source(),
-            house_number.get_comment(),
-        ));
+        let mut range =
+            HouseNumberRange::new(house_number.get_source(), house_number.get_comment());
+        range.interpolated = house_number.is_interpolated();
+        ret.push(range);
     }
     ret.sort();
     ret.dedup();
@@ -1166,13 +1118,17 @@ pub fn split_house_number_by_separator(
 
 /// Constructs a city name based on postcode the nominal city.
 pub fn get_city_key(
+    ini: &context::Ini,
     postcode: &str,
     city: &str,
     valid_settlements: &HashSet<String>,
 ) -> anyhow::Result<String> {
-    if !city.is_empty() && postcode.starts_with('1') {
+    let prefix = ini.get_capital_postcode_prefix();
+    if !city.is_empty() && postcode.starts_with(&prefix) {
         let mut chars = postcode.chars();
-        chars.next();
+        for _ in 0..prefix.chars().count() {
+            chars.next();
+        }
         chars.next_back();
         let district = match chars.as_str().parse::<i32>() {
             Ok(value) => value,
@@ -1180,13 +1136,13 @@ pub fn get_city_key(
                 return Ok("_Invalid".into());
             }
         };
-        if (1..=23).contains(&district) {
+        if (1..=ini.get_capital_district_count()?).contains(&district) {
             return Ok(city.to_string() + "_" + chars.as_str());
         }
         return Ok(city.to_string());
     }
 
-    if valid_settlements.contains(&city.to_string()) || city == "Budapest" {
+    if valid_settlements.contains(&city.to_string()) || city == ini.get_capital_name() {
         return Ok(city.to_string());
     }
     if !city.is_empty() {
@@ -1195,6 +1151,31 @@ pub fn get_city_key(
     Ok("_Empty".into())
 }
 
+/// Creates a GeoJSON feature for a street, the geometry is a LineString if it's known.
+pub fn make_street_feature(
+    name: &str,
+    osm_id: Option<u64>,
+    coordinates: Option<&[[f64; 2]]>,
+) -> serde_json::Value {
+    let geometry = match coordinates {
+        Some(coordinates) => serde_json::json!({
+            "type": "LineString",
+            "coordinates": coordinates,
+        }),
+        None => serde_json::Value::Null,
+    };
+    let mut properties = serde_json::Map::new();
+    properties.insert("name".into(), name.into());
+    if let Some(osm_id) = osm_id {
+        properties.insert("osm_id".into(), osm_id.into());
+    }
+    serde_json::json!({
+        "type": "Feature",
+        "geometry": geometry,
+        "properties": properties,
+    })
+}
+
 /// Returns a string comparator which allows locale-aware lexical sorting.
 #[cfg(feature = "icu")]
 pub fn get_sort_key(bytes: &str) -> Vec<u8> {
@@ -1222,7 +1203,7 @@ pub fn get_valid_settlements(ctx: &context::Context) -> anyhow::Result<HashSet<S
         .get_file_system()
         .open_read(&path)
         .context("open_read() failed")?;
-    let mut guard = stream.borrow_mut();
+    let mut guard = stream.lock().unwrap();
     let mut read = guard.deref_mut();
     let mut csv_reader = make_csv_reader(&mut read);
     for result in csv_reader.deserialize() {
@@ -1238,6 +1219,26 @@ pub fn get_valid_settlements(ctx: &context::Context) -> anyhow::Result<HashSet<S
     Ok(settlements)
 }
 
+/// Gets the postcodes of the reference.
+pub fn get_valid_postcodes(ctx: &context::Context) -> anyhow::Result<HashSet<String>> {
+    let mut postcodes: HashSet<String> = HashSet::new();
+
+    let path = ctx.get_ini().get_reference_zipcounts_path()?;
+    let stream = ctx
+        .get_file_system()
+        .open_read(&path)
+        .context("open_read() failed")?;
+    let mut guard = stream.lock().unwrap();
+    let mut read = guard.deref_mut();
+    let mut csv_reader = make_csv_reader(&mut read);
+    for result in csv_reader.deserialize() {
+        let row: ZipCount = result?;
+        postcodes.insert(row.zip);
+    }
+
+    Ok(postcodes)
+}
+
 /// Formats a percentage, taking locale into account.
 #[cfg(feature = "icu")]
 pub fn format_percent(parsed: f64) -> anyhow::Result<String> {
@@ -12,7 +12,7 @@
 
 use super::*;
 use std::io::Write;
-use std::rc::Rc;
+use std::sync::Arc;
 
 /// Convers a string list into a street list.
 fn street_list(streets: &[&str]) -> Vec<Street> {
@@ -88,6 +88,17 @@ fn test_format_even_odd_html_comment() {
     assert_eq!(doc.get_value(), expected);
 }
 
+/// Tests format_even_odd(): interpolated house numbers are in italic.
+#[test]
+fn test_format_even_odd_html_interpolated() {
+    let mut house_number = HouseNumber::new("3", "3", "");
+    house_number.set_interpolated(true);
+    let house_numbers = get_housenumber_ranges(&[house_number, HouseNumber::new("5", "5", "")]);
+    let doc = format_even_odd_html(&house_numbers);
+    let expected = r#"<span style="font-style: italic;" title="interpolated">3</span>, 5"#;
+    assert_eq!(doc.get_value(), expected);
+}
+
 /// Tests format_even_odd(): HTML output with multiple odd numbers.
 #[test]
 fn test_format_even_odd_html_multi_odd() {
@@ -230,7 +241,7 @@ fn test_parse_filters_refsettlement() {
 /// Tests handle_overpass_error(): the case when no sleep is needed.
 #[test]
 fn test_handle_overpass_error_no_sleep() {
-    let error = "HTTP Error 404: no such file";
+    let error = overpass_query::OverpassError::Network("HTTP Error 404: no such file".into());
     let mut ctx = context::tests::make_test_context().unwrap();
     let routes = vec![context::tests::URLRoute::new(
         /*url=*/ "https://overpass-api.de/api/status",
@@ -238,9 +249,9 @@ fn test_handle_overpass_error_no_sleep() {
         /*result_path=*/ "src/fixtures/network/overpass-status-happy.txt",
     )];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     ctx.set_network(network_rc);
-    let doc = handle_overpass_error(&ctx, error);
+    let doc = handle_overpass_error(&ctx, &error);
     let expected = r#"<div id="overpass-error">Overpass error: HTTP Error 404: no such file</div>"#;
     assert_eq!(doc.get_value(), expected);
 }
@@ -248,7 +259,7 @@ fn test_handle_overpass_error_no_sleep() {
 /// Tests handle_overpass_error(): the case when sleep is needed.
 #[test]
 fn test_handle_overpass_error_need_sleep() {
-    let error = "HTTP Error 404: no such file";
+    let error = overpass_query::OverpassError::Network("HTTP Error 404: no such file".into());
     let mut ctx = context::tests::make_test_context().unwrap();
     let routes = vec![context::tests::URLRoute::new(
         /*url=*/ "https://overpass-api.de/api/status",
@@ -256,13 +267,25 @@ fn test_handle_overpass_error_need_sleep() {
         /*result_path=*/ "src/fixtures/network/overpass-status-wait.txt",
     )];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     ctx.set_network(network_rc);
-    let doc = handle_overpass_error(&ctx, error);
+    let doc = handle_overpass_error(&ctx, &error);
     let expected = r#"<div id="overpass-error">Overpass error: HTTP Error 404: no such file<br />Note: wait for 12 seconds</div>"#;
     assert_eq!(doc.get_value(), expected);
 }
 
+/// Tests handle_overpass_error(): the case when the query timed out.
+#[test]
+fn test_handle_overpass_error_timeout() {
+    let ctx = context::tests::make_test_context().unwrap();
+    let error = overpass_query::OverpassError::Timeout;
+
+    let doc = handle_overpass_error(&ctx, &error);
+
+    let expected = r#"<div id="overpass-error">Overpass error: the query timed out</div>"#;
+    assert_eq!(doc.get_value(), expected);
+}
+
 /// Tests setup_localization().
 #[test]
 fn test_setup_localization() {
@@ -314,6 +337,26 @@ fn test_process_template() {
     assert_eq!(actual, expected);
 }
 
+/// Tests process_country_template().
+#[test]
+fn test_process_country_template() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    let template = "area(@COUNTRY_AREA@) [name~\"@STREET_NAME_PATTERN@\"] @RELATION@";
+    assert_eq!(
+        process_country_template(template, ctx.get_ini()),
+        "area(3600021335) [name~\"utca|út|köz|árok|lépcső|gát|part|tér|tere|sétány|park|dűlő|sor|kert\"] @RELATION@"
+    );
+
+    context::tests::set_ini(
+        &mut ctx,
+        "country_area = '3600014296'\nstreet_name_pattern = 'ulica|námestie'\n",
+    );
+    assert_eq!(
+        process_country_template(template, ctx.get_ini()),
+        "area(3600014296) [name~\"ulica|námestie\"] @RELATION@"
+    );
+}
+
 /// Tests html_table_from_list().
 #[test]
 fn test_html_table_from_list() {
@@ -555,40 +598,81 @@ fn test_street() {
 /// Tests get_city_key().
 #[test]
 fn test_get_city_key() {
+    let ctx = context::tests::make_test_context().unwrap();
     let mut valid_settlements: HashSet<String> = HashSet::new();
     valid_settlements.insert("Lábatlan".into());
     assert_eq!(
-        get_city_key("1234", "Budapest", &valid_settlements).unwrap(),
+        get_city_key(ctx.get_ini(), "1234", "Budapest", &valid_settlements).unwrap(),
         "Budapest_23"
     );
     assert_eq!(
-        get_city_key("1889", "Budapest", &valid_settlements).unwrap(),
+        get_city_key(ctx.get_ini(), "1889", "Budapest", &valid_settlements).unwrap(),
         "Budapest"
     );
     assert_eq!(
-        get_city_key("9999", "", &valid_settlements).unwrap(),
+        get_city_key(ctx.get_ini(), "9999", "", &valid_settlements).unwrap(),
         "_Empty"
     );
     assert_eq!(
-        get_city_key("9999", "Lábatlan", &valid_settlements).unwrap(),
+        get_city_key(ctx.get_ini(), "9999", "Lábatlan", &valid_settlements).unwrap(),
         "Lábatlan"
     );
     assert_eq!(
-        get_city_key("9999", "junk", &valid_settlements).unwrap(),
+        get_city_key(ctx.get_ini(), "9999", "junk", &valid_settlements).unwrap(),
         "_Invalid"
     );
     // Even if the pos does not start with 1.
     assert_eq!(
-        get_city_key("9999", "Budapest", &valid_settlements).unwrap(),
+        get_city_key(ctx.get_ini(), "9999", "Budapest", &valid_settlements).unwrap(),
         "Budapest"
     );
     // postcode vs housenumber swap.
     assert_eq!(
-        get_city_key("1/A", "junk", &valid_settlements).unwrap(),
+        get_city_key(ctx.get_ini(), "1/A", "junk", &valid_settlements).unwrap(),
+        "_Invalid"
+    );
+}
+
+/// Tests get_city_key(): the capital districts are configured.
+#[test]
+fn test_get_city_key_custom_capital() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    context::tests::set_ini(
+        &mut ctx,
+        "capital_name = 'Wien'\ncapital_postcode_prefix = '1'\ncapital_district_count = '23'\n",
+    );
+    let valid_settlements: HashSet<String> = HashSet::new();
+
+    assert_eq!(
+        get_city_key(ctx.get_ini(), "1100", "Wien", &valid_settlements).unwrap(),
+        "Wien_10"
+    );
+    assert_eq!(
+        get_city_key(ctx.get_ini(), "9999", "Wien", &valid_settlements).unwrap(),
+        "Wien"
+    );
+    assert_eq!(
+        get_city_key(ctx.get_ini(), "9999", "Budapest", &valid_settlements).unwrap(),
         "_Invalid"
     );
 }
 
+/// Tests get_city_key(): the capital postcode prefix has multiple characters.
+#[test]
+fn test_get_city_key_long_prefix() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    context::tests::set_ini(
+        &mut ctx,
+        "capital_name = 'Wien'\ncapital_postcode_prefix = '10'\ncapital_district_count = '23'\n",
+    );
+    let valid_settlements: HashSet<String> = HashSet::new();
+
+    assert_eq!(
+        get_city_key(ctx.get_ini(), "10123", "Wien", &valid_settlements).unwrap(),
+        "Wien_12"
+    );
+}
+
 /// Tests get_street_from_housenumber(): the case when addr:place is used.
 #[test]
 fn test_get_street_from_housenumber_addr_place() {
@@ -692,7 +776,8 @@ fn test_get_valid_settlements() {
     let citycounts_path = "workdir/refs/varosok_count_20190717.tsv";
     let citycounts = context::tests::TestFileSystem::make_file();
     citycounts
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(b"CITY\tCNT\tORIG\nmycity1\t1\tMycity1\nmycity2\t2\tMycity2\n")
         .unwrap();
     let files = context::tests::TestFileSystem::make_files(&ctx, &[(citycounts_path, &citycounts)]);
@@ -714,7 +799,8 @@ fn test_get_valid_settlements_error() {
     let citycounts_path = "workdir/refs/varosok_count_20190717.tsv";
     let citycounts = context::tests::TestFileSystem::make_file();
     citycounts
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(b"CITY\tCNT\tORIG\nmycity1\t1\tMycity1\nmycity2\t2\n")
         .unwrap();
     let files = context::tests::TestFileSystem::make_files(&ctx, &[(citycounts_path, &citycounts)]);
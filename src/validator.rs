@@ -106,6 +106,26 @@ fn validate_filter_invalid_valid(
     Ok(())
 }
 
+/// Validates the structured items of an 'invalid' list.
+fn validate_filter_invalid_resurvey(
+    errors: &mut Vec<String>,
+    parent: &str,
+    invalid: &[areas::RelationInvalidDict],
+) -> anyhow::Result<()> {
+    let format = time::format_description::parse("[year]-[month]-[day]")?;
+    for (index, invalid_data) in invalid.iter().enumerate() {
+        if let Some(resurvey_after) = invalid_data.get_resurvey_after() {
+            if time::Date::parse(resurvey_after, &format).is_err() {
+                errors.push(format!(
+                    "expected format for '{parent}[{index}].resurvey-after' is 'YYYY-MM-DD'"
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
 /// Validates a filter dictionary.
 fn validate_filter(
     errors: &mut Vec<String>,
@@ -118,7 +138,9 @@ fn validate_filter(
     }
 
     if let Some(ref invalid) = filter_data.invalid {
-        validate_filter_invalid_valid(errors, &format!("{}{}", context, "invalid"), invalid)?;
+        let numbers: Vec<String> = invalid.iter().map(|i| i.get_number().to_string()).collect();
+        validate_filter_invalid_valid(errors, &format!("{}{}", context, "invalid"), &numbers)?;
+        validate_filter_invalid_resurvey(errors, &format!("{}{}", context, "invalid"), invalid)?;
     }
     if let Some(ref valid) = filter_data.valid {
         validate_filter_invalid_valid(errors, &format!("{}{}", context, "valid"), valid)?;
@@ -236,6 +258,13 @@ fn validate_relation(
             street_filters,
         )?;
     }
+    if let Some(ref parent) = relation.parent {
+        if parent.is_empty() || parent.contains('/') {
+            errors.push(format!(
+                "expected value for '{context}parent' is a non-empty str without '/'"
+            ));
+        }
+    }
     if let Some(ref source) = relation.source {
         if source.parse::<i64>().is_ok() {
             errors.push(format!("expected value type for '{context}source' is str"));
@@ -25,7 +25,7 @@ fn test_relations() {
     let argv: &[String] = &["".into(), ctx.get_abspath(path)];
     let mut buf: std::io::Cursor<Vec<u8>> = std::io::Cursor::new(Vec::new());
     let file = context::tests::TestFileSystem::make_file();
-    file.borrow_mut().write_all(content.as_bytes()).unwrap();
+    file.lock().unwrap().write_all(content.as_bytes()).unwrap();
     let files = context::tests::TestFileSystem::make_files(&ctx, &[(path, &file)]);
     let file_system = context::tests::TestFileSystem::from_files(&files);
     ctx.set_file_system(&file_system);
@@ -45,7 +45,8 @@ fn test_relations_missing_osmrelation() {
     let mut buf: std::io::Cursor<Vec<u8>> = std::io::Cursor::new(Vec::new());
     let relations_yaml = context::tests::TestFileSystem::make_file();
     relations_yaml
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(
             br#"gazdagret:
 # osmrelation is intentionally missing.
@@ -79,7 +80,8 @@ fn test_relations_missing_refcounty() {
     let mut buf: std::io::Cursor<Vec<u8>> = std::io::Cursor::new(Vec::new());
     let relations_yaml = context::tests::TestFileSystem::make_file();
     relations_yaml
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(
             br#"gazdagret:
     osmrelation: 42
@@ -113,7 +115,8 @@ fn test_relations_missing_refsettlement() {
     let mut buf: std::io::Cursor<Vec<u8>> = std::io::Cursor::new(Vec::new());
     let relations_yaml = context::tests::TestFileSystem::make_file();
     relations_yaml
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(
             br#"gazdagret:
     osmrelation: 42
@@ -146,7 +149,8 @@ fn test_relation() {
     let mut buf: std::io::Cursor<Vec<u8>> = std::io::Cursor::new(Vec::new());
     let relations_yaml = context::tests::TestFileSystem::make_file();
     relations_yaml
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(
             br#"filters:
   'my street':
@@ -173,7 +177,7 @@ fn assert_failure_msg(content: &str, expected: &str) {
     let mut buf: std::io::Cursor<Vec<u8>> = std::io::Cursor::new(Vec::new());
 
     let file = context::tests::TestFileSystem::make_file();
-    file.borrow_mut().write_all(content.as_bytes()).unwrap();
+    file.lock().unwrap().write_all(content.as_bytes()).unwrap();
     let files = context::tests::TestFileSystem::make_files(&ctx, &[(path, &file)]);
     let file_system = context::tests::TestFileSystem::from_files(&files);
     ctx.set_file_system(&file_system);
@@ -193,7 +197,7 @@ fn assert_success(content: &str) {
     let mut buf: std::io::Cursor<Vec<u8>> = std::io::Cursor::new(Vec::new());
 
     let file = context::tests::TestFileSystem::make_file();
-    file.borrow_mut().write_all(content.as_bytes()).unwrap();
+    file.lock().unwrap().write_all(content.as_bytes()).unwrap();
     let files = context::tests::TestFileSystem::make_files(&ctx, &[(path, &file)]);
     let file_system = context::tests::TestFileSystem::from_files(&files);
     ctx.set_file_system(&file_system);
@@ -297,6 +301,28 @@ fn test_relation_filters_invalid_bad2() {
     assert_failure_msg(content, expected);
 }
 
+/// Tests the relation path: structured filters -> ... -> invalid items.
+#[test]
+fn test_relation_filters_invalid_structured() {
+    let content = r#"filters:
+  'Budaörsi út':
+    invalid: ['1', {number: '11', reason: 'school', resurvey-after: '2024-09-30'}]
+"#;
+    assert_success(content);
+}
+
+/// Tests the relation path: bad house number or date in structured filters -> ... -> invalid
+/// items.
+#[test]
+fn test_relation_filters_invalid_structured_bad() {
+    let content = r#"filters:
+  'Budaörsi út':
+    invalid: [{number: '1c 1'}, {number: '11', resurvey-after: '30/09/2024'}]
+"#;
+    let expected = "expected format for 'filters.Budaörsi út.invalid[0]' is '42', '42a' or '42/1'\nexpected format for 'filters.Budaörsi út.invalid[1].resurvey-after' is 'YYYY-MM-DD'\nfailed to validate {0}\n";
+    assert_failure_msg(content, expected);
+}
+
 /// Tests the relation path: bad type for the filters -> ... -> invalid subkey.
 #[test]
 fn test_relation_filters_invalid_bad_type() {
@@ -433,6 +459,15 @@ fn test_relation_alias_bad() {
     assert_failure_msg(content, expected);
 }
 
+/// Tests the relation path: bad parent value.
+#[test]
+fn test_relation_parent_bad() {
+    let content = "parent: 'budapest/xi'\n";
+    let expected =
+        "expected value for 'parent' is a non-empty str without '/'\nfailed to validate {0}\n";
+    assert_failure_msg(content, expected);
+}
+
 /// Tests the relation path: bad type for the alias subkey.
 #[test]
 fn test_relation_filters_alias_bad_type() {
@@ -14,16 +14,17 @@ use crate::areas;
 use crate::context;
 use crate::cron;
 use crate::i18n::translate as tr;
+use crate::ref_snapshots;
 use crate::stats;
 use crate::util;
 use crate::yattag;
 use anyhow::Context;
 use std::borrow::Cow;
-use std::cell::RefCell;
 use std::collections::HashMap;
 use std::io::Read;
 use std::ops::DerefMut;
-use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::Mutex;
 
 /// Produces the end of the page.
 pub fn get_footer(last_updated: &str) -> yattag::Doc {
@@ -545,10 +546,10 @@ fn handle_stats_cityprogress(
     );
 
     let mut ref_citycounts: HashMap<String, u64> = HashMap::new();
-    let csv_stream: Rc<RefCell<dyn Read>> = ctx
+    let csv_stream: Arc<Mutex<dyn Read + Send>> = ctx
         .get_file_system()
         .open_read(&ctx.get_ini().get_reference_citycounts_path()?)?;
-    let mut guard = csv_stream.borrow_mut();
+    let mut guard = csv_stream.lock().unwrap();
     let mut read = guard.deref_mut();
     let mut csv_reader = util::make_csv_reader(&mut read);
     for result in csv_reader.deserialize() {
@@ -634,10 +635,10 @@ fn handle_stats_zipprogress(
         .get_value(),
     );
     let mut ref_zipcounts: HashMap<String, u64> = HashMap::new();
-    let csv_stream: Rc<RefCell<dyn Read>> = ctx
+    let csv_stream: Arc<Mutex<dyn Read + Send>> = ctx
         .get_file_system()
         .open_read(&ctx.get_ini().get_reference_zipcounts_path()?)?;
-    let mut guard = csv_stream.borrow_mut();
+    let mut guard = csv_stream.lock().unwrap();
     let mut read = guard.deref_mut();
     let mut csv_reader = util::make_csv_reader(&mut read);
     for result in csv_reader.deserialize() {
@@ -798,6 +799,83 @@ fn handle_invalid_addr_cities(
     Ok(doc)
 }
 
+/// Expected request uri: /lints/whole-country/invalid-postcodes.
+fn handle_invalid_postcodes(
+    ctx: &context::Context,
+    relations: &mut areas::Relations<'_>,
+) -> anyhow::Result<yattag::Doc> {
+    let doc = yattag::Doc::new();
+    doc.append_value(
+        get_toolbar(
+            ctx,
+            Some(relations),
+            /*function=*/ "",
+            /*relation_name=*/ "",
+            /*relation_osmid=*/ 0,
+        )?
+        .get_value(),
+    );
+
+    let mut table: Vec<Vec<yattag::Doc>> = vec![vec![
+        yattag::Doc::from_text(&tr("Identifier")),
+        yattag::Doc::from_text(&tr("Type")),
+        yattag::Doc::from_text(&tr("Postcode")),
+        yattag::Doc::from_text(&tr("Reason")),
+        yattag::Doc::from_text(&tr("City")),
+        yattag::Doc::from_text(&tr("Street")),
+        yattag::Doc::from_text(&tr("Housenumber")),
+        yattag::Doc::from_text(&tr("User")),
+        yattag::Doc::from_text(&tr("Timestamp")),
+        yattag::Doc::from_text(&tr("Fixme")),
+    ]];
+    let mut count = 0;
+    {
+        let conn = ctx.get_database_connection()?;
+        let mut stmt = conn.prepare("select osm_id, osm_type, postcode, reason, city, street, housenumber, user, timestamp, fixme from stats_invalid_postcodes order by city, street, osm_type, cast(osm_id as integer)")?;
+        let mut invalids = stmt.query([])?;
+        while let Some(invalid) = invalids.next()? {
+            let mut cells: Vec<yattag::Doc> = Vec::new();
+            let osm_id: String = invalid.get(0).unwrap();
+            let osm_type: String = invalid.get(1).unwrap();
+            {
+                let cell = yattag::Doc::new();
+                let href = format!("https://www.openstreetmap.org/{osm_type}/{osm_id}");
+                {
+                    let a = cell.tag("a", &[("href", href.as_str()), ("target", "_blank")]);
+                    a.text(&osm_id);
+                }
+                cells.push(cell);
+            }
+            cells.push(yattag::Doc::from_text(&osm_type));
+            let postcode: String = invalid.get(2).unwrap();
+            cells.push(yattag::Doc::from_text(&postcode));
+            let reason: String = invalid.get(3).unwrap();
+            let reason = match reason.as_str() {
+                "malformed-postcode" => tr("malformed postcode"),
+                "unknown-postcode" => tr("unknown postcode"),
+                _ => tr("differs from the postcode of the street"),
+            };
+            cells.push(yattag::Doc::from_text(&reason));
+            for index in 4..10 {
+                let value: String = invalid.get(index).unwrap();
+                cells.push(yattag::Doc::from_text(&value));
+            }
+            table.push(cells);
+            count += 1;
+        }
+    }
+    {
+        let p = doc.tag("p", &[]);
+        p.text(
+            &tr("The addr:postcode key of the below {0} objects probably has an invalid value.")
+                .replace("{0}", &count.to_string()),
+        );
+    }
+    doc.append_value(util::html_table_from_list(&table).get_value());
+    doc.append_value(get_footer(&get_whole_county_last_modified(ctx)?).get_value());
+    Ok(doc)
+}
+
 fn handle_invalid_addr_cities_update(ctx: &context::Context) -> anyhow::Result<()> {
     cron::update_stats_overpass(ctx).context("update_stats_overpass failed")?;
     stats::update_invalid_addr_cities(ctx).context("update_invalid_addr_cities failed")?;
@@ -893,6 +971,84 @@ fn handle_invalid_refstreets(
     Ok(doc)
 }
 
+/// Expected request_uri: e.g. /osm/lints/whole-country/reference-diff.
+fn handle_reference_diff(
+    ctx: &context::Context,
+    relations: &mut areas::Relations<'_>,
+) -> anyhow::Result<yattag::Doc> {
+    let doc = yattag::Doc::new();
+    doc.append_value(
+        get_toolbar(
+            ctx,
+            Some(relations),
+            /*function=*/ "",
+            /*relation_name=*/ "",
+            /*relation_osmid=*/ 0,
+        )?
+        .get_value(),
+    );
+
+    let (from, to) = match ref_snapshots::get_latest_pair(ctx)? {
+        Some(value) => value,
+        None => {
+            {
+                let p = doc.tag("p", &[]);
+                p.text(&tr("Not enough reference imports yet."));
+            }
+            doc.append_value(get_footer(/*last_updated=*/ "").get_value());
+            return Ok(doc);
+        }
+    };
+    {
+        let p = doc.tag("p", &[]);
+        p.text(
+            &tr("Reference changes between the imports of {0} and {1}:")
+                .replace("{0}", &format_timestamp(&from.created)?)
+                .replace("{1}", &format_timestamp(&to.created)?),
+        );
+    }
+
+    let prefix = ctx.get_ini().get_uri_prefix();
+    for diff in ref_snapshots::get_relation_diffs(ctx, relations, from.id, to.id)? {
+        {
+            let h1 = doc.tag("h1", &[]);
+            let relation_name = &diff.relation_name;
+            let a = h1.tag(
+                "a",
+                &[(
+                    "href",
+                    &format!("{prefix}/missing-housenumbers/{relation_name}/view-result"),
+                )],
+            );
+            a.text(relation_name);
+        }
+        let ul = doc.tag("ul", &[]);
+        for street in &diff.added_streets {
+            let li = ul.tag("li", &[]);
+            li.text(&tr("New street: {0}").replace("{0}", street));
+        }
+        for street in &diff.removed_streets {
+            let li = ul.tag("li", &[]);
+            li.text(&tr("Removed street: {0}").replace("{0}", street));
+        }
+        for (street, housenumber) in &diff.added_housenumbers {
+            let li = ul.tag("li", &[]);
+            li.text(
+                &tr("New house number: {0}").replace("{0}", &format!("{street} {housenumber}")),
+            );
+        }
+        for (street, housenumber) in &diff.removed_housenumbers {
+            let li = ul.tag("li", &[]);
+            li.text(
+                &tr("Removed house number: {0}").replace("{0}", &format!("{street} {housenumber}")),
+            );
+        }
+    }
+
+    doc.append_value(get_footer(/*last_updated=*/ "").get_value());
+    Ok(doc)
+}
+
 /// Expected request_uri: e.g. /osm/housenumber-stats/whole-country/.
 pub fn handle_stats(
     ctx: &context::Context,
@@ -1102,6 +1258,14 @@ pub fn handle_lints(
         return handle_invalid_addr_cities_update_html(ctx, relations);
     }
 
+    if request_uri.ends_with("/invalid-postcodes") {
+        return handle_invalid_postcodes(ctx, relations);
+    }
+
+    if request_uri.ends_with("/reference-diff") {
+        return handle_reference_diff(ctx, relations);
+    }
+
     let doc = yattag::Doc::new();
     doc.append_value(
         get_toolbar(
@@ -1119,31 +1283,19 @@ pub fn handle_lints(
     let title_ids = &[
         (tr("Invalid relation settings"), "invalid-relations"),
         (tr("Invalid addr:city values"), "invalid-addr-cities"),
+        (tr("Invalid addr:postcode values"), "invalid-postcodes"),
+        (tr("Reference changes"), "reference-diff"),
     ];
 
     {
         let ul = doc.tag("ul", &[]);
         for (title, identifier) in title_ids {
-            let identifier = identifier.to_string();
             let li = ul.tag("li", &[]);
-            if identifier == "invalid-relations" {
-                let a = li.tag(
-                    "a",
-                    &[(
-                        "href",
-                        &format!("{prefix}/lints/whole-country/invalid-relations"),
-                    )],
-                );
-                a.text(title);
-                continue;
-            }
-
-            // Assume invalid-addr-cities.
             let a = li.tag(
                 "a",
                 &[(
                     "href",
-                    &format!("{prefix}/lints/whole-country/invalid-addr-cities"),
+                    &format!("{prefix}/lints/whole-country/{identifier}"),
                 )],
             );
             a.text(title);
@@ -1319,7 +1471,7 @@ fn get_content_with_meta(ctx: &context::Context, path: &str) -> anyhow::Result<(
         .open_read(path)
         .context("open_read() failed")?;
     let mut buf: Vec<u8> = Vec::new();
-    let mut guard = stream.borrow_mut();
+    let mut guard = stream.lock().unwrap();
     guard.read_to_end(&mut buf).unwrap();
 
     let mtime = ctx
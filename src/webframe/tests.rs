@@ -21,22 +21,19 @@ fn test_handle_static() {
     let mut ctx = context::tests::make_test_context().unwrap();
     let css = context::tests::TestFileSystem::make_file();
     {
-        let mut guard = css.borrow_mut();
+        let mut guard = css.lock().unwrap();
         let write = guard.deref_mut();
         write.write_all(b"/* comment */").unwrap();
     }
     let mut file_system = context::tests::TestFileSystem::new();
     let files =
         context::tests::TestFileSystem::make_files(&ctx, &[("target/browser/osm.min.css", &css)]);
-    let mut mtimes: HashMap<String, Rc<RefCell<time::OffsetDateTime>>> = HashMap::new();
+    let mut mtimes: HashMap<String, Arc<Mutex<time::OffsetDateTime>>> = HashMap::new();
     let path = ctx.get_abspath("target/browser/osm.min.css");
-    mtimes.insert(
-        path,
-        Rc::new(RefCell::new(time::OffsetDateTime::UNIX_EPOCH)),
-    );
+    mtimes.insert(path, Arc::new(Mutex::new(time::OffsetDateTime::UNIX_EPOCH)));
     file_system.set_files(&files);
     file_system.set_mtimes(&mtimes);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
 
     let prefix = ctx.get_ini().get_uri_prefix();
@@ -81,21 +78,18 @@ fn test_handle_static_ico() {
     let mut ctx = context::tests::make_test_context().unwrap();
     let ico = context::tests::TestFileSystem::make_file();
     {
-        let mut guard = ico.borrow_mut();
+        let mut guard = ico.lock().unwrap();
         let write = guard.deref_mut();
         write.write_all(b"\0").unwrap();
     }
     let mut file_system = context::tests::TestFileSystem::new();
     let files = context::tests::TestFileSystem::make_files(&ctx, &[("favicon.ico", &ico)]);
-    let mut mtimes: HashMap<String, Rc<RefCell<time::OffsetDateTime>>> = HashMap::new();
+    let mut mtimes: HashMap<String, Arc<Mutex<time::OffsetDateTime>>> = HashMap::new();
     let path = ctx.get_abspath("favicon.ico");
-    mtimes.insert(
-        path,
-        Rc::new(RefCell::new(time::OffsetDateTime::UNIX_EPOCH)),
-    );
+    mtimes.insert(path, Arc::new(Mutex::new(time::OffsetDateTime::UNIX_EPOCH)));
     file_system.set_files(&files);
     file_system.set_mtimes(&mtimes);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
 
     let (content, content_type, extra_headers) = handle_static(&ctx, "/favicon.ico").unwrap();
@@ -112,21 +106,18 @@ fn test_handle_static_svg() {
     let mut ctx = context::tests::make_test_context().unwrap();
     let svg = context::tests::TestFileSystem::make_file();
     {
-        let mut guard = svg.borrow_mut();
+        let mut guard = svg.lock().unwrap();
         let write = guard.deref_mut();
         write.write_all(b"<svg").unwrap();
     }
     let mut file_system = context::tests::TestFileSystem::new();
     let files = context::tests::TestFileSystem::make_files(&ctx, &[("favicon.svg", &svg)]);
-    let mut mtimes: HashMap<String, Rc<RefCell<time::OffsetDateTime>>> = HashMap::new();
+    let mut mtimes: HashMap<String, Arc<Mutex<time::OffsetDateTime>>> = HashMap::new();
     let path = ctx.get_abspath("favicon.svg");
-    mtimes.insert(
-        path,
-        Rc::new(RefCell::new(time::OffsetDateTime::UNIX_EPOCH)),
-    );
+    mtimes.insert(path, Arc::new(Mutex::new(time::OffsetDateTime::UNIX_EPOCH)));
     file_system.set_files(&files);
     file_system.set_mtimes(&mtimes);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
 
     let (content, content_type, extra_headers) = handle_static(&ctx, "/favicon.svg").unwrap();
@@ -232,6 +223,35 @@ fn test_handle_invalid_addr_cities() {
     assert_eq!(results.len(), 2);
 }
 
+/// Tests handle_invalid_postcodes().
+#[test]
+fn test_handle_invalid_postcodes() {
+    let mut test_wsgi = wsgi::tests::TestWsgi::new();
+    {
+        let conn = test_wsgi.get_ctx().get_database_connection().unwrap();
+        conn.execute("insert into stats_invalid_postcodes (osm_id, osm_type, postcode, city, street, housenumber, user, timestamp, fixme, reason) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                   ["42", "node", "9999", "mycity", "mystreet", "myhousenumber", "myuser", "", "", "unknown-postcode"]).unwrap();
+        conn.execute(
+            "insert into mtimes (page, last_modified) values (?1, ?2)",
+            ["whole-country/osm-base", "0"],
+        )
+        .unwrap();
+        conn.execute(
+            "insert into mtimes (page, last_modified) values (?1, ?2)",
+            ["whole-country/areas-base", "0"],
+        )
+        .unwrap();
+    }
+
+    let root = test_wsgi.get_dom_for_path("/lints/whole-country/invalid-postcodes");
+
+    let results = wsgi::tests::TestWsgi::find_all(&root, "body/table/tr");
+    // header + 1 row.
+    assert_eq!(results.len(), 2);
+    let results = wsgi::tests::TestWsgi::find_all(&root, "body/table/tr[2]/td[4]");
+    assert_eq!(results, ["unknown postcode"]);
+}
+
 /// Tests handle_invalid_refstreets(), the case when a relation has no errors.
 #[test]
 fn test_handle_invalid_refstreets_no_errors() {
@@ -298,8 +318,68 @@ fn test_handle_lints() {
     let root = test_wsgi.get_dom_for_path("/lints/whole-country/");
 
     let results = wsgi::tests::TestWsgi::find_all(&root, "body/ul/li");
-    // 2 lint types.
-    assert_eq!(results.len(), 2);
+    // 4 lint types.
+    assert_eq!(results.len(), 4);
+}
+
+/// Tests handle_reference_diff().
+#[test]
+fn test_handle_reference_diff() {
+    let mut test_wsgi = wsgi::tests::TestWsgi::new();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "osmrelation": 2713748,
+                "refcounty": "01",
+                "refsettlement": "011",
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        test_wsgi.get_ctx(),
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    test_wsgi.get_ctx().set_file_system(&file_system);
+    {
+        let ctx = test_wsgi.get_ctx();
+        {
+            let conn = ctx.get_database_connection().unwrap();
+            conn.execute_batch(
+                "insert into ref_housenumbers (county_code, settlement_code, street, housenumber, comment) values ('01', '011', 'Tűzkő utca', '1', '')",
+            )
+            .unwrap();
+        }
+        ref_snapshots::create_snapshot(ctx).unwrap();
+        {
+            let conn = ctx.get_database_connection().unwrap();
+            conn.execute_batch(
+                "update ref_housenumbers set housenumber = '2';
+                insert into ref_streets (county_code, settlement_code, street) values ('01', '011', 'Tűzkő utca')",
+            )
+            .unwrap();
+        }
+        ref_snapshots::create_snapshot(ctx).unwrap();
+    }
+
+    let root = test_wsgi.get_dom_for_path("/lints/whole-country/reference-diff");
+
+    let results = wsgi::tests::TestWsgi::find_all(&root, "body/h1/a");
+    assert_eq!(results, vec!["gazdagret"]);
+    let results = wsgi::tests::TestWsgi::find_all(&root, "body/ul/li");
+    assert_eq!(results.len(), 3);
+}
+
+/// Tests handle_reference_diff(), when there are no snapshots yet.
+#[test]
+fn test_handle_reference_diff_no_snapshots() {
+    let mut test_wsgi = wsgi::tests::TestWsgi::new();
+
+    let root = test_wsgi.get_dom_for_path("/lints/whole-country/reference-diff");
+
+    let results = wsgi::tests::TestWsgi::find_all(&root, "body/p");
+    assert_eq!(results, vec!["Not enough reference imports yet."]);
 }
 
 /// Tests handle_invalid_addr_cities_update().
@@ -320,19 +400,19 @@ fn test_handle_invalid_addr_cities_update() {
         ),
     ];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     test_wsgi.get_ctx().set_network(network_rc);
     let overpass_template = context::tests::TestFileSystem::make_file();
     let files = context::tests::TestFileSystem::make_files(
         &test_wsgi.get_ctx(),
         &[(
-            "data/street-housenumbers-hungary.overpassql",
+            "data/street-housenumbers-country.overpassql",
             &overpass_template,
         )],
     );
     let mut file_system = context::tests::TestFileSystem::new();
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     test_wsgi.get_ctx().set_file_system(&file_system_rc);
 
     // When getting that page:
@@ -373,19 +453,19 @@ fn test_handle_invalid_addr_cities_update_json() {
         ),
     ];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     test_wsgi.get_ctx().set_network(network_rc);
     let overpass_template = context::tests::TestFileSystem::make_file();
     let files = context::tests::TestFileSystem::make_files(
         &test_wsgi.get_ctx(),
         &[(
-            "data/street-housenumbers-hungary.overpassql",
+            "data/street-housenumbers-country.overpassql",
             &overpass_template,
         )],
     );
     let mut file_system = context::tests::TestFileSystem::new();
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     test_wsgi.get_ctx().set_file_system(&file_system_rc);
 
     // When getting that page:
@@ -13,12 +13,17 @@
 use crate::areas;
 use crate::cache;
 use crate::context;
+use crate::filter_patch;
 use crate::i18n::translate as tr;
-use crate::overpass_query;
+use crate::jobs;
+use crate::metrics;
+use crate::rate_limit;
 use crate::stats;
+use crate::street_matching;
 use crate::util;
 use crate::webframe;
 use crate::wsgi_additional;
+use crate::wsgi_api;
 use crate::wsgi_json;
 use crate::yattag;
 use anyhow::Context;
@@ -60,6 +65,77 @@ fn get_housenumbers_last_modified(
     Ok(format.replace("{0}", &osm).replace("{1}", &areas))
 }
 
+/// Creates a link to the status page of a job.
+fn get_job_link(ctx: &context::Context, job_id: i64) -> yattag::Doc {
+    let doc = yattag::Doc::new();
+    doc.text(&tr("Update queued: "));
+    let prefix = ctx.get_ini().get_uri_prefix();
+    let link = format!("{prefix}/jobs/{job_id}/view-result");
+    doc.append_value(util::gen_link(&link, &tr("View update status")).get_value());
+    doc
+}
+
+/// Expected request_uri: e.g. /osm/jobs/42/view-result.
+fn handle_job(
+    ctx: &context::Context,
+    relations: &mut areas::Relations<'_>,
+    request_uri: &str,
+) -> anyhow::Result<yattag::Doc> {
+    let mut tokens = request_uri.split('/');
+    tokens.next_back();
+    let job_id = tokens.next_back().context("no job_id")?;
+
+    let doc = yattag::Doc::new();
+    let job = match job_id.parse::<i64>() {
+        Ok(value) => jobs::get_job(ctx, value)?,
+        Err(_) => None,
+    };
+    let job = match job {
+        Some(value) => value,
+        None => {
+            doc.append_value(webframe::get_toolbar(ctx, Some(relations), "", "", 0)?.get_value());
+            let div = doc.tag("div", &[("id", "no-such-job-error")]);
+            div.text(&tr("No such job: {0}").replace("{0}", job_id));
+            return Ok(doc);
+        }
+    };
+
+    let relation = relations.get_relation(&job.relation)?;
+    let osmrelation = relation.get_config().get_osmrelation();
+    doc.append_value(
+        webframe::get_toolbar(ctx, Some(relations), "", &job.relation, osmrelation)?.get_value(),
+    );
+
+    let prefix = ctx.get_ini().get_uri_prefix();
+    match job.state {
+        jobs::JobState::Queued => {
+            doc.text(&tr("Update queued, reload this page to see its progress."));
+        }
+        jobs::JobState::Running => {
+            doc.text(&tr("Update running, reload this page to see its progress."));
+        }
+        jobs::JobState::Done => {
+            let streets = relation.get_config().should_check_missing_streets();
+            if job.kind == jobs::JobKind::Streets && streets == "only" {
+                doc.text(&tr("Update successful."));
+            } else {
+                doc.text(&tr("Update successful: "));
+                let link = format!("{prefix}/missing-housenumbers/{}/view-result", job.relation);
+                doc.append_value(
+                    util::gen_link(&link, &tr("View missing house numbers")).get_value(),
+                );
+            }
+        }
+        jobs::JobState::Failed => {
+            let div = doc.tag("div", &[("id", "overpass-error")]);
+            div.text(&tr("Overpass error: {0}").replace("{0}", &job.error));
+        }
+    }
+
+    doc.append_value(webframe::get_footer(/*last_updated=*/ "").get_value());
+    Ok(doc)
+}
+
 /// Expected request_uri: e.g. /osm/streets/ormezo/view-query.
 fn handle_streets(
     ctx: &context::Context,
@@ -83,26 +159,8 @@ fn handle_streets(
         let pre = doc.tag("pre", &[]);
         pre.text(&relation.get_osm_streets_query()?);
     } else if action == "update-result" {
-        let query = relation.get_osm_streets_json_query()?;
-        match overpass_query::overpass_query(ctx, &query) {
-            Ok(buf) => {
-                relation.get_files().write_osm_json_streets(ctx, &buf)?;
-                let streets = relation.get_config().should_check_missing_streets();
-                if streets != "only" {
-                    doc.text(&tr("Update successful: "));
-                    let prefix = ctx.get_ini().get_uri_prefix();
-                    let link = format!("{prefix}/missing-housenumbers/{relation_name}/view-result");
-                    doc.append_value(
-                        util::gen_link(&link, &tr("View missing house numbers")).get_value(),
-                    );
-                } else {
-                    doc.text(&tr("Update successful."));
-                }
-            }
-            Err(err) => {
-                doc.append_value(util::handle_overpass_error(ctx, &err.to_string()).get_value());
-            }
-        }
+        let job_id = jobs::enqueue(ctx, &jobs::JobKind::Streets, relation_name)?;
+        doc.append_value(get_job_link(ctx, job_id).get_value());
     } else {
         // assume view-result
         let mut csv: String =
@@ -156,27 +214,12 @@ fn handle_street_housenumbers(
         .get_value(),
     );
 
-    let prefix = ctx.get_ini().get_uri_prefix();
     if action == "view-query" {
         let pre = doc.tag("pre", &[]);
         pre.text(&relation.get_osm_housenumbers_query()?);
     } else if action == "update-result" {
-        let query = relation.get_osm_housenumbers_query()?;
-        match overpass_query::overpass_query(ctx, &query) {
-            Ok(buf) => {
-                relation
-                    .get_files()
-                    .write_osm_json_housenumbers(ctx, &buf)?;
-                doc.text(&tr("Update successful: "));
-                let link = format!("{prefix}/missing-housenumbers/{relation_name}/view-result");
-                doc.append_value(
-                    util::gen_link(&link, &tr("View missing house numbers")).get_value(),
-                );
-            }
-            Err(err) => {
-                doc.append_value(util::handle_overpass_error(ctx, &err.to_string()).get_value());
-            }
-        }
+        let job_id = jobs::enqueue(ctx, &jobs::JobKind::Housenumbers, relation_name)?;
+        doc.append_value(get_job_link(ctx, job_id).get_value());
     } else {
         // assume view-result
         if !stats::has_sql_mtime(ctx, &format!("housenumbers/{}", relation_name))? {
@@ -257,7 +300,7 @@ fn missing_housenumbers_view_lints(
     {
         let conn = ctx.get_database_connection()?;
         let mut stmt = conn
-        .prepare("select street_name, source, housenumber, reason, object_id, object_type from relation_lints where relation_name = ?1")?;
+        .prepare("select street_name, source, housenumber, reason, object_id, object_type, other_object_id, other_object_type from relation_lints where relation_name = ?1")?;
         let mut lints = stmt.query([relation.get_name()])?;
         {
             let cells: Vec<yattag::Doc> = vec![
@@ -277,15 +320,28 @@ fn missing_housenumbers_view_lints(
             let source_string = match source {
                 areas::RelationLintSource::Range => tr("street ranges"),
                 areas::RelationLintSource::Invalid => tr("invalid housenumbers"),
+                areas::RelationLintSource::Resurvey => tr("resurvey dates"),
+                areas::RelationLintSource::Duplicate => tr("duplicate addresses"),
+                areas::RelationLintSource::Postcode => tr("postcodes"),
             };
             let housenumber: String = lint.get(2).unwrap();
             let reason: areas::RelationLintReason = lint.get(3).unwrap();
             let id: String = lint.get(4).unwrap();
             let object_type: String = lint.get(5).unwrap();
+            let other_id: String = lint.get(6).unwrap();
+            let other_object_type: String = lint.get(7).unwrap();
             let reason_string = match reason {
                 areas::RelationLintReason::CreatedInOsm => tr("created in OSM"),
                 areas::RelationLintReason::DeletedFromRef => tr("deleted from reference"),
                 areas::RelationLintReason::OutOfRange => tr("out of range"),
+                areas::RelationLintReason::ResurveyDue => tr("resurvey date passed"),
+                areas::RelationLintReason::DuplicateInOsm => tr("duplicate in OSM"),
+                areas::RelationLintReason::MissingPostcode => tr("missing postcode"),
+                areas::RelationLintReason::MalformedPostcode => tr("malformed postcode"),
+                areas::RelationLintReason::UnknownPostcode => tr("unknown postcode"),
+                areas::RelationLintReason::PostcodeMismatch => {
+                    tr("differs from the postcode of the street")
+                }
             };
             cells.push(yattag::Doc::from_text(&street));
             cells.push(yattag::Doc::from_text(&source_string));
@@ -303,11 +359,26 @@ fn missing_housenumbers_view_lints(
                     let a = cell.tag("a", &[("href", &href), ("target", "_blank")]);
                     a.text(&id.to_string());
                 }
+                if other_id != "0" {
+                    cell.text(", ");
+                    let href = format!(
+                        "https://www.openstreetmap.org/{}/{}",
+                        other_object_type, other_id,
+                    );
+                    let a = cell.tag("a", &[("href", &href), ("target", "_blank")]);
+                    a.text(&other_id);
+                }
                 cells.push(cell);
             } else {
                 cells.push(yattag::Doc::new());
             }
-            cells.push(yattag::Doc::from_text(&object_type));
+            if other_id != "0" {
+                cells.push(yattag::Doc::from_text(&format!(
+                    "{object_type}, {other_object_type}"
+                )));
+            } else {
+                cells.push(yattag::Doc::from_text(&object_type));
+            }
             table.push(cells);
             count += 1;
         }
@@ -318,12 +389,52 @@ fn missing_housenumbers_view_lints(
             &tr("The below {0} filters for this relation are probably no longer necessary.")
                 .replace("{0}", &count.to_string()),
         );
+        p.stag("br", &[]);
+        let prefix = ctx.get_ini().get_uri_prefix();
+        let a = p.tag(
+            "a",
+            &[(
+                "href",
+                &format!(
+                    "{prefix}/missing-housenumbers/{}/view-lints.patch",
+                    relation.get_name()
+                ),
+            )],
+        );
+        a.text(&tr("Download as patch"));
     }
     doc.append_value(util::html_table_from_list(&table).get_value());
 
     Ok(doc)
 }
 
+/// Expected request uri: /osm/missing-housenumbers/ormezo/view-lints.patch.
+fn missing_housenumbers_view_lints_patch(
+    ctx: &context::Context,
+    relations: &mut areas::Relations<'_>,
+    request_uri: &str,
+) -> anyhow::Result<(String, String)> {
+    let mut tokens = request_uri.split('/');
+    tokens.next_back();
+    let relation_name = tokens.next_back().context("no relation_name")?;
+    let mut relation = relations.get_relation(relation_name)?;
+    let output = filter_patch::get_filter_patch(ctx, &mut relation)?;
+    Ok((output, relation_name.into()))
+}
+
+/// Counts the house numbers which are both in the reference and in OSM, but only as part of an
+/// addr:interpolation way.
+fn get_interpolated_count(relation: &mut areas::Relation<'_>) -> anyhow::Result<usize> {
+    let json = cache::get_missing_housenumbers_json(relation)?;
+    let missing_housenumbers: areas::MissingHousenumbers = serde_json::from_str(&json)?;
+    Ok(missing_housenumbers
+        .done_streets
+        .iter()
+        .flat_map(|i| i.house_numbers.iter())
+        .filter(|i| i.is_interpolated())
+        .count())
+}
+
 /// The actual HTML part of missing_housenumbers_view_res().
 fn missing_housenumbers_view_res_html(
     ctx: &context::Context,
@@ -349,6 +460,13 @@ fn missing_housenumbers_view_res_html(
                 .replace("{0}", &done_count.to_string())
                 .replace("{1}", &percent),
         );
+        let interpolated_count = get_interpolated_count(relation)?;
+        if interpolated_count > 0 {
+            p.text(
+                &tr(" {0} of the existing house numbers are interpolated.")
+                    .replace("{0}", &interpolated_count.to_string()),
+            );
+        }
         doc.stag("br");
         {
             let a = doc.tag(
@@ -395,6 +513,28 @@ fn missing_housenumbers_view_res_html(
             a.text(&tr("Checklist format"));
         }
         doc.stag("br");
+        {
+            let a = doc.tag(
+                "a",
+                &[(
+                    "href",
+                    &format!("{prefix}/missing-housenumbers/{relation_name}/view-result.gpx"),
+                )],
+            );
+            a.text(&tr("GPX format"));
+        }
+        doc.stag("br");
+        {
+            let a = doc.tag(
+                "a",
+                &[(
+                    "href",
+                    &format!("{prefix}/missing-housenumbers/{relation_name}/view-result.geojson"),
+                )],
+            );
+            a.text(&tr("GeoJSON format"));
+        }
+        doc.stag("br");
         {
             let a = doc.tag(
                 "a",
@@ -405,6 +545,17 @@ fn missing_housenumbers_view_res_html(
             );
             a.text(&tr("View lints"));
         }
+        doc.stag("br");
+        {
+            let a = doc.tag(
+                "a",
+                &[(
+                    "href",
+                    &format!("{prefix}/coverage-history/{relation_name}/view-result"),
+                )],
+            );
+            a.text(&tr("Coverage history"));
+        }
     }
 
     doc.append_value(util::html_table_from_list(&table).get_value());
@@ -515,6 +666,39 @@ fn missing_streets_view_result(
             );
             a.text(&tr("Checklist format"));
         }
+        p.stag("br", &[]);
+        {
+            let a = doc.tag(
+                "a",
+                &[(
+                    "href",
+                    &format!("{prefix}/missing-streets/{relation_name}/view-result.geojson"),
+                )],
+            );
+            a.text(&tr("GeoJSON format"));
+        }
+        p.stag("br", &[]);
+        {
+            let a = doc.tag(
+                "a",
+                &[(
+                    "href",
+                    &format!("{prefix}/suggested-refstreets/{relation_name}/view-result"),
+                )],
+            );
+            a.text(&tr("Suggested refstreets"));
+        }
+        p.stag("br", &[]);
+        {
+            let a = doc.tag(
+                "a",
+                &[(
+                    "href",
+                    &format!("{prefix}/coverage-history/{relation_name}/view-result"),
+                )],
+            );
+            a.text(&tr("Coverage history"));
+        }
     }
 
     doc.append_value(util::html_table_from_list(&table).get_value());
@@ -639,6 +823,187 @@ fn missing_housenumbers_view_chkl(
     Ok((output, relation_name.into()))
 }
 
+/// A missing house number, where the reference knows its coordinates.
+struct MissingHousenumberPoint {
+    street: String,
+    housenumber: String,
+    lat: String,
+    lon: String,
+}
+
+/// Gets the missing house numbers of a relation which have their own coordinates in the reference.
+fn get_missing_housenumber_points(
+    ctx: &context::Context,
+    relation: &mut areas::Relation<'_>,
+) -> anyhow::Result<Vec<MissingHousenumberPoint>> {
+    let relation_name = relation.get_name();
+    if !stats::has_sql_mtime(ctx, &format!("streets/{relation_name}"))?
+        || !stats::has_sql_mtime(ctx, &format!("housenumbers/{relation_name}"))?
+    {
+        return Ok(Vec::new());
+    }
+
+    let json = cache::get_missing_housenumbers_json(relation)?;
+    let missing_housenumbers: areas::MissingHousenumbers = serde_json::from_str(&json)?;
+    let mut points: Vec<MissingHousenumberPoint> = Vec::new();
+    for result in missing_housenumbers.ongoing_streets {
+        let street = result.street.get_osm_name();
+        let coordinates = relation.get_ref_housenumber_coordinates(street)?;
+        for house_number in result.house_numbers {
+            // Only use the coordinates of the house number itself: a range in the reference has a
+            // single coordinate for all its house numbers, which would be misleading.
+            if let Some((lat, lon)) = coordinates.get(house_number.get_number()) {
+                points.push(MissingHousenumberPoint {
+                    street: street.to_string(),
+                    housenumber: house_number.get_number().to_string(),
+                    lat: lat.to_string(),
+                    lon: lon.to_string(),
+                });
+            }
+        }
+    }
+    points.sort_by_key(|point| {
+        (
+            util::get_sort_key(&point.street),
+            util::split_house_number(&point.housenumber),
+        )
+    });
+    Ok(points)
+}
+
+/// Expected request_uri: e.g. /osm/missing-housenumbers/ormezo/view-result.gpx.
+fn missing_housenumbers_view_gpx(
+    ctx: &context::Context,
+    relations: &mut areas::Relations<'_>,
+    request_uri: &str,
+) -> anyhow::Result<(String, String)> {
+    let mut tokens = request_uri.split('/');
+    tokens.next_back();
+    let relation_name = tokens.next_back().context("no relation_name")?;
+    let mut relation = relations.get_relation(relation_name)?;
+    let points = get_missing_housenumber_points(ctx, &mut relation)?;
+
+    let doc = yattag::Doc::new();
+    doc.append_value("<?xml version='1.0' encoding='UTF-8'?>".into());
+    {
+        let gpx = doc.tag(
+            "gpx",
+            &[
+                ("version", "1.1"),
+                ("creator", "osm-gimmisn"),
+                ("xmlns", "http://www.topografix.com/GPX/1/1"),
+                ("xmlns:xsi", "http://www.w3.org/2001/XMLSchema-instance"),
+                (
+                    "xsi:schemaLocation",
+                    "http://www.topografix.com/GPX/1/1 http://www.topografix.com/GPX/1/1/gpx.xsd",
+                ),
+            ],
+        );
+        {
+            let metadata = gpx.tag("metadata", &[]);
+            {
+                let desc = metadata.tag("desc", &[]);
+                desc.text(relation_name);
+            }
+            {
+                let time = metadata.tag("time", &[]);
+                let now = ctx.get_time().now();
+                time.text(&now.format(&time::format_description::well_known::Rfc3339)?);
+            }
+        }
+        for point in points {
+            let wpt = gpx.tag("wpt", &[("lat", &point.lat), ("lon", &point.lon)]);
+            let name = wpt.tag("name", &[]);
+            name.text(&format!("{} {}", point.street, point.housenumber));
+        }
+    }
+    Ok((doc.get_value(), relation_name.into()))
+}
+
+/// Expected request_uri: e.g. /osm/missing-housenumbers/ormezo/view-result.geojson.
+fn missing_housenumbers_view_geojson(
+    ctx: &context::Context,
+    relations: &mut areas::Relations<'_>,
+    request_uri: &str,
+) -> anyhow::Result<(String, String)> {
+    let mut tokens = request_uri.split('/');
+    tokens.next_back();
+    let relation_name = tokens.next_back().context("no relation_name")?;
+    let mut relation = relations.get_relation(relation_name)?;
+    let points = get_missing_housenumber_points(ctx, &mut relation)?;
+
+    let mut features: Vec<serde_json::Value> = Vec::new();
+    for point in points {
+        let lat: f64 = point
+            .lat
+            .parse()
+            .context(format!("failed to parse '{}' as a latitude", point.lat))?;
+        let lon: f64 = point
+            .lon
+            .parse()
+            .context(format!("failed to parse '{}' as a longitude", point.lon))?;
+        features.push(serde_json::json!({
+            "type": "Feature",
+            "geometry": {
+                "type": "Point",
+                "coordinates": [lon, lat],
+            },
+            "properties": {
+                "addr:street": point.street,
+                "addr:housenumber": point.housenumber,
+            },
+        }));
+    }
+    let collection = serde_json::json!({
+        "type": "FeatureCollection",
+        "features": features,
+    });
+    Ok((serde_json::to_string(&collection)?, relation_name.into()))
+}
+
+/// Expected request_uri: e.g. /osm/missing-streets/ujbuda/view-result.geojson.
+fn missing_streets_view_geojson(
+    relations: &mut areas::Relations<'_>,
+    request_uri: &str,
+) -> anyhow::Result<(String, String)> {
+    let mut tokens = request_uri.split('/');
+    tokens.next_back();
+    let relation_name = tokens.next_back().context("no relation_name")?;
+    let relation = relations.get_relation(relation_name)?;
+
+    // Missing streets are not in OSM, so there is no way geometry for them. Use the coordinates of
+    // their reference house numbers instead, if there are any: this helps to find the street.
+    let (mut todo_streets, _) = relation.get_missing_streets()?;
+    todo_streets.sort_by_key(|i| util::get_sort_key(i));
+    let mut features: Vec<serde_json::Value> = Vec::new();
+    for street in todo_streets {
+        let mut feature = util::make_street_feature(&street, None, None);
+        let mut points: Vec<[f64; 2]> = Vec::new();
+        for (lat, lon) in relation.get_ref_housenumber_coordinates(&street)?.values() {
+            // A bad coordinate in the reference only loses that point, not the whole response.
+            let (Ok(lon), Ok(lat)) = (lon.parse::<f64>(), lat.parse::<f64>()) else {
+                continue;
+            };
+            if lon.is_finite() && lat.is_finite() {
+                points.push([lon, lat]);
+            }
+        }
+        if !points.is_empty() {
+            points.sort_by(|a, b| a[0].total_cmp(&b[0]).then(a[1].total_cmp(&b[1])));
+            feature["geometry"] = serde_json::json!({
+                "type": "MultiPoint",
+                "coordinates": points,
+            });
+        }
+        features.push(feature);
+    }
+    let collection = serde_json::json!({
+        "type": "FeatureCollection",
+        "features": features,
+    });
+    Ok((serde_json::to_string(&collection)?, relation_name.into()))
+}
+
 /// Expected request_uri: e.g. /osm/missing-streets/ujbuda/view-result.txt.
 fn missing_streets_view_txt(
     ctx: &context::Context,
@@ -806,8 +1171,11 @@ fn handle_missing_streets(
         doc.append_value(missing_streets_view_turbo(relations, request_uri)?.get_value());
     } else if action == "view-query" {
         let pre = doc.tag("pre", &[]);
-        let reference = ctx.get_ini().get_reference_street_path()?;
-        util::build_street_reference_index(ctx, &reference)?;
+        // Without reference_street, the streets come from reference_source sections, which are
+        // indexed by cron.
+        if let Some(reference) = ctx.get_ini().get_reference_street_path() {
+            util::build_street_reference_index(ctx, &reference)?;
+        }
         let conn = ctx.get_database_connection()?;
         let mut lst: Vec<String> = Vec::new();
         let mut stmt = conn.prepare(
@@ -831,6 +1199,270 @@ fn handle_missing_streets(
     Ok(doc)
 }
 
+/// Expected request_uri: e.g. /osm/suggested-refstreets/ujbuda/view-result.
+fn handle_suggested_refstreets(
+    ctx: &context::Context,
+    relations: &mut areas::Relations<'_>,
+    request_uri: &str,
+) -> anyhow::Result<yattag::Doc> {
+    let mut tokens = request_uri.split('/');
+    tokens.next_back();
+    let relation_name = tokens.next_back().context("no relation_name")?;
+
+    let relation = relations.get_relation(relation_name)?;
+    let osmrelation = relation.get_config().get_osmrelation();
+
+    let doc = yattag::Doc::new();
+    doc.append_value(
+        webframe::get_toolbar(
+            ctx,
+            Some(relations),
+            "suggested-refstreets",
+            relation_name,
+            osmrelation,
+        )?
+        .get_value(),
+    );
+
+    let prefix = ctx.get_ini().get_uri_prefix();
+    if !stats::has_sql_mtime(ctx, &format!("streets/{relation_name}"))? {
+        doc.append_value(webframe::handle_no_osm_streets(&prefix, relation_name).get_value());
+    } else {
+        let suggestions = relation.get_suggested_refstreets()?;
+        {
+            let p = doc.tag("p", &[]);
+            p.text(
+                &tr("The below {0} streets are probably spelled differently in OpenStreetMap and in the reference.")
+                    .replace("{0}", &suggestions.len().to_string()),
+            );
+            p.stag("br", &[]);
+            let a = p.tag(
+                "a",
+                &[(
+                    "href",
+                    &format!("{prefix}/suggested-refstreets/{relation_name}/view-result.json"),
+                )],
+            );
+            a.text(&tr("JSON format"));
+        }
+        let mut table = vec![vec![
+            yattag::Doc::from_text(&tr("OSM street name")),
+            yattag::Doc::from_text(&tr("Reference street name")),
+            yattag::Doc::from_text(&tr("Confidence")),
+        ]];
+        for suggestion in &suggestions {
+            table.push(vec![
+                yattag::Doc::from_text(&suggestion.osm_name),
+                yattag::Doc::from_text(&suggestion.ref_name),
+                yattag::Doc::from_text(&util::format_percent(suggestion.confidence * 100_f64)?),
+            ]);
+        }
+        doc.append_value(util::html_table_from_list(&table).get_value());
+        if !suggestions.is_empty() {
+            {
+                let p = doc.tag("p", &[]);
+                p.text(
+                    &tr("Add the below to the refstreets of {0} after checking the pairs:")
+                        .replace("{0}", &format!("relation-{relation_name}.yaml")),
+                );
+            }
+            let pre = doc.tag("pre", &[]);
+            pre.text(&street_matching::to_yaml(&suggestions));
+        }
+    }
+
+    doc.append_value(webframe::get_footer(&get_streets_last_modified(ctx, &relation)?).get_value());
+    Ok(doc)
+}
+
+/// Describes the aggregated coverage of a relation group.
+fn get_group_coverage_text(
+    group_relations: &[areas::Relation<'_>],
+    kind: &str,
+) -> anyhow::Result<String> {
+    let coverage = match areas::get_group_coverage(group_relations, kind)? {
+        Some(value) => value,
+        None => return Ok(tr("not yet calculated")),
+    };
+    let percent: f64 = coverage.percent.parse()?;
+    Ok(tr("{0} (existing: {1}, missing: {2}, as of {3})")
+        .replace("{0}", &util::format_percent(percent)?)
+        .replace("{1}", &coverage.done_count.to_string())
+        .replace("{2}", &coverage.todo_count.to_string())
+        .replace("{3}", &coverage.date))
+}
+
+/// Expected request_uri: e.g. /osm/relation-groups/budapest/view-result.
+fn handle_relation_group(
+    ctx: &context::Context,
+    relations: &mut areas::Relations<'_>,
+    request_uri: &str,
+) -> anyhow::Result<yattag::Doc> {
+    let mut tokens = request_uri.split('/');
+    tokens.next_back();
+    let group_name = tokens.next_back().context("no group_name")?;
+
+    let doc = yattag::Doc::new();
+    doc.append_value(
+        webframe::get_toolbar(
+            ctx,
+            Some(relations),
+            /*function=*/ "",
+            /*relation_name=*/ "",
+            /*relation_osmid=*/ 0,
+        )?
+        .get_value(),
+    );
+
+    let group_relations = relations.get_group_relations(group_name)?;
+    if group_relations.is_empty() {
+        return Err(anyhow::anyhow!("unknown relation group: {group_name}"));
+    }
+    {
+        let p = doc.tag("p", &[]);
+        p.text(&tr("House number coverage: {0}").replace(
+            "{0}",
+            &get_group_coverage_text(&group_relations, "housenumbers")?,
+        ));
+        p.stag("br", &[]);
+        p.text(&tr("Street coverage: {0}").replace(
+            "{0}",
+            &get_group_coverage_text(&group_relations, "streets")?,
+        ));
+    }
+
+    let mut table = vec![vec![
+        yattag::Doc::from_text(&tr("Area")),
+        yattag::Doc::from_text(&tr("House number coverage")),
+        yattag::Doc::from_text(&tr("Street coverage")),
+    ]];
+    for relation in &group_relations {
+        let streets = relation.get_config().should_check_missing_streets();
+        let mut row = vec![yattag::Doc::from_text(&relation.get_name())];
+        if streets != "only" {
+            row.push(handle_main_housenr_percent(ctx, relation)?.0);
+        } else {
+            row.push(yattag::Doc::new());
+        }
+        if streets != "no" {
+            row.push(handle_main_street_percent(ctx, relation)?.0);
+        } else {
+            row.push(yattag::Doc::new());
+        }
+        table.push(row);
+    }
+    doc.append_value(util::html_table_from_list(&table).get_value());
+
+    doc.append_value(webframe::get_footer(/*last_updated=*/ "").get_value());
+    Ok(doc)
+}
+
+/// Turns coverage snapshots into a HTML table.
+fn coverage_snapshots_to_table(
+    snapshots: &[areas::CoverageSnapshot],
+) -> anyhow::Result<yattag::HtmlTable> {
+    let mut table = vec![vec![
+        yattag::Doc::from_text(&tr("Date")),
+        yattag::Doc::from_text(&tr("Missing")),
+        yattag::Doc::from_text(&tr("Existing")),
+        yattag::Doc::from_text(&tr("Coverage")),
+    ]];
+    for snapshot in snapshots {
+        let percent: f64 = snapshot.percent.parse()?;
+        table.push(vec![
+            yattag::Doc::from_text(&snapshot.date),
+            yattag::Doc::from_text(&snapshot.todo_count.to_string()),
+            yattag::Doc::from_text(&snapshot.done_count.to_string()),
+            yattag::Doc::from_text(&util::format_percent(percent)?),
+        ]);
+    }
+    Ok(table)
+}
+
+/// Expected request_uri: e.g. /osm/coverage-history/ujbuda/view-result.
+fn handle_coverage_history(
+    ctx: &context::Context,
+    relations: &mut areas::Relations<'_>,
+    request_uri: &str,
+) -> anyhow::Result<yattag::Doc> {
+    let mut tokens = request_uri.split('/');
+    tokens.next_back();
+    let relation_name = tokens.next_back().context("no relation_name")?;
+
+    let relation = relations.get_relation(relation_name)?;
+    let osmrelation = relation.get_config().get_osmrelation();
+
+    let doc = yattag::Doc::new();
+    doc.append_value(
+        webframe::get_toolbar(
+            ctx,
+            Some(relations),
+            "coverage-history",
+            relation_name,
+            osmrelation,
+        )?
+        .get_value(),
+    );
+
+    let prefix = ctx.get_ini().get_uri_prefix();
+    {
+        let p = doc.tag("p", &[]);
+        let a = p.tag(
+            "a",
+            &[(
+                "href",
+                &format!("{prefix}/coverage-history/{relation_name}/view-result.json"),
+            )],
+        );
+        a.text(&tr("JSON format"));
+    }
+
+    let string_pairs = &[
+        (
+            "str-coverage-history-housenumbers",
+            tr("House number coverage"),
+        ),
+        ("str-coverage-history-streets", tr("Street coverage")),
+        ("str-coverage-history-x-axis", tr("At the end of this day")),
+        ("str-coverage-history-y-axis", tr("Coverage (%)")),
+    ];
+    webframe::emit_l10n_strings_for_js(&doc, string_pairs);
+    {
+        let div = doc.tag("div", &[("class", "canvasblock js")]);
+        let canvas = div.tag(
+            "canvas",
+            &[
+                ("id", "coverage-history"),
+                (
+                    "data-url",
+                    &format!("{prefix}/coverage-history/{relation_name}/view-result.json"),
+                ),
+            ],
+        );
+        drop(canvas);
+    }
+
+    let history = relation.get_coverage_history()?;
+    {
+        let h2 = doc.tag("h2", &[]);
+        h2.text(&tr("House number coverage"));
+    }
+    doc.append_value(
+        util::html_table_from_list(&coverage_snapshots_to_table(&history.housenumbers)?)
+            .get_value(),
+    );
+    {
+        let h2 = doc.tag("h2", &[]);
+        h2.text(&tr("Street coverage"));
+    }
+    doc.append_value(
+        util::html_table_from_list(&coverage_snapshots_to_table(&history.streets)?).get_value(),
+    );
+
+    doc.append_value(webframe::get_footer(/*last_updated=*/ "").get_value());
+    Ok(doc)
+}
+
 /// Expected request_uri: e.g. /osm/additional-streets/ujbuda/view-[result|query].
 fn handle_additional_streets(
     ctx: &context::Context,
@@ -1076,7 +1708,7 @@ pub fn handle_main_housenr_additional_count(
 }
 
 /// Does not filter out anything.
-fn filter_for_everything(_complete: bool, _relation: &areas::Relation<'_>) -> bool {
+pub fn filter_for_everything(_complete: bool, _relation: &areas::Relation<'_>) -> bool {
     true
 }
 
@@ -1113,6 +1745,15 @@ fn create_filter_for_relations(relation_filter: &str) -> Box<RelationFilter> {
     })
 }
 
+/// Creates a function that filters for the relations of a group.
+fn create_filter_for_parent(parent_filter: &str) -> Box<RelationFilter> {
+    let parent_filter_arc = Arc::new(parent_filter.to_string());
+    let parent_filter = parent_filter_arc;
+    Box::new(move |_complete, relation| {
+        relation.get_config().get_parent().as_deref() == Some(parent_filter.as_str())
+    })
+}
+
 /// Creates a function that filters for a single refsettlement in a refcounty.
 fn create_filter_for_refcounty_refsettlement(
     refcounty_filter: &str,
@@ -1130,7 +1771,7 @@ fn create_filter_for_refcounty_refsettlement(
 }
 
 /// Sets up a filter-for function from request uri: only certain areas are shown then.
-fn setup_main_filter_for(request_uri: &str) -> anyhow::Result<(Box<RelationFilter>, String)> {
+pub fn setup_main_filter_for(request_uri: &str) -> anyhow::Result<(Box<RelationFilter>, String)> {
     let tokens: Vec<String> = request_uri.split('/').map(|i| i.to_string()).collect();
     let mut filter_for: Box<RelationFilter> = Box::new(filter_for_incomplete);
     let filters = util::parse_filters(&tokens);
@@ -1152,6 +1793,10 @@ fn setup_main_filter_for(request_uri: &str) -> anyhow::Result<(Box<RelationFilte
         // /osm/filter-for/refcounty/<value>/whole-county
         refcounty = filters.get("refcounty").context("no refcounty")?;
         filter_for = create_filter_for_refcounty(refcounty);
+    } else if filters.contains_key("parent") {
+        // /osm/filter-for/parent/<value>
+        let parent = filters.get("parent").context("no parent")?;
+        filter_for = create_filter_for_parent(parent);
     } else if filters.contains_key("relations") {
         // /osm/filter-for/relations/<id1>,<id2>
         let relations = filters.get("relations").context("no relations")?;
@@ -1216,6 +1861,42 @@ fn handle_main_filters_refcounty(
     Ok(doc)
 }
 
+/// Handles one relation group in the filter part of the main wsgi page.
+fn handle_main_filters_group(
+    ctx: &context::Context,
+    group_name: &str,
+    group_relations: &[areas::Relation<'_>],
+) -> anyhow::Result<yattag::Doc> {
+    let doc = yattag::Doc::new();
+    let prefix = ctx.get_ini().get_uri_prefix();
+    {
+        let a = doc.tag(
+            "a",
+            &[("href", &format!("{prefix}/filter-for/parent/{group_name}"))],
+        );
+        a.text(group_name);
+    }
+    doc.text(" (");
+    {
+        let a = doc.tag(
+            "a",
+            &[(
+                "href",
+                &format!("{prefix}/relation-groups/{group_name}/view-result"),
+            )],
+        );
+        match areas::get_group_coverage(group_relations, "housenumbers")? {
+            Some(coverage) => {
+                let percent: f64 = coverage.percent.parse()?;
+                a.text(&util::format_percent(percent)?);
+            }
+            None => a.text(&tr("coverage")),
+        }
+    }
+    doc.text(")");
+    Ok(doc)
+}
+
 /// Handlers the filter part of the main wsgi page.
 fn handle_main_filters(
     ctx: &context::Context,
@@ -1256,6 +1937,13 @@ fn handle_main_filters(
             &refcounty,
         )?);
     }
+    for (group_name, group_relations) in relations.get_groups()? {
+        items.push(handle_main_filters_group(
+            ctx,
+            &group_name,
+            &group_relations,
+        )?);
+    }
     doc = yattag::Doc::new();
     {
         let h1 = doc.tag("h1", &[]);
@@ -1306,6 +1994,19 @@ fn handle_main_relation(
             .context("handle_main_housenr_percent() failed")?;
         let doc = yattag::Doc::new();
         doc.append_value(cell.get_value());
+        let resurvey_count = relation.get_resurvey_lints()?.len();
+        if resurvey_count > 0 {
+            doc.stag("br");
+            let prefix = ctx.get_ini().get_uri_prefix();
+            let a = doc.tag(
+                "a",
+                &[(
+                    "href",
+                    &format!("{prefix}/missing-housenumbers/{relation_name}/view-lints"),
+                )],
+            );
+            a.text(&tr("{0} to resurvey").replace("{0}", &resurvey_count.to_string()));
+        }
         row.push(doc);
         complete &= percent >= 100_f64;
 
@@ -1424,6 +2125,10 @@ fn get_html_title(request_uri: &str) -> String {
         "missing-streets" => format!(" - {} {}", relation_name, tr("missing streets")),
         "street-housenumbers" => format!(" - {} {}", relation_name, tr("existing house numbers")),
         "streets" => format!(" - {} {}", relation_name, tr("existing streets")),
+        "suggested-refstreets" => format!(" - {} {}", relation_name, tr("suggested refstreets")),
+        "coverage-history" => format!(" - {} {}", relation_name, tr("coverage history")),
+        "jobs" => format!(" - {}", tr("update status")),
+        "relation-groups" => format!(" - {} {}", relation_name, tr("relation group")),
         _ => "".into(),
     }
 }
@@ -1467,7 +2172,7 @@ fn write_html_head(ctx: &context::Context, doc: &yattag::Tag, title: &str) -> an
     if ctx.get_file_system().path_exists(&css_path) {
         let stream = ctx.get_file_system().open_read(&css_path)?;
         let mut buf: Vec<u8> = Vec::new();
-        let mut guard = stream.borrow_mut();
+        let mut guard = stream.lock().unwrap();
         guard.read_to_end(&mut buf)?;
         let contents = String::from_utf8(buf)?;
         let style = head.tag("style", &[]);
@@ -1500,10 +2205,16 @@ fn our_application_gpx(
 ) -> anyhow::Result<rouille::Response> {
     let content_type = "text/gpx+xml; charset=utf-8";
     let mut headers: webframe::Headers = Vec::new();
-    // assume prefix + "/additional-streets/"
+    let prefix = ctx.get_ini().get_uri_prefix();
     let (output, relation_name) =
-        wsgi_additional::additional_streets_view_gpx(ctx, relations, request_uri)
-            .context("additional_streets_view_gpx() failed")?;
+        if request_uri.starts_with(&format!("{prefix}/missing-housenumbers/")) {
+            missing_housenumbers_view_gpx(ctx, relations, request_uri)
+                .context("missing_housenumbers_view_gpx() failed")?
+        } else {
+            // assume prefix + "/additional-streets/"
+            wsgi_additional::additional_streets_view_gpx(ctx, relations, request_uri)
+                .context("additional_streets_view_gpx() failed")?
+        };
     headers.push((
         "Content-Disposition".into(),
         format!(r#"attachment;filename="{relation_name}.gpx""#).into(),
@@ -1513,6 +2224,57 @@ fn our_application_gpx(
     Ok(webframe::make_response(200_u16, headers, data))
 }
 
+/// Dispatches GeoJSON requests based on their URIs.
+fn our_application_geojson(
+    ctx: &context::Context,
+    relations: &mut areas::Relations<'_>,
+    request_uri: &str,
+) -> anyhow::Result<rouille::Response> {
+    let content_type = "application/geo+json; charset=utf-8";
+    let mut headers: webframe::Headers = Vec::new();
+    let prefix = ctx.get_ini().get_uri_prefix();
+    let (output, relation_name) = if request_uri.starts_with(&format!("{prefix}/missing-streets/"))
+    {
+        missing_streets_view_geojson(relations, request_uri)
+            .context("missing_streets_view_geojson() failed")?
+    } else if request_uri.starts_with(&format!("{prefix}/additional-streets/")) {
+        wsgi_additional::additional_streets_view_geojson(ctx, relations, request_uri)
+            .context("additional_streets_view_geojson() failed")?
+    } else {
+        // assume prefix + "/missing-housenumbers/"
+        missing_housenumbers_view_geojson(ctx, relations, request_uri)
+            .context("missing_housenumbers_view_geojson() failed")?
+    };
+    headers.push((
+        "Content-Disposition".into(),
+        format!(r#"attachment;filename="{relation_name}.geojson""#).into(),
+    ));
+    let data = output.as_bytes().to_vec();
+    headers.push(("Content-type".into(), content_type.into()));
+    Ok(webframe::make_response(200_u16, headers, data))
+}
+
+/// Dispatches patch requests based on their URIs.
+fn our_application_patch(
+    ctx: &context::Context,
+    relations: &mut areas::Relations<'_>,
+    request_uri: &str,
+) -> anyhow::Result<rouille::Response> {
+    let content_type = "text/x-diff; charset=utf-8";
+    let mut headers: webframe::Headers = Vec::new();
+    // Assume prefix + "/missing-housenumbers/", ending with view-lints.patch.
+    let (output, relation_name) =
+        missing_housenumbers_view_lints_patch(ctx, relations, request_uri)
+            .context("missing_housenumbers_view_lints_patch() failed")?;
+    headers.push((
+        "Content-Disposition".into(),
+        format!(r#"attachment;filename="relation-{relation_name}.yaml.patch""#).into(),
+    ));
+    let data = output.as_bytes().to_vec();
+    headers.push(("Content-type".into(), content_type.into()));
+    Ok(webframe::make_response(200_u16, headers, data))
+}
+
 /// Dispatches plain text requests based on their URIs.
 fn our_application_txt(
     ctx: &context::Context,
@@ -1585,6 +2347,10 @@ lazy_static! {
         ret.insert("/streets/".into(), handle_streets);
         ret.insert("/missing-streets/".into(), handle_missing_streets);
         ret.insert("/additional-streets/".into(), handle_additional_streets);
+        ret.insert("/suggested-refstreets/".into(), handle_suggested_refstreets);
+        ret.insert("/coverage-history/".into(), handle_coverage_history);
+        ret.insert("/relation-groups/".into(), handle_relation_group);
+        ret.insert("/jobs/".into(), handle_job);
         ret.insert(
             "/additional-housenumbers/".into(),
             handle_additional_housenumbers,
@@ -1608,6 +2374,56 @@ fn get_handler(ctx: &context::Context, request_uri: &str) -> anyhow::Result<Opti
     Ok(None)
 }
 
+/// Gets the name of the handler of request_uri for the metrics: a key of HANDLERS or 'other'.
+fn get_handler_name(ctx: &context::Context, request_uri: &str) -> String {
+    let prefix = ctx.get_ini().get_uri_prefix();
+    for key in HANDLERS.keys() {
+        if request_uri.starts_with(&format!("{prefix}{key}")) {
+            return key.clone();
+        }
+    }
+    "other".into()
+}
+
+/// Creates the response for a refused or throttled Overpass-triggering request.
+fn make_rate_limit_response(
+    ctx: &context::Context,
+    language: &str,
+    request_uri: &str,
+    retry_after: Option<u64>,
+) -> anyhow::Result<rouille::Response> {
+    let doc = yattag::Doc::new();
+    util::write_html_header(&doc);
+    {
+        let html = doc.tag("html", &[("lang", language)]);
+        write_html_head(ctx, &html, &get_html_title(request_uri))?;
+        let body = html.tag("body", &[]);
+        let div = body.tag("div", &[("id", "rate-limited")]);
+        match retry_after {
+            Some(seconds) => div.text(
+                &tr("Too many update requests, try again in {0} seconds.")
+                    .replace("{0}", &seconds.to_string()),
+            ),
+            None => div.text(&tr("Automated clients are not allowed to trigger updates.")),
+        }
+    }
+
+    let mut headers: webframe::Headers =
+        vec![("Content-type".into(), "text/html; charset=utf-8".into())];
+    let status = match retry_after {
+        Some(seconds) => {
+            headers.push(("Retry-After".into(), seconds.to_string().into()));
+            429_u16
+        }
+        None => 403_u16,
+    };
+    Ok(webframe::make_response(
+        status,
+        headers,
+        doc.get_value().as_bytes().to_vec(),
+    ))
+}
+
 /// Dispatches the request based on its URI.
 fn our_application(
     request: &rouille::Request,
@@ -1625,6 +2441,41 @@ fn our_application(
         ext = (*last).into();
     }
 
+    let prefix = ctx.get_ini().get_uri_prefix();
+    if request_uri == "/metrics" || request_uri == format!("{prefix}/metrics") {
+        let output = metrics::render(ctx, &mut relations).context("metrics::render() failed")?;
+        return Ok(webframe::make_response(
+            200_u16,
+            vec![(
+                "Content-type".into(),
+                "text/plain; version=0.0.4; charset=utf-8".into(),
+            )],
+            output.as_bytes().to_vec(),
+        ));
+    }
+
+    if rate_limit::is_overpass_route(ctx, &request_uri) {
+        let client = rate_limit::get_client(ctx, request)?;
+        let user_agent = request.header("User-Agent").unwrap_or_default();
+        match rate_limit::check(ctx, &client, user_agent)? {
+            rate_limit::Verdict::Allowed => (),
+            rate_limit::Verdict::Throttled(retry_after) => {
+                return make_rate_limit_response(ctx, &language, &request_uri, Some(retry_after));
+            }
+            rate_limit::Verdict::Refused => {
+                return make_rate_limit_response(ctx, &language, &request_uri, None);
+            }
+        }
+    }
+
+    if request_uri.starts_with(&format!("{}/api/v1/", ctx.get_ini().get_uri_prefix())) {
+        return Ok(wsgi_api::our_application_api(
+            ctx,
+            &mut relations,
+            &request_uri,
+        ));
+    }
+
     if ext == "txt" || ext == "chkl" {
         return our_application_txt(ctx, &mut relations, &request_uri);
     }
@@ -1634,7 +2485,16 @@ fn our_application(
             .context("our_application_gpx() failed");
     }
 
-    let prefix = ctx.get_ini().get_uri_prefix();
+    if ext == "geojson" {
+        return our_application_geojson(ctx, &mut relations, &request_uri)
+            .context("our_application_geojson() failed");
+    }
+
+    if ext == "patch" {
+        return our_application_patch(ctx, &mut relations, &request_uri)
+            .context("our_application_patch() failed");
+    }
+
     if !(request_uri == "/" || request_uri.starts_with(&prefix)) {
         let doc = webframe::handle_404();
         return Ok(webframe::make_response(
@@ -1657,6 +2517,20 @@ fn our_application(
         return wsgi_json::our_application_json(ctx, &mut relations, &request_uri);
     }
 
+    // Unknown relations would get a default config: don't queue an Overpass query for them.
+    if request_uri.ends_with("/update-result")
+        && !webframe::check_existing_relation(ctx, &relations, &request_uri)?
+            .get_value()
+            .is_empty()
+    {
+        let doc = webframe::handle_404();
+        return Ok(webframe::make_response(
+            404_u16,
+            vec![("Content-type".into(), "text/html; charset=utf-8".into())],
+            doc.get_value().as_bytes().to_vec(),
+        ));
+    }
+
     let doc = yattag::Doc::new();
     util::write_html_header(&doc);
     {
@@ -1693,11 +2567,22 @@ fn our_application(
 
 /// The entry point of this WSGI app.
 pub fn application(request: &rouille::Request, ctx: &context::Context) -> rouille::Response {
-    match our_application(request, ctx).context("our_application() failed") {
+    let start = ctx.get_time().now();
+    let response = match our_application(request, ctx).context("our_application() failed") {
         // Compress.
         Ok(value) => rouille::content_encoding::apply(request, value),
-        Err(err) => webframe::handle_error(request, &format!("{err:?}")),
-    }
+        Err(err) => {
+            if request.url().contains("/api/v1/") {
+                wsgi_api::handle_error(&request.url(), &err)
+            } else {
+                webframe::handle_error(request, &format!("{err:?}"))
+            }
+        }
+    };
+    let handler = get_handler_name(ctx, &request.url());
+    ctx.get_metrics()
+        .record_request(&handler, ctx.get_time().now() - start);
+    response
 }
 
 #[cfg(test)]
@@ -11,11 +11,11 @@
 //! Tests for the wsgi module.
 
 use super::*;
-use std::cell::RefCell;
 use std::io::Read;
 use std::io::Write;
 use std::ops::DerefMut;
-use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::Mutex;
 
 /// Shared struct for wsgi tests.
 pub struct TestWsgi {
@@ -129,6 +129,8 @@ impl TestWsgi {
         }
         if path.ends_with(".chkl") {
             assert_eq!(headers_map["Content-type"], "application/octet-stream");
+        } else if path.ends_with(".patch") {
+            assert_eq!(headers_map["Content-type"], "text/x-diff; charset=utf-8");
         } else {
             assert_eq!(headers_map["Content-type"], "text/plain; charset=utf-8");
         }
@@ -151,10 +153,17 @@ impl TestWsgi {
         // Make sure the built-in exception catcher is not kicking in.
         assert_eq!(response.status_code, 200);
         let headers_map: HashMap<_, _> = response.headers.into_iter().collect();
-        assert_eq!(
-            headers_map["Content-type"],
-            "application/json; charset=utf-8"
-        );
+        if path.ends_with(".geojson") {
+            assert_eq!(
+                headers_map["Content-type"],
+                "application/geo+json; charset=utf-8"
+            );
+        } else {
+            assert_eq!(
+                headers_map["Content-type"],
+                "application/json; charset=utf-8"
+            );
+        }
         let value: serde_json::Value = serde_json::from_str(&output).unwrap();
         value
     }
@@ -241,7 +250,8 @@ fn test_handle_streets_view_query_well_formed() {
     let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
     let template_value = context::tests::TestFileSystem::make_file();
     template_value
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(b"aaa @RELATION@ bbb @AREA@ ccc\n")
         .unwrap();
     let files = context::tests::TestFileSystem::make_files(
@@ -270,7 +280,7 @@ fn test_handle_streets_update_result_well_formed() {
         /*result_path=*/ "src/fixtures/network/overpass-streets-gazdagret.json",
     )];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     test_wsgi.ctx.set_network(network_rc);
     let yamls_cache = serde_json::json!({
         "relations.yaml": {
@@ -282,7 +292,8 @@ fn test_handle_streets_update_result_well_formed() {
     let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
     let template_value = context::tests::TestFileSystem::make_file();
     template_value
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(b"aaa @RELATION@ bbb @AREA@ ccc\n")
         .unwrap();
     let files = context::tests::TestFileSystem::make_files(
@@ -296,6 +307,7 @@ fn test_handle_streets_update_result_well_formed() {
     test_wsgi.ctx.set_file_system(&file_system);
 
     let root = test_wsgi.get_dom_for_path("/streets/gazdagret/update-result");
+    jobs::run_queued(&test_wsgi.ctx).unwrap();
 
     let mut relations = areas::Relations::new(&test_wsgi.ctx).unwrap();
     assert_eq!(
@@ -308,10 +320,156 @@ fn test_handle_streets_update_result_well_formed() {
             .len(),
         4
     );
+    let results = TestWsgi::find_all(&root, "body/a[@href='/osm/jobs/1/view-result']");
+    assert_eq!(results.len(), 1);
+    let root = test_wsgi.get_dom_for_path("/jobs/1/view-result");
+    let results = TestWsgi::find_all(
+        &root,
+        "body/a[@href='/osm/missing-housenumbers/gazdagret/view-result']",
+    );
+    assert_eq!(results.len(), 1);
+}
+
+/// Tests handle_streets(): if updating twice while the first update is queued creates a single
+/// job.
+#[test]
+fn test_handle_streets_update_result_coalesce() {
+    let mut test_wsgi = TestWsgi::new();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "osmrelation": 42,
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        &test_wsgi.ctx,
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    test_wsgi.ctx.set_file_system(&file_system);
+
+    test_wsgi.get_dom_for_path("/streets/gazdagret/update-result");
+    let root = test_wsgi.get_dom_for_path("/streets/gazdagret/update-result");
+
+    let results = TestWsgi::find_all(&root, "body/a[@href='/osm/jobs/1/view-result']");
+    assert_eq!(results.len(), 1);
+    let root = test_wsgi.get_dom_for_path("/jobs/1/view-result");
     let results = TestWsgi::find_all(&root, "body");
+    assert_eq!(
+        results[0].contains("Update queued, reload this page to see its progress."),
+        true
+    );
+}
+
+/// Tests handle_streets() and handle_street_housenumbers(): an unknown relation is not enqueued.
+#[test]
+fn test_update_result_no_such_relation() {
+    let mut test_wsgi = TestWsgi::new();
+    test_wsgi.expected_status = 404;
+
+    let root = test_wsgi.get_dom_for_path("/streets/nosuchrelation/update-result");
+    let results = TestWsgi::find_all(&root, "body/h1");
+    assert_eq!(results.is_empty(), false);
+    test_wsgi.get_dom_for_path("/street-housenumbers/nosuchrelation/update-result");
+
+    let conn = test_wsgi.get_ctx().get_database_connection().unwrap();
+    let count: i64 = conn
+        .query_row("select count(*) from jobs", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
+/// Tests our_application(): if Overpass-triggering requests are throttled.
+#[test]
+fn test_update_result_rate_limited() {
+    let mut test_wsgi = TestWsgi::new();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "osmrelation": 42,
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        &test_wsgi.ctx,
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    test_wsgi.ctx.set_file_system(&file_system);
+    context::tests::set_ini(&mut test_wsgi.ctx, "rate_limit_client_burst = '1'\n");
+    test_wsgi.get_dom_for_path("/streets/gazdagret/update-result");
+
+    test_wsgi.expected_status = 429_u16;
+    let root = test_wsgi.get_dom_for_path("/streets/gazdagret/update-result");
+
+    let results = TestWsgi::find_all(&root, "body/div[@id='rate-limited']");
+    assert_eq!(
+        results,
+        vec!["Too many update requests, try again in 30 seconds.".to_string()]
+    );
+    let request = rouille::Request::fake_http(
+        "GET",
+        "/osm/streets/gazdagret/update-result",
+        vec![],
+        vec![],
+    );
+    let response = application(&request, &test_wsgi.ctx);
+    assert_eq!(response.status_code, 429);
+    let headers: HashMap<_, _> = response.headers.into_iter().collect();
+    assert_eq!(headers["Retry-After"], "30");
+}
+
+/// Tests our_application(): if Overpass-triggering requests from search bots are refused.
+#[test]
+fn test_update_result_search_bot() {
+    let mut test_wsgi = TestWsgi::new();
+    test_wsgi.headers.push((
+        "User-Agent".into(),
+        "Mozilla/5.0 (compatible; bingbot/2.0)".into(),
+    ));
+    test_wsgi.expected_status = 403_u16;
+
+    let root = test_wsgi.get_dom_for_path("/streets/gazdagret/update-result");
+
+    let results = TestWsgi::find_all(&root, "body/div[@id='rate-limited']");
     assert_eq!(results.len(), 1);
 }
 
+/// Tests our_application(): the metrics endpoint counts the served requests.
+#[test]
+fn test_metrics() {
+    let mut test_wsgi = TestWsgi::new();
+    test_wsgi.get_dom_for_path("/streets/gazdagret/view-result");
+
+    let request = rouille::Request::fake_http("GET", "/osm/metrics", vec![], vec![]);
+    let response = application(&request, &test_wsgi.ctx);
+
+    assert_eq!(response.status_code, 200);
+    let mut data = Vec::new();
+    let (mut reader, _size) = response.data.into_reader_and_size();
+    reader.read_to_end(&mut data).unwrap();
+    let output = String::from_utf8(data).unwrap();
+    assert_eq!(
+        output.contains(r#"osm_gimmisn_http_requests_total{handler="/streets/"} 1"#),
+        true
+    );
+    assert_eq!(output.contains("osm_gimmisn_active_relations "), true);
+}
+
+/// Tests handle_job(): if the output is well-formed for an unknown job.
+#[test]
+fn test_handle_job_no_such_job() {
+    let mut test_wsgi = TestWsgi::new();
+
+    let root = test_wsgi.get_dom_for_path("/jobs/42/view-result");
+
+    let results = TestWsgi::find_all(&root, "body/div[@id='no-such-job-error']");
+    assert_eq!(results, vec!["No such job: 42".to_string()]);
+}
+
 /// Tests handle_streets(): if the update-result output on error is well-formed.
 #[test]
 fn test_handle_streets_update_result_error_well_formed() {
@@ -322,7 +480,7 @@ fn test_handle_streets_update_result_error_well_formed() {
         /*result_path=*/ "", // no result -> error
     )];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     test_wsgi.ctx.set_network(network_rc);
     let yamls_cache = serde_json::json!({
         "relations.yaml": {
@@ -334,7 +492,8 @@ fn test_handle_streets_update_result_error_well_formed() {
     let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
     let template_value = context::tests::TestFileSystem::make_file();
     template_value
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(b"aaa @RELATION@ bbb @AREA@ ccc\n")
         .unwrap();
     let files = context::tests::TestFileSystem::make_files(
@@ -347,8 +506,10 @@ fn test_handle_streets_update_result_error_well_formed() {
     let file_system = context::tests::TestFileSystem::from_files(&files);
     test_wsgi.get_ctx().set_file_system(&file_system);
 
-    let root = test_wsgi.get_dom_for_path("/streets/gazdagret/update-result");
+    test_wsgi.get_dom_for_path("/streets/gazdagret/update-result");
+    jobs::run_queued(&test_wsgi.ctx).unwrap();
 
+    let root = test_wsgi.get_dom_for_path("/jobs/1/view-result");
     let results = TestWsgi::find_all(&root, "body/div[@id='overpass-error']");
     // Error during JSON query.
     assert_eq!(results.len(), 1);
@@ -365,7 +526,7 @@ fn test_handle_streets_update_result_missing_streets_well_formed() {
         /*result_path=*/ "src/fixtures/network/overpass-streets-ujbuda.json",
     )];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     test_wsgi.ctx.set_network(network_rc);
     let yamls_cache = serde_json::json!({
         "relations.yaml": {
@@ -380,7 +541,8 @@ fn test_handle_streets_update_result_missing_streets_well_formed() {
     let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
     let template_value = context::tests::TestFileSystem::make_file();
     template_value
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(b"aaa @RELATION@ bbb @AREA@ ccc\n")
         .unwrap();
     let files = context::tests::TestFileSystem::make_files(
@@ -394,6 +556,7 @@ fn test_handle_streets_update_result_missing_streets_well_formed() {
     test_wsgi.ctx.set_file_system(&file_system);
 
     let root = test_wsgi.get_dom_for_path("/streets/ujbuda/update-result");
+    jobs::run_queued(&test_wsgi.ctx).unwrap();
 
     let mut relations = areas::Relations::new(&test_wsgi.ctx).unwrap();
     assert_eq!(
@@ -434,7 +597,7 @@ fn test_per_relation_lints() {
                     ],
                 },
                 "Törökugrató utca": {
-                    "invalid": [ "1", "11", "12", "42" ],
+                    "invalid": [ "1", "11", { "number": "12", "resurvey-after": "2020-01-01" }, "42" ],
                 }
             },
         },
@@ -445,7 +608,7 @@ fn test_per_relation_lints() {
         &[("data/yamls.cache", &yamls_cache_value)],
     );
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     test_wsgi.ctx.set_file_system(&file_system_rc);
     let mtime = test_wsgi.get_ctx().get_time().now_string();
     {
@@ -536,6 +699,64 @@ fn test_per_relation_lints() {
         .len(),
         1
     );
+    // 12 has a resurvey date in the past
+    assert_eq!(
+        TestWsgi::find_all(&root, "body/table/tr/td/div[@data-value='resurvey-due']").len(),
+        1
+    );
+}
+
+/// Tests the per-relation lints page, the duplicate address case.
+#[test]
+fn test_per_relation_lints_duplicate() {
+    let mut test_wsgi = TestWsgi::new();
+    let mut file_system = context::tests::TestFileSystem::new();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "refcounty": "0",
+                "refsettlement": "0",
+                "osmrelation": 42,
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        &test_wsgi.ctx,
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    file_system.set_files(&files);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
+    test_wsgi.ctx.set_file_system(&file_system_rc);
+    {
+        let conn = test_wsgi.get_ctx().get_database_connection().unwrap();
+        conn.execute_batch(
+            "insert into ref_housenumbers (county_code, settlement_code, street, housenumber, comment) values ('0', '0', 'Tűzkő utca', '1', '');
+             insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values ('gazdagret', '1', 'Tűzkő utca', '1', '', '', '', '', '', '', '', '', '', 'way');
+             insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values ('gazdagret', '2', 'Tűzkő utca', '1', '', '', '', '', '', '', '', '', '', 'node');
+             insert into mtimes (page, last_modified) values ('housenumbers/gazdagret', '0');",
+        )
+        .unwrap();
+    }
+
+    let root = test_wsgi.get_dom_for_path("/missing-housenumbers/gazdagret/view-lints");
+
+    assert_eq!(
+        TestWsgi::find_all(
+            &root,
+            "body/table/tr/td/div[@data-value='duplicate-in-osm']"
+        )
+        .len(),
+        1
+    );
+    // Both objects are linked.
+    let results = TestWsgi::find_all(&root, "body/table/tr/td/a");
+    assert_eq!(results.len(), 2);
+    let results = TestWsgi::find_all(
+        &root,
+        "body/table/tr/td/a[@href='https://www.openstreetmap.org/way/1']",
+    );
+    assert_eq!(results.len(), 1);
 }
 
 /// Tests the per-relation lints page, the out-of-range case.
@@ -571,7 +792,7 @@ fn test_per_relation_lints_out_of_range() {
         &[("data/yamls.cache", &yamls_cache_value)],
     );
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     test_wsgi.ctx.set_file_system(&file_system_rc);
     let mtime = test_wsgi.get_ctx().get_time().now_string();
     {
@@ -604,6 +825,74 @@ fn test_per_relation_lints_out_of_range() {
     );
 }
 
+/// Tests the per-relation lints page: the patch output.
+#[test]
+fn test_per_relation_lints_patch() {
+    let mut test_wsgi = TestWsgi::new();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gh3073": {
+                "refcounty": "0",
+                "refsettlement": "0",
+                "osmrelation": 42,
+            },
+        },
+        "relation-gh3073.yaml": {
+            "filters": {
+                "Hadak útja": {
+                    "invalid": [ "3" ],
+                    "ranges": [
+                        {
+                            "start": "5",
+                            "end": "7",
+                        }
+                    ],
+                },
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let relation_yaml = context::tests::TestFileSystem::make_file();
+    relation_yaml
+        .lock().unwrap()
+        .write_all(
+            "filters:\n  'Hadak útja':\n    invalid: ['3']\n    ranges:\n      - {start: '5', end: '7'}\n"
+                .as_bytes(),
+        )
+        .unwrap();
+    let files = context::tests::TestFileSystem::make_files(
+        &test_wsgi.ctx,
+        &[
+            ("data/yamls.cache", &yamls_cache_value),
+            ("data/relation-gh3073.yaml", &relation_yaml),
+        ],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    test_wsgi.ctx.set_file_system(&file_system);
+    let mtime = test_wsgi.get_ctx().get_time().now_string();
+    {
+        let conn = test_wsgi.get_ctx().get_database_connection().unwrap();
+        conn.execute_batch(
+            "insert into ref_housenumbers (county_code, settlement_code, street, housenumber, comment) values ('0', '0', 'Hadak útja', '3', '');
+             insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values ('gh3073', '1', 'Hadak útja', '5', '', '', '', '', '', '', '', '', '', 'node');",
+        )
+        .unwrap();
+        conn.execute(
+            "insert into mtimes (page, last_modified) values (?1, ?2)",
+            ["housenumbers/gh3073", &mtime],
+        )
+        .unwrap();
+    }
+
+    let result = test_wsgi.get_txt_for_path("/missing-housenumbers/gh3073/view-lints.patch");
+
+    // 3 is out of range, so the invalid item is not needed.
+    assert_eq!(
+        result,
+        "--- a/data/relation-gh3073.yaml\n+++ b/data/relation-gh3073.yaml\n@@ -1,5 +1,4 @@\n filters:\n   'Hadak útja':\n-    invalid: ['3']\n     ranges:\n       - {start: '5', end: '7'}\n"
+    );
+}
+
 /// Tests the missing house numbers page: if the output is well-formed.
 #[test]
 fn test_missing_housenumbers_well_formed() {
@@ -629,7 +918,7 @@ fn test_missing_housenumbers_well_formed() {
         &[("data/yamls.cache", &yamls_cache_value)],
     );
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     test_wsgi.ctx.set_file_system(&file_system_rc);
     let mtime = test_wsgi.get_ctx().get_time().now_string();
     {
@@ -724,7 +1013,7 @@ fn test_missing_housenumbers_compat() {
         &[("data/yamls.cache", &yamls_cache_value)],
     );
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     test_wsgi.ctx.set_file_system(&file_system_rc);
     let mtime = test_wsgi.get_ctx().get_time().now_string();
     {
@@ -823,7 +1112,7 @@ fn test_missing_housenumbers_compat_relation() {
     );
     let mut file_system = context::tests::TestFileSystem::new();
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     test_wsgi.ctx.set_file_system(&file_system_rc);
     let mtime = test_wsgi.get_ctx().get_time().now_string();
     {
@@ -956,7 +1245,7 @@ fn test_missing_housenumbers_view_result_txt() {
         &[("data/yamls.cache", &yamls_cache_value)],
     );
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     test_wsgi.ctx.set_file_system(&file_system_rc);
     let mtime = test_wsgi.get_ctx().get_time().now_string();
     {
@@ -989,6 +1278,93 @@ fn test_missing_housenumbers_view_result_txt() {
     assert_eq!(result, "Vöröskúti határsor\t[2, 12]");
 }
 
+/// Creates a test wsgi for the geo outputs of missing house numbers: 2 and 12 are missing, only 12
+/// has coordinates.
+fn make_test_wsgi_geo() -> TestWsgi {
+    let mut test_wsgi = TestWsgi::new();
+    let mut file_system = context::tests::TestFileSystem::new();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "budafok": {
+                "refcounty": "0",
+                "refsettlement": "0",
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        &test_wsgi.ctx,
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    file_system.set_files(&files);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
+    test_wsgi.ctx.set_file_system(&file_system_rc);
+    let mtime = test_wsgi.get_ctx().get_time().now_string();
+    {
+        let conn = test_wsgi.ctx.get_database_connection().unwrap();
+        conn.execute_batch(
+            "insert into ref_housenumbers (county_code, settlement_code, street, housenumber, comment, lat, lon) values ('0', '0', 'Vöröskúti határsor', '12', '', '47.42', '19.02');
+             insert into ref_housenumbers (county_code, settlement_code, street, housenumber, comment, lat, lon) values ('0', '0', 'Vöröskúti határsor', '4-8', '', '47.43', '19.03');
+             insert into ref_housenumbers (county_code, settlement_code, street, housenumber, comment) values ('0', '0', 'Vöröskúti határsor', '2', '');",
+         )
+         .unwrap();
+        conn.execute(
+            r#"insert into osm_streets (relation, osm_id, name, highway, service, surface, leisure, osm_type) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"#,
+            ["budafok", "458338075", "Vöröskúti határsor", "", "", "", "", ""],
+        )
+        .unwrap();
+        conn.execute(
+            "insert into mtimes (page, last_modified) values (?1, ?2)",
+            ["streets/budafok", &mtime],
+        )
+        .unwrap();
+        conn.execute(
+            "insert into mtimes (page, last_modified) values (?1, ?2)",
+            ["housenumbers/budafok", &mtime],
+        )
+        .unwrap();
+    }
+    test_wsgi
+}
+
+/// Tests the missing house numbers page: the gpx output.
+#[test]
+fn test_missing_housenumbers_view_result_gpx() {
+    let mut test_wsgi = make_test_wsgi_geo();
+    test_wsgi.set_content_type("text/gpx+xml; charset=utf-8");
+
+    let root = test_wsgi.get_dom_for_path("/missing-housenumbers/budafok/view-result.gpx");
+
+    // The gpx elements are in a namespace, so can't use find_all().
+    let document = root.as_document();
+    let count = sxd_xpath::evaluate_xpath(&document, "count(//*[local-name()='wpt'])").unwrap();
+    // 2 has no coordinates, 4-8 is a range: no exact coordinates.
+    assert_eq!(count.number(), 1.0);
+    let name =
+        sxd_xpath::evaluate_xpath(&document, "//*[local-name()='wpt']/*[local-name()='name']")
+            .unwrap();
+    assert_eq!(name.string(), "Vöröskúti határsor 12");
+}
+
+/// Tests the missing house numbers page: the geojson output.
+#[test]
+fn test_missing_housenumbers_view_result_geojson() {
+    let mut test_wsgi = make_test_wsgi_geo();
+
+    let root = test_wsgi.get_json_for_path("/missing-housenumbers/budafok/view-result.geojson");
+
+    let features = root["features"].as_array().unwrap();
+    assert_eq!(features.len(), 1);
+    assert_eq!(
+        features[0]["geometry"]["coordinates"],
+        serde_json::json!([19.02, 47.42])
+    );
+    assert_eq!(
+        features[0]["properties"]["addr:housenumber"],
+        serde_json::json!("12")
+    );
+}
+
 /// Tests the missing house numbers page: the txt output (even-odd streets).
 #[test]
 fn test_missing_housenumbers_view_result_txt_even_odd() {
@@ -1110,7 +1486,7 @@ fn test_missing_housenumbers_view_result_txt_even_odd() {
         )
         .unwrap();
     }
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     test_wsgi.ctx.set_file_system(&file_system_rc);
 
     let result = test_wsgi.get_txt_for_path("/missing-housenumbers/gazdagret/view-result.txt");
@@ -1755,7 +2131,8 @@ fn test_housenumbers_view_query_well_formed() {
     let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
     let overpass_template = context::tests::TestFileSystem::make_file();
     overpass_template
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(b"housenr aaa @RELATION@ bbb @AREA@ ccc\n")
         .unwrap();
     let files = context::tests::TestFileSystem::make_files(
@@ -1787,7 +2164,7 @@ fn test_housenumbers_update_result_well_formed() {
         /*result_path=*/ "src/fixtures/network/overpass-housenumbers-gazdagret.json",
     )];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     test_wsgi.ctx.set_network(network_rc);
     let yamls_cache = serde_json::json!({
         "relations.yaml": {
@@ -1799,7 +2176,8 @@ fn test_housenumbers_update_result_well_formed() {
     let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
     let overpass_template = context::tests::TestFileSystem::make_file();
     overpass_template
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(b"housenr aaa @RELATION@ bbb @AREA@ ccc\n")
         .unwrap();
     let files = context::tests::TestFileSystem::make_files(
@@ -1845,6 +2223,7 @@ fn test_housenumbers_update_result_well_formed() {
     }
 
     let root = test_wsgi.get_dom_for_path("/street-housenumbers/gazdagret/update-result");
+    jobs::run_queued(&test_wsgi.ctx).unwrap();
 
     let results = TestWsgi::find_all(&root, "body");
     assert_eq!(results.len(), 1);
@@ -1878,7 +2257,7 @@ fn test_housenumbers_update_result_error_well_formed() {
         ),
     ];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     test_wsgi.ctx.set_network(network_rc);
     let yamls_cache = serde_json::json!({
         "relations.yaml": {
@@ -1890,7 +2269,8 @@ fn test_housenumbers_update_result_error_well_formed() {
     let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
     let overpass_template = context::tests::TestFileSystem::make_file();
     overpass_template
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(b"housenr aaa @RELATION@ bbb @AREA@ ccc\n")
         .unwrap();
     let files = context::tests::TestFileSystem::make_files(
@@ -1906,8 +2286,10 @@ fn test_housenumbers_update_result_error_well_formed() {
     let file_system = context::tests::TestFileSystem::from_files(&files);
     test_wsgi.ctx.set_file_system(&file_system);
 
-    let root = test_wsgi.get_dom_for_path("/street-housenumbers/gazdagret/update-result");
+    test_wsgi.get_dom_for_path("/street-housenumbers/gazdagret/update-result");
+    jobs::run_queued(&test_wsgi.ctx).unwrap();
 
+    let root = test_wsgi.get_dom_for_path("/jobs/1/view-result");
     let results = TestWsgi::find_all(&root, "body/div[@id='overpass-error']");
     assert_eq!(results.len(), 1);
 }
@@ -2093,6 +2475,121 @@ fn test_missing_streets_no_osm_streets_well_formed() {
     assert_eq!(results.len(), 1);
 }
 
+/// Tests the suggested refstreets page: if the output is well-formed.
+#[test]
+fn test_suggested_refstreets_well_formed() {
+    let mut test_wsgi = TestWsgi::new();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "refcounty": "01",
+                "refsettlement": "011",
+                "osmrelation": 42,
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        &test_wsgi.ctx,
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    test_wsgi.ctx.set_file_system(&file_system);
+    {
+        let conn = test_wsgi.ctx.get_database_connection().unwrap();
+        conn.execute_batch(
+            "delete from ref_streets;
+            insert into ref_streets (county_code, settlement_code, street) values ('01', '011', 'Tűzkő utca');
+            insert into ref_streets (county_code, settlement_code, street) values ('01', '011', 'Kossuth Lajos utca');
+            insert into osm_streets (relation, osm_id, name, highway, service, surface, leisure, osm_type) values ('gazdagret', '1', 'Tűzkő utca', '', '', '', '', '');
+            insert into osm_streets (relation, osm_id, name, highway, service, surface, leisure, osm_type) values ('gazdagret', '2', 'Kossuth L. u.', '', '', '', '', '');
+            insert into mtimes (page, last_modified) values ('streets/gazdagret', '0');",
+        )
+        .unwrap();
+    }
+
+    let root = test_wsgi.get_dom_for_path("/suggested-refstreets/gazdagret/view-result");
+
+    let results = TestWsgi::find_all(&root, "body/table/tr/td[1]");
+    assert_eq!(results, vec!["Kossuth L. u."]);
+    let results = TestWsgi::find_all(&root, "body/table/tr/td[2]");
+    assert_eq!(results, vec!["Kossuth Lajos utca"]);
+    let results = TestWsgi::find_all(&root, "body/pre");
+    assert_eq!(results, vec!["  'Kossuth L. u.': 'Kossuth Lajos utca'\n"]);
+}
+
+/// Tests the coverage history page.
+#[test]
+fn test_coverage_history_well_formed() {
+    let mut test_wsgi = TestWsgi::new();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "refcounty": "01",
+                "refsettlement": "011",
+                "osmrelation": 42,
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        &test_wsgi.ctx,
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    test_wsgi.ctx.set_file_system(&file_system);
+    {
+        let conn = test_wsgi.ctx.get_database_connection().unwrap();
+        conn.execute_batch(
+            "insert into relation_coverage_history (relation_name, kind, date, todo_count, done_count, percent) values ('gazdagret', 'housenumbers', '2020-05-09', '3', '1', '25.00');
+            insert into relation_coverage_history (relation_name, kind, date, todo_count, done_count, percent) values ('gazdagret', 'housenumbers', '2020-05-10', '2', '2', '50.00');
+            insert into relation_coverage_history (relation_name, kind, date, todo_count, done_count, percent) values ('gazdagret', 'streets', '2020-05-10', '0', '4', '100.00');",
+        )
+        .unwrap();
+    }
+
+    let root = test_wsgi.get_dom_for_path("/coverage-history/gazdagret/view-result");
+
+    let results = TestWsgi::find_all(&root, "body/table[1]/tr[2]/td[1]");
+    assert_eq!(results, vec!["2020-05-09"]);
+    let results = TestWsgi::find_all(&root, "body/table[1]/tr[3]/td[1]");
+    assert_eq!(results, vec!["2020-05-10"]);
+    let results = TestWsgi::find_all(&root, "body/table[1]/tr[3]/td[4]");
+    assert_eq!(results, vec!["50.00%"]);
+    let results = TestWsgi::find_all(&root, "body/table[2]/tr/td[3]");
+    assert_eq!(results, vec!["4"]);
+    let results = TestWsgi::find_all(&root, "body/div[@class='canvasblock js']/canvas/@data-url");
+    assert_eq!(
+        results,
+        vec!["/osm/coverage-history/gazdagret/view-result.json"]
+    );
+}
+
+/// Tests the suggested refstreets page: no osm streets case.
+#[test]
+fn test_suggested_refstreets_no_osm_streets() {
+    let mut test_wsgi = TestWsgi::new();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "osmrelation": 2713748,
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        &test_wsgi.ctx,
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    test_wsgi.ctx.set_file_system(&file_system);
+
+    let root = test_wsgi.get_dom_for_path("/suggested-refstreets/gazdagret/view-result");
+
+    let results = TestWsgi::find_all(&root, "body/div[@id='no-osm-streets']");
+    assert_eq!(results.len(), 1);
+}
+
 /// Tests the missing streets page: the txt output.
 #[test]
 fn test_missing_streets_view_result_txt() {
@@ -2157,6 +2654,66 @@ fn test_missing_streets_view_result_txt() {
     assert_eq!(result, "Only In Ref Nonsense utca\nOnly In Ref utca\n");
 }
 
+/// Tests the missing streets page: the geojson output.
+#[test]
+fn test_missing_streets_view_result_geojson() {
+    let mut test_wsgi = TestWsgi::new();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "refcounty": "01",
+                "refsettlement": "011",
+                "osmrelation": 42,
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        &test_wsgi.ctx,
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    test_wsgi.ctx.set_file_system(&file_system);
+    let mtime = test_wsgi.get_ctx().get_time().now_string();
+    {
+        let conn = test_wsgi.ctx.get_database_connection().unwrap();
+        conn.execute_batch(
+            "delete from ref_streets;
+            insert into ref_streets (county_code, settlement_code, street) values ('01', '011', 'Tűzkő utca');
+            insert into ref_streets (county_code, settlement_code, street) values ('01', '011', 'Only In Ref utca');
+            insert into ref_streets (county_code, settlement_code, street) values ('01', '011', 'Only In Ref Too utca');
+            insert into ref_housenumbers (county_code, settlement_code, street, housenumber, comment, lat, lon) values ('01', '011', 'Only In Ref Too utca', '1', '', '47.41', '19.01');
+            insert into ref_housenumbers (county_code, settlement_code, street, housenumber, comment, lat, lon) values ('01', '011', 'Only In Ref Too utca', '3', '', '47.42', '19.02');
+            insert into ref_housenumbers (county_code, settlement_code, street, housenumber, comment, lat, lon) values ('01', '011', 'Only In Ref Too utca', '5', '', 'NaN', '19.03');
+            insert into ref_housenumbers (county_code, settlement_code, street, housenumber, comment, lat, lon) values ('01', '011', 'Only In Ref Too utca', '7', '', '47.44', 'x');
+            insert into osm_streets (relation, osm_id, name, highway, service, surface, leisure, osm_type) values ('gazdagret', '1', 'Tűzkő utca', '', '', '', '', '');",
+        )
+        .unwrap();
+        conn.execute(
+            "insert into mtimes (page, last_modified) values (?1, ?2)",
+            ["streets/gazdagret", &mtime],
+        )
+        .unwrap();
+    }
+
+    let root = test_wsgi.get_json_for_path("/missing-streets/gazdagret/view-result.geojson");
+
+    // Missing streets are not in OSM, the geometry is from the reference house numbers, if possible;
+    // bad reference coordinates are ignored.
+    let features = root["features"].as_array().unwrap();
+    assert_eq!(features.len(), 2);
+    assert_eq!(features[0]["properties"]["name"], "Only In Ref Too utca");
+    assert_eq!(
+        features[0]["geometry"],
+        serde_json::json!({
+            "type": "MultiPoint",
+            "coordinates": [[19.01, 47.41], [19.02, 47.42]],
+        })
+    );
+    assert_eq!(features[1]["properties"]["name"], "Only In Ref utca");
+    assert_eq!(features[1]["geometry"], serde_json::Value::Null);
+}
+
 /// Tests the missing streets page: the chkl output.
 #[test]
 fn test_missing_streets_view_result_chkl() {
@@ -2436,6 +2993,85 @@ fn test_main_filter_for_refcounty_refsettlement() {
     assert_eq!(results.len(), 2);
 }
 
+/// Creates a test context where budapest_11 and budapest_12 belong to the budapest group.
+fn make_group_test_wsgi() -> TestWsgi {
+    let mut test_wsgi = TestWsgi::new();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "budapest_11": {
+                "osmrelation": 42,
+                "refcounty": "01",
+                "refsettlement": "011",
+                "parent": "budapest",
+            },
+            "budapest_12": {
+                "osmrelation": 43,
+                "refcounty": "01",
+                "refsettlement": "012",
+                "parent": "budapest",
+            },
+            "gazdagret": {
+                "osmrelation": 44,
+                "refcounty": "01",
+                "refsettlement": "011",
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        &test_wsgi.ctx,
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    test_wsgi.ctx.set_file_system(&file_system);
+    {
+        let conn = test_wsgi.ctx.get_database_connection().unwrap();
+        conn.execute_batch(
+            "insert into relation_coverage_history (relation_name, kind, date, todo_count, done_count, percent) values ('budapest_11', 'housenumbers', '2020-05-10', '1', '3', '75.00');
+            insert into relation_coverage_history (relation_name, kind, date, todo_count, done_count, percent) values ('budapest_12', 'housenumbers', '2020-05-09', '3', '1', '25.00');
+            insert into osm_housenumber_coverages (relation_name, coverage, last_modified, todo_count, done_count) values ('budapest_11', '75.00', '1589068800000000000', 1, 3);
+            insert into osm_housenumber_coverages (relation_name, coverage, last_modified, todo_count, done_count) values ('budapest_12', '25.00', '1588982400000000000', 3, 1);",
+        )
+        .unwrap();
+    }
+    test_wsgi
+}
+
+/// Tests handle_main(): the /osm/filter-for/parent/<value> output.
+#[test]
+fn test_main_filter_for_parent() {
+    let mut test_wsgi = make_group_test_wsgi();
+
+    let root = test_wsgi.get_dom_for_path("/filter-for/parent/budapest");
+
+    let results = TestWsgi::find_all(&root, "body/table/tr");
+    // header + budapest_11 + budapest_12, but not gazdagret.
+    assert_eq!(results.len(), 3);
+    let results = TestWsgi::find_all(
+        &root,
+        "body/p/a[@href='/osm/relation-groups/budapest/view-result']",
+    );
+    assert_eq!(results, vec!["50.00%"]);
+}
+
+/// Tests handle_relation_group().
+#[test]
+fn test_relation_group_well_formed() {
+    let mut test_wsgi = make_group_test_wsgi();
+
+    let root = test_wsgi.get_dom_for_path("/relation-groups/budapest/view-result");
+
+    let results = TestWsgi::find_all(&root, "body/p[1]");
+    assert_eq!(
+        results,
+        vec!["House number coverage: 50.00% (existing: 4, missing: 4, as of 2020-05-09)Street coverage: not yet calculated"]
+    );
+    let results = TestWsgi::find_all(&root, "body/table/tr[2]/td[1]");
+    assert_eq!(results, vec!["budapest_11"]);
+    let results = TestWsgi::find_all(&root, "body/table/tr[3]/td[2]/strong/a");
+    assert_eq!(results, vec!["25.00%"]);
+}
+
 /// Tests handle_main(): the /osm/filter-for/relations/... output
 #[test]
 fn test_main_filter_for_relations() {
@@ -2495,11 +3131,11 @@ fn test_main_filter_for_relations_empty() {
 fn test_application_error() {
     let mut ctx = context::tests::make_test_context().unwrap();
     let unit = context::tests::TestUnit::new();
-    let unit_rc: Rc<dyn context::Unit> = Rc::new(unit);
+    let unit_rc: Arc<dyn context::Unit> = Arc::new(unit);
     ctx.set_unit(&unit_rc);
     let css = context::tests::TestFileSystem::make_file();
     {
-        let mut guard = css.borrow_mut();
+        let mut guard = css.lock().unwrap();
         let write = guard.deref_mut();
         write.write_all(b"/* comment */").unwrap();
     }
@@ -2507,7 +3143,7 @@ fn test_application_error() {
     let files =
         context::tests::TestFileSystem::make_files(&ctx, &[("target/browser/osm.min.css", &css)]);
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
     let bytes: Vec<u8> = Vec::new();
 
@@ -2540,7 +3176,7 @@ fn test_webhooks_github() {
     let expected_args = format!("make -C {} deploy", test_wsgi.ctx.get_abspath(""));
     let outputs: HashMap<_, _> = vec![(expected_args, "".to_string())].into_iter().collect();
     let subprocess = context::tests::TestSubprocess::new(&outputs);
-    let subprocess_rc: Rc<dyn context::Subprocess> = Rc::new(subprocess);
+    let subprocess_rc: Arc<dyn context::Subprocess> = Arc::new(subprocess);
     test_wsgi.ctx.set_subprocess(&subprocess_rc);
 
     test_wsgi.get_dom_for_path("/webhooks/github");
@@ -2559,7 +3195,7 @@ fn test_webhooks_github_branch() {
     let mut ctx = context::tests::make_test_context().unwrap();
     let outputs: HashMap<String, String> = HashMap::new();
     let subprocess = context::tests::TestSubprocess::new(&outputs);
-    let subprocess_rc: Rc<dyn context::Subprocess> = Rc::new(subprocess);
+    let subprocess_rc: Arc<dyn context::Subprocess> = Arc::new(subprocess);
     ctx.set_subprocess(&subprocess_rc);
     let root = serde_json::json!({"ref": "refs/heads/stable"});
     let payload = serde_json::to_string(&root).unwrap();
@@ -2596,19 +3232,19 @@ fn test_static_css() {
     let mut test_wsgi = TestWsgi::new();
     let mut file_system = context::tests::TestFileSystem::new();
     let css_value = context::tests::TestFileSystem::make_file();
-    css_value.borrow_mut().write_all(b"{}").unwrap();
+    css_value.lock().unwrap().write_all(b"{}").unwrap();
     let files = context::tests::TestFileSystem::make_files(
         &test_wsgi.ctx,
         &[("target/browser/osm.min.css", &css_value)],
     );
     file_system.set_files(&files);
-    let mut mtimes: HashMap<String, Rc<RefCell<time::OffsetDateTime>>> = HashMap::new();
+    let mut mtimes: HashMap<String, Arc<Mutex<time::OffsetDateTime>>> = HashMap::new();
     mtimes.insert(
         test_wsgi.ctx.get_abspath("target/browser/osm.min.css"),
-        Rc::new(RefCell::new(time::OffsetDateTime::UNIX_EPOCH)),
+        Arc::new(Mutex::new(time::OffsetDateTime::UNIX_EPOCH)),
     );
     file_system.set_mtimes(&mtimes);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     test_wsgi.ctx.set_file_system(&file_system_rc);
 
     let result = test_wsgi.get_css_for_path("/static/osm.min.css");
@@ -2623,7 +3259,8 @@ fn test_static_text() {
     let mut file_system = context::tests::TestFileSystem::new();
     let txt_value = context::tests::TestFileSystem::make_file();
     txt_value
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(b"User-agent: *\n")
         .unwrap();
     let files = context::tests::TestFileSystem::make_files(
@@ -2631,7 +3268,7 @@ fn test_static_text() {
         &[("data/robots.txt", &txt_value)],
     );
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     test_wsgi.ctx.set_file_system(&file_system_rc);
 
     let result = test_wsgi.get_txt_for_path("/robots.txt");
@@ -2936,6 +3573,45 @@ fn test_handle_main_relation() {
     assert_eq!(ret[4].get_value(), "");
 }
 
+/// Tests handle_main_relation(): invalid items with a past resurvey date are counted.
+#[test]
+fn test_handle_main_relation_resurvey() {
+    let mut ctx = context::tests::make_test_context().unwrap();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "myrelation": {
+                "osmrelation": 42,
+            },
+        },
+        "relation-myrelation.yaml": {
+            "filters": {
+                "Tűzkő utca": {
+                    "invalid": [
+                        { "number": "1", "resurvey-after": "2020-01-01" },
+                        { "number": "2", "resurvey-after": "2020-02-01" },
+                        { "number": "3", "resurvey-after": "2021-01-01" },
+                    ],
+                },
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        &ctx,
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    ctx.set_file_system(&file_system);
+    let mut relations = areas::Relations::new(&ctx).unwrap();
+    let filter_for = Box::new(filter_for_everything);
+
+    let ret = handle_main_relation(&ctx, &mut relations, &filter_for, "myrelation").unwrap();
+
+    assert!(ret[1].get_value().ends_with(
+        r#"<br /><a href="/osm/missing-housenumbers/myrelation/view-lints">2 to resurvey</a>"#
+    ));
+}
+
 /// Tests handle_main_relation() for the missing-streets=only case.
 #[test]
 fn test_handle_main_relation_streets_only() {
@@ -2996,7 +3672,7 @@ fn test_handle_main_housenr_percent() {
             ["gazdagret", "4.2", "0"],
         ).unwrap();
     }
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
     let mut relations = areas::Relations::new(&ctx).unwrap();
     let relation = relations.get_relation("gazdagret").unwrap();
@@ -3032,7 +3708,7 @@ fn test_handle_main_street_percent() {
     );
     let mut file_system = context::tests::TestFileSystem::new();
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
     let mut relations = areas::Relations::new(&ctx).unwrap();
     let relation = relations.get_relation("gazdagret").unwrap();
@@ -3069,7 +3745,7 @@ fn test_handle_main_street_additional_count() {
         )
         .unwrap();
     }
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     ctx.set_file_system(&file_system_rc);
     let mut relations = areas::Relations::new(&ctx).unwrap();
     let relation = relations.get_relation("gazdagret").unwrap();
@@ -131,6 +131,41 @@ pub fn additional_streets_view_gpx(
     let output = doc.get_value();
     Ok((output, relation_name.into()))
 }
+
+/// Expected request_uri: e.g. /osm/additional-streets/ujbuda/view-result.geojson.
+pub fn additional_streets_view_geojson(
+    ctx: &context::Context,
+    relations: &mut areas::Relations<'_>,
+    request_uri: &str,
+) -> anyhow::Result<(String, String)> {
+    let mut tokens = request_uri.split('/');
+    tokens.next_back();
+    let relation_name = tokens.next_back().context("next_back() failed")?;
+    let relation = relations
+        .get_relation(relation_name)
+        .context("get_relation() failed")?;
+
+    // The geometries are stored by cron, no overpass query here.
+    let mut features: Vec<serde_json::Value> = Vec::new();
+    let mut streets = relation.get_additional_streets(/*sorted_result=*/ true)?;
+    streets.sort_by_key(|street| util::get_sort_key(street.get_osm_name()));
+    for street in streets {
+        let name = street.get_osm_name();
+        for (osm_id, coordinates) in relation.get_files().get_osm_street_geometries(ctx, name)? {
+            features.push(util::make_street_feature(
+                name,
+                Some(osm_id),
+                Some(&coordinates),
+            ));
+        }
+    }
+    let collection = serde_json::json!({
+        "type": "FeatureCollection",
+        "features": features,
+    });
+    Ok((serde_json::to_string(&collection)?, relation_name.into()))
+}
+
 /// Expected request_uri: e.g. /osm/additional-streets/ujbuda/view-result.txt.
 pub fn additional_streets_view_txt(
     ctx: &context::Context,
@@ -249,6 +284,17 @@ pub fn additional_streets_view_result(
                 a.text(&tr("GPX format"));
             }
             p.stag("br", &[]);
+            {
+                let a = p.tag(
+                    "a",
+                    &[(
+                        "href",
+                        &format!("{prefix}/additional-streets/{relation_name}/view-result.geojson"),
+                    )],
+                );
+                a.text(&tr("GeoJSON format"));
+            }
+            p.stag("br", &[]);
             {
                 let a = doc.tag(
                     "a",
@@ -10,7 +10,7 @@
 
 //! Tests for the wsgi_additional module.
 
-use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::areas;
 use crate::context;
@@ -130,7 +130,7 @@ fn test_streets_view_result_gpx() {
         /*result_path=*/ "src/fixtures/network/overpass-additional-streets.json",
     )];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     test_wsgi.get_ctx().set_network(network_rc);
     test_wsgi.set_content_type("text/gpx+xml; charset=utf-8");
     let mtime = test_wsgi.get_ctx().get_time().now_string();
@@ -218,6 +218,50 @@ fn test_streets_view_result_gpx() {
     // TODO assert that there are two results here
 }
 
+/// Tests additional streets: the geojson output.
+#[test]
+fn test_streets_view_result_geojson() {
+    let mut test_wsgi = wsgi::tests::TestWsgi::new();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "osmrelation": 42,
+                "refcounty": "01",
+                "refsettlement": "011",
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        test_wsgi.get_ctx(),
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    test_wsgi.get_ctx().set_file_system(&file_system);
+    {
+        let conn = test_wsgi.get_ctx().get_database_connection().unwrap();
+        conn.execute_batch(
+            "insert into ref_streets (county_code, settlement_code, street) values ('01', '011', 'Tűzkő utca');
+            insert into osm_streets (relation, osm_id, name, highway, service, surface, leisure, osm_type) values ('gazdagret', '1', 'Tűzkő utca', '', '', '', '', 'way');
+            insert into osm_streets (relation, osm_id, name, highway, service, surface, leisure, osm_type, geometry) values ('gazdagret', '2', 'Only In OSM utca', '', '', '', '', 'way', '[[19.01,47.46],[19.02,47.47]]');
+            insert into mtimes (page, last_modified) values ('streets/gazdagret', '0');",
+        )
+        .unwrap();
+    }
+
+    let root = test_wsgi.get_json_for_path("/additional-streets/gazdagret/view-result.geojson");
+
+    let features = root["features"].as_array().unwrap();
+    assert_eq!(features.len(), 1);
+    assert_eq!(features[0]["geometry"]["type"], "LineString");
+    assert_eq!(
+        features[0]["geometry"]["coordinates"],
+        serde_json::json!([[19.01, 47.46], [19.02, 47.47]])
+    );
+    assert_eq!(features[0]["properties"]["name"], "Only In OSM utca");
+    assert_eq!(features[0]["properties"]["osm_id"], 2);
+}
+
 /// Tests additional streets: the chkl output.
 #[test]
 fn test_streets_view_result_chkl() {
@@ -469,7 +513,7 @@ fn test_additional_housenumbers_well_formed() {
         ],
     );
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     test_wsgi.get_ctx().set_file_system(&file_system_rc);
     let mtime = test_wsgi.get_ctx().get_time().now_string();
     {
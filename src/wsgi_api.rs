@@ -0,0 +1,284 @@
+/*
+ * Copyright 2024 Miklos Vajna
+ *
+ * SPDX-License-Identifier: MIT
+ */
+
+#![deny(warnings)]
+#![warn(clippy::all)]
+#![warn(missing_docs)]
+
+//! Contains the versioned JSON REST API of the web interface, under /osm/api/v1/.
+
+use crate::areas;
+use crate::cache;
+use crate::context;
+use crate::stats;
+use crate::webframe;
+use crate::wsgi;
+use crate::wsgi_json;
+
+#[cfg(not(test))]
+use log::error;
+
+#[cfg(test)]
+use std::println as error;
+
+/// An error with a HTTP status code, reported as an error object.
+#[derive(Debug)]
+struct ApiError {
+    status: u16,
+    message: String,
+}
+
+impl ApiError {
+    fn not_found(message: &str) -> anyhow::Error {
+        anyhow::Error::new(ApiError {
+            status: 404_u16,
+            message: message.into(),
+        })
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// One relation in the relation list.
+#[derive(serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct RelationSummary {
+    name: String,
+    osmrelation: u64,
+    refcounty: String,
+    refsettlement: String,
+    parent: Option<String>,
+    missing_streets: String,
+    housenumber_coverage: Option<String>,
+    additional_housenumbers_count: Option<u64>,
+    street_coverage: Option<String>,
+    additional_streets_count: Option<u64>,
+}
+
+/// Gets a count from one of the *_counts SQL tables, if it's known.
+fn get_count(
+    ctx: &context::Context,
+    table: &str,
+    relation_name: &str,
+) -> anyhow::Result<Option<u64>> {
+    if !stats::has_sql_count(ctx, table, relation_name)? {
+        return Ok(None);
+    }
+
+    Ok(Some(
+        stats::get_sql_count(ctx, table, relation_name)?.parse()?,
+    ))
+}
+
+/// Builds the summary of a relation, also decides if it's complete.
+fn get_relation_summary(
+    ctx: &context::Context,
+    relation: &areas::Relation<'_>,
+) -> anyhow::Result<(RelationSummary, bool)> {
+    let config = relation.get_config();
+    let name = relation.get_name();
+    let streets = config.should_check_missing_streets();
+    let mut complete = true;
+
+    let mut housenumber_coverage = None;
+    let mut additional_housenumbers_count = None;
+    if streets != "only" {
+        if relation.has_osm_housenumber_coverage()? {
+            housenumber_coverage = Some(relation.get_osm_housenumber_coverage()?);
+        }
+        if config.should_check_additional_housenumbers() {
+            additional_housenumbers_count =
+                get_count(ctx, "additional_housenumbers_counts", &name)?;
+        }
+        complete &= is_complete(&housenumber_coverage)?;
+    }
+
+    let mut street_coverage = None;
+    let mut additional_streets_count = None;
+    if streets != "no" {
+        if relation.has_osm_street_coverage()? {
+            street_coverage = Some(relation.get_osm_street_coverage()?);
+        }
+        additional_streets_count = get_count(ctx, "additional_streets_counts", &name)?;
+        complete &= is_complete(&street_coverage)?;
+    }
+
+    let summary = RelationSummary {
+        name,
+        osmrelation: config.get_osmrelation(),
+        refcounty: config.get_refcounty(),
+        refsettlement: config.get_refsettlement(),
+        parent: config.get_parent(),
+        missing_streets: streets,
+        housenumber_coverage,
+        additional_housenumbers_count,
+        street_coverage,
+        additional_streets_count,
+    };
+    Ok((summary, complete))
+}
+
+/// Decides if a coverage is complete, an unknown coverage is not.
+fn is_complete(coverage: &Option<String>) -> anyhow::Result<bool> {
+    match coverage {
+        Some(value) => Ok(value.parse::<f64>()? >= 100_f64),
+        None => Ok(false),
+    }
+}
+
+/// Expected request_uri: e.g. /osm/api/v1/relations or
+/// /osm/api/v1/relations/filter-for/refcounty/01/whole-county.
+fn relations_json(
+    ctx: &context::Context,
+    relations: &mut areas::Relations<'_>,
+    request_uri: &str,
+) -> anyhow::Result<String> {
+    // Unlike on the main page, no filter means everything.
+    let filter_for = if request_uri.contains("/filter-for/") {
+        wsgi::setup_main_filter_for(request_uri)?.0
+    } else {
+        Box::new(wsgi::filter_for_everything)
+    };
+    let mut ret: Vec<RelationSummary> = Vec::new();
+    for relation_name in relations.get_names() {
+        let relation = relations.get_relation(&relation_name)?;
+        let (summary, complete) = get_relation_summary(ctx, &relation)?;
+        if filter_for(complete, &relation) {
+            ret.push(summary);
+        }
+    }
+    Ok(serde_json::to_string(&ret)?)
+}
+
+/// Expected request_uri: e.g. /osm/api/v1/relations/ormezo/<view>.
+fn relation_json(
+    ctx: &context::Context,
+    relations: &mut areas::Relations<'_>,
+    relation_name: &str,
+    view: Option<&str>,
+) -> anyhow::Result<String> {
+    if !relations.get_names().contains(&relation_name.to_string()) {
+        return Err(ApiError::not_found(&format!(
+            "no such relation: {relation_name}"
+        )));
+    }
+
+    let mut relation = relations.get_relation(relation_name)?;
+    match view {
+        None => {
+            let (summary, _complete) = get_relation_summary(ctx, &relation)?;
+            Ok(serde_json::to_string(&summary)?)
+        }
+        Some("coverage") => Ok(serde_json::to_string(&relation.get_coverage_history()?)?),
+        Some("missing-housenumbers") => cache::get_missing_housenumbers_json(&mut relation),
+        Some("additional-housenumbers") => cache::get_additional_housenumbers_json(&mut relation),
+        Some("missing-streets") => {
+            let (todo, done) = relation.get_missing_streets()?;
+            Ok(serde_json::to_string(&serde_json::json!({
+                "todo": todo,
+                "done": done,
+            }))?)
+        }
+        Some("additional-streets") => Ok(serde_json::to_string(
+            &relation.get_additional_streets(/*sorted_result=*/ true)?,
+        )?),
+        Some("lints") => Ok(serde_json::to_string(&wsgi_json::get_relation_lints(
+            ctx,
+            &mut relation,
+        )?)?),
+        Some(view) => Err(ApiError::not_found(&format!("no such view: {view}"))),
+    }
+}
+
+/// Expected request_uri: e.g. /osm/api/v1/stats.
+fn stats_json(ctx: &context::Context) -> anyhow::Result<String> {
+    let path = format!("{}/stats/stats.json", ctx.get_ini().get_workdir());
+    if !ctx.get_file_system().path_exists(&path) {
+        return Err(ApiError::not_found("no stats yet"));
+    }
+
+    ctx.get_file_system().read_to_string(&path)
+}
+
+/// Dispatches an API request to the handler of its endpoint.
+fn dispatch(
+    ctx: &context::Context,
+    relations: &mut areas::Relations<'_>,
+    request_uri: &str,
+) -> anyhow::Result<String> {
+    let prefix = ctx.get_ini().get_uri_prefix();
+    let path = request_uri
+        .strip_prefix(&format!("{prefix}/api/v1"))
+        .unwrap_or_default();
+    let tokens: Vec<&str> = path.split('/').filter(|i| !i.is_empty()).collect();
+    match tokens.as_slice() {
+        ["relations"] | ["relations", "filter-for", ..] => {
+            relations_json(ctx, relations, request_uri)
+        }
+        ["relations", relation_name] => relation_json(ctx, relations, relation_name, None),
+        ["relations", relation_name, view] => {
+            relation_json(ctx, relations, relation_name, Some(view))
+        }
+        ["stats"] => stats_json(ctx),
+        _ => Err(ApiError::not_found(&format!("no such endpoint: {path}"))),
+    }
+}
+
+/// Creates a response with an error object.
+fn make_error_response(status_code: u16, message: &str) -> rouille::Response {
+    let error = serde_json::json!({
+        "error": {
+            "status": status_code,
+            "message": message,
+        }
+    });
+    webframe::make_response(
+        status_code,
+        vec![(
+            "Content-type".into(),
+            "application/json; charset=utf-8".into(),
+        )],
+        error.to_string().as_bytes().to_vec(),
+    )
+}
+
+/// Dispatches API requests based on their URIs.
+pub fn our_application_api(
+    ctx: &context::Context,
+    relations: &mut areas::Relations<'_>,
+    request_uri: &str,
+) -> rouille::Response {
+    match dispatch(ctx, relations, request_uri) {
+        Ok(output) => webframe::make_response(
+            200_u16,
+            vec![(
+                "Content-type".into(),
+                "application/json; charset=utf-8".into(),
+            )],
+            output.as_bytes().to_vec(),
+        ),
+        Err(err) => handle_error(request_uri, &err),
+    }
+}
+
+/// Reports an error as an error object, without exposing the details of internal errors.
+pub fn handle_error(request_uri: &str, err: &anyhow::Error) -> rouille::Response {
+    if let Some(api_error) = err.downcast_ref::<ApiError>() {
+        return make_error_response(api_error.status, &api_error.message);
+    }
+
+    error!("api: failed to serve {request_uri}: {err:?}");
+    make_error_response(500_u16, "internal error")
+}
+
+#[cfg(test)]
+mod tests;
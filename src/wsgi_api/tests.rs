@@ -0,0 +1,262 @@
+/*
+ * Copyright 2024 Miklos Vajna
+ *
+ * SPDX-License-Identifier: MIT
+ */
+
+#![deny(warnings)]
+#![warn(clippy::all)]
+#![warn(missing_docs)]
+
+//! Tests for the wsgi_api module.
+
+use super::*;
+use std::collections::HashMap;
+use std::io::Read as _;
+use std::io::Write as _;
+
+/// Creates a TestWsgi with 2 relations: gazdagret has data, budafok is in the budapest group.
+fn make_test_wsgi() -> wsgi::tests::TestWsgi {
+    let mut test_wsgi = wsgi::tests::TestWsgi::new();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "budafok": {
+                "osmrelation": 43,
+                "refcounty": "0",
+                "refsettlement": "1",
+                "parent": "budapest",
+            },
+            "gazdagret": {
+                "osmrelation": 42,
+                "refcounty": "0",
+                "refsettlement": "0",
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        test_wsgi.get_ctx(),
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    test_wsgi.get_ctx().set_file_system(&file_system);
+    {
+        let conn = test_wsgi.get_ctx().get_database_connection().unwrap();
+        conn.execute_batch(
+            "insert into ref_housenumbers (county_code, settlement_code, street, housenumber, comment) values ('0', '0', 'Tűzkő utca', '1', '');
+             insert into ref_streets (county_code, settlement_code, street) values ('0', '0', 'Tűzkő utca');
+             insert into ref_streets (county_code, settlement_code, street) values ('0', '0', 'Only In Ref utca');
+             insert into osm_streets (relation, osm_id, name, highway, service, surface, leisure, osm_type) values ('gazdagret', '3', 'Tűzkő utca', '', '', '', '', 'way');
+             insert into osm_streets (relation, osm_id, name, highway, service, surface, leisure, osm_type) values ('gazdagret', '4', 'Only In OSM utca', '', '', '', '', 'way');
+             insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values ('gazdagret', '1', 'Tűzkő utca', '1', '', '', '', '', '', '', '', '', '', 'way');
+             insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values ('gazdagret', '2', 'Tűzkő utca', '1', '', '', '', '', '', '', '', '', '', 'node');
+             insert into mtimes (page, last_modified) values ('streets/gazdagret', '0');
+             insert into mtimes (page, last_modified) values ('housenumbers/gazdagret', '0');
+             insert into osm_housenumber_coverages (relation_name, coverage, last_modified) values ('gazdagret', '100.00', '0');
+             insert into osm_street_coverages (relation_name, coverage, last_modified) values ('gazdagret', '50.00', '0');
+             insert into additional_streets_counts (relation, count) values ('gazdagret', '1');
+             insert into relation_coverage_history (relation_name, kind, date, todo_count, done_count, percent) values ('gazdagret', 'streets', '2020-05-10', '1', '1', '50.00');",
+        )
+        .unwrap();
+    }
+    test_wsgi
+}
+
+/// Gets the status code and the parsed output for an API path.
+fn get_api(test_wsgi: &mut wsgi::tests::TestWsgi, path: &str) -> (u16, serde_json::Value) {
+    let prefix = test_wsgi.get_ctx().get_ini().get_uri_prefix();
+    let request = rouille::Request::fake_http("GET", format!("{prefix}{path}"), vec![], vec![]);
+    let response = wsgi::application(&request, test_wsgi.get_ctx());
+    let headers: HashMap<_, _> = response.headers.into_iter().collect();
+    assert_eq!(headers["Content-type"], "application/json; charset=utf-8");
+    let mut data = Vec::new();
+    let (mut reader, _size) = response.data.into_reader_and_size();
+    reader.read_to_end(&mut data).unwrap();
+    let value: serde_json::Value = serde_json::from_slice(&data).unwrap();
+    (response.status_code, value)
+}
+
+/// Tests relations_json(): no filter means everything.
+#[test]
+fn test_relations() {
+    let mut test_wsgi = make_test_wsgi();
+
+    let (status, value) = get_api(&mut test_wsgi, "/api/v1/relations");
+
+    assert_eq!(status, 200);
+    assert_eq!(
+        value,
+        serde_json::json!([
+            {
+                "name": "budafok",
+                "osmrelation": 43,
+                "refcounty": "0",
+                "refsettlement": "1",
+                "parent": "budapest",
+                "missing-streets": "yes",
+                "housenumber-coverage": null,
+                "additional-housenumbers-count": null,
+                "street-coverage": null,
+                "additional-streets-count": null,
+            },
+            {
+                "name": "gazdagret",
+                "osmrelation": 42,
+                "refcounty": "0",
+                "refsettlement": "0",
+                "parent": null,
+                "missing-streets": "yes",
+                "housenumber-coverage": "100.00",
+                "additional-housenumbers-count": null,
+                "street-coverage": "50.00",
+                "additional-streets-count": 1,
+            },
+        ])
+    );
+}
+
+/// Tests relations_json(): the filters of the main page are supported.
+#[test]
+fn test_relations_filter_for() {
+    let mut test_wsgi = make_test_wsgi();
+
+    let (status, value) = get_api(
+        &mut test_wsgi,
+        "/api/v1/relations/filter-for/parent/budapest",
+    );
+    assert_eq!(status, 200);
+    let names: Vec<&str> = value
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|i| i["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, vec!["budafok"]);
+
+    let (_status, value) = get_api(
+        &mut test_wsgi,
+        "/api/v1/relations/filter-for/refcounty/0/refsettlement/0",
+    );
+    assert_eq!(value[0]["name"], "gazdagret");
+    assert_eq!(value.as_array().unwrap().len(), 1);
+}
+
+/// Tests relation_json(): the summary and the views of a relation.
+#[test]
+fn test_relation() {
+    let mut test_wsgi = make_test_wsgi();
+
+    let (status, value) = get_api(&mut test_wsgi, "/api/v1/relations/gazdagret");
+    assert_eq!(status, 200);
+    assert_eq!(value["street-coverage"], "50.00");
+
+    let (status, value) = get_api(&mut test_wsgi, "/api/v1/relations/gazdagret/coverage");
+    assert_eq!(status, 200);
+    assert_eq!(value["streets"][0]["percent"], "50.00");
+    assert_eq!(value["housenumbers"], serde_json::json!([]));
+
+    let (status, value) = get_api(
+        &mut test_wsgi,
+        "/api/v1/relations/gazdagret/missing-streets",
+    );
+    assert_eq!(status, 200);
+    assert_eq!(
+        value,
+        serde_json::json!({"todo": ["Only In Ref utca"], "done": ["Tűzkő utca"]})
+    );
+
+    let (status, value) = get_api(
+        &mut test_wsgi,
+        "/api/v1/relations/gazdagret/additional-streets",
+    );
+    assert_eq!(status, 200);
+    assert_eq!(value[0]["osm-name"], "Only In OSM utca");
+
+    let (status, value) = get_api(
+        &mut test_wsgi,
+        "/api/v1/relations/gazdagret/missing-housenumbers",
+    );
+    assert_eq!(status, 200);
+    assert_eq!(value["ongoing-streets"], serde_json::json!([]));
+
+    let (status, value) = get_api(
+        &mut test_wsgi,
+        "/api/v1/relations/gazdagret/additional-housenumbers",
+    );
+    assert_eq!(status, 200);
+    assert_eq!(value, serde_json::json!([]));
+
+    let (status, value) = get_api(&mut test_wsgi, "/api/v1/relations/gazdagret/lints");
+    assert_eq!(status, 200);
+    assert_eq!(value[0]["reason"], "duplicate-in-osm");
+}
+
+/// Tests the error objects: unknown relations, views and endpoints are 404s.
+#[test]
+fn test_not_found() {
+    let mut test_wsgi = make_test_wsgi();
+
+    let (status, value) = get_api(&mut test_wsgi, "/api/v1/relations/nosuchrelation");
+    assert_eq!(status, 404);
+    assert_eq!(
+        value,
+        serde_json::json!({"error": {"status": 404, "message": "no such relation: nosuchrelation"}})
+    );
+
+    let (status, value) = get_api(&mut test_wsgi, "/api/v1/relations/gazdagret/nosuchview");
+    assert_eq!(status, 404);
+    assert_eq!(value["error"]["message"], "no such view: nosuchview");
+
+    let (status, value) = get_api(&mut test_wsgi, "/api/v1/nosuchendpoint");
+    assert_eq!(status, 404);
+    assert_eq!(
+        value["error"]["message"],
+        "no such endpoint: /nosuchendpoint"
+    );
+
+    let (status, value) = get_api(&mut test_wsgi, "/api/v1/stats");
+    assert_eq!(status, 404);
+    assert_eq!(value["error"]["message"], "no stats yet");
+}
+
+/// Tests stats_json().
+#[test]
+fn test_stats() {
+    let mut test_wsgi = wsgi::tests::TestWsgi::new();
+    let stats_value = context::tests::TestFileSystem::make_file();
+    stats_value
+        .lock()
+        .unwrap()
+        .write_all(br#"{"progress": {"date": "2020-05-10"}}"#)
+        .unwrap();
+    let files = context::tests::TestFileSystem::make_files(
+        test_wsgi.get_ctx(),
+        &[("workdir/stats/stats.json", &stats_value)],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    test_wsgi.get_ctx().set_file_system(&file_system);
+
+    let (status, value) = get_api(&mut test_wsgi, "/api/v1/stats");
+
+    assert_eq!(status, 200);
+    assert_eq!(value["progress"]["date"], "2020-05-10");
+}
+
+/// Tests our_application_api(): an internal error is reported as an error object.
+#[test]
+fn test_internal_error() {
+    let mut test_wsgi = make_test_wsgi();
+    {
+        let conn = test_wsgi.get_ctx().get_database_connection().unwrap();
+        conn.execute_batch("drop table relation_coverage_history")
+            .unwrap();
+    }
+
+    let (status, value) = get_api(&mut test_wsgi, "/api/v1/relations/gazdagret/coverage");
+
+    assert_eq!(status, 500);
+    assert_eq!(
+        value,
+        serde_json::json!({"error": {"status": 500, "message": "internal error"}})
+    );
+}
@@ -13,61 +13,98 @@
 use crate::areas;
 use crate::cache;
 use crate::context;
-use crate::overpass_query;
+use crate::jobs;
 use crate::webframe;
 use anyhow::Context;
 use std::collections::HashMap;
 
-/// Expected request_uri: e.g. /osm/streets/ormezo/update-result.json.
-fn streets_update_result_json(
+/// Enqueues an update job for the relation of request_uri, e.g.
+/// /osm/streets/ormezo/update-result.json.
+fn update_result_json(
     ctx: &context::Context,
     relations: &mut areas::Relations<'_>,
+    kind: &jobs::JobKind,
     request_uri: &str,
 ) -> anyhow::Result<String> {
     let mut tokens = request_uri.split('/');
     tokens.next_back();
     let relation_name = tokens.next_back().context("short tokens")?;
-    let relation = relations
-        .get_relation(relation_name)
-        .context("get_relation() failed")?;
-    let mut ret: HashMap<String, String> = HashMap::new();
-    let query = relation.get_osm_streets_json_query()?;
-    match overpass_query::overpass_query(ctx, &query) {
-        Ok(buf) => {
-            relation.get_files().write_osm_json_streets(ctx, &buf)?;
-            ret.insert("error".into(), "".into())
-        }
-        Err(err) => ret.insert("error".into(), err.to_string()),
+    if !relations.get_names().contains(&relation_name.to_string()) {
+        let mut ret: HashMap<String, String> = HashMap::new();
+        ret.insert("error".into(), format!("no such relation: {relation_name}"));
+        return Ok(serde_json::to_string(&ret)?);
+    }
+    relations.get_relation(relation_name)?;
+    let job_id = jobs::enqueue(ctx, kind, relation_name)?;
+    let job = jobs::get_job(ctx, job_id)?.context("no job")?;
+    Ok(serde_json::to_string(&job)?)
+}
+
+/// Expected request_uri: e.g. /osm/jobs/42/view-result.json.
+fn jobs_view_result_json(ctx: &context::Context, request_uri: &str) -> anyhow::Result<String> {
+    let mut tokens = request_uri.split('/');
+    tokens.next_back();
+    let job_id = tokens.next_back().context("short tokens")?;
+    let job = match job_id.parse::<i64>() {
+        Ok(value) => jobs::get_job(ctx, value)?,
+        Err(_) => None,
     };
-    Ok(serde_json::to_string(&ret)?)
+    match job {
+        Some(value) => Ok(serde_json::to_string(&value)?),
+        None => {
+            let mut ret: HashMap<String, String> = HashMap::new();
+            ret.insert("error".into(), format!("no such job: {job_id}"));
+            Ok(serde_json::to_string(&ret)?)
+        }
+    }
 }
 
-/// Expected request_uri: e.g. /osm/street-housenumbers/ormezo/update-result.json.
-fn street_housenumbers_update_result_json(
-    ctx: &context::Context,
+/// Expected request_uri: e.g. /osm/missing-housenumbers/ormezo/view-result.json.
+fn missing_housenumbers_view_result_json(
     relations: &mut areas::Relations<'_>,
     request_uri: &str,
 ) -> anyhow::Result<String> {
     let mut tokens = request_uri.split('/');
     tokens.next_back();
     let relation_name = tokens.next_back().context("short tokens")?;
-    let relation = relations.get_relation(relation_name)?;
-    let mut ret: HashMap<String, String> = HashMap::new();
-    let query = relation.get_osm_housenumbers_json_query()?;
-    match overpass_query::overpass_query(ctx, &query) {
-        Ok(buf) => {
-            relation
-                .get_files()
-                .write_osm_json_housenumbers(ctx, &buf)?;
-            ret.insert("error".into(), "".into())
-        }
-        Err(err) => ret.insert("error".into(), err.to_string()),
-    };
-    Ok(serde_json::to_string(&ret)?)
+    let mut relation = relations.get_relation(relation_name)?;
+    cache::get_missing_housenumbers_json(&mut relation)
 }
 
-/// Expected request_uri: e.g. /osm/missing-housenumbers/ormezo/view-result.json.
-fn missing_housenumbers_view_result_json(
+/// Gets the lints of a relation, updating them if they are outdated.
+pub fn get_relation_lints(
+    ctx: &context::Context,
+    relation: &mut areas::Relation<'_>,
+) -> anyhow::Result<Vec<serde_json::Value>> {
+    // Update lints if they are outdated.
+    cache::get_missing_housenumbers_json(relation)?;
+
+    let mut ret: Vec<serde_json::Value> = Vec::new();
+    let conn = ctx.get_database_connection()?;
+    let mut stmt = conn.prepare("select street_name, source, housenumber, reason, object_id, object_type, other_object_id, other_object_type from relation_lints where relation_name = ?1 order by id")?;
+    let mut lints = stmt.query([relation.get_name()])?;
+    while let Some(lint) = lints.next()? {
+        let source: areas::RelationLintSource = lint.get(1)?;
+        let reason: areas::RelationLintReason = lint.get(3)?;
+        let object_id: String = lint.get(4)?;
+        let other_object_id: String = lint.get(6)?;
+        ret.push(serde_json::json!({
+            "street": lint.get::<_, String>(0)?,
+            "source": source.to_string(),
+            "housenumber": lint.get::<_, String>(2)?,
+            "reason": reason.to_string(),
+            "object-id": object_id.parse::<u64>().unwrap_or_default(),
+            "object-type": lint.get::<_, String>(5)?,
+            "other-object-id": other_object_id.parse::<u64>().unwrap_or_default(),
+            "other-object-type": lint.get::<_, String>(7)?,
+        }));
+    }
+    Ok(ret)
+}
+
+/// Expected request_uri: e.g. /osm/missing-housenumbers/ormezo/view-lints.json.
+fn missing_housenumbers_view_lints_json(
+    ctx: &context::Context,
     relations: &mut areas::Relations<'_>,
     request_uri: &str,
 ) -> anyhow::Result<String> {
@@ -75,7 +112,8 @@ fn missing_housenumbers_view_result_json(
     tokens.next_back();
     let relation_name = tokens.next_back().context("short tokens")?;
     let mut relation = relations.get_relation(relation_name)?;
-    cache::get_missing_housenumbers_json(&mut relation)
+    let ret = get_relation_lints(ctx, &mut relation)?;
+    Ok(serde_json::to_string(&ret)?)
 }
 
 /// Expected request_uri: e.g. /osm/additional-housenumbers/ormezo/view-result.json.
@@ -90,6 +128,32 @@ fn additional_housenumbers_view_result_json(
     cache::get_additional_housenumbers_json(&mut relation)
 }
 
+/// Expected request_uri: e.g. /osm/suggested-refstreets/ormezo/view-result.json.
+fn suggested_refstreets_view_result_json(
+    relations: &mut areas::Relations<'_>,
+    request_uri: &str,
+) -> anyhow::Result<String> {
+    let mut tokens = request_uri.split('/');
+    tokens.next_back();
+    let relation_name = tokens.next_back().context("short tokens")?;
+    let relation = relations.get_relation(relation_name)?;
+    let suggestions = relation.get_suggested_refstreets()?;
+    Ok(serde_json::to_string(&suggestions)?)
+}
+
+/// Expected request_uri: e.g. /osm/coverage-history/ormezo/view-result.json.
+fn coverage_history_view_result_json(
+    relations: &mut areas::Relations<'_>,
+    request_uri: &str,
+) -> anyhow::Result<String> {
+    let mut tokens = request_uri.split('/');
+    tokens.next_back();
+    let relation_name = tokens.next_back().context("short tokens")?;
+    let relation = relations.get_relation(relation_name)?;
+    let history = relation.get_coverage_history()?;
+    Ok(serde_json::to_string(&history)?)
+}
+
 /// Dispatches json requests based on their URIs.
 pub fn our_application_json(
     ctx: &context::Context,
@@ -100,12 +164,22 @@ pub fn our_application_json(
     let prefix = ctx.get_ini().get_uri_prefix();
     let output: String;
     if request_uri.starts_with(&format!("{prefix}/streets/")) {
-        output = streets_update_result_json(ctx, relations, request_uri)?;
+        output = update_result_json(ctx, relations, &jobs::JobKind::Streets, request_uri)?;
     } else if request_uri.starts_with(&format!("{prefix}/street-housenumbers/")) {
-        output = street_housenumbers_update_result_json(ctx, relations, request_uri)?;
+        output = update_result_json(ctx, relations, &jobs::JobKind::Housenumbers, request_uri)?;
+    } else if request_uri.starts_with(&format!("{prefix}/jobs/")) {
+        output = jobs_view_result_json(ctx, request_uri)?;
+    } else if request_uri.starts_with(&format!("{prefix}/missing-housenumbers/"))
+        && request_uri.ends_with("/view-lints.json")
+    {
+        output = missing_housenumbers_view_lints_json(ctx, relations, request_uri)?;
     } else if request_uri.starts_with(&format!("{prefix}/missing-housenumbers/")) {
         // Assume request_uri ends with view-result.json.
         output = missing_housenumbers_view_result_json(relations, request_uri)?;
+    } else if request_uri.starts_with(&format!("{prefix}/suggested-refstreets/")) {
+        output = suggested_refstreets_view_result_json(relations, request_uri)?;
+    } else if request_uri.starts_with(&format!("{prefix}/coverage-history/")) {
+        output = coverage_history_view_result_json(relations, request_uri)?;
     } else if request_uri
         == format!("{prefix}/lints/whole-country/invalid-addr-cities/update-result.json")
     {
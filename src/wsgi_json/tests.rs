@@ -11,14 +11,15 @@
 //! Tests for the wsgi_json module.
 
 use std::io::Write as _;
-use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::areas;
 use crate::context;
+use crate::jobs;
 use crate::util;
 use crate::wsgi;
 
-/// Tests streets_update_result_json(): if the update-result json output is well-formed.
+/// Tests update_result_json(): if the update-result json output is well-formed.
 #[test]
 fn test_json_streets_update_result() {
     let mut test_wsgi = wsgi::tests::TestWsgi::new();
@@ -28,7 +29,7 @@ fn test_json_streets_update_result() {
         /*result_path=*/ "src/fixtures/network/overpass-streets-gazdagret.json",
     )];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     test_wsgi.get_ctx().set_network(network_rc);
     let yamls_cache = serde_json::json!({
         "relations.yaml": {
@@ -42,7 +43,8 @@ fn test_json_streets_update_result() {
     let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
     let template_value = context::tests::TestFileSystem::make_file();
     template_value
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(b"aaa @RELATION@ bbb @AREA@ ccc\n")
         .unwrap();
     let files = context::tests::TestFileSystem::make_files(
@@ -57,7 +59,11 @@ fn test_json_streets_update_result() {
 
     let root = test_wsgi.get_json_for_path("/streets/myrelation/update-result.json");
 
-    assert_eq!(root.as_object().unwrap()["error"], "");
+    assert_eq!(root["state"], "queued");
+    jobs::run_queued(test_wsgi.get_ctx()).unwrap();
+    let root = test_wsgi.get_json_for_path(&format!("/jobs/{}/view-result.json", root["id"]));
+    assert_eq!(root["state"], "done");
+    assert_eq!(root["error"], "");
     let ctx = test_wsgi.get_ctx();
     let mut relations = areas::Relations::new(ctx).unwrap();
     assert_eq!(
@@ -72,7 +78,7 @@ fn test_json_streets_update_result() {
     );
 }
 
-/// Tests streets_update_result_json(): if the update-result json output on error is well-formed.
+/// Tests update_result_json(): if the update-result json output on error is well-formed.
 #[test]
 fn test_json_streets_update_result_error() {
     let mut test_wsgi = wsgi::tests::TestWsgi::new();
@@ -82,7 +88,7 @@ fn test_json_streets_update_result_error() {
         /*result_path=*/ "",
     )];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     test_wsgi.get_ctx().set_network(network_rc);
     let yamls_cache = serde_json::json!({
         "relations.yaml": {
@@ -94,7 +100,8 @@ fn test_json_streets_update_result_error() {
     let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
     let template_value = context::tests::TestFileSystem::make_file();
     template_value
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(b"aaa @RELATION@ bbb @AREA@ ccc\n")
         .unwrap();
     let files = context::tests::TestFileSystem::make_files(
@@ -109,11 +116,29 @@ fn test_json_streets_update_result_error() {
 
     let root = test_wsgi.get_json_for_path("/streets/myrelation/update-result.json");
 
+    jobs::run_queued(test_wsgi.get_ctx()).unwrap();
+    let root = test_wsgi.get_json_for_path(&format!("/jobs/{}/view-result.json", root["id"]));
+    assert_eq!(root["state"], "failed");
     let error = root.as_object().unwrap()["error"].as_str().unwrap();
     assert_eq!(error.is_empty(), false);
 }
 
-/// Tests street_housenumbers_update_result_json(): if the update-result output is well-formed.
+/// Tests update_result_json(): an unknown relation is not enqueued.
+#[test]
+fn test_json_streets_update_result_no_such_relation() {
+    let mut test_wsgi = wsgi::tests::TestWsgi::new();
+
+    let root = test_wsgi.get_json_for_path("/streets/nosuchrelation/update-result.json");
+
+    assert_eq!(root["error"], "no such relation: nosuchrelation");
+    let conn = test_wsgi.get_ctx().get_database_connection().unwrap();
+    let count: i64 = conn
+        .query_row("select count(*) from jobs", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
+/// Tests update_result_json(): if the update-result output is well-formed.
 #[test]
 fn test_json_housenumbers_update_result() {
     let mut test_wsgi = wsgi::tests::TestWsgi::new();
@@ -123,7 +148,7 @@ fn test_json_housenumbers_update_result() {
         /*result_path=*/ "src/fixtures/network/overpass-housenumbers-gazdagret.json",
     )];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     test_wsgi.get_ctx().set_network(network_rc);
     let yamls_cache = serde_json::json!({
         "relations.yaml": {
@@ -137,7 +162,8 @@ fn test_json_housenumbers_update_result() {
     let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
     let overpass_template = context::tests::TestFileSystem::make_file();
     overpass_template
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(b"housenr aaa @RELATION@ bbb @AREA@ ccc\n")
         .unwrap();
     let files = context::tests::TestFileSystem::make_files(
@@ -166,7 +192,11 @@ fn test_json_housenumbers_update_result() {
 
     let root = test_wsgi.get_json_for_path("/street-housenumbers/gazdagret/update-result.json");
 
-    assert_eq!(root.as_object().unwrap()["error"], "");
+    assert_eq!(root["state"], "queued");
+    jobs::run_queued(test_wsgi.get_ctx()).unwrap();
+    let root = test_wsgi.get_json_for_path(&format!("/jobs/{}/view-result.json", root["id"]));
+    assert_eq!(root["state"], "done");
+    assert_eq!(root["error"], "");
     let ctx = test_wsgi.get_ctx();
     let mut relations = areas::Relations::new(ctx).unwrap();
     let relation = relations.get_relation("gazdagret").unwrap();
@@ -180,7 +210,7 @@ fn test_json_housenumbers_update_result() {
     );
 }
 
-/// Tests street_housenumbers_update_result_json(): if the update-result output on error is
+/// Tests update_result_json(): if the update-result output on error is
 /// well-formed.
 #[test]
 fn test_json_housenumbers_update_result_error() {
@@ -191,7 +221,7 @@ fn test_json_housenumbers_update_result_error() {
         /*result_path=*/ "",
     )];
     let network = context::tests::TestNetwork::new(&routes);
-    let network_rc: Rc<dyn context::Network> = Rc::new(network);
+    let network_rc: Arc<dyn context::Network> = Arc::new(network);
     test_wsgi.get_ctx().set_network(network_rc);
     let yamls_cache = serde_json::json!({
         "relations.yaml": {
@@ -205,7 +235,8 @@ fn test_json_housenumbers_update_result_error() {
     let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
     let overpass_template = context::tests::TestFileSystem::make_file();
     overpass_template
-        .borrow_mut()
+        .lock()
+        .unwrap()
         .write_all(b"housenr aaa @RELATION@ bbb @AREA@ ccc\n")
         .unwrap();
     let files = context::tests::TestFileSystem::make_files(
@@ -223,6 +254,9 @@ fn test_json_housenumbers_update_result_error() {
 
     let root = test_wsgi.get_json_for_path("/street-housenumbers/gazdagret/update-result.json");
 
+    jobs::run_queued(test_wsgi.get_ctx()).unwrap();
+    let root = test_wsgi.get_json_for_path(&format!("/jobs/{}/view-result.json", root["id"]));
+    assert_eq!(root["state"], "failed");
     let error = root.as_object().unwrap()["error"].as_str().unwrap();
     assert_eq!(error.is_empty(), false);
 }
@@ -246,7 +280,7 @@ fn test_missing_housenumbers_view_result_json() {
         &[("data/yamls.cache", &yamls_cache_value)],
     );
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     test_wsgi.get_ctx().set_file_system(&file_system_rc);
     {
         let conn = test_wsgi.get_ctx().get_database_connection().unwrap();
@@ -274,6 +308,56 @@ fn test_missing_housenumbers_view_result_json() {
     assert_eq!(ongoing_street.house_numbers.len(), 4);
 }
 
+/// Tests missing_housenumbers_view_lints_json().
+#[test]
+fn test_missing_housenumbers_view_lints_json() {
+    let mut test_wsgi = wsgi::tests::TestWsgi::new();
+    let mut file_system = context::tests::TestFileSystem::new();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "refcounty": "0",
+                "refsettlement": "0",
+                "osmrelation": 42,
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        test_wsgi.get_ctx(),
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    file_system.set_files(&files);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
+    test_wsgi.get_ctx().set_file_system(&file_system_rc);
+    {
+        let conn = test_wsgi.get_ctx().get_database_connection().unwrap();
+        conn.execute_batch(
+            "insert into ref_housenumbers (county_code, settlement_code, street, housenumber, comment) values ('0', '0', 'Tűzkő utca', '1', '');
+             insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values ('gazdagret', '1', 'Tűzkő utca', '1', '', '', '', '', '', '', '', '', '', 'way');
+             insert into osm_housenumbers (relation, osm_id, street, housenumber, postcode, place, housename, conscriptionnumber, flats, floor, door, unit, name, osm_type) values ('gazdagret', '2', 'Tűzkő utca', '1', '', '', '', '', '', '', '', '', '', 'node');
+             insert into mtimes (page, last_modified) values ('housenumbers/gazdagret', '0');",
+        )
+        .unwrap();
+    }
+
+    let result = test_wsgi.get_json_for_path("/missing-housenumbers/gazdagret/view-lints.json");
+
+    assert_eq!(
+        result,
+        serde_json::json!([{
+            "street": "Tűzkő utca",
+            "source": "duplicate",
+            "housenumber": "1",
+            "reason": "duplicate-in-osm",
+            "object-id": 2,
+            "object-type": "node",
+            "other-object-id": 1,
+            "other-object-type": "way",
+        }])
+    );
+}
+
 /// Tests additional_housenumbers_view_result_json().
 #[test]
 fn test_additional_housenumbers_view_result_json() {
@@ -294,7 +378,7 @@ fn test_additional_housenumbers_view_result_json() {
         &[("data/yamls.cache", &yamls_cache_value)],
     );
     file_system.set_files(&files);
-    let file_system_rc: Rc<dyn context::FileSystem> = Rc::new(file_system);
+    let file_system_rc: Arc<dyn context::FileSystem> = Arc::new(file_system);
     test_wsgi.get_ctx().set_file_system(&file_system_rc);
     {
         let conn = test_wsgi.get_ctx().get_database_connection().unwrap();
@@ -313,3 +397,102 @@ fn test_additional_housenumbers_view_result_json() {
     let additional_housenumbers: util::NumberedStreets = serde_json::from_value(result).unwrap();
     assert_eq!(additional_housenumbers.len(), 0);
 }
+
+/// Tests suggested_refstreets_view_result_json().
+#[test]
+fn test_suggested_refstreets_view_result_json() {
+    let mut test_wsgi = wsgi::tests::TestWsgi::new();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "refcounty": "01",
+                "refsettlement": "011",
+                "osmrelation": 42,
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        test_wsgi.get_ctx(),
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    test_wsgi.get_ctx().set_file_system(&file_system);
+    {
+        let conn = test_wsgi.get_ctx().get_database_connection().unwrap();
+        conn.execute_batch(
+            "delete from ref_streets;
+            insert into ref_streets (county_code, settlement_code, street) values ('01', '011', 'Petőfi utca');
+            insert into osm_streets (relation, osm_id, name, highway, service, surface, leisure, osm_type) values ('gazdagret', '1', 'Petőfy utca', '', '', '', '', '');
+            insert into mtimes (page, last_modified) values ('streets/gazdagret', '0');",
+        )
+        .unwrap();
+    }
+
+    let result = test_wsgi.get_json_for_path("/suggested-refstreets/gazdagret/view-result.json");
+
+    assert_eq!(
+        result,
+        serde_json::json!([{
+            "osm_name": "Petőfy utca",
+            "ref_name": "Petőfi utca",
+            "confidence": 0.91,
+        }])
+    );
+}
+
+/// Tests coverage_history_view_result_json().
+#[test]
+fn test_coverage_history_view_result_json() {
+    let mut test_wsgi = wsgi::tests::TestWsgi::new();
+    let yamls_cache = serde_json::json!({
+        "relations.yaml": {
+            "gazdagret": {
+                "refcounty": "01",
+                "refsettlement": "011",
+                "osmrelation": 42,
+            },
+        },
+    });
+    let yamls_cache_value = context::tests::TestFileSystem::write_json_to_file(&yamls_cache);
+    let files = context::tests::TestFileSystem::make_files(
+        test_wsgi.get_ctx(),
+        &[("data/yamls.cache", &yamls_cache_value)],
+    );
+    let file_system = context::tests::TestFileSystem::from_files(&files);
+    test_wsgi.get_ctx().set_file_system(&file_system);
+    {
+        let mut relations = areas::Relations::new(test_wsgi.get_ctx()).unwrap();
+        let relation = relations.get_relation("gazdagret").unwrap();
+        relation
+            .add_coverage_snapshot("housenumbers", 3, 1, 25_f64)
+            .unwrap();
+        // The second snapshot of the same day wins.
+        relation
+            .add_coverage_snapshot("housenumbers", 2, 2, 50_f64)
+            .unwrap();
+        relation
+            .add_coverage_snapshot("streets", 0, 4, 100_f64)
+            .unwrap();
+    }
+
+    let result = test_wsgi.get_json_for_path("/coverage-history/gazdagret/view-result.json");
+
+    assert_eq!(
+        result,
+        serde_json::json!({
+            "housenumbers": [{
+                "date": "2020-05-10",
+                "todo-count": 2,
+                "done-count": 2,
+                "percent": "50.00",
+            }],
+            "streets": [{
+                "date": "2020-05-10",
+                "todo-count": 0,
+                "done-count": 4,
+                "percent": "100.00",
+            }],
+        })
+    );
+}